@@ -4,11 +4,66 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             "GameState",
             "#[derive(serde::Serialize, serde::Deserialize)]",
         )
+        .field_attribute(
+            "GameState.start_nonce",
+            "#[serde(default, skip_serializing_if = \"Option::is_none\")]",
+        )
+        .field_attribute(
+            "GameState.start_position",
+            "#[serde(default, skip_serializing_if = \"Option::is_none\")]",
+        )
+        .field_attribute(
+            "GameState.time_control",
+            "#[serde(default, skip_serializing_if = \"Option::is_none\")]",
+        )
+        .field_attribute(
+            "GameState.clocks",
+            "#[serde(default, skip_serializing_if = \"Option::is_none\")]",
+        )
+        .field_attribute(
+            "GameState.takebacks",
+            "#[serde(default, skip_serializing_if = \"Option::is_none\")]",
+        )
+        .field_attribute(
+            "GameState.checks",
+            "#[serde(default, skip_serializing_if = \"Option::is_none\")]",
+        )
+        .field_attribute(
+            "GameState.game_id",
+            "#[serde(default, skip_serializing_if = \"Option::is_none\")]",
+        )
+        .field_attribute("GameState.halfmove_clock", "#[serde(default)]")
+        .field_attribute("GameState.fullmove_number", "#[serde(default)]")
+        .type_attribute(
+            "TimeControl",
+            "#[derive(serde::Serialize, serde::Deserialize)]",
+        )
+        .type_attribute("Clocks", "#[derive(serde::Serialize, serde::Deserialize)]")
+        .type_attribute("Checks", "#[derive(serde::Serialize, serde::Deserialize)]")
+        .type_attribute(
+            "GameResult",
+            "#[derive(serde::Serialize, serde::Deserialize)]",
+        )
+        .type_attribute("Variant", "#[derive(serde::Serialize, serde::Deserialize)]")
+        .type_attribute("Team", "#[derive(serde::Serialize, serde::Deserialize)]")
+        .type_attribute(
+            "MoveRecord",
+            "#[derive(serde::Serialize, serde::Deserialize)]",
+        )
+        .field_attribute(
+            "MoveRecord.comment",
+            "#[serde(default, skip_serializing_if = \"Option::is_none\")]",
+        )
+        .type_attribute(
+            "Termination",
+            "#[derive(serde::Serialize, serde::Deserialize)]",
+        )
         .type_attribute("Board", "#[derive(serde::Serialize, serde::Deserialize)]")
         .type_attribute("Piece", "#[derive(serde::Serialize, serde::Deserialize)]")
+        .field_attribute("Piece.kind", "#[serde(with = \"super::super::kind\")]")
         .type_attribute("Color", "#[derive(serde::Serialize, serde::Deserialize)]")
         .type_attribute(
-            "Location",
+            "Visibility",
             "#[derive(serde::Serialize, serde::Deserialize)]",
         )
         .type_attribute("Row", "#[derive(serde::Serialize, serde::Deserialize)]")
@@ -21,15 +76,122 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             "StartRequest",
             "#[derive(serde::Deserialize, serde::Serialize)]",
         )
+        .field_attribute("StartRequest.nonce", "#[serde(default)]")
+        .field_attribute(
+            "StartRequest.signatures",
+            "#[serde(default, skip_serializing_if = \"Vec::is_empty\")]",
+        )
         .type_attribute(
             "Transaction",
             "#[derive(serde::Serialize, serde::Deserialize)]",
         )
+        .field_attribute(
+            "Transaction.promotion",
+            "#[serde(default, skip_serializing_if = \"Option::is_none\")]",
+        )
+        .field_attribute(
+            "Transaction.cosignatures",
+            "#[serde(default, skip_serializing_if = \"Vec::is_empty\")]",
+        )
+        .field_attribute(
+            "Transaction.san",
+            "#[serde(default, skip_serializing_if = \"Option::is_none\")]",
+        )
+        .field_attribute(
+            "Transaction.uci",
+            "#[serde(default, skip_serializing_if = \"Option::is_none\")]",
+        )
+        .field_attribute(
+            "Transaction.ply",
+            "#[serde(default, skip_serializing_if = \"Option::is_none\")]",
+        )
+        .field_attribute(
+            "Transaction.game_id",
+            "#[serde(default, skip_serializing_if = \"Option::is_none\")]",
+        )
+        .field_attribute(
+            "Transaction.comment",
+            "#[serde(default, skip_serializing_if = \"Option::is_none\")]",
+        )
+        .field_attribute(
+            "Transaction.governance",
+            "#[serde(default, skip_serializing_if = \"Option::is_none\")]",
+        )
+        .field_attribute(
+            "Transaction.start",
+            "#[serde(default, skip_serializing_if = \"Option::is_none\")]",
+        )
+        .field_attribute(
+            "Transaction.accept_seek",
+            "#[serde(default, skip_serializing_if = \"Option::is_none\")]",
+        )
+        .field_attribute(
+            "Transaction.seek",
+            "#[serde(default, skip_serializing_if = \"Option::is_none\")]",
+        )
+        .field_attribute(
+            "Transaction.cancel_seek",
+            "#[serde(default, skip_serializing_if = \"Option::is_none\")]",
+        )
+        .type_attribute(
+            "TeamSignature",
+            "#[derive(serde::Serialize, serde::Deserialize)]",
+        )
         .type_attribute(
             "Position",
             "#[derive(serde::Serialize, serde::Deserialize)]",
         )
-        .compile(&["proto/game.proto", "proto/query.proto"], &["proto"])?;
+        .type_attribute("Seek", "#[derive(serde::Serialize, serde::Deserialize)]")
+        .field_attribute("Seek.expires_at", "#[serde(default)]")
+        .type_attribute(
+            "AcceptSeekRequest",
+            "#[derive(serde::Serialize, serde::Deserialize)]",
+        )
+        .type_attribute(
+            "CancelSeekRequest",
+            "#[derive(serde::Serialize, serde::Deserialize)]",
+        )
+        .type_attribute("PeerInfo", "#[derive(serde::Serialize)]")
+        .type_attribute(
+            "GovernanceRequest",
+            "#[derive(serde::Serialize, serde::Deserialize)]",
+        )
+        .field_attribute(
+            "GovernanceRequest.min_version",
+            "#[serde(default, skip_serializing_if = \"Option::is_none\")]",
+        )
+        .type_attribute(
+            "ValidatorSignature",
+            "#[derive(serde::Serialize, serde::Deserialize)]",
+        )
+        .type_attribute(
+            "Rejection",
+            "#[derive(serde::Serialize, serde::Deserialize)]",
+        )
+        .type_attribute("ValidateGenesisResponse", "#[derive(serde::Serialize)]")
+        .type_attribute("GenesisError", "#[derive(serde::Serialize)]")
+        .type_attribute("Block", "#[derive(serde::Serialize, serde::Deserialize)]")
+        .type_attribute(
+            "QuorumCertificate",
+            "#[derive(serde::Serialize, serde::Deserialize)]",
+        )
+        .field_attribute(
+            "QuorumCertificate.votes",
+            "#[serde(default, skip_serializing_if = \"Vec::is_empty\")]",
+        )
+        .type_attribute("Commit", "#[derive(serde::Serialize, serde::Deserialize)]")
+        .type_attribute(
+            "VoteLatency",
+            "#[derive(serde::Serialize, serde::Deserialize)]",
+        )
+        .compile(
+            &[
+                "proto/game/v1/game.proto",
+                "proto/query/v1/query.proto",
+                "proto/consensus.proto",
+            ],
+            &["proto"],
+        )?;
 
     Ok(())
 }
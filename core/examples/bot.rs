@@ -0,0 +1,139 @@
+//! A bot that plays both sides of a game through a node's gRPC API.
+//!
+//!     cargo run --example bot -- [NODE_URL] [random|greedy] [MAX_MOVES]
+//!
+//! Implement `Strategy` to plug in your own engine. Running many bots at once
+//! against a testnet doubles as load generation.
+
+use common::{
+    candidates, next_state,
+    pb::{
+        game::{Color, GameState, PieceKind},
+        query::{node_client::NodeClient, StartRequest, StateRequest},
+    },
+    piece_at, Move, Player,
+};
+use rand::seq::SliceRandom;
+use std::error::Error;
+use std::time::Duration;
+use tonic::transport::Channel;
+
+mod common;
+
+/// Orders the candidate moves of the side to move, best first. Candidates
+/// only respect piece ownership; the node rejects illegal ones and the bot
+/// moves on to the next.
+pub trait Strategy {
+    fn rank(&mut self, state: &GameState, candidates: Vec<Move>) -> Vec<Move>;
+}
+
+pub struct RandomStrategy;
+
+impl Strategy for RandomStrategy {
+    fn rank(&mut self, _: &GameState, mut candidates: Vec<Move>) -> Vec<Move> {
+        candidates.shuffle(&mut rand::thread_rng());
+        candidates
+    }
+}
+
+/// Takes the most valuable piece on offer, picking randomly among equals.
+pub struct GreedyStrategy;
+
+impl Strategy for GreedyStrategy {
+    fn rank(&mut self, state: &GameState, candidates: Vec<Move>) -> Vec<Move> {
+        let mut candidates = RandomStrategy.rank(state, candidates);
+        candidates.sort_by_key(|(_, to)| {
+            std::cmp::Reverse(match piece_at(state, to).map(|p| p.kind()) {
+                Some(PieceKind::Queen) => 9,
+                Some(PieceKind::Rook) => 5,
+                Some(PieceKind::Bishop | PieceKind::Knight) => 3,
+                Some(PieceKind::Pawn) => 1,
+                Some(PieceKind::King) | None => 0,
+            })
+        });
+        candidates
+    }
+}
+
+/// Plays one move for the side to move. Returns false if no candidate was
+/// accepted.
+async fn play_move(
+    client: &mut NodeClient<Channel>,
+    player: &Player,
+    state: &GameState,
+    strategy: &mut dyn Strategy,
+) -> Result<bool, Box<dyn Error + Send + Sync>> {
+    for candidate in strategy.rank(state, candidates(state)) {
+        let response = client
+            .transact(player.sign_move(state, &candidate))
+            .await?
+            .into_inner();
+
+        if response.ok {
+            let (from, to) = candidate;
+            println!("({},{}) -> ({},{})", from.x, from.y, to.x, to.y);
+            return Ok(true);
+        }
+    }
+
+    Ok(false)
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn Error + Send + Sync>> {
+    let mut args = std::env::args().skip(1);
+    let url = args.next().unwrap_or("http://127.0.0.1:50050".into());
+    let mut strategy: Box<dyn Strategy> = match args.next().as_deref() {
+        Some("greedy") => Box::new(GreedyStrategy),
+        _ => Box::new(RandomStrategy),
+    };
+    let max_moves: usize = args.next().map(|n| n.parse()).transpose()?.unwrap_or(200);
+
+    let mut client = NodeClient::connect(url).await?;
+    let (white, black) = (Player::new(), Player::new());
+
+    let mut state = client
+        .start(StartRequest {
+            white_player: white.public_key.clone(),
+            black_player: black.public_key.clone(),
+            ..Default::default()
+        })
+        .await?
+        .into_inner()
+        .state
+        .ok_or("no game started")?;
+    let request = StateRequest {
+        white_player: state.white_player.clone(),
+        black_player: state.black_player.clone(),
+        game_id: state.game_id.clone(),
+        ..Default::default()
+    };
+
+    for _ in 0..max_moves {
+        if state.result.is_some() {
+            break;
+        }
+
+        let player = if state.turn == Color::White as i32 {
+            &white
+        } else {
+            &black
+        };
+
+        if !play_move(&mut client, player, &state, strategy.as_mut()).await? {
+            println!("no move accepted");
+            break;
+        }
+
+        state = next_state(
+            &mut client,
+            &request,
+            state.turn,
+            Duration::from_millis(200),
+        )
+        .await?;
+    }
+
+    println!("{}", state.history.unwrap_or_default());
+    Ok(())
+}
@@ -0,0 +1,133 @@
+//! Client plumbing shared by the examples: the generated API, keypairs that
+//! sign transactions the way the node checks them, and move candidates.
+// Each example uses only part of this.
+#![allow(dead_code)]
+
+use libsecp256k1::{sign, Message, PublicKey, SecretKey};
+use pb::{
+    game::{GameState, Piece},
+    query::{node_client::NodeClient, Position, StateRequest, Transaction},
+};
+use sha2::{Digest, Sha256};
+use std::error::Error;
+use std::time::Duration;
+use tonic::transport::Channel;
+
+pub mod pb {
+    pub mod game {
+        pub mod v1 {
+            tonic::include_proto!("game.v1");
+        }
+        pub use v1::*;
+    }
+    pub mod query {
+        pub mod v1 {
+            tonic::include_proto!("query.v1");
+        }
+        pub use v1::*;
+    }
+    pub use super::kind;
+}
+
+// How the node spells piece kinds in JSON, shared with it as is.
+#[path = "../../src/pb/kind.rs"]
+pub mod kind;
+use pb::game;
+
+pub type Move = (Position, Position);
+
+pub fn piece_at<'a>(state: &'a GameState, pos: &Position) -> Option<&'a Piece> {
+    state.board.as_ref()?.rows[pos.x as usize].cells[pos.y as usize]
+        .piece
+        .as_ref()
+}
+
+/// Moves of the side to move that respect piece ownership. Most are
+/// illegal; the node rejects those.
+pub fn candidates(state: &GameState) -> Vec<Move> {
+    let squares: Vec<_> = (0..8)
+        .flat_map(|x| (0..8).map(move |y| Position { x, y }))
+        .collect();
+    let own = |pos: &Position| piece_at(state, pos).is_some_and(|p| p.color == state.turn);
+
+    squares
+        .iter()
+        .filter(|from| own(from))
+        .flat_map(|from| {
+            squares
+                .iter()
+                .filter(|to| !own(to))
+                .map(move |to| (from.clone(), to.clone()))
+        })
+        .collect()
+}
+
+pub struct Player {
+    secret_key: SecretKey,
+    pub public_key: String,
+}
+
+impl Player {
+    pub fn new() -> Self {
+        let secret_key = SecretKey::random(&mut rand::thread_rng());
+        let public_key =
+            hex::encode(PublicKey::from_secret_key(&secret_key).serialize_compressed());
+
+        Self {
+            secret_key,
+            public_key,
+        }
+    }
+
+    /// A MOVE transaction, signed over {whitePlayer, blackPlayer, action}
+    /// and the game's id.
+    pub fn sign_move(&self, state: &GameState, (from, to): &Move) -> Transaction {
+        let mut message = serde_json::json!({
+            "whitePlayer": state.white_player,
+            "blackPlayer": state.black_player,
+            "action": [
+                {"x": from.x, "y": from.y},
+                {"x": to.x, "y": to.y},
+            ],
+        });
+        if let Some(game_id) = &state.game_id {
+            message["gameId"] = game_id.as_str().into();
+        }
+        let hash = Sha256::digest(message.to_string().as_bytes());
+        let (signature, _) = sign(&Message::parse_slice(&hash).unwrap(), &self.secret_key);
+
+        Transaction {
+            white_player: state.white_player.clone(),
+            black_player: state.black_player.clone(),
+            action: vec![from.clone(), to.clone()],
+            signature: hex::encode(signature.serialize()),
+            pub_key: self.public_key.clone(),
+            game_id: state.game_id.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+/// Polls the game until the turn changes or it ends, i.e. until the node
+/// has committed the pending move.
+pub async fn next_state(
+    client: &mut NodeClient<Channel>,
+    request: &StateRequest,
+    turn: i32,
+    poll: Duration,
+) -> Result<GameState, Box<dyn Error + Send + Sync>> {
+    loop {
+        let state = client
+            .state(request.clone())
+            .await?
+            .into_inner()
+            .state
+            .ok_or("the game is gone")?;
+
+        if state.turn != turn || state.result.is_some() {
+            return Ok(state);
+        }
+
+        tokio::time::sleep(poll).await;
+    }
+}
@@ -0,0 +1,227 @@
+//! Drives signed move traffic at a fixed rate against one or more nodes and
+//! reports how long moves take to commit.
+//!
+//!     cargo run --example loadgen -- --node http://127.0.0.1:50050 \
+//!         --players 20 --games 10 --rate 5 --duration 60
+//!
+//! Each game is played by its own task with random moves. A move's latency
+//! runs from submission to the node serving the updated game.
+
+use clap::{Arg, ArgAction, Command};
+use common::{
+    candidates, next_state,
+    pb::query::{node_client::NodeClient, StartRequest, StateRequest},
+    Player,
+};
+use rand::seq::SliceRandom;
+use std::error::Error;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+use tokio::time::{interval, Interval, MissedTickBehavior};
+
+mod common;
+
+type BoxError = Box<dyn Error + Send + Sync>;
+
+#[derive(Default)]
+struct Stats {
+    latencies_ms: Vec<u64>,
+    rejected: u64,
+    failed: u64,
+    games: u64,
+}
+
+/// Plays games between `white` and `black` on `url` until `deadline`,
+/// taking one move slot from `rate` per move.
+async fn drive(
+    url: String,
+    white: Arc<Player>,
+    black: Arc<Player>,
+    rate: Arc<Mutex<Interval>>,
+    stats: Arc<Mutex<Stats>>,
+    deadline: Instant,
+) -> Result<(), BoxError> {
+    let mut client = NodeClient::connect(url).await?;
+    let request = StateRequest {
+        white_player: white.public_key.clone(),
+        black_player: black.public_key.clone(),
+        ..Default::default()
+    };
+
+    while Instant::now() < deadline {
+        let mut state = client
+            .start(StartRequest {
+                white_player: white.public_key.clone(),
+                black_player: black.public_key.clone(),
+                ..Default::default()
+            })
+            .await?
+            .into_inner()
+            .state
+            .ok_or("no game started")?;
+        stats.lock().await.games += 1;
+
+        while state.result.is_none() && Instant::now() < deadline {
+            rate.lock().await.tick().await;
+
+            let player = if state.turn == 0 { &white } else { &black };
+            let mut moves = candidates(&state);
+            moves.shuffle(&mut rand::thread_rng());
+
+            let mut submitted = None;
+            for candidate in moves {
+                let response = client
+                    .transact(player.sign_move(&state, &candidate))
+                    .await?
+                    .into_inner();
+
+                if response.ok {
+                    submitted = Some(Instant::now());
+                    break;
+                }
+                stats.lock().await.rejected += 1;
+            }
+
+            let Some(submitted) = submitted else {
+                break;
+            };
+
+            let next = tokio::time::timeout(
+                deadline.saturating_duration_since(Instant::now()) + Duration::from_secs(10),
+                next_state(&mut client, &request, state.turn, Duration::from_millis(20)),
+            )
+            .await;
+
+            match next {
+                Ok(next) => {
+                    state = next?;
+                    let ms = submitted.elapsed().as_millis() as u64;
+                    stats.lock().await.latencies_ms.push(ms);
+                }
+                Err(_) => {
+                    stats.lock().await.failed += 1;
+                    break;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn percentile(sorted: &[u64], p: f64) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    sorted[((sorted.len() - 1) as f64 * p).round() as usize]
+}
+
+#[tokio::main]
+async fn main() -> Result<(), BoxError> {
+    let matches = Command::new("loadgen")
+        .arg(
+            Arg::new("node")
+                .long("node")
+                .help("gRPC URL of a node; games are spread over all given nodes")
+                .default_value("http://127.0.0.1:50050")
+                .action(ArgAction::Append),
+        )
+        .arg(
+            Arg::new("players")
+                .long("players")
+                .help("Keypairs to create")
+                .default_value("2")
+                .value_parser(clap::value_parser!(usize)),
+        )
+        .arg(
+            Arg::new("games")
+                .long("games")
+                .help("Games to play at once, each between a distinct pair of players")
+                .default_value("1")
+                .value_parser(clap::value_parser!(usize)),
+        )
+        .arg(
+            Arg::new("rate")
+                .long("rate")
+                .help("Moves per second across all games")
+                .default_value("1")
+                .value_parser(clap::value_parser!(f64)),
+        )
+        .arg(
+            Arg::new("duration")
+                .long("duration")
+                .help("Seconds to run for")
+                .default_value("30")
+                .value_parser(clap::value_parser!(u64)),
+        )
+        .get_matches();
+
+    let nodes: Vec<String> = matches.get_many("node").unwrap().cloned().collect();
+    let players: Vec<_> = (0..*matches.get_one::<usize>("players").unwrap())
+        .map(|_| Arc::new(Player::new()))
+        .collect();
+    let games = *matches.get_one::<usize>("games").unwrap();
+    let rate = *matches.get_one::<f64>("rate").unwrap();
+    let duration = Duration::from_secs(*matches.get_one::<u64>("duration").unwrap());
+
+    let pairs: Vec<_> = (0..players.len())
+        .flat_map(|w| (0..players.len()).map(move |b| (w, b)))
+        .filter(|(w, b)| w != b)
+        .take(games)
+        .collect();
+    if pairs.len() < games || rate <= 0.0 {
+        return Err("need players * (players - 1) >= games and a positive rate".into());
+    }
+
+    let mut ticks = interval(Duration::from_secs_f64(1.0 / rate));
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    let ticks = Arc::new(Mutex::new(ticks));
+    let stats = Arc::new(Mutex::new(Stats::default()));
+    let started = Instant::now();
+    let deadline = started + duration;
+
+    let tasks: Vec<_> = pairs
+        .into_iter()
+        .enumerate()
+        .map(|(i, (w, b))| {
+            tokio::spawn(drive(
+                nodes[i % nodes.len()].clone(),
+                players[w].clone(),
+                players[b].clone(),
+                ticks.clone(),
+                stats.clone(),
+                deadline,
+            ))
+        })
+        .collect();
+
+    for task in tasks {
+        if let Err(e) = task.await? {
+            eprintln!("game failed: {}", e);
+        }
+    }
+
+    let mut stats = stats.lock().await;
+    stats.latencies_ms.sort_unstable();
+    let committed = stats.latencies_ms.len();
+    let elapsed = started.elapsed().as_secs_f64();
+
+    println!("games started:   {}", stats.games);
+    println!(
+        "moves committed: {} ({:.2}/s)",
+        committed,
+        committed as f64 / elapsed
+    );
+    println!("candidates rejected: {}", stats.rejected);
+    println!("moves timed out: {}", stats.failed);
+    for (name, p) in [("p50", 0.5), ("p90", 0.9), ("p99", 0.99), ("max", 1.0)] {
+        println!(
+            "commit latency {}: {} ms",
+            name,
+            percentile(&stats.latencies_ms, p)
+        );
+    }
+
+    Ok(())
+}
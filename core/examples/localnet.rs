@@ -0,0 +1,297 @@
+//! Runs a network described by a `network.toml`, or writes the manifests to
+//! run it elsewhere.
+//!
+//!     cargo build && cargo run --example localnet -- launch [network.toml]
+//!     cargo run --example localnet -- compose [network.toml] > docker-compose.yml
+//!     cargo run --example localnet -- k8s [network.toml] > network.yaml
+//!
+//! Paths in the descriptor are relative to it, and the compose file is meant
+//! to sit next to it. Key files that do not exist yet are generated, so the
+//! first run of any command fixes the network's peer ids.
+
+use libp2p::{identity, PeerId};
+use serde::Deserialize;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command};
+
+/// Where compose and k8s mount the key and genesis files in the container.
+const KEY_DIR: &str = "/keys";
+const GENESIS_DIR: &str = "/genesis";
+
+#[derive(Deserialize)]
+struct Network {
+    /// Genesis file every node starts from.
+    genesis: Option<PathBuf>,
+    /// Image the manifests run. Compose builds it from the Dockerfile next
+    /// to the descriptor.
+    #[serde(default = "default_image")]
+    image: String,
+    #[serde(rename = "node")]
+    nodes: Vec<Node>,
+}
+
+fn default_image() -> String {
+    "distributed-chess".into()
+}
+
+#[derive(Deserialize)]
+struct Node {
+    /// Also the node's host name in compose and k8s.
+    name: String,
+    /// File holding the node's ed25519 secret key, 64 hex characters.
+    key: PathBuf,
+    grpc_port: u16,
+    p2p_port: u16,
+    admin_port: Option<u16>,
+}
+
+impl Node {
+    /// Arguments the node runs with, where `key` and `genesis` are the paths
+    /// it sees and `host` names the host each peer is reached on.
+    fn args(
+        &self,
+        network: &Network,
+        peer_ids: &[PeerId],
+        key: &Path,
+        genesis: Option<&Path>,
+        host: impl Fn(&Node) -> String,
+    ) -> Vec<String> {
+        let mut args = vec![
+            "--port".into(),
+            self.grpc_port.to_string(),
+            "--p2p-port".into(),
+            self.p2p_port.to_string(),
+            "--key".into(),
+            key.display().to_string(),
+        ];
+        if let Some(port) = self.admin_port {
+            args.extend(["--admin-port".into(), port.to_string()]);
+        }
+        if let Some(genesis) = genesis {
+            args.extend(["--genesis".into(), genesis.display().to_string()]);
+        }
+
+        let peers: Vec<_> = network
+            .nodes
+            .iter()
+            .zip(peer_ids)
+            .filter(|(peer, _)| peer.name != self.name)
+            .collect();
+        if !peers.is_empty() {
+            args.push("--peers".into());
+        }
+        for (peer, id) in peers {
+            args.extend([host(peer), id.to_string()]);
+        }
+        args
+    }
+
+    fn ports(&self) -> Vec<u16> {
+        [Some(self.grpc_port), Some(self.p2p_port), self.admin_port]
+            .into_iter()
+            .flatten()
+            .collect()
+    }
+}
+
+/// The secret key in `path`, generated first if the file does not exist.
+fn load_key(path: &Path) -> Result<String, Box<dyn Error>> {
+    if !path.exists() {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, hex::encode(rand::random::<[u8; 32]>()))?;
+    }
+
+    Ok(fs::read_to_string(path)?.trim().to_string())
+}
+
+fn peer_id(key: &str) -> Result<PeerId, Box<dyn Error>> {
+    let secret = identity::ed25519::SecretKey::from_bytes(hex::decode(key)?)?;
+    Ok(identity::Keypair::Ed25519(secret.into())
+        .public()
+        .to_peer_id())
+}
+
+/// Starts every node on this machine and waits for them to exit.
+fn launch(network: &Network, dir: &Path, peer_ids: &[PeerId]) -> Result<(), Box<dyn Error>> {
+    // Examples build into target/<profile>/examples, next to the node binary.
+    let exe = std::env::current_exe()?;
+    let binary = exe.parent().and_then(Path::parent).unwrap().join("core");
+    if !binary.exists() {
+        return Err(format!("{} not found, run cargo build first", binary.display()).into());
+    }
+
+    let genesis = network.genesis.as_ref().map(|g| dir.join(g));
+    let mut children: Vec<Child> = Vec::new();
+    for node in &network.nodes {
+        let args = node.args(
+            network,
+            peer_ids,
+            &dir.join(&node.key),
+            genesis.as_deref(),
+            |peer| format!("/ip4/127.0.0.1/tcp/{}", peer.p2p_port),
+        );
+        println!("{}: {} {}", node.name, binary.display(), args.join(" "));
+        children.push(Command::new(&binary).args(args).spawn()?);
+    }
+
+    for mut child in children {
+        child.wait()?;
+    }
+    Ok(())
+}
+
+fn compose(network: &Network, peer_ids: &[PeerId]) -> String {
+    let mut out = String::from("services:\n");
+    for node in &network.nodes {
+        let key = Path::new(KEY_DIR).join(format!("{}.key", node.name));
+        let genesis = network
+            .genesis
+            .as_ref()
+            .map(|_| Path::new(GENESIS_DIR).join("genesis.json"));
+        let args = node.args(network, peer_ids, &key, genesis.as_deref(), |peer| {
+            format!("/dns4/{}/tcp/{}", peer.name, peer.p2p_port)
+        });
+
+        out += &format!("  {}:\n", node.name);
+        out += &format!("    image: {}\n", network.image);
+        out += "    build: .\n";
+        out += &format!(
+            "    command: {}\n",
+            serde_json::to_string(
+                &[&["--address-family".into(), "ipv4".into()], &args[..]].concat()
+            )
+            .unwrap()
+        );
+        out += "    ports:\n";
+        for port in node.ports() {
+            out += &format!("      - \"{}:{}\"\n", port, port);
+        }
+        out += "    volumes:\n";
+        out += &format!("      - ./{}:{}:ro\n", node.key.display(), key.display());
+        if let (Some(src), Some(dst)) = (&network.genesis, &genesis) {
+            out += &format!("      - ./{}:{}:ro\n", src.display(), dst.display());
+        }
+    }
+    out
+}
+
+/// A block scalar holding `text`, indented to `indent` spaces.
+fn block(text: &str, indent: usize) -> String {
+    let pad = " ".repeat(indent);
+    let lines: Vec<_> = text
+        .lines()
+        .map(|line| format!("{}{}", pad, line))
+        .collect();
+    format!("|\n{}\n", lines.join("\n"))
+}
+
+fn k8s(
+    network: &Network,
+    dir: &Path,
+    keys: &[String],
+    peer_ids: &[PeerId],
+) -> Result<String, Box<dyn Error>> {
+    let mut out = String::new();
+    let genesis = match &network.genesis {
+        Some(path) => {
+            out += "---\napiVersion: v1\nkind: ConfigMap\nmetadata:\n  name: genesis\ndata:\n";
+            out += &format!(
+                "  genesis.json: {}",
+                block(&fs::read_to_string(dir.join(path))?, 4)
+            );
+            Some(Path::new(GENESIS_DIR).join("genesis.json"))
+        }
+        None => None,
+    };
+
+    for (node, key) in network.nodes.iter().zip(keys) {
+        let name = &node.name;
+        let args = node.args(
+            network,
+            peer_ids,
+            &Path::new(KEY_DIR).join("node.key"),
+            genesis.as_deref(),
+            |peer| format!("/dns4/{}/tcp/{}", peer.name, peer.p2p_port),
+        );
+
+        out += &format!(
+            "---\napiVersion: v1\nkind: Secret\nmetadata:\n  name: {}-key\nstringData:\n  node.key: \"{}\"\n",
+            name, key
+        );
+
+        out += &format!(
+            "---\napiVersion: v1\nkind: Service\nmetadata:\n  name: {}\nspec:\n  selector:\n    app: distributed-chess\n    node: {}\n  ports:\n",
+            name, name
+        );
+        for port in node.ports() {
+            out += &format!("    - name: port-{}\n      port: {}\n", port, port);
+        }
+
+        out += &format!(
+            "---\napiVersion: apps/v1\nkind: Deployment\nmetadata:\n  name: {}\nspec:\n  replicas: 1\n  selector:\n    matchLabels:\n      app: distributed-chess\n      node: {}\n  template:\n    metadata:\n      labels:\n        app: distributed-chess\n        node: {}\n    spec:\n      containers:\n        - name: node\n          image: {}\n",
+            name, name, name, network.image
+        );
+        out += &format!(
+            "          args: {}\n",
+            serde_json::to_string(
+                &[&["--address-family".into(), "ipv4".into()], &args[..]].concat()
+            )?
+        );
+        out += "          ports:\n";
+        for port in node.ports() {
+            out += &format!("            - containerPort: {}\n", port);
+        }
+        out += &format!(
+            "          volumeMounts:\n            - name: key\n              mountPath: {}\n              readOnly: true\n",
+            KEY_DIR
+        );
+        if genesis.is_some() {
+            out += &format!(
+                "            - name: genesis\n              mountPath: {}\n              readOnly: true\n",
+                GENESIS_DIR
+            );
+        }
+        out += &format!(
+            "      volumes:\n        - name: key\n          secret:\n            secretName: {}-key\n",
+            name
+        );
+        if genesis.is_some() {
+            out += "        - name: genesis\n          configMap:\n            name: genesis\n";
+        }
+    }
+    Ok(out)
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let mut args = std::env::args().skip(1);
+    let command = args.next().unwrap_or_else(|| "launch".into());
+    let path = PathBuf::from(args.next().unwrap_or_else(|| "network.toml".into()));
+
+    let network: Network = toml::from_str(&fs::read_to_string(&path)?)?;
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let keys = network
+        .nodes
+        .iter()
+        .map(|node| load_key(&dir.join(&node.key)))
+        .collect::<Result<Vec<_>, _>>()?;
+    let peer_ids = keys
+        .iter()
+        .map(|key| peer_id(key))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    match command.as_str() {
+        "launch" => launch(&network, dir, &peer_ids)?,
+        "compose" => print!("{}", compose(&network, &peer_ids)),
+        "k8s" => print!("{}", k8s(&network, dir, &keys, &peer_ids)?),
+        other => {
+            return Err(
+                format!("unknown command {}, expected launch, compose or k8s", other).into(),
+            )
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,381 @@
+//! Encrypted backups of a validator's local state, written on a schedule to
+//! a directory. A backup holds the games, and optionally the recording the
+//! node keeps with --record and its identity key; `restore` unpacks one.
+//! Shipping the directory to object storage is left to the operator's own
+//! tooling.
+
+use crate::{
+    network::p2p::LOCAL_KEYS,
+    pb::game::GameState,
+    schema::{GameRecord, SchemaError},
+    App,
+};
+use chacha20poly1305::aead::{Aead, NewAead, Payload};
+use chacha20poly1305::{Key, XChaCha20Poly1305, XNonce};
+use chrono::Utc;
+use libp2p::identity;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tracing::{error, info};
+
+type BoxError = Box<dyn Error + Send + Sync>;
+
+/// Starts every backup file, and is authenticated along with its contents.
+const MAGIC: &[u8] = b"chess-backup-v1\n";
+
+const NONCE_LEN: usize = 24;
+
+/// The `backup` section of the node config.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct BackupConfig {
+    /// Where backups are written.
+    pub dir: PathBuf,
+    /// The encryption key, 64 hex characters. Keep it away from `dir`.
+    pub key_file: PathBuf,
+    /// Seconds between backups.
+    #[serde(default = "default_interval")]
+    pub interval: u64,
+    /// Backups kept; older ones are deleted after each new one.
+    #[serde(default = "default_keep")]
+    pub keep: usize,
+    /// Include the recording given with --record, if any.
+    #[serde(default = "default_true")]
+    pub include_recording: bool,
+    /// Include the node's identity key, so a restored node keeps its peer ID.
+    #[serde(default)]
+    pub include_node_key: bool,
+}
+
+fn default_interval() -> u64 {
+    3600
+}
+
+fn default_keep() -> usize {
+    24
+}
+
+fn default_true() -> bool {
+    true
+}
+
+/// What one backup holds.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Snapshot {
+    pub created_at: i64,
+    pub height: u64,
+    /// Each game as written, upgraded only once it is read.
+    pub games: BTreeMap<String, GameRecord>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub recording: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub node_key: Option<String>,
+}
+
+pub struct Backups {
+    config: BackupConfig,
+    key: [u8; 32],
+    recording: Option<PathBuf>,
+}
+
+impl Backups {
+    /// Reads the encryption key up front, so a bad config stops the node
+    /// before it runs without backups.
+    pub fn new(config: BackupConfig, recording: Option<PathBuf>) -> Result<Self, BoxError> {
+        Ok(Self {
+            key: read_key(&config.key_file)?,
+            recording: recording.filter(|_| config.include_recording),
+            config,
+        })
+    }
+
+    /// Writes a backup every `interval` seconds, the first one right away.
+    pub async fn run(self, app: &'static App) {
+        let mut interval = tokio::time::interval(Duration::from_secs(self.config.interval.max(1)));
+        loop {
+            interval.tick().await;
+            match self.back_up(app).await {
+                Ok(path) => info!("Backed up to {}", path.display()),
+                Err(e) => error!("Backup failed: {}", e),
+            }
+        }
+    }
+
+    async fn back_up(&self, app: &App) -> Result<PathBuf, BoxError> {
+        let snapshot = Snapshot {
+            created_at: Utc::now().timestamp(),
+            height: app.height.load(Ordering::Relaxed),
+            games: app
+                .db
+                .snapshot()
+                .await
+                .into_iter()
+                .map(|(key, game)| (key, GameRecord::new(&game)))
+                .collect(),
+            recording: match &self.recording {
+                Some(path) => Some(tokio::fs::read_to_string(path).await?),
+                None => None,
+            },
+            node_key: self.config.include_node_key.then(node_key).transpose()?,
+        };
+
+        let (dir, keep, key) = (self.config.dir.clone(), self.config.keep, self.key);
+        tokio::task::spawn_blocking(move || {
+            let path = write(&dir, &key, &snapshot)?;
+            prune(&dir, keep)?;
+            Ok(path)
+        })
+        .await?
+    }
+}
+
+impl Snapshot {
+    /// The games in the current schema, keyed by game id.
+    pub fn games(&self) -> Result<BTreeMap<String, GameState>, SchemaError> {
+        load_records(&self.games)
+    }
+}
+
+fn load_records(
+    records: &BTreeMap<String, GameRecord>,
+) -> Result<BTreeMap<String, GameState>, SchemaError> {
+    let mut games = BTreeMap::new();
+    for record in records.values() {
+        let game = record.load()?;
+        games.insert(game.game_id.clone().unwrap_or_default(), game);
+    }
+    Ok(games)
+}
+
+impl App {
+    /// Starts the node from the games `restore` wrote to `dir`, before it
+    /// joins the network.
+    pub async fn load_restored(&self, dir: &Path) -> Result<usize, BoxError> {
+        let records: BTreeMap<String, GameRecord> =
+            serde_json::from_str(&tokio::fs::read_to_string(dir.join("games.json")).await?)?;
+        let games = load_records(&records)?;
+
+        let mut db = self.db.write().await;
+        let mut hasher = self.state_hasher.write().await;
+        for (key, game) in games {
+            hasher.update(&key, &game);
+            db.insert(key, Arc::new(RwLock::new(game)));
+        }
+        Ok(db.len())
+    }
+}
+
+/// Reads a 32-byte key stored as 64 hex characters.
+fn read_key(path: &Path) -> Result<[u8; 32], BoxError> {
+    hex::decode(std::fs::read_to_string(path)?.trim())?
+        .try_into()
+        .map_err(|_| format!("{} does not hold a 32-byte key", path.display()).into())
+}
+
+/// The node's ed25519 secret key, in the form --key reads.
+fn node_key() -> Result<String, BoxError> {
+    match &*LOCAL_KEYS {
+        identity::Keypair::Ed25519(keypair) => Ok(hex::encode(keypair.secret())),
+        #[allow(unreachable_patterns)]
+        _ => Err("only ed25519 node keys can be backed up".into()),
+    }
+}
+
+fn encrypt(key: &[u8; 32], plaintext: &[u8]) -> Result<Vec<u8>, BoxError> {
+    let mut nonce = [0; NONCE_LEN];
+    rand::thread_rng().fill_bytes(&mut nonce);
+    let ciphertext = XChaCha20Poly1305::new(Key::from_slice(key))
+        .encrypt(
+            XNonce::from_slice(&nonce),
+            Payload {
+                msg: plaintext,
+                aad: MAGIC,
+            },
+        )
+        .map_err(|_| "encryption failed")?;
+
+    Ok([MAGIC, &nonce, &ciphertext].concat())
+}
+
+fn decrypt(key: &[u8; 32], data: &[u8]) -> Result<Vec<u8>, BoxError> {
+    let rest = data.strip_prefix(MAGIC).ok_or("not a backup file")?;
+    if rest.len() < NONCE_LEN {
+        return Err("truncated backup".into());
+    }
+
+    let (nonce, ciphertext) = rest.split_at(NONCE_LEN);
+    XChaCha20Poly1305::new(Key::from_slice(key))
+        .decrypt(
+            XNonce::from_slice(nonce),
+            Payload {
+                msg: ciphertext,
+                aad: MAGIC,
+            },
+        )
+        .map_err(|_| "wrong key or corrupted backup".into())
+}
+
+/// Backup files sort by name in the order they were written.
+fn file_name(snapshot: &Snapshot) -> String {
+    format!(
+        "backup-{:012}-{:012}.bin",
+        snapshot.created_at, snapshot.height
+    )
+}
+
+fn is_backup(name: &str) -> bool {
+    name.starts_with("backup-") && name.ends_with(".bin")
+}
+
+/// Writes `snapshot` to `dir`, whole or not at all.
+fn write(dir: &Path, key: &[u8; 32], snapshot: &Snapshot) -> Result<PathBuf, BoxError> {
+    std::fs::create_dir_all(dir)?;
+    let path = dir.join(file_name(snapshot));
+    let partial = path.with_extension("partial");
+    std::fs::write(&partial, encrypt(key, &serde_json::to_vec(snapshot)?)?)?;
+    std::fs::rename(&partial, &path)?;
+    Ok(path)
+}
+
+/// Deletes all but the newest `keep` backups in `dir`.
+fn prune(dir: &Path, keep: usize) -> Result<(), BoxError> {
+    let mut backups: Vec<_> = std::fs::read_dir(dir)?
+        .filter_map(Result::ok)
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .filter(|name| is_backup(name))
+        .collect();
+    backups.sort();
+
+    for name in backups.iter().rev().skip(keep) {
+        std::fs::remove_file(dir.join(name))?;
+    }
+    Ok(())
+}
+
+/// Decrypts the backup at `from` into `to`: games.json, with the games
+/// upgraded to the current schema, plus recording.jsonl and node.key if the
+/// backup has them.
+pub fn restore(from: &Path, key_file: &Path, to: &Path) -> Result<Snapshot, BoxError> {
+    let snapshot: Snapshot =
+        serde_json::from_slice(&decrypt(&read_key(key_file)?, &std::fs::read(from)?)?)?;
+    let games: BTreeMap<_, _> = snapshot
+        .games()?
+        .into_iter()
+        .map(|(key, game)| (key, GameRecord::new(&game)))
+        .collect();
+
+    std::fs::create_dir_all(to)?;
+    std::fs::write(to.join("games.json"), serde_json::to_string_pretty(&games)?)?;
+    if let Some(recording) = &snapshot.recording {
+        std::fs::write(to.join("recording.jsonl"), recording)?;
+    }
+    if let Some(node_key) = &snapshot.node_key {
+        write_secret(&to.join("node.key"), node_key)?;
+    }
+    Ok(snapshot)
+}
+
+/// Writes `contents` readable by the owner only.
+#[cfg(unix)]
+fn write_secret(path: &Path, contents: &str) -> std::io::Result<()> {
+    use std::io::Write;
+    use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(path)?;
+    // The mode applies only to a file created here.
+    file.set_permissions(std::fs::Permissions::from_mode(0o600))?;
+    file.write_all(contents.as_bytes())
+}
+
+#[cfg(not(unix))]
+fn write_secret(path: &Path, contents: &str) -> std::io::Result<()> {
+    std::fs::write(path, contents)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_backups_round_trip_and_rotate() {
+        let dir = std::env::temp_dir().join(format!("chess-backup-{}", rand::random::<u64>()));
+        let key_file = dir.join("key");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(&key_file, hex::encode([7; 32])).unwrap();
+        let key = read_key(&key_file).unwrap();
+
+        let backups = dir.join("backups");
+        let snapshot = |created_at| Snapshot {
+            created_at,
+            height: 3,
+            games: [(
+                "Alice:Bob".to_string(),
+                GameRecord::new(&GameState::new("Alice".to_string(), "Bob".to_string())),
+            )]
+            .into(),
+            recording: Some("{}\n".to_string()),
+            node_key: None,
+        };
+        for created_at in [100, 300, 200] {
+            write(&backups, &key, &snapshot(created_at)).unwrap();
+        }
+        prune(&backups, 2).unwrap();
+
+        let mut left: Vec<_> = std::fs::read_dir(&backups)
+            .unwrap()
+            .map(|e| e.unwrap().path())
+            .collect();
+        left.sort();
+        assert_eq!(left.len(), 2);
+
+        let restored = dir.join("restored");
+        assert_eq!(
+            restore(&left[0], &key_file, &restored).unwrap(),
+            snapshot(200)
+        );
+        assert!(restored.join("games.json").exists());
+        assert!(restored.join("recording.jsonl").exists());
+        assert!(!restored.join("node.key").exists());
+
+        let app = App::new(tokio::sync::mpsc::channel(1).0);
+        assert_eq!(app.load_restored(&restored).await.unwrap(), 1);
+        assert_eq!(
+            app.db.snapshot().await,
+            snapshot(200)
+                .games()
+                .unwrap()
+                .into_iter()
+                .collect::<Vec<_>>()
+        );
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+
+            let key = restored.join("node.key");
+            write_secret(&key, "secret").unwrap();
+            let mode = std::fs::metadata(&key).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        let mut data = std::fs::read(&left[1]).unwrap();
+        *data.last_mut().unwrap() ^= 1;
+        assert!(decrypt(&key, &data).is_err());
+        assert!(decrypt(&[8; 32], &std::fs::read(&left[1]).unwrap()).is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -3,10 +3,23 @@ use std::ops::{Index, IndexMut};
 use crate::{
     errors::AppError,
     pb::{
-        game::{Board, Cell, Color, GameState, Location, Piece, Row},
+        game::{
+            Board, Cell, Color, GameResult, GameState, MoveRecord, Piece, PieceKind, Row, Team,
+            Termination, Variant, Visibility,
+        },
         query::Position,
     },
+    FIRST_MOVE_TIMEOUT,
 };
+use diff::BoardDiff;
+
+/// Pieces a pawn may promote to.
+pub const PROMOTIONS: [PieceKind; 4] = [
+    PieceKind::Queen,
+    PieceKind::Rook,
+    PieceKind::Bishop,
+    PieceKind::Knight,
+];
 
 impl GameState {
     pub fn new(white: String, black: String) -> Self {
@@ -16,6 +29,165 @@ impl GameState {
             turn: Color::White as i32,
             history: Some("".to_string()),
             board: Some(Board::new()),
+            visibility: Visibility::Public as i32,
+            spectators: Vec::new(),
+            started_at: 0,
+            result: None,
+            moves: Vec::new(),
+            white_team: None,
+            black_team: None,
+            variant: Variant::Standard as i32,
+            commitment: None,
+            en_passant: None,
+            start_nonce: None,
+            start_position: None,
+            time_control: None,
+            clocks: None,
+            takebacks: None,
+            checks: None,
+            game_id: None,
+            halfmove_clock: 0,
+            fullmove_number: 1,
+        }
+    }
+
+    pub fn with_access(self, visibility: i32, spectators: Vec<String>) -> Self {
+        Self {
+            visibility,
+            spectators,
+            ..self
+        }
+    }
+
+    pub fn with_started_at(self, started_at: i64) -> Self {
+        Self { started_at, ..self }
+    }
+
+    pub fn with_game_id(self, game_id: String) -> Self {
+        Self {
+            game_id: Some(game_id),
+            ..self
+        }
+    }
+
+    pub fn with_start_nonce(self, nonce: u64) -> Self {
+        Self {
+            start_nonce: (nonce != 0).then_some(nonce),
+            ..self
+        }
+    }
+
+    pub fn with_teams(self, white_team: Option<Team>, black_team: Option<Team>) -> Self {
+        Self {
+            white_team,
+            black_team,
+            ..self
+        }
+    }
+
+    pub fn player(&self, color: Color) -> &str {
+        match color {
+            Color::White => &self.white_player,
+            Color::Black => &self.black_player,
+        }
+    }
+
+    pub fn team(&self, color: Color) -> Option<&Team> {
+        match color {
+            Color::White => self.white_team.as_ref(),
+            Color::Black => self.black_team.as_ref(),
+        }
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.result.is_some()
+    }
+
+    /// Ends the game. `winner` is `None` for draws and aborted games.
+    pub fn finish(&mut self, termination: Termination, winner: Option<Color>) {
+        self.result = Some(GameResult {
+            winner: winner.map(|c| c as i32),
+            termination: termination as i32,
+        });
+    }
+
+    pub fn has_started(&self) -> bool {
+        !self.history.as_deref().unwrap_or_default().is_empty()
+    }
+
+    /// A game White never moved in may be aborted once FIRST_MOVE_TIMEOUT
+    /// has passed since it was created.
+    pub fn validate_abort(&self, timestamp: i64) -> Result<(), AppError> {
+        if self.is_over() {
+            return Err(AppError::InternalGameError(
+                "The game is already over".to_string(),
+            ));
+        }
+
+        if self.has_started() {
+            return Err(AppError::InternalGameError(
+                "The game has already started".to_string(),
+            ));
+        }
+
+        if timestamp - self.started_at < FIRST_MOVE_TIMEOUT {
+            return Err(AppError::InternalGameError(
+                "White still has time for the first move".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Games may be aborted by agreement until both sides have moved.
+    pub fn validate_agreed_abort(&self) -> Result<(), AppError> {
+        if self.is_over() {
+            return Err(AppError::InternalGameError(
+                "The game is already over".to_string(),
+            ));
+        }
+
+        if self.moves.len() >= 2 {
+            return Err(AppError::InternalGameError(
+                "Both sides have already moved".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// A player may resign any game that is not over yet.
+    pub fn validate_resign(&self) -> Result<(), AppError> {
+        if self.is_over() {
+            return Err(AppError::InternalGameError(
+                "The game is already over".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Ends the game with `color` resigning.
+    pub fn resign(&mut self, color: Color) {
+        let winner = match color {
+            Color::White => Color::Black,
+            Color::Black => Color::White,
+        };
+        self.finish(Termination::Resignation, Some(winner));
+    }
+
+    pub fn is_visible_to(&self, viewer: Option<&str>) -> bool {
+        if self.visibility != Visibility::Restricted as i32 {
+            return true;
+        }
+
+        match viewer {
+            Some(v) => {
+                v == self.white_player
+                    || v == self.black_player
+                    || self.spectators.iter().any(|s| s == v)
+            }
+            None => false,
         }
     }
 
@@ -40,28 +212,27 @@ impl GameState {
         }
     }
 
-    pub fn apply_move(&mut self, from: Position, to: Position) -> Result<(), AppError> {
-        if let Err(e) = self.validate_move(&from, &to) {
-            return Err(e);
-        }
+    pub fn apply_move(&mut self, from: Position, to: Position) -> Result<BoardDiff, AppError> {
+        self.apply_promoting_move(from, to, None)
+    }
 
-        self.update_history(&[&from, &to])?;
+    /// Applies a move, replacing the pawn with `promotion` if it is one
+    /// reaching the last rank. Without a promotion such a pawn stays a pawn.
+    /// Returns what the move changed on the board.
+    pub fn apply_promoting_move(
+        &mut self,
+        from: Position,
+        to: Position,
+        promotion: Option<PieceKind>,
+    ) -> Result<BoardDiff, AppError> {
+        self.validate_move(&from, &to)?;
+        self.validate_promotion(&from, &to, promotion)?;
 
-        let from = Location::from_pos(
-            from.clone(),
-            self.board.clone().unwrap().rows[from.clone().x as usize].cells
-                [from.clone().y as usize]
-                .piece
-                .clone(),
-        );
-        let to = Location::from_pos(
-            to.clone(),
-            self.board.clone().unwrap().rows[to.clone().x as usize].cells[to.clone().y as usize]
-                .piece
-                .clone(),
-        );
+        self.update_history(&[&from, &to], promotion)?;
 
-        if let Some(p) = to.clone().piece {
+        let (from, to) = (Square::try_from(&from)?, Square::try_from(&to)?);
+
+        if let Some(p) = self.board.as_ref().unwrap().get_piece_at(to) {
             if p.color == self.turn {
                 return Err(AppError::InternalGameError(
                     "You cannot move onto your own piece".to_string(),
@@ -69,36 +240,57 @@ impl GameState {
             }
         }
 
-        self.board.as_mut().unwrap().rows[to.coords[0] as usize].cells[to.coords[1] as usize]
-            .piece = from.piece;
-        self.board.as_mut().unwrap().rows[from.coords[0] as usize].cells[from.coords[1] as usize]
-            .piece = None;
+        let diff = self.diff_of(from, to, promotion)?;
+        let pawn = self
+            .board
+            .as_ref()
+            .unwrap()
+            .get_piece_at(from)
+            .is_some_and(|p| p.kind() == PieceKind::Pawn);
+        let double_step = pawn && from.x().abs_diff(to.x()) == 2;
+        self.halfmove_clock = if pawn || self.is_capture(from, to) {
+            0
+        } else {
+            self.halfmove_clock + 1
+        };
+        if self.turn == Color::Black as i32 {
+            self.fullmove_number += 1;
+        }
+
+        let mut board = self.board_after(from, to);
+        if let Some(kind) = promotion {
+            board.rows[to.x() as usize].cells[to.y() as usize].piece =
+                Some(Piece::new_from_i32(self.turn, kind));
+        }
+        self.board = Some(board);
+        self.en_passant =
+            double_step.then(|| Square::new((from.x() + to.x()) / 2, from.y()).index());
 
         self.turn = (self.turn + 1) % 2;
 
-        Ok(())
+        if self.board.as_ref().unwrap().is_in_check(self.turn) {
+            let mark = if self.has_check_reply() { '+' } else { '#' };
+            self.moves.last_mut().unwrap().san.push(mark);
+        }
+
+        Ok(diff)
     }
 
+    /// Checks the move by how its piece moves, whatever it leaves its own
+    /// king to; rules from version 7 also call `validate_king_safety`.
     pub fn validate_move(&self, from: &Position, to: &Position) -> Result<(), AppError> {
-        let from = Location::from_pos(
-            from.clone(),
-            self.board.clone().unwrap().rows[from.clone().x as usize].cells
-                [from.clone().y as usize]
-                .piece
-                .clone(),
-        );
-        let to = Location::from_pos(
-            to.clone(),
-            self.board.clone().unwrap().rows[to.clone().x as usize].cells[to.clone().y as usize]
-                .piece
-                .clone(),
-        );
+        if self.is_over() {
+            return Err(AppError::InternalGameError(
+                "The game is already over".to_string(),
+            ));
+        }
 
-        self.validate_move_inner(&from, &to)
+        self.validate_move_inner(Square::try_from(from)?, Square::try_from(to)?)
     }
 
-    fn validate_move_inner(&self, from: &Location, to: &Location) -> Result<(), AppError> {
-        let piece = match &from.piece {
+    fn validate_move_inner(&self, from: Square, to: Square) -> Result<(), AppError> {
+        let board = self.board.as_ref().unwrap();
+        let piece = match board.get_piece_at(from) {
             Some(p) => p,
             None => {
                 return Err(AppError::InternalGameError(
@@ -115,7 +307,7 @@ impl GameState {
             ));
         }
 
-        if !piece.can_move_to(from, to, self.board.as_ref().unwrap()) {
+        if !piece.can_move_to(from, to, board) && !self.is_en_passant(from, to) {
             return Err(AppError::InternalGameError(
                 "Invalid move for the piece".to_string(),
             ));
@@ -124,6 +316,201 @@ impl GameState {
         Ok(())
     }
 
+    /// Refuses a move that leaves the mover's own king in check.
+    pub fn validate_king_safety(&self, from: &Position, to: &Position) -> Result<(), AppError> {
+        let (from, to) = (Square::try_from(from)?, Square::try_from(to)?);
+        if self.board_after(from, to).is_in_check(self.turn) {
+            return Err(AppError::InternalGameError(
+                "The move leaves the king in check".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Whether the move is legal in full, king safety included, as mates,
+    /// stalemates and SAN judge moves under every rules version.
+    fn is_legal(&self, from: Square, to: Square) -> bool {
+        self.validate_move_inner(from, to).is_ok()
+            && !self.board_after(from, to).is_in_check(self.turn)
+    }
+
+    /// Whether the move takes a pawn to the last rank.
+    pub fn promotes(&self, from: &Position, to: &Position) -> bool {
+        let Ok(from) = Square::try_from(from) else {
+            return false;
+        };
+        let last_rank = if self.turn == Color::White as i32 {
+            7
+        } else {
+            0
+        };
+
+        self.board
+            .as_ref()
+            .unwrap()
+            .get_piece_at(from)
+            .is_some_and(|p| p.kind() == PieceKind::Pawn)
+            && to.x == last_rank
+    }
+
+    pub fn validate_promotion(
+        &self,
+        from: &Position,
+        to: &Position,
+        promotion: Option<PieceKind>,
+    ) -> Result<(), AppError> {
+        match promotion {
+            Some(_) if !self.promotes(from, to) => Err(AppError::InternalGameError(
+                "Only a pawn reaching the last rank promotes".to_string(),
+            )),
+            Some(kind) if !PROMOTIONS.contains(&kind) => Err(AppError::InternalGameError(
+                "A pawn promotes to Q, R, B or N".to_string(),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// Whether moving `from` to `to` is a pawn capturing en passant.
+    fn is_en_passant(&self, from: Square, to: Square) -> bool {
+        let Some(piece) = self.board.as_ref().unwrap().get_piece_at(from) else {
+            return false;
+        };
+        let direction = if piece.color == Color::White as i32 {
+            1
+        } else {
+            -1
+        };
+
+        piece.kind() == PieceKind::Pawn
+            && self.en_passant == Some(to.index())
+            && to.x() as i32 - from.x() as i32 == direction
+            && from.y().abs_diff(to.y()) == 1
+    }
+
+    /// The board after the move, taking a pawn captured en passant off the
+    /// square it actually stands on.
+    fn board_after(&self, from: Square, to: Square) -> Board {
+        let mut board = self.board.as_ref().unwrap().with_move(from, to);
+        if self.is_en_passant(from, to) {
+            board.rows[from.x() as usize].cells[to.y() as usize].piece = None;
+        }
+        board
+    }
+
+    /// Whether the move takes a piece, en passant included.
+    fn is_capture(&self, from: Square, to: Square) -> bool {
+        self.board.as_ref().unwrap().get_piece_at(to).is_some() || self.is_en_passant(from, to)
+    }
+
+    /// The side to move is in check and has no legal reply.
+    pub fn is_checkmate(&self) -> bool {
+        self.board.as_ref().unwrap().is_in_check(self.turn) && !self.has_check_reply()
+    }
+
+    /// Whether the side to move, in check, has a legal reply. Only moves
+    /// that could answer the check are tried: the king's and, against a
+    /// single checker, capturing it or blocking its line, en passant included.
+    fn has_check_reply(&self) -> bool {
+        let board = self.board.as_ref().unwrap();
+        let Some(king) = board.king_location(self.turn) else {
+            return false;
+        };
+        if Square::all().any(|to| self.is_legal(king, to)) {
+            return true;
+        }
+
+        let opponent = (self.turn + 1) % 2;
+        let mut checkers = board
+            .pieces()
+            .filter(|(from, piece)| {
+                piece.color == opponent && piece.can_move_to(*from, king, board)
+            })
+            .map(|(from, _)| from);
+        // Only the king can answer a double check.
+        let (Some(checker), None) = (checkers.next(), checkers.next()) else {
+            return false;
+        };
+
+        let mut targets = vec![checker];
+        let (dx, dy) = (
+            king.x() as i32 - checker.x() as i32,
+            king.y() as i32 - checker.y() as i32,
+        );
+        if dx == 0 || dy == 0 || dx.abs() == dy.abs() {
+            let step = |from: u8, d: i32, i: i32| (from as i32 + d.signum() * i) as u8;
+            targets.extend(
+                (1..dx.abs().max(dy.abs()))
+                    .map(|i| Square::new(step(checker.x(), dx, i), step(checker.y(), dy, i))),
+            );
+        }
+        if let Some(en_passant) = self.en_passant {
+            targets.push(Square::new((en_passant / 8) as u8, (en_passant % 8) as u8));
+        }
+
+        board
+            .pieces()
+            .filter(|(from, piece)| piece.color == self.turn && *from != king)
+            .any(|(from, _)| targets.iter().any(|&to| self.is_legal(from, to)))
+    }
+
+    /// The side to move is not in check but has no legal move.
+    pub fn is_stalemate(&self) -> bool {
+        !self.board.as_ref().unwrap().is_in_check(self.turn) && self.legal_moves().is_empty()
+    }
+
+    /// Ends the game as a win for the side that just moved if it gave mate.
+    pub fn adjudicate_checkmate(&mut self) {
+        if !self.is_over() && self.is_checkmate() {
+            let winner = Color::from_i32((self.turn + 1) % 2).expect("Correct color");
+            self.finish(Termination::Checkmate, Some(winner));
+        }
+    }
+
+    /// Ends the game drawn if the side to move is stalemated.
+    pub fn adjudicate_stalemate(&mut self) {
+        if !self.is_over() && self.is_stalemate() {
+            self.finish(Termination::Stalemate, None);
+        }
+    }
+
+    /// All moves the side to move can legally make.
+    pub fn legal_moves(&self) -> Vec<(Position, Position)> {
+        Square::all()
+            .map(Position::from)
+            .flat_map(|from| {
+                self.legal_targets(&from)
+                    .into_iter()
+                    .map(move |to| (from.clone(), to))
+            })
+            .collect()
+    }
+
+    /// The squares the piece on `from` can legally move to: none unless it
+    /// belongs to the side to move, and none that would leave its king in
+    /// check, so pinned pieces stay on their line.
+    pub fn legal_targets(&self, from: &Position) -> Vec<Position> {
+        let Ok(square) = Square::try_from(from) else {
+            return Vec::new();
+        };
+        let board = self.board.as_ref().unwrap();
+        if board
+            .get_piece_at(square)
+            .is_none_or(|p| p.color != self.turn)
+        {
+            return Vec::new();
+        }
+
+        if self.is_over() {
+            return Vec::new();
+        }
+
+        Square::all()
+            .filter(|&to| self.is_legal(square, to))
+            .map(Position::from)
+            .collect()
+    }
+
     fn position_to_notation(pos: &Position) -> String {
         let col = (b'a' + pos.y as u8) as char;
         let row = (pos.x + 1).to_string();
@@ -138,12 +525,12 @@ impl GameState {
     ) -> String {
         let mut notation = String::new();
 
-        if piece.kind != "P" {
-            notation.push_str(&piece.kind);
+        if piece.kind() != PieceKind::Pawn {
+            notation.push_str(piece.kind().letter());
         }
 
         if capture {
-            if piece.kind == "P" {
+            if piece.kind() == PieceKind::Pawn {
                 notation.push((b'a' + from.y as u8) as char);
             }
             notation.push('x');
@@ -153,18 +540,71 @@ impl GameState {
         notation
     }
 
-    pub fn update_history(&mut self, action: &[&Position]) -> Result<(), AppError> {
-        let notation = Self::convert_move_to_notation(
-            action[0],
-            action[1],
-            self.board.as_ref().unwrap().rows[action[0].x as usize].cells[action[0].y as usize]
-                .piece
-                .as_ref()
-                .unwrap(),
-            self.board.as_ref().unwrap().rows[action[1].x as usize].cells[action[1].y as usize]
-                .piece
-                .is_some(),
-        );
+    /// SAN of a move on the current board, without the check mark. Pieces are
+    /// disambiguated by file, then rank, then both, against every other piece
+    /// of the same kind that could legally reach the target.
+    fn san(&self, from: Square, to: Square, piece: &Piece) -> String {
+        let board = self.board.as_ref().unwrap();
+        let capture = self.is_capture(from, to);
+        if piece.kind() == PieceKind::Pawn {
+            let (from, to) = (&Position::from(from), &Position::from(to));
+            return Self::convert_move_to_notation(from, to, piece, capture);
+        }
+
+        let rivals: Vec<_> = Square::all()
+            .filter(|&square| board.get_piece_at(square) == Some(piece))
+            .filter(|&square| square != from && self.is_legal(square, to))
+            .map(Position::from)
+            .collect();
+        let (from, to) = (&Position::from(from), &Position::from(to));
+
+        let square = Self::position_to_notation(from);
+        let disambiguation = if rivals.is_empty() {
+            ""
+        } else if rivals.iter().all(|p| p.y != from.y) {
+            &square[..1]
+        } else if rivals.iter().all(|p| p.x != from.x) {
+            &square[1..]
+        } else {
+            &square
+        };
+
+        format!(
+            "{}{}{}{}",
+            piece.kind().letter(),
+            disambiguation,
+            if capture { "x" } else { "" },
+            Self::position_to_notation(to)
+        )
+    }
+
+    pub fn update_history(
+        &mut self,
+        action: &[&Position],
+        promotion: Option<PieceKind>,
+    ) -> Result<(), AppError> {
+        let suffix = promotion
+            .map(|p| format!("={}", p.letter()))
+            .unwrap_or_default();
+        let (from, to) = (Square::try_from(action[0])?, Square::try_from(action[1])?);
+        let piece = self
+            .board
+            .as_ref()
+            .unwrap()
+            .get_piece_at(from)
+            .ok_or_else(|| {
+                AppError::InternalGameError("No piece at the source location".to_string())
+            })?;
+        let notation =
+            Self::convert_move_to_notation(action[0], action[1], piece, self.is_capture(from, to))
+                + suffix.as_str();
+        let san = self.san(from, to, piece) + suffix.as_str();
+
+        self.moves.push(MoveRecord {
+            uci: to_uci(action[0], action[1], promotion.map(PieceKind::letter)),
+            san,
+            comment: None,
+        });
 
         let n = self
             .history
@@ -185,36 +625,35 @@ impl GameState {
 }
 
 impl Piece {
-    pub fn new(color: Color, kind: String) -> Self {
-        Self {
-            color: color as i32,
-            kind,
-        }
+    pub fn new(color: Color, kind: PieceKind) -> Self {
+        Self::new_from_i32(color as i32, kind)
     }
 
-    pub fn new_from_i32(color: i32, kind: String) -> Self {
-        Self { color, kind }
+    pub fn new_from_i32(color: i32, kind: PieceKind) -> Self {
+        Self {
+            color,
+            kind: kind as i32,
+        }
     }
 
-    pub fn can_move_to(&self, from: &Location, to: &Location, board: &Board) -> bool {
-        let dx = to.coords[0] as i32 - from.coords[0] as i32;
-        let dy = to.coords[1] as i32 - from.coords[1] as i32;
-
-        match self.kind.as_str() {
-            "P" => self.validate_pawn_move(from, to, dx, dy, board),
-            "R" => self.validate_rook_move(from, to, dx, dy, board),
-            "N" => self.validate_knight_move(from, to, dx, dy, board),
-            "B" => self.validate_bishop_move(from, to, dx, dy, board),
-            "Q" => self.validate_queen_move(from, to, dx, dy, board),
-            "K" => self.validate_king_move(from, to, dx, dy, board),
-            _ => false,
+    pub fn can_move_to(&self, from: Square, to: Square, board: &Board) -> bool {
+        let dx = to.x() as i32 - from.x() as i32;
+        let dy = to.y() as i32 - from.y() as i32;
+
+        match self.kind() {
+            PieceKind::Pawn => self.validate_pawn_move(from, to, dx, dy, board),
+            PieceKind::Rook => self.validate_rook_move(from, to, dx, dy, board),
+            PieceKind::Knight => self.validate_knight_move(from, to, dx, dy, board),
+            PieceKind::Bishop => self.validate_bishop_move(from, to, dx, dy, board),
+            PieceKind::Queen => self.validate_queen_move(from, to, dx, dy, board),
+            PieceKind::King => self.validate_king_move(from, to, dx, dy, board),
         }
     }
 
     fn validate_pawn_move(
         &self,
-        from: &Location,
-        to: &Location,
+        from: Square,
+        to: Square,
         dx: i32,
         dy: i32,
         board: &Board,
@@ -237,12 +676,9 @@ impl Piece {
         }
 
         // Initial double move
-        if dy == 0 && dx == 2 * direction && from.coords[0] as i32 == initial_row {
-            let mid_location = Location::new(
-                vec![(from.coords[0] as i32 + direction) as u32, from.coords[1]],
-                Piece::new_from_i32(self.color, "P".to_string()),
-            );
-            return board.is_empty(to) && board.is_empty(&mid_location);
+        if dy == 0 && dx == 2 * direction && from.x() as i32 == initial_row {
+            let middle = Square::new((from.x() as i32 + direction) as u8, from.y());
+            return board.is_empty(to) && board.is_empty(middle);
         }
 
         // Capture move
@@ -255,8 +691,8 @@ impl Piece {
 
     fn validate_rook_move(
         &self,
-        from: &Location,
-        to: &Location,
+        from: Square,
+        to: Square,
         dx: i32,
         dy: i32,
         board: &Board,
@@ -269,14 +705,11 @@ impl Piece {
         let (x_direction, y_direction) = (dx.signum(), dy.signum());
 
         // Check if the path is clear
-        let mut x_coord = from.coords[0] as i32 + x_direction;
-        let mut y_coord = from.coords[1] as i32 + y_direction;
-
-        while x_coord != to.coords[0] as i32 || y_coord != to.coords[1] as i32 {
-            if !board.is_empty(&Location::new(
-                vec![x_coord as u32, y_coord as u32],
-                Piece::new_from_i32(self.color, self.kind.clone()),
-            )) {
+        let mut x_coord = from.x() as i32 + x_direction;
+        let mut y_coord = from.y() as i32 + y_direction;
+
+        while x_coord != to.x() as i32 || y_coord != to.y() as i32 {
+            if !board.is_empty(Square::new(x_coord as u8, y_coord as u8)) {
                 return false;
             }
             x_coord += x_direction;
@@ -286,14 +719,7 @@ impl Piece {
         board.is_empty_or_enemy(to, self.color)
     }
 
-    fn validate_knight_move(
-        &self,
-        _: &Location,
-        to: &Location,
-        dx: i32,
-        dy: i32,
-        board: &Board,
-    ) -> bool {
+    fn validate_knight_move(&self, _: Square, to: Square, dx: i32, dy: i32, board: &Board) -> bool {
         // Knight moves: in "L" shapes
         ((dx.abs() == 2 && dy.abs() == 1) || (dx.abs() == 1 && dy.abs() == 2))
             && board.is_empty_or_enemy(to, self.color)
@@ -301,8 +727,8 @@ impl Piece {
 
     fn validate_bishop_move(
         &self,
-        from: &Location,
-        to: &Location,
+        from: Square,
+        to: Square,
         dx: i32,
         dy: i32,
         board: &Board,
@@ -316,14 +742,11 @@ impl Piece {
         let x_direction = dx.signum();
         let y_direction = dy.signum();
 
-        let mut x = from.coords[0] as i32 + x_direction;
-        let mut y = from.coords[1] as i32 + y_direction;
+        let mut x = from.x() as i32 + x_direction;
+        let mut y = from.y() as i32 + y_direction;
 
-        while x != to.coords[0] as i32 || y != to.coords[1] as i32 {
-            if !board.is_empty(&Location::new(
-                vec![x as u32, y as u32],
-                Piece::new_from_i32(self.color, self.kind.clone()),
-            )) {
+        while x != to.x() as i32 || y != to.y() as i32 {
+            if !board.is_empty(Square::new(x as u8, y as u8)) {
                 return false;
             }
 
@@ -336,8 +759,8 @@ impl Piece {
 
     fn validate_queen_move(
         &self,
-        from: &Location,
-        to: &Location,
+        from: Square,
+        to: Square,
         dx: i32,
         dy: i32,
         board: &Board,
@@ -347,36 +770,123 @@ impl Piece {
             || self.validate_bishop_move(from, to, dx, dy, board)
     }
 
-    fn validate_king_move(
-        &self,
-        _: &Location,
-        to: &Location,
-        dx: i32,
-        dy: i32,
-        board: &Board,
-    ) -> bool {
+    fn validate_king_move(&self, _: Square, to: Square, dx: i32, dy: i32, board: &Board) -> bool {
         // King moves: one square in any direction
         dx.abs() <= 1 && dy.abs() <= 1 && board.is_empty_or_enemy(to, self.color)
     }
 }
 
-impl Location {
-    pub fn new(coords: Vec<u32>, piece: Piece) -> Self {
-        Self {
-            coords,
-            piece: Some(piece),
+/// A square on the board: `x` is the rank and `y` the file, as in `Position`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Square([u8; 2]);
+
+impl Square {
+    pub fn new(x: u8, y: u8) -> Self {
+        debug_assert!(x < 8 && y < 8);
+        Self([x, y])
+    }
+
+    pub fn x(self) -> u8 {
+        self.0[0]
+    }
+
+    pub fn y(self) -> u8 {
+        self.0[1]
+    }
+
+    /// Rank * 8 + file.
+    pub fn index(self) -> u32 {
+        self.x() as u32 * 8 + self.y() as u32
+    }
+
+    pub fn all() -> impl Iterator<Item = Square> {
+        (0..8).flat_map(|x| (0..8).map(move |y| Square::new(x, y)))
+    }
+}
+
+impl TryFrom<&Position> for Square {
+    type Error = AppError;
+
+    fn try_from(pos: &Position) -> Result<Self, Self::Error> {
+        if pos.x >= 8 || pos.y >= 8 {
+            return Err(AppError::InternalGameError(
+                "The position is off the board".to_string(),
+            ));
         }
+
+        Ok(Self::new(pos.x as u8, pos.y as u8))
     }
+}
 
-    pub fn with_coords(self, coords: Vec<u32>) -> Self {
-        Self { coords, ..self }
+impl From<Square> for Position {
+    fn from(square: Square) -> Self {
+        Position {
+            x: square.x() as u32,
+            y: square.y() as u32,
+        }
     }
+}
 
-    pub fn with_piece(self, piece: Piece) -> Self {
-        Self {
-            piece: Some(piece),
-            ..self
+/// A move in the long algebraic notation UCI engines speak: the two squares,
+/// then the lowercase letter of the piece a pawn promotes to, e.g. "e2e4" or
+/// "e7e8q".
+pub fn to_uci(from: &Position, to: &Position, promotion: Option<&str>) -> String {
+    format!(
+        "{}{}{}",
+        GameState::position_to_notation(from),
+        GameState::position_to_notation(to),
+        promotion.unwrap_or_default().to_lowercase()
+    )
+}
+
+/// The squares of a UCI move and the letter of the piece it promotes to.
+/// Whether the move is legal is up to the game it is played in.
+pub fn parse_uci(uci: &str) -> Result<(Position, Position, Option<&'static str>), AppError> {
+    let invalid = || AppError::InternalGameError(format!("{} is not a UCI move", uci));
+    let square = |name: &[u8]| match name {
+        [file @ b'a'..=b'h', rank @ b'1'..=b'8'] => {
+            Some(Position::from(Square::new(rank - b'1', file - b'a')))
         }
+        _ => None,
+    };
+
+    let bytes = uci.as_bytes();
+    if bytes.len() != 4 && bytes.len() != 5 {
+        return Err(invalid());
+    }
+    let from = square(&bytes[..2]).ok_or_else(invalid)?;
+    let to = square(&bytes[2..4]).ok_or_else(invalid)?;
+    let promotion = match bytes.get(4) {
+        Some(letter) if letter.is_ascii_lowercase() => {
+            let kind = PieceKind::from_letter(&(letter.to_ascii_uppercase() as char).to_string())
+                .filter(|kind| PROMOTIONS.contains(kind))
+                .ok_or_else(invalid)?;
+            Some(kind.letter())
+        }
+        Some(_) => return Err(invalid()),
+        None => None,
+    };
+
+    Ok((from, to, promotion))
+}
+
+impl Team {
+    pub fn validate(&self) -> Result<(), AppError> {
+        let mut members = self.members.clone();
+        members.sort();
+        members.dedup();
+
+        if members.len() != self.members.len() {
+            return Err(AppError::StartGameError("duplicate team member".into()));
+        }
+
+        if self.threshold == 0 || self.threshold as usize > self.members.len() {
+            return Err(AppError::StartGameError(
+                "team threshold must be between 1 and the member count".into(),
+            ));
+        }
+
+        Ok(())
     }
 }
 
@@ -385,73 +895,107 @@ impl Board {
         let mut board: Vec<Row> = Vec::default();
         board.resize(8, Row::default());
 
-        for i in 1..7 {
-            let mut row: Vec<Cell> = Vec::default();
-            row.resize(8, Cell::default());
-            board[i] = Row::from(row);
-        }
-
-        let white_figures: Vec<(&str, (usize, usize))> = vec![
-            ("R", (0, 0)),
-            ("N", (0, 1)),
-            ("B", (0, 2)),
-            ("Q", (0, 3)),
-            ("K", (0, 4)),
-            ("B", (0, 5)),
-            ("N", (0, 6)),
-            ("R", (0, 7)),
+        for row in board.iter_mut().take(7).skip(1) {
+            let mut cells: Vec<Cell> = Vec::default();
+            cells.resize(8, Cell::default());
+            *row = Row::from(cells);
+        }
+
+        let white_figures: Vec<(PieceKind, (usize, usize))> = vec![
+            (PieceKind::Rook, (0, 0)),
+            (PieceKind::Knight, (0, 1)),
+            (PieceKind::Bishop, (0, 2)),
+            (PieceKind::Queen, (0, 3)),
+            (PieceKind::King, (0, 4)),
+            (PieceKind::Bishop, (0, 5)),
+            (PieceKind::Knight, (0, 6)),
+            (PieceKind::Rook, (0, 7)),
         ];
 
         board[0].cells.resize(8, Cell::default());
 
         for (kind, coords) in white_figures {
-            board[0][coords.1] = Cell::new(Piece::new(Color::White, kind.to_string()));
-        }
-
-        let black_figures: Vec<(&str, (usize, usize))> = vec![
-            ("R", (7, 0)),
-            ("N", (7, 1)),
-            ("B", (7, 2)),
-            ("Q", (7, 3)),
-            ("K", (7, 4)),
-            ("B", (7, 5)),
-            ("N", (7, 6)),
-            ("R", (7, 7)),
+            board[0][coords.1] = Cell::new(Piece::new(Color::White, kind));
+        }
+
+        let black_figures: Vec<(PieceKind, (usize, usize))> = vec![
+            (PieceKind::Rook, (7, 0)),
+            (PieceKind::Knight, (7, 1)),
+            (PieceKind::Bishop, (7, 2)),
+            (PieceKind::Queen, (7, 3)),
+            (PieceKind::King, (7, 4)),
+            (PieceKind::Bishop, (7, 5)),
+            (PieceKind::Knight, (7, 6)),
+            (PieceKind::Rook, (7, 7)),
         ];
 
         board[7].cells.resize(8, Cell::default());
 
         for (kind, coords) in black_figures {
-            board[7][coords.1] = Cell::new(Piece::new(Color::Black, kind.to_string()));
+            board[7][coords.1] = Cell::new(Piece::new(Color::Black, kind));
         }
 
-        for j in 0..8 {
-            board[1][j] = Cell::new(Piece::new(Color::White, "P".to_string()));
-            board[6][j] = Cell::new(Piece::new(Color::Black, "P".to_string()));
+        for cell in board[1].cells.iter_mut() {
+            *cell = Cell::new(Piece::new(Color::White, PieceKind::Pawn));
+        }
+        for cell in board[6].cells.iter_mut() {
+            *cell = Cell::new(Piece::new(Color::Black, PieceKind::Pawn));
         }
 
         Self { rows: board }
     }
 
-    pub fn get_piece_at(&self, location: &Location) -> Option<&Piece> {
-        self.rows[location.coords[0] as usize].cells[location.coords[1] as usize]
+    pub fn get_piece_at(&self, square: Square) -> Option<&Piece> {
+        self.rows[square.x() as usize].cells[square.y() as usize]
             .piece
             .as_ref()
     }
 
-    pub fn has_enemy_piece(&self, location: &Location, color: i32) -> bool {
-        if let Some(piece) = self.get_piece_at(location) {
+    pub fn has_enemy_piece(&self, square: Square, color: i32) -> bool {
+        if let Some(piece) = self.get_piece_at(square) {
             return piece.color != color;
         }
         false
     }
 
-    pub fn is_empty(&self, location: &Location) -> bool {
-        self.get_piece_at(location).is_none()
+    pub fn is_empty(&self, square: Square) -> bool {
+        self.get_piece_at(square).is_none()
+    }
+
+    pub fn is_empty_or_enemy(&self, square: Square, color: i32) -> bool {
+        self.is_empty(square) || self.has_enemy_piece(square, color)
+    }
+
+    pub fn king_location(&self, color: i32) -> Option<Square> {
+        self.pieces()
+            .find(|(_, p)| p.color == color && p.kind() == PieceKind::King)
+            .map(|(square, _)| square)
+    }
+
+    /// Whether any piece of color `by` could move to `target`.
+    pub fn is_attacked(&self, target: Square, by: i32) -> bool {
+        self.pieces()
+            .any(|(from, piece)| piece.color == by && piece.can_move_to(from, target, self))
     }
 
-    pub fn is_empty_or_enemy(&self, location: &Location, color: i32) -> bool {
-        self.is_empty(location) || self.has_enemy_piece(location, color)
+    pub fn is_in_check(&self, color: i32) -> bool {
+        self.king_location(color)
+            .is_some_and(|king| self.is_attacked(king, (color + 1) % 2))
+    }
+
+    /// The board after moving the piece on `from` to `to`, without any checks.
+    pub fn with_move(&self, from: Square, to: Square) -> Board {
+        let mut board = self.clone();
+        let piece = board.rows[from.x() as usize].cells[from.y() as usize]
+            .piece
+            .take();
+        board.rows[to.x() as usize].cells[to.y() as usize].piece = piece;
+        board
+    }
+
+    /// Occupied squares with their piece.
+    fn pieces(&self) -> impl Iterator<Item = (Square, &Piece)> + '_ {
+        Square::all().filter_map(|square| self.get_piece_at(square).map(|p| (square, p)))
     }
 }
 
@@ -487,20 +1031,72 @@ impl Cell {
     }
 }
 
-impl Location {
-    pub fn from_pos(pos: Position, piece: Option<Piece>) -> Self {
-        Self {
-            coords: vec![pos.x, pos.y],
-            piece,
-        }
-    }
-}
+pub mod chess960;
+pub mod clock;
+pub mod dark;
+pub mod diff;
+pub mod draw;
+pub mod eval;
+pub mod fen;
+pub mod pgn;
+pub mod render;
+pub mod san;
+pub mod takeback;
+pub mod variant;
+
+#[cfg(test)]
+mod perft;
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::pb::game::{Color, GameState};
 
+    #[test]
+    fn test_piece_kinds_are_letters_in_json() {
+        let knight = Piece::new(Color::Black, PieceKind::Knight);
+        let json = serde_json::to_string(&knight).unwrap();
+        assert_eq!(json, r#"{"color":1,"kind":"N"}"#);
+        assert_eq!(serde_json::from_str::<Piece>(&json).unwrap(), knight);
+
+        assert!(serde_json::from_str::<Piece>(r#"{"color":1,"kind":"Z"}"#).is_err());
+        assert!(serde_json::from_str::<Piece>(r#"{"color":1,"kind":1}"#).is_err());
+    }
+
+    #[test]
+    fn test_uci_moves() {
+        let (e2, e4) = (Position { x: 1, y: 4 }, Position { x: 3, y: 4 });
+        assert_eq!(to_uci(&e2, &e4, None), "e2e4");
+        assert_eq!(parse_uci("e2e4").unwrap(), (e2, e4, None));
+
+        let (a7, a8) = (Position { x: 6, y: 0 }, Position { x: 7, y: 0 });
+        assert_eq!(to_uci(&a7, &a8, Some("N")), "a7a8n");
+        assert_eq!(parse_uci("a7a8n").unwrap(), (a7, a8, Some("N")));
+
+        for bad in ["e2", "e2e9", "i2e4", "e7e8k", "e7e8Q", "e2e4e5", "é2e4"] {
+            assert!(parse_uci(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_legal_targets() {
+        let game = GameState::new("Alice".to_string(), "Bob".to_string());
+        let targets = |game: &GameState, x, y| game.legal_targets(&Position { x, y });
+        assert_eq!(
+            targets(&game, 0, 6),
+            [Position { x: 2, y: 5 }, Position { x: 2, y: 7 }]
+        );
+        assert!(targets(&game, 6, 4).is_empty());
+        assert!(targets(&game, 0, 0).is_empty());
+        assert!(targets(&game, 9, 0).is_empty());
+
+        // The rook on e8 pins the knight on e2 to its king.
+        let game = GameState::new("Alice".to_string(), "Bob".to_string())
+            .with_board(Board::from_fen("k3r3/8/8/8/8/8/4N3/4K3").unwrap());
+        assert!(targets(&game, 1, 4).is_empty());
+        assert_eq!(targets(&game, 0, 4).len(), 4);
+    }
+
     #[test]
     fn test_initial_game_state() {
         let white_player = "Alice".to_string();
@@ -554,4 +1150,176 @@ mod tests {
         let to_black = Position { x: 5, y: 0 };
         assert!(game_state.validate_move(&from_black, &to_black).is_ok());
     }
+
+    #[test]
+    fn test_restricted_game_visibility() {
+        let public = GameState::new("Alice".to_string(), "Bob".to_string());
+        assert!(public.is_visible_to(None));
+
+        let restricted =
+            public.with_access(Visibility::Restricted as i32, vec!["Carol".to_string()]);
+        assert!(restricted.is_visible_to(Some("Alice")));
+        assert!(restricted.is_visible_to(Some("Carol")));
+        assert!(!restricted.is_visible_to(Some("Dave")));
+        assert!(!restricted.is_visible_to(None));
+    }
+
+    #[test]
+    fn test_abort_after_first_move_timeout() {
+        let mut game_state =
+            GameState::new("Alice".to_string(), "Bob".to_string()).with_started_at(1000);
+
+        assert!(game_state.validate_abort(1000).is_err());
+        assert!(game_state.validate_abort(1000 + FIRST_MOVE_TIMEOUT).is_ok());
+
+        game_state
+            .apply_move(Position { x: 1, y: 4 }, Position { x: 3, y: 4 })
+            .unwrap();
+        assert!(game_state
+            .validate_abort(1000 + FIRST_MOVE_TIMEOUT)
+            .is_err());
+    }
+
+    #[test]
+    fn test_finished_game_rejects_moves() {
+        let mut game_state =
+            GameState::new("Alice".to_string(), "Bob".to_string()).with_started_at(1000);
+        game_state.finish(Termination::Aborted, None);
+
+        assert!(game_state.is_over());
+        assert!(game_state
+            .validate_move(&Position { x: 1, y: 4 }, &Position { x: 3, y: 4 })
+            .is_err());
+        assert!(game_state
+            .validate_abort(1000 + FIRST_MOVE_TIMEOUT)
+            .is_err());
+    }
+
+    #[test]
+    fn test_check_replies_match_legal_moves() {
+        for fen in [
+            // The king steps aside.
+            "4k3/8/8/8/8/8/8/4R2K b - - 0 1",
+            // Back-rank mate, and the same with a rook to block it.
+            "R5k1/5ppp/8/8/8/8/8/6K1 b - - 0 1",
+            "R5k1/5ppp/8/8/8/8/3r4/6K1 b - - 0 1",
+            // The checking pawn taken en passant.
+            "8/8/8/3k4/2Pp4/2K5/8/8 b - c3 0 1",
+            // Scholar's mate, and a double check.
+            "r1bqkb1r/pppp1Qpp/2n2n2/4p3/2B1P3/8/PPPP1PPP/RNB1K1NR b KQkq - 0 4",
+            "4k3/8/8/1B6/8/8/8/K3R3 b - - 0 1",
+        ] {
+            let game = GameState::from_fen("Alice".into(), "Bob".into(), fen).unwrap();
+            assert!(
+                game.board.as_ref().unwrap().is_in_check(game.turn),
+                "{}",
+                fen
+            );
+            assert_eq!(
+                game.has_check_reply(),
+                !game.legal_moves().is_empty(),
+                "{}",
+                fen
+            );
+        }
+    }
+
+    type Coords = (u32, u32);
+
+    fn play(game_state: &mut GameState, moves: &[(Coords, Coords)]) {
+        for ((fx, fy), (tx, ty)) in moves {
+            game_state
+                .apply_move(Position { x: *fx, y: *fy }, Position { x: *tx, y: *ty })
+                .unwrap();
+        }
+    }
+
+    #[test]
+    fn test_move_records_carry_uci_and_san() {
+        let mut game_state = GameState::new("Alice".to_string(), "Bob".to_string());
+        // 1. e4 e5 2. Qh5 Nc6 3. Bc4 Nf6 4. Qxf7#
+        play(
+            &mut game_state,
+            &[
+                ((1, 4), (3, 4)),
+                ((6, 4), (4, 4)),
+                ((0, 3), (4, 7)),
+                ((7, 1), (5, 2)),
+                ((0, 5), (3, 2)),
+                ((7, 6), (5, 5)),
+                ((4, 7), (6, 5)),
+            ],
+        );
+
+        let san: Vec<_> = game_state.moves.iter().map(|m| m.san.as_str()).collect();
+        assert_eq!(san, ["e4", "e5", "Qh5", "Nc6", "Bc4", "Nf6", "Qxf7#"]);
+        assert_eq!(game_state.moves[0].uci, "e2e4");
+        assert_eq!(game_state.moves[6].uci, "h5f7");
+    }
+
+    #[test]
+    fn test_san_disambiguates_by_file() {
+        let mut game_state = GameState::new("Alice".to_string(), "Bob".to_string());
+        // 1. e3 a6 2. Nc3 a5 3. Nge2
+        play(
+            &mut game_state,
+            &[
+                ((1, 4), (2, 4)),
+                ((6, 0), (5, 0)),
+                ((0, 1), (2, 2)),
+                ((5, 0), (4, 0)),
+                ((0, 6), (1, 4)),
+            ],
+        );
+
+        assert_eq!(game_state.moves.last().unwrap().san, "Nge2");
+    }
+
+    #[test]
+    fn test_en_passant_capture() {
+        let mut game_state = GameState::new("Alice".to_string(), "Bob".to_string());
+        // 1. e4 a6 2. e5 d5
+        play(
+            &mut game_state,
+            &[
+                ((1, 4), (3, 4)),
+                ((6, 0), (5, 0)),
+                ((3, 4), (4, 4)),
+                ((6, 3), (4, 3)),
+            ],
+        );
+        assert_eq!(game_state.en_passant, Some(5 * 8 + 3));
+
+        // 3. exd6
+        play(&mut game_state, &[((4, 4), (5, 3))]);
+        let board = game_state.board.as_ref().unwrap();
+        assert!(board.is_empty(Square::new(4, 3)));
+        assert_eq!(
+            board.get_piece_at(Square::new(5, 3)).unwrap().kind(),
+            PieceKind::Pawn
+        );
+        assert_eq!(game_state.moves.last().unwrap().san, "exd6");
+        assert!(game_state.history.unwrap().ends_with("exd6"));
+    }
+
+    #[test]
+    fn test_en_passant_only_on_the_next_move() {
+        let mut game_state = GameState::new("Alice".to_string(), "Bob".to_string());
+        // 1. e4 a6 2. e5 d5 3. a3 h6
+        play(
+            &mut game_state,
+            &[
+                ((1, 4), (3, 4)),
+                ((6, 0), (5, 0)),
+                ((3, 4), (4, 4)),
+                ((6, 3), (4, 3)),
+                ((1, 0), (2, 0)),
+                ((6, 7), (5, 7)),
+            ],
+        );
+
+        assert!(game_state
+            .validate_move(&Position { x: 4, y: 4 }, &Position { x: 5, y: 3 })
+            .is_err());
+    }
 }
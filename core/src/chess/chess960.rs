@@ -0,0 +1,135 @@
+//! Chess960: the back ranks are shuffled, the same way for both sides, with
+//! the bishops on opposite colours and the king between the rooks. The 960
+//! start positions are numbered as Scharnagl did, so every node derives the
+//! same board from the number a start request carries. Games here have no
+//! castling, so the rooks simply stay where they start.
+
+use crate::{
+    errors::AppError,
+    pb::game::{Board, Color, GameState, Piece, PieceKind, Variant},
+};
+
+/// How many start positions there are.
+pub const POSITIONS: u32 = 960;
+
+/// Where the two knights go among the five squares left after the bishops
+/// and the queen, by the knights' digit of the position number.
+const KNIGHTS: [(usize, usize); 10] = [
+    (0, 1),
+    (0, 2),
+    (0, 3),
+    (0, 4),
+    (1, 2),
+    (1, 3),
+    (1, 4),
+    (2, 3),
+    (2, 4),
+    (3, 4),
+];
+
+/// The back rank of start position `id`, from the a-file.
+pub fn back_rank(id: u32) -> Result<[PieceKind; 8], AppError> {
+    if id >= POSITIONS {
+        return Err(AppError::StartGameError(format!(
+            "chess960 positions are numbered 0 to {}",
+            POSITIONS - 1
+        )));
+    }
+
+    let mut rank = [None; 8];
+    let n = id as usize;
+    rank[2 * (n % 4) + 1] = Some(PieceKind::Bishop);
+    rank[2 * (n / 4 % 4)] = Some(PieceKind::Bishop);
+
+    let mut place = |kind: PieceKind, nth: usize| {
+        let file = (0..8).filter(|&f| rank[f].is_none()).nth(nth).unwrap();
+        rank[file] = Some(kind);
+    };
+    place(PieceKind::Queen, n / 16 % 6);
+    let (first, second) = KNIGHTS[n / 96];
+    // The second knight counts the squares left after the first.
+    place(PieceKind::Knight, first);
+    place(PieceKind::Knight, second - 1);
+    for kind in [PieceKind::Rook, PieceKind::King, PieceKind::Rook] {
+        place(kind, 0);
+    }
+
+    Ok(rank.map(Option::unwrap))
+}
+
+impl Board {
+    /// The board of Chess960 start position `id`.
+    pub fn chess960(id: u32) -> Result<Board, AppError> {
+        let rank = back_rank(id)?;
+        let mut board = Board::new();
+        for (file, kind) in rank.into_iter().enumerate() {
+            for (x, color) in [(0, Color::White), (7, Color::Black)] {
+                board.rows[x].cells[file].piece = Some(Piece::new(color, kind));
+            }
+        }
+        Ok(board)
+    }
+}
+
+impl GameState {
+    pub fn is_chess960(&self) -> bool {
+        self.variant == Variant::Chess960 as i32
+    }
+
+    /// Sets up Chess960 start position `id`.
+    pub fn with_start_position(self, id: u32) -> Result<Self, AppError> {
+        Ok(Self {
+            start_position: Some(id),
+            ..self.with_board(Board::chess960(id)?)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Number of the standard start position.
+    const STANDARD_POSITION: u32 = 518;
+
+    fn letters(id: u32) -> String {
+        back_rank(id).unwrap().iter().map(|k| k.letter()).collect()
+    }
+
+    #[test]
+    fn test_start_positions() {
+        assert_eq!(letters(STANDARD_POSITION), "RNBQKBNR");
+        assert_eq!(letters(0), "BBQNNRKR");
+        assert_eq!(letters(959), "RKRNNQBB");
+        assert_eq!(Board::chess960(STANDARD_POSITION).unwrap(), Board::new());
+        assert!(back_rank(POSITIONS).is_err());
+
+        // Every number names a different legal arrangement.
+        let mut seen = std::collections::HashSet::new();
+        for id in 0..POSITIONS {
+            let rank = back_rank(id).unwrap();
+            let files = |kind| (0..8).filter(move |&f| rank[f] == kind);
+            let bishops: Vec<_> = files(PieceKind::Bishop).collect();
+            let rooks: Vec<_> = files(PieceKind::Rook).collect();
+            let king = files(PieceKind::King).next().unwrap();
+            assert_ne!(bishops[0] % 2, bishops[1] % 2, "{}", id);
+            assert!(rooks[0] < king && king < rooks[1], "{}", id);
+            assert!(seen.insert(rank), "{}", id);
+        }
+    }
+
+    #[test]
+    fn test_chess960_games_record_their_start() {
+        let game = GameState::new("Alice".to_string(), "Bob".to_string())
+            .with_variant(Variant::Chess960 as i32)
+            .with_start_position(0)
+            .unwrap();
+        assert!(game.is_chess960());
+        assert!(game
+            .to_fen()
+            .starts_with("bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w"));
+        assert!(game.to_pgn().contains(
+            "[Variant \"Chess960\"]\n[SetUp \"1\"]\n[FEN \"bbqnnrkr/pppppppp/8/8/8/8/PPPPPPPP/BBQNNRKR w - - 0 1\"]\n"
+        ));
+    }
+}
@@ -0,0 +1,160 @@
+//! Chess clocks, run on block time. A move is charged the seconds between
+//! the blocks of the previous move and its own, so every replica charges
+//! the same, and a move that comes after its side's time ran out loses the
+//! game on time instead of being played.
+
+use crate::pb::game::{Clocks, Color, GameState, Termination, TimeControl};
+
+impl TimeControl {
+    /// Reads the "minutes+increment" form seeks use, e.g. "5+3".
+    pub fn parse(s: &str) -> Option<Self> {
+        let (minutes, increment) = s.trim().split_once('+')?;
+        let minutes: u32 = minutes.trim().parse().ok()?;
+        Some(Self {
+            base: minutes.checked_mul(60).filter(|&base| base > 0)?,
+            increment: increment.trim().parse().ok()?,
+        })
+    }
+}
+
+impl Clocks {
+    fn of(&mut self, color: i32) -> &mut i64 {
+        if color == Color::White as i32 {
+            &mut self.white
+        } else {
+            &mut self.black
+        }
+    }
+}
+
+impl GameState {
+    /// Gives both sides the time control's base time.
+    pub fn with_time_control(self, time_control: Option<TimeControl>) -> Self {
+        Self {
+            clocks: time_control.as_ref().map(|tc| Clocks {
+                white: tc.base as i64,
+                black: tc.base as i64,
+                last_move_at: 0,
+            }),
+            time_control,
+            ..self
+        }
+    }
+
+    /// The clocks as they read at `timestamp`, the side to move's run down,
+    /// possibly below zero.
+    fn clocks_running_at(&self, timestamp: i64) -> Option<Clocks> {
+        let mut clocks = self.clocks.clone()?;
+        if clocks.last_move_at != 0 && !self.is_over() {
+            *clocks.of(self.turn) -= (timestamp - clocks.last_move_at).max(0);
+        }
+        Some(clocks)
+    }
+
+    /// The clocks as they read at `timestamp`, for display.
+    pub fn clocks_at(&self, timestamp: i64) -> Option<Clocks> {
+        self.clocks_running_at(timestamp).map(|mut clocks| {
+            clocks.white = clocks.white.max(0);
+            clocks.black = clocks.black.max(0);
+            clocks
+        })
+    }
+
+    /// Whether the side to move ran out of time by `timestamp`.
+    pub fn out_of_time(&self, timestamp: i64) -> bool {
+        self.clocks_running_at(timestamp)
+            .is_some_and(|mut clocks| *clocks.of(self.turn) < 0)
+    }
+
+    pub fn lose_on_time(&mut self) {
+        let winner = if self.turn == Color::White as i32 {
+            Color::Black
+        } else {
+            Color::White
+        };
+        self.finish(Termination::Timeout, Some(winner));
+    }
+
+    /// Charges the side to move for the time up to `timestamp` and runs the
+    /// clocks from then, if they run yet.
+    pub fn charge_clock(&mut self, timestamp: i64) {
+        if let Some(running) = self.clocks_running_at(timestamp) {
+            if running.last_move_at != 0 {
+                self.clocks = Some(Clocks {
+                    last_move_at: timestamp,
+                    ..running
+                });
+            }
+        }
+    }
+
+    /// Charges the side to move for a move made at `timestamp` and credits
+    /// its increment. White's first move only starts the clocks.
+    pub fn punch_clock(&mut self, timestamp: i64) {
+        let increment = self.time_control.as_ref().map_or(0, |tc| tc.increment);
+        let turn = self.turn;
+        let Some(clocks) = self.clocks.as_mut() else {
+            return;
+        };
+        if clocks.last_move_at == 0 {
+            clocks.last_move_at = timestamp;
+            return;
+        }
+
+        self.charge_clock(timestamp);
+        if let Some(clocks) = self.clocks.as_mut() {
+            *clocks.of(turn) += increment as i64;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pb::query::Position;
+
+    #[test]
+    fn test_clocks() {
+        assert_eq!(
+            TimeControl::parse("5+3"),
+            Some(TimeControl {
+                base: 300,
+                increment: 3
+            })
+        );
+        for bad in ["5", "0+1", "+3", "five+3"] {
+            assert_eq!(TimeControl::parse(bad), None, "{}", bad);
+        }
+
+        let mut game = GameState::new("Alice".to_string(), "Bob".to_string())
+            .with_time_control(TimeControl::parse("1+2"));
+        let mut play = |timestamp, from: (u32, u32), to: (u32, u32)| {
+            game.punch_clock(timestamp);
+            game.apply_move(
+                Position {
+                    x: from.0,
+                    y: from.1,
+                },
+                Position { x: to.0, y: to.1 },
+            )
+            .unwrap();
+            let clocks = game.clocks.clone().unwrap();
+            (clocks.white, clocks.black)
+        };
+
+        // Nothing runs until White's first move.
+        assert_eq!(play(1000, (1, 4), (3, 4)), (60, 60));
+        assert_eq!(play(1010, (6, 4), (4, 4)), (60, 52));
+        assert_eq!(play(1070, (0, 6), (2, 5)), (2, 52));
+        assert_eq!(game.clocks_at(1080).map(|c| c.black), Some(42));
+
+        assert!(!game.out_of_time(1070 + 52));
+        assert!(game.out_of_time(1070 + 53));
+        assert_eq!(game.clocks_at(2000).map(|c| c.black), Some(0));
+        game.lose_on_time();
+        assert_eq!(
+            game.result.map(|r| (r.winner, r.termination)),
+            Some((Some(Color::White as i32), Termination::Timeout as i32))
+        );
+    }
+}
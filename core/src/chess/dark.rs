@@ -0,0 +1,200 @@
+//! Dark chess: moves are committed as a hash and revealed in a later block,
+//! and each player is served only the squares their own pieces can see.
+
+use super::{diff::BoardDiff, Square};
+use crate::{
+    errors::AppError,
+    pb::{
+        game::{Board, Checks, Color, GameState, PieceKind, Variant},
+        query::Position,
+    },
+};
+use alloy_primitives::keccak256;
+
+/// Commitment to a move: keccak256 of `{"action":[{x,y},{x,y}],"salt":salt}`.
+pub fn commitment(from: &Position, to: &Position, salt: &str) -> String {
+    let message = serde_json::json!({
+        "action": [
+            {"x": from.x, "y": from.y},
+            {"x": to.x, "y": to.y},
+        ],
+        "salt": salt,
+    });
+
+    keccak256(message.to_string()).to_string()
+}
+
+impl GameState {
+    pub fn is_dark(&self) -> bool {
+        self.variant == Variant::Dark as i32
+    }
+
+    pub fn with_variant(self, variant: i32) -> Self {
+        Self {
+            variant,
+            checks: (variant == Variant::ThreeCheck as i32).then(Checks::default),
+            ..self
+        }
+    }
+
+    pub fn validate_commit(&self) -> Result<(), AppError> {
+        if !self.is_dark() {
+            return Err(AppError::InternalGameError(
+                "Only dark games take commitments".to_string(),
+            ));
+        }
+
+        if self.is_over() {
+            return Err(AppError::InternalGameError(
+                "The game is already over".to_string(),
+            ));
+        }
+
+        if self.commitment.is_some() {
+            return Err(AppError::InternalGameError(
+                "A move is already committed".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    pub fn validate_reveal(
+        &self,
+        from: &Position,
+        to: &Position,
+        salt: &str,
+    ) -> Result<(), AppError> {
+        match &self.commitment {
+            Some(c) if *c == commitment(from, to, salt) => self.validate_move(from, to),
+            Some(_) => Err(AppError::InternalGameError(
+                "The move does not match the commitment".to_string(),
+            )),
+            None => Err(AppError::InternalGameError(
+                "No move is committed".to_string(),
+            )),
+        }
+    }
+
+    pub fn commit_move(&mut self, commitment: String) -> Result<(), AppError> {
+        self.validate_commit()?;
+        self.commitment = Some(commitment);
+        Ok(())
+    }
+
+    pub fn reveal_move(
+        &mut self,
+        from: Position,
+        to: Position,
+        salt: &str,
+        promotion: Option<PieceKind>,
+    ) -> Result<BoardDiff, AppError> {
+        self.validate_reveal(&from, &to, salt)?;
+        let diff = self.apply_promoting_move(from, to, promotion)?;
+        self.commitment = None;
+        Ok(diff)
+    }
+
+    /// The state as `viewer` may see it. Ongoing dark games hide every piece
+    /// outside the viewer's sight along with the move history; anyone who is
+    /// not a player sees no pieces at all.
+    pub fn view_for(&self, viewer: Option<&str>) -> GameState {
+        if !self.is_dark() || self.is_over() {
+            return self.clone();
+        }
+
+        let color = match viewer {
+            Some(v) if v == self.white_player => Some(Color::White),
+            Some(v) if v == self.black_player => Some(Color::Black),
+            _ => None,
+        };
+
+        let board = self.board.as_ref().unwrap();
+        let mut fogged = board.clone();
+        for square in Square::all() {
+            if !color.is_some_and(|c| board.is_seen_by(square, c)) {
+                fogged.rows[square.x() as usize].cells[square.y() as usize].piece = None;
+            }
+        }
+
+        GameState {
+            board: Some(fogged),
+            history: Some("".to_string()),
+            moves: Vec::new(),
+            // Would tell when the last capture or pawn move was.
+            halfmove_clock: 0,
+            ..self.clone()
+        }
+    }
+}
+
+impl Board {
+    /// Whether `color` has a piece on `target` or a piece that can move there.
+    fn is_seen_by(&self, target: Square, color: Color) -> bool {
+        if self
+            .get_piece_at(target)
+            .is_some_and(|p| p.color == color as i32)
+        {
+            return true;
+        }
+
+        Square::all().any(|from| match self.get_piece_at(from) {
+            Some(p) if p.color == color as i32 => p.can_move_to(from, target, self),
+            _ => false,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dark_game() -> GameState {
+        GameState::new("Alice".to_string(), "Bob".to_string()).with_variant(Variant::Dark as i32)
+    }
+
+    #[test]
+    fn test_commit_then_reveal() {
+        let mut game_state = dark_game();
+        let (from, to) = (Position { x: 1, y: 4 }, Position { x: 3, y: 4 });
+
+        assert!(game_state
+            .reveal_move(from.clone(), to.clone(), "salt", None)
+            .is_err());
+
+        game_state
+            .commit_move(commitment(&from, &to, "salt"))
+            .unwrap();
+        assert!(game_state.commit_move("0x00".to_string()).is_err());
+        assert!(game_state
+            .validate_reveal(&from, &Position { x: 2, y: 4 }, "salt")
+            .is_err());
+
+        game_state.reveal_move(from, to, "salt", None).unwrap();
+        assert!(game_state.commitment.is_none());
+        assert_eq!(game_state.moves[0].uci, "e2e4");
+    }
+
+    #[test]
+    fn test_view_hides_pieces_out_of_sight() {
+        let game_state = dark_game();
+        let pieces = |g: &GameState| {
+            g.board
+                .as_ref()
+                .unwrap()
+                .rows
+                .iter()
+                .flat_map(|r| r.cells.iter())
+                .filter(|c| c.piece.is_some())
+                .count()
+        };
+
+        assert_eq!(pieces(&game_state.view_for(Some("Alice"))), 16);
+        assert_eq!(pieces(&game_state.view_for(Some("Bob"))), 16);
+        assert_eq!(pieces(&game_state.view_for(None)), 0);
+        assert_eq!(
+            pieces(&GameState::new("A".into(), "B".into()).view_for(None)),
+            32
+        );
+    }
+}
@@ -0,0 +1,142 @@
+//! What a move changed on the board. Consumers that already hold the board
+//! can follow a game from these alone instead of a full board per move.
+
+use super::Square;
+use crate::{
+    errors::AppError,
+    pb::{
+        game::{Board, GameState, Piece, PieceKind},
+        query::Position,
+    },
+};
+use serde::Serialize;
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BoardDiff {
+    /// The piece that moved, as it stood on `from`.
+    pub piece: Piece,
+    pub from: Position,
+    pub to: Position,
+    /// The piece taken and the square it stood on, which differs from `to`
+    /// for en passant.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub captured: Option<(Position, Piece)>,
+    /// Where the rook went from and to when castling.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rook_shift: Option<(Position, Position)>,
+    /// The letter of the piece a pawn promoted to.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub promotion: Option<&'static str>,
+}
+
+impl GameState {
+    /// The diff of a validated move from `from` to `to`, before it is made.
+    pub(super) fn diff_of(
+        &self,
+        from: Square,
+        to: Square,
+        promotion: Option<PieceKind>,
+    ) -> Result<BoardDiff, AppError> {
+        let board = self.board.as_ref().unwrap();
+        let piece = board
+            .get_piece_at(from)
+            .cloned()
+            .ok_or_else(|| AppError::InternalGameError("No piece at the source location".into()))?;
+
+        let captured = if self.is_en_passant(from, to) {
+            let square = Square::new(from.x(), to.y());
+            board.get_piece_at(square).map(|p| (square, p))
+        } else {
+            board.get_piece_at(to).map(|p| (to, p))
+        };
+
+        Ok(BoardDiff {
+            piece,
+            from: from.into(),
+            to: to.into(),
+            captured: captured.map(|(square, p)| (square.into(), p.clone())),
+            rook_shift: None,
+            promotion: promotion.map(PieceKind::letter),
+        })
+    }
+}
+
+impl Board {
+    /// Makes the move `diff` describes on this board.
+    pub fn apply_diff(&mut self, diff: &BoardDiff) -> Result<(), AppError> {
+        if let Some((square, _)) = &diff.captured {
+            self.put(square, None)?;
+        }
+        self.put(&diff.from, None)?;
+        let piece = match diff.promotion.and_then(PieceKind::from_letter) {
+            Some(kind) => Piece::new_from_i32(diff.piece.color, kind),
+            None => diff.piece.clone(),
+        };
+        self.put(&diff.to, Some(piece))?;
+
+        if let Some((from, to)) = &diff.rook_shift {
+            let rook = self.get_piece_at(Square::try_from(from)?).cloned();
+            self.put(from, None)?;
+            self.put(to, rook)?;
+        }
+
+        Ok(())
+    }
+
+    fn put(&mut self, pos: &Position, piece: Option<Piece>) -> Result<(), AppError> {
+        let square = Square::try_from(pos)?;
+        self.rows[square.x() as usize].cells[square.y() as usize].piece = piece;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pb::{
+        game::{Color, GameState, PieceKind},
+        query::Position,
+    };
+
+    #[test]
+    fn test_diffs_replay_the_board() {
+        let mut game = GameState::from_fen(
+            "Alice".to_string(),
+            "Bob".to_string(),
+            "k7/4P3/8/3pP3/8/8/8/4K3 w - d6 0 1",
+        )
+        .unwrap();
+        let mut board = game.board.clone().unwrap();
+
+        let diff = game
+            .apply_move(Position { x: 4, y: 4 }, Position { x: 5, y: 3 })
+            .unwrap();
+        assert_eq!(diff.piece.kind(), PieceKind::Pawn);
+        assert_eq!(
+            diff.captured
+                .as_ref()
+                .map(|(pos, p)| (pos.x, pos.y, p.color)),
+            Some((4, 3, Color::Black as i32))
+        );
+        board.apply_diff(&diff).unwrap();
+        assert_eq!(Some(&board), game.board.as_ref());
+
+        game.apply_move(Position { x: 7, y: 0 }, Position { x: 7, y: 1 })
+            .unwrap();
+        board = game.board.clone().unwrap();
+        let diff = game
+            .apply_promoting_move(
+                Position { x: 6, y: 4 },
+                Position { x: 7, y: 4 },
+                Some(PieceKind::Knight),
+            )
+            .unwrap();
+        assert_eq!((diff.captured.is_none(), diff.promotion), (true, Some("N")));
+        board.apply_diff(&diff).unwrap();
+        assert_eq!(Some(&board), game.board.as_ref());
+
+        let json = serde_json::to_value(&diff).unwrap();
+        assert_eq!(json["piece"]["kind"], "P");
+        assert!(json.get("captured").is_none());
+    }
+}
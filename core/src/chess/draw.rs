@@ -0,0 +1,215 @@
+//! Dead positions: neither side can ever checkmate, whatever is played. Both
+//! checks are a fixed analysis of the board rather than a search, so every
+//! replica reaches the same verdict regardless of time or hardware.
+
+use super::Square;
+use crate::pb::game::{Board, Color, GameState, PieceKind, Termination};
+
+/// Board offsets of the squares a king can step to.
+const KING_STEPS: [(i8, i8); 8] = [
+    (-1, -1),
+    (-1, 0),
+    (-1, 1),
+    (0, -1),
+    (0, 1),
+    (1, -1),
+    (1, 0),
+    (1, 1),
+];
+
+fn offset(square: Square, (dx, dy): (i8, i8)) -> Option<Square> {
+    let x = square.x().checked_add_signed(dx).filter(|x| *x < 8)?;
+    let y = square.y().checked_add_signed(dy).filter(|y| *y < 8)?;
+    Some(Square::new(x, y))
+}
+
+fn forward(color: i32) -> i8 {
+    if color == Color::White as i32 {
+        1
+    } else {
+        -1
+    }
+}
+
+impl Board {
+    /// Neither side has material to mate with: bare kings, a single minor
+    /// piece, or bishops that all stand on squares of one color.
+    pub fn has_insufficient_material(&self) -> bool {
+        let others: Vec<_> = self
+            .pieces()
+            .filter(|(_, p)| p.kind() != PieceKind::King)
+            .collect();
+
+        match others.as_slice() {
+            [] => true,
+            [(_, p)] => matches!(p.kind(), PieceKind::Knight | PieceKind::Bishop),
+            [(first, _), ..] => others.iter().all(|(square, p)| {
+                p.kind() == PieceKind::Bishop
+                    && (square.x() + square.y()) % 2 == (first.x() + first.y()) % 2
+            }),
+        }
+    }
+
+    /// Only kings and pawns remain, every pawn is stuck for good and neither
+    /// king can ever reach an enemy pawn, so only the kings will ever move.
+    pub fn is_blocked(&self) -> bool {
+        let mut pawns = Vec::new();
+        for (square, piece) in self.pieces() {
+            match piece.kind() {
+                PieceKind::King => {}
+                PieceKind::Pawn => pawns.push((square, piece.color)),
+                _ => return false,
+            }
+        }
+
+        let stuck = pawns.iter().all(|(square, color)| {
+            let ahead = forward(*color);
+            let blocked = offset(*square, (ahead, 0)).is_none_or(|s| {
+                self.get_piece_at(s)
+                    .is_some_and(|p| p.kind() == PieceKind::Pawn)
+            });
+            let captures = [-1, 1]
+                .into_iter()
+                .filter_map(|dy| offset(*square, (ahead, dy)))
+                .any(|s| self.has_enemy_piece(s, *color));
+
+            blocked && !captures
+        });
+
+        stuck
+            && [Color::White as i32, Color::Black as i32]
+                .into_iter()
+                .all(|color| !self.king_reaches_enemy_pawn(color, &pawns))
+    }
+
+    /// Walks every square the king of `color` can reach through empty squares
+    /// no enemy pawn attacks, looking for one next to an enemy pawn.
+    fn king_reaches_enemy_pawn(&self, color: i32, pawns: &[(Square, i32)]) -> bool {
+        let Some(king) = self.king_location(color) else {
+            return false;
+        };
+
+        let attacked = |square: Square| {
+            pawns.iter().any(|(pawn, c)| {
+                *c != color
+                    && [-1, 1]
+                        .into_iter()
+                        .any(|dy| offset(*pawn, (forward(*c), dy)) == Some(square))
+            })
+        };
+        let enemy_pawn = |square: Square| pawns.iter().any(|(p, c)| *p == square && *c != color);
+
+        let mut seen = vec![king];
+        let mut frontier = vec![king];
+        while let Some(square) = frontier.pop() {
+            for next in KING_STEPS.iter().filter_map(|step| offset(square, *step)) {
+                if enemy_pawn(next) {
+                    return true;
+                }
+                if seen.contains(&next) || !self.is_empty(next) || attacked(next) {
+                    continue;
+                }
+                seen.push(next);
+                frontier.push(next);
+            }
+        }
+
+        false
+    }
+}
+
+impl GameState {
+    /// Ends the game as a draw if no sequence of moves can lead to mate.
+    pub fn adjudicate_dead_position(&mut self) {
+        let board = self.board.as_ref().unwrap();
+
+        if board.has_insufficient_material() {
+            self.finish(Termination::InsufficientMaterial, None);
+        } else if board.is_blocked() {
+            self.finish(Termination::DeadPosition, None);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pb::game::{Cell, Piece, Row};
+
+    fn board(pieces: &[(&str, Color, u8, u8)]) -> Board {
+        let mut rows = vec![Row::from(vec![Cell::default(); 8]); 8];
+        for (kind, color, x, y) in pieces {
+            rows[*x as usize][*y as usize] =
+                Cell::new(Piece::new(*color, PieceKind::from_letter(kind).unwrap()));
+        }
+        Board::from(rows)
+    }
+
+    #[test]
+    fn test_insufficient_material() {
+        use Color::*;
+
+        assert!(board(&[("K", White, 0, 4), ("K", Black, 7, 4)]).has_insufficient_material());
+        assert!(
+            board(&[("K", White, 0, 4), ("K", Black, 7, 4), ("N", Black, 5, 5)])
+                .has_insufficient_material()
+        );
+        assert!(board(&[
+            ("K", White, 0, 4),
+            ("K", Black, 7, 4),
+            ("B", White, 0, 2),
+            ("B", Black, 7, 5),
+        ])
+        .has_insufficient_material());
+        assert!(!board(&[
+            ("K", White, 0, 4),
+            ("K", Black, 7, 4),
+            ("B", White, 0, 2),
+            ("B", Black, 7, 2),
+        ])
+        .has_insufficient_material());
+        assert!(
+            !board(&[("K", White, 0, 4), ("K", Black, 7, 4), ("P", White, 1, 0)])
+                .has_insufficient_material()
+        );
+    }
+
+    #[test]
+    fn test_locked_pawn_chain_is_dead() {
+        use Color::*;
+
+        // A full wall of rammed pawns on ranks 4 and 5 that no king can pass.
+        let mut pieces = vec![("K", White, 0, 0), ("K", Black, 7, 7)];
+        for y in 0..8 {
+            let x = if y % 2 == 0 { 3 } else { 4 };
+            pieces.push(("P", White, x, y));
+            pieces.push(("P", Black, x + 1, y));
+        }
+
+        let mut game =
+            GameState::new("Alice".to_string(), "Bob".to_string()).with_board(board(&pieces));
+        game.adjudicate_dead_position();
+        assert_eq!(
+            game.result.unwrap().termination,
+            Termination::DeadPosition as i32
+        );
+
+        // A king on the wrong side of the wall can eat into it.
+        let mut behind = pieces.clone();
+        behind[1] = ("K", Black, 2, 6);
+        assert!(!board(&behind).is_blocked());
+
+        // And a pawn with an open file can still advance.
+        pieces.retain(|(_, c, _, y)| !(*y == 0 && *c == Black));
+        assert!(!board(&pieces).is_blocked());
+
+        // Nor is a position where a pawn can still capture.
+        assert!(!board(&[
+            ("K", White, 0, 0),
+            ("K", Black, 7, 7),
+            ("P", White, 3, 3),
+            ("P", Black, 4, 4),
+        ])
+        .is_blocked());
+    }
+}
@@ -0,0 +1,138 @@
+//! Material and a simple static evaluation, for a material bar and, later,
+//! adjudicating abandoned games. Fixed weights and no search, so every node
+//! gets the same numbers.
+
+use super::Square;
+use crate::pb::game::{Board, Color, Evaluation, PieceKind, SideEvaluation};
+
+/// Centipawns per square a piece other than the king can move to.
+const MOBILITY: i32 = 2;
+/// Centipawns per rank a pawn has advanced.
+const ADVANCE: i32 = 5;
+
+impl PieceKind {
+    /// Material in pawns, as a material bar counts it. Kings count nothing.
+    pub fn points(self) -> u32 {
+        match self {
+            PieceKind::Pawn => 1,
+            PieceKind::Knight | PieceKind::Bishop => 3,
+            PieceKind::Rook => 5,
+            PieceKind::Queen => 9,
+            PieceKind::King => 0,
+        }
+    }
+
+    /// Material in centipawns, which tells a bishop above a knight.
+    fn centipawns(self) -> i32 {
+        match self {
+            PieceKind::Pawn => 100,
+            PieceKind::Knight => 320,
+            PieceKind::Bishop => 330,
+            PieceKind::Rook => 500,
+            PieceKind::Queen => 900,
+            PieceKind::King => 0,
+        }
+    }
+}
+
+impl Board {
+    /// Each side's material and score, whoever is to move.
+    pub fn evaluate(&self) -> Evaluation {
+        let white = self.evaluate_side(Color::White);
+        let black = self.evaluate_side(Color::Black);
+        Evaluation {
+            balance: white.score - black.score,
+            white: Some(white),
+            black: Some(black),
+        }
+    }
+
+    fn evaluate_side(&self, color: Color) -> SideEvaluation {
+        let mut side = SideEvaluation::default();
+        for (from, piece) in self.pieces().filter(|(_, p)| p.color == color as i32) {
+            let kind = piece.kind();
+            match kind {
+                PieceKind::Pawn => side.pawns += 1,
+                PieceKind::Knight => side.knights += 1,
+                PieceKind::Bishop => side.bishops += 1,
+                PieceKind::Rook => side.rooks += 1,
+                PieceKind::Queen => side.queens += 1,
+                PieceKind::King => continue,
+            }
+            side.material += kind.points();
+            side.score += kind.centipawns();
+
+            let reach = Square::all()
+                .filter(|to| piece.can_move_to(from, *to, self))
+                .count();
+            side.score += MOBILITY * reach as i32;
+            if kind == PieceKind::Pawn {
+                let advanced = match color {
+                    Color::White => from.x() as i32 - 1,
+                    Color::Black => 6 - from.x() as i32,
+                };
+                side.score += ADVANCE * advanced;
+            }
+        }
+        side
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pb::{
+        game::{Board, Color, GameState, Piece, PieceKind},
+        query::Position,
+    };
+
+    #[test]
+    fn test_evaluation() {
+        let start = Board::new().evaluate();
+        let white = start.white.unwrap();
+        assert_eq!(
+            (
+                white.pawns,
+                white.knights,
+                white.bishops,
+                white.rooks,
+                white.queens
+            ),
+            (8, 2, 2, 2, 1)
+        );
+        assert_eq!(white.material, 39);
+        // Only pawns and knights can move, to 16 and 4 squares.
+        assert_eq!(white.score, 4_000 + 2 * 20);
+        assert_eq!(start.balance, 0);
+        assert_eq!(start.black.unwrap(), white);
+
+        // White wins a pawn.
+        let mut game = GameState::new("Alice".to_string(), "Bob".to_string());
+        for ((fx, fy), (tx, ty)) in [((1, 4), (3, 4)), ((6, 3), (4, 3)), ((3, 4), (4, 3))] {
+            game.apply_move(Position { x: fx, y: fy }, Position { x: tx, y: ty })
+                .unwrap();
+        }
+        let evaluation = game.board.unwrap().evaluate();
+        let (white, black) = (evaluation.white.unwrap(), evaluation.black.unwrap());
+        assert_eq!((white.material, black.material), (39, 38));
+        assert!(evaluation.balance > 0);
+
+        // Bare kings are even, and kings are worth nothing.
+        let mut board = Board::new();
+        for row in board.rows.iter_mut() {
+            for cell in row.cells.iter_mut() {
+                if cell
+                    .piece
+                    .as_ref()
+                    .is_some_and(|p| p.kind() != PieceKind::King)
+                {
+                    cell.piece = None;
+                }
+            }
+        }
+        board.rows[3].cells[3].piece = Some(Piece::new(Color::Black, PieceKind::Queen));
+        let evaluation = board.evaluate();
+        assert_eq!(evaluation.white.unwrap().material, 0);
+        assert_eq!(evaluation.black.unwrap().material, 9);
+        assert!(evaluation.balance < -900);
+    }
+}
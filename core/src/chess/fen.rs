@@ -0,0 +1,228 @@
+//! FEN, the one-line position format chess frontends and engines exchange.
+//! Games here have no castling, so exported castling rights are always `-`
+//! and imported ones are only checked for form.
+
+use super::Square;
+use crate::{
+    errors::AppError,
+    pb::game::{Board, Cell, Color, GameState, Piece, PieceKind, Row},
+};
+
+fn invalid(reason: impl Into<String>) -> AppError {
+    AppError::InternalGameError(format!("Invalid FEN: {}", reason.into()))
+}
+
+fn square_name(square: Square) -> String {
+    format!("{}{}", (b'a' + square.y()) as char, square.x() + 1)
+}
+
+impl Board {
+    /// The piece placement field, rank 8 first.
+    pub fn to_fen(&self) -> String {
+        let ranks: Vec<_> = (0..8)
+            .rev()
+            .map(|x| {
+                let mut rank = String::new();
+                let mut empty = 0;
+                for y in 0..8 {
+                    let Some(piece) = self.get_piece_at(Square::new(x, y)) else {
+                        empty += 1;
+                        continue;
+                    };
+                    if empty > 0 {
+                        rank.push_str(&empty.to_string());
+                        empty = 0;
+                    }
+                    let letter = piece.kind().letter();
+                    if piece.color == Color::White as i32 {
+                        rank.push_str(letter);
+                    } else {
+                        rank.push_str(&letter.to_lowercase());
+                    }
+                }
+                if empty > 0 {
+                    rank.push_str(&empty.to_string());
+                }
+                rank
+            })
+            .collect();
+
+        ranks.join("/")
+    }
+
+    /// Parses a piece placement field. Each side needs exactly one king and
+    /// no pawn may stand on the first or last rank.
+    pub fn from_fen(placement: &str) -> Result<Self, AppError> {
+        let ranks: Vec<_> = placement.split('/').collect();
+        if ranks.len() != 8 {
+            return Err(invalid("the placement needs 8 ranks"));
+        }
+
+        let mut rows = vec![Row::from(vec![Cell::default(); 8]); 8];
+        for (i, rank) in ranks.iter().enumerate() {
+            let x = 7 - i;
+            let mut y = 0;
+            for c in rank.chars() {
+                if let Some(n) = c.to_digit(10).filter(|n| (1..=8).contains(n)) {
+                    y += n as usize;
+                    continue;
+                }
+                let kind = PieceKind::from_letter(&c.to_ascii_uppercase().to_string())
+                    .ok_or_else(|| invalid(format!("unknown piece {}", c)))?;
+                if y >= 8 {
+                    return Err(invalid(format!("rank {} has more than 8 squares", x + 1)));
+                }
+                if kind == PieceKind::Pawn && (x == 0 || x == 7) {
+                    return Err(invalid("pawns cannot stand on the first or last rank"));
+                }
+                let color = if c.is_ascii_uppercase() {
+                    Color::White
+                } else {
+                    Color::Black
+                };
+                rows[x][y] = Cell::new(Piece::new(color, kind));
+                y += 1;
+            }
+            if y != 8 {
+                return Err(invalid(format!("rank {} does not have 8 squares", x + 1)));
+            }
+        }
+
+        let board = Board::from(rows);
+        for color in [Color::White, Color::Black] {
+            let kings = board
+                .pieces()
+                .filter(|(_, p)| p.color == color as i32 && p.kind() == PieceKind::King)
+                .count();
+            if kings != 1 {
+                return Err(invalid("each side needs exactly one king"));
+            }
+        }
+
+        Ok(board)
+    }
+}
+
+impl GameState {
+    /// The position as FEN.
+    pub fn to_fen(&self) -> String {
+        let turn = if self.turn == Color::White as i32 {
+            "w"
+        } else {
+            "b"
+        };
+        let en_passant = self.en_passant.map_or("-".to_string(), |i| {
+            square_name(Square::new((i / 8) as u8, (i % 8) as u8))
+        });
+        format!(
+            "{} {} - {} {} {}",
+            self.board.as_ref().unwrap().to_fen(),
+            turn,
+            en_passant,
+            self.halfmove_clock,
+            self.fullmove_number
+        )
+    }
+
+    /// A game between `white` and `black` from the position in `fen`.
+    pub fn from_fen(white: String, black: String, fen: &str) -> Result<Self, AppError> {
+        let fields: Vec<_> = fen.split_whitespace().collect();
+        let [placement, turn, castling, en_passant, halfmoves, fullmoves] = fields[..] else {
+            return Err(invalid("expected 6 fields"));
+        };
+
+        let mut game = GameState::new(white, black).with_board(Board::from_fen(placement)?);
+        game.turn = match turn {
+            "w" => Color::White as i32,
+            "b" => Color::Black as i32,
+            _ => return Err(invalid("the side to move is w or b")),
+        };
+
+        if castling != "-" && !castling.chars().all(|c| "KQkq".contains(c)) {
+            return Err(invalid("malformed castling rights"));
+        }
+
+        if en_passant != "-" {
+            // The square a pawn just skipped: rank 3 with Black to move, 6
+            // with White.
+            let rank = if turn == "b" { "3" } else { "6" };
+            let square = Square::all()
+                .find(|s| square_name(*s) == en_passant && en_passant.ends_with(rank))
+                .ok_or_else(|| invalid("malformed en passant square"))?;
+            game.en_passant = Some(square.index());
+        }
+
+        let (Ok(halfmoves), Ok(fullmoves)) = (halfmoves.parse::<u32>(), fullmoves.parse::<u32>())
+        else {
+            return Err(invalid("move counters must be numbers"));
+        };
+        if fullmoves == 0 {
+            return Err(invalid("the fullmove number starts at 1"));
+        }
+        game.halfmove_clock = halfmoves;
+        game.fullmove_number = fullmoves;
+
+        Ok(game)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pb::query::Position;
+
+    const START: &str = "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w - - 0 1";
+
+    #[test]
+    fn test_fen_export() {
+        let mut game = GameState::new("Alice".to_string(), "Bob".to_string());
+        assert_eq!(game.to_fen(), START);
+
+        game.apply_move(Position { x: 1, y: 4 }, Position { x: 3, y: 4 })
+            .unwrap();
+        assert_eq!(
+            game.to_fen(),
+            "rnbqkbnr/pppppppp/8/8/4P3/8/PPPP1PPP/RNBQKBNR b - e3 0 1"
+        );
+
+        game.apply_move(Position { x: 7, y: 6 }, Position { x: 5, y: 5 })
+            .unwrap();
+        assert_eq!(
+            game.to_fen(),
+            "rnbqkb1r/pppppppp/5n2/8/4P3/8/PPPP1PPP/RNBQKBNR w - - 1 2"
+        );
+    }
+
+    #[test]
+    fn test_fen_import() {
+        let fen = "rnbqkbnr/pppp1ppp/8/4p3/4P3/8/PPPP1PPP/RNBQKBNR w KQkq e6 0 2";
+        let game = GameState::from_fen("Alice".to_string(), "Bob".to_string(), fen).unwrap();
+        assert_eq!(game.turn, Color::White as i32);
+        assert_eq!((game.halfmove_clock, game.fullmove_number), (0, 2));
+        assert_eq!(game.en_passant, Some(Square::new(5, 4).index()));
+        assert_eq!(
+            game.board.as_ref().unwrap().to_fen(),
+            fen.split(' ').next().unwrap()
+        );
+
+        let start = GameState::from_fen("Alice".to_string(), "Bob".to_string(), START).unwrap();
+        assert_eq!(start.board, Some(Board::new()));
+
+        for bad in [
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP w - - 0 1",
+            "rnbqkbnr/pppppppp/9/8/8/8/PPPPPPPP/RNBQKBNR w - - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQXBNR w - - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQQBNR w - - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR x - - 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w - e3 0 1",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w - - 0",
+            "rnbqkbnr/pppppppp/8/8/8/8/PPPPPPPP/RNBQKBNR w - - 0 0",
+        ] {
+            assert!(
+                GameState::from_fen("Alice".to_string(), "Bob".to_string(), bad).is_err(),
+                "{}",
+                bad
+            );
+        }
+    }
+}
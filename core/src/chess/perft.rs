@@ -0,0 +1,97 @@
+//! Perft: counts the leaf nodes of the legal move tree to a fixed depth and
+//! compares them with published reference values, so any move generation or
+//! validation regression shows up as a count mismatch. A pawn reaching the
+//! last rank counts once per piece it may promote to, as in the references.
+//! Games here have no castling, so only positions without castling rights
+//! are checked. The references count only moves that keep the king safe,
+//! as the rules require from version 7, so the tree is walked under it.
+
+use super::{Square, PROMOTIONS};
+use crate::{consensus::rules::Rules, pb::game::GameState};
+
+const RULES: Rules = Rules::V7;
+
+fn perft(game: &GameState, depth: u32) -> u64 {
+    if depth == 0 {
+        return 1;
+    }
+
+    let board = game.board.as_ref().unwrap();
+    let moves = Square::all()
+        .filter(|&from| {
+            board
+                .get_piece_at(from)
+                .is_some_and(|p| p.color == game.turn)
+        })
+        .flat_map(|from| Square::all().map(move |to| (from.into(), to.into())));
+    let mut nodes = 0;
+    for (from, to) in moves {
+        if RULES.validate_move(game, &from, &to, None).is_err() {
+            continue;
+        }
+        let promotions: Vec<_> = if game.promotes(&from, &to) {
+            PROMOTIONS.into_iter().map(Some).collect()
+        } else {
+            vec![None]
+        };
+
+        for promotion in promotions {
+            if depth == 1 {
+                nodes += 1;
+                continue;
+            }
+            let mut next = game.clone();
+            next.apply_promoting_move(from.clone(), to.clone(), promotion)
+                .expect("generated move is legal");
+            nodes += perft(&next, depth - 1);
+        }
+    }
+    nodes
+}
+
+fn start_position() -> GameState {
+    GameState::new("Alice".to_string(), "Bob".to_string())
+}
+
+fn position(fen: &str) -> GameState {
+    GameState::from_fen("Alice".to_string(), "Bob".to_string(), fen).unwrap()
+}
+
+#[test]
+fn test_perft_start_position_depth_1() {
+    assert_eq!(perft(&start_position(), 1), 20);
+}
+
+#[test]
+fn test_perft_start_position_depth_2() {
+    assert_eq!(perft(&start_position(), 2), 400);
+}
+
+#[test]
+fn test_perft_start_position_depth_3() {
+    assert_eq!(perft(&start_position(), 3), 8_902);
+}
+
+#[test]
+fn test_perft_start_position_depth_4() {
+    assert_eq!(perft(&start_position(), 4), 197_281);
+}
+
+/// Position 3 of the chessprogramming wiki: en passant, including captures
+/// that would expose the king along the rank, and discovered checks.
+#[test]
+fn test_perft_en_passant_and_pins() {
+    let game = position("8/2p5/3p4/KP5r/1R3p1k/8/4P1P1/8 w - - 0 1");
+    for (depth, nodes) in [(1, 14), (2, 191), (3, 2_812), (4, 43_238)] {
+        assert_eq!(perft(&game, depth), nodes, "depth {}", depth);
+    }
+}
+
+/// Both sides promote, capturing and not, to every piece.
+#[test]
+fn test_perft_promotions() {
+    let game = position("n1n5/PPPk4/8/8/8/8/4Kppp/5N1N b - - 0 1");
+    for (depth, nodes) in [(1, 24), (2, 496), (3, 9_483)] {
+        assert_eq!(perft(&game, depth), nodes, "depth {}", depth);
+    }
+}
@@ -0,0 +1,341 @@
+//! PGN, the format chess databases and GUIs exchange games in. Every game
+//! here starts from the initial position, so the movetext is the recorded
+//! SAN, and an imported game is its moves replayed under the rules.
+
+use crate::{
+    consensus::rules::Rules,
+    errors::AppError,
+    pb::{
+        game::{Color, GameState, Termination},
+        query::IllegalMove,
+    },
+};
+use chrono::{TimeZone, Utc};
+
+/// Export format keeps movetext lines within 80 characters.
+const LINE_WIDTH: usize = 80;
+
+/// Bytes a move's comment may take.
+pub const MAX_COMMENT: usize = 200;
+
+/// Move annotations PGN writes right after the SAN; other comments go in
+/// braces.
+const ANNOTATIONS: [&str; 6] = ["!", "?", "!!", "??", "!?", "?!"];
+
+/// A comment has to fit in a PGN brace comment on the movetext's lines.
+pub fn validate_comment(comment: &str) -> Result<(), AppError> {
+    if comment.trim().is_empty() {
+        return Err(AppError::InvalidTransactionError("empty comment".into()));
+    }
+    if comment.len() > MAX_COMMENT {
+        return Err(AppError::InvalidTransactionError(format!(
+            "comment is longer than {} bytes",
+            MAX_COMMENT
+        )));
+    }
+    if comment.contains(['{', '}']) || comment.contains(char::is_control) {
+        return Err(AppError::InvalidTransactionError(
+            "comment has braces or control characters".into(),
+        ));
+    }
+    Ok(())
+}
+
+fn tag(name: &str, value: &str) -> String {
+    format!(
+        "[{} \"{}\"]\n",
+        name,
+        value.replace('\\', "\\\\").replace('"', "\\\"")
+    )
+}
+
+impl GameState {
+    /// Keeps `comment` with the move just made.
+    pub fn annotate_last_move(&mut self, comment: Option<String>) {
+        if let Some(record) = self.moves.last_mut() {
+            record.comment = comment;
+        }
+    }
+
+    /// The result token: `*` while the game is on or if it was aborted.
+    pub fn pgn_result(&self) -> &'static str {
+        match &self.result {
+            None => "*",
+            Some(r) if r.termination == Termination::Aborted as i32 => "*",
+            Some(r) => match r.winner {
+                None => "1/2-1/2",
+                Some(w) if w == Color::White as i32 => "1-0",
+                Some(_) => "0-1",
+            },
+        }
+    }
+
+    /// The game as a PGN document with the seven tag roster.
+    pub fn to_pgn(&self) -> String {
+        let date = Utc
+            .timestamp_opt(self.started_at, 0)
+            .single()
+            .map_or("????.??.??".to_string(), |d| {
+                d.format("%Y.%m.%d").to_string()
+            });
+        let result = self.pgn_result();
+
+        let mut pgn = String::new();
+        for (name, value) in [
+            ("Event", "?"),
+            ("Site", "?"),
+            ("Date", &date),
+            ("Round", "-"),
+            ("White", &self.white_player),
+            ("Black", &self.black_player),
+            ("Result", result),
+        ] {
+            pgn.push_str(&tag(name, value));
+        }
+        // Chess960 games name their start position, as the PGN standard's
+        // setup tags do.
+        if let Some(id) = self.start_position {
+            let start = GameState::new(String::new(), String::new())
+                .with_start_position(id)
+                .map_or_else(|_| "?".to_string(), |g| g.to_fen());
+            pgn.push_str(&tag("Variant", "Chess960"));
+            pgn.push_str(&tag("SetUp", "1"));
+            pgn.push_str(&tag("FEN", &start));
+        }
+        pgn.push('\n');
+
+        let mut tokens = Vec::new();
+        for (i, m) in self.moves.iter().enumerate() {
+            if i % 2 == 0 {
+                tokens.push(format!("{}.", i / 2 + 1));
+            }
+            match m.comment.as_deref() {
+                Some(a) if ANNOTATIONS.contains(&a) => tokens.push(format!("{}{}", m.san, a)),
+                Some(comment) => {
+                    tokens.push(m.san.clone());
+                    let words: Vec<_> = comment.split_whitespace().collect();
+                    let last = words.len() - 1;
+                    for (j, word) in words.into_iter().enumerate() {
+                        let open = if j == 0 { "{" } else { "" };
+                        let close = if j == last { "}" } else { "" };
+                        tokens.push(format!("{}{}{}", open, word, close));
+                    }
+                }
+                None => tokens.push(m.san.clone()),
+            }
+        }
+        tokens.push(result.to_string());
+
+        let mut line = String::new();
+        for token in tokens {
+            if !line.is_empty() && line.len() + 1 + token.len() > LINE_WIDTH {
+                pgn.push_str(&line);
+                pgn.push('\n');
+                line.clear();
+            }
+            if !line.is_empty() {
+                line.push(' ');
+            }
+            line.push_str(&token);
+        }
+        pgn.push_str(&line);
+        pgn.push('\n');
+
+        pgn
+    }
+}
+
+/// A PGN document split into its tag pairs and the moves of its main line.
+/// Comments, variations, move numbers, NAGs and the result are dropped.
+fn parse(pgn: &str) -> (Vec<(String, String)>, Vec<String>) {
+    let (mut tags, mut moves) = (Vec::new(), Vec::new());
+    let mut chars = pgn.chars();
+    let mut token = String::new();
+    let mut depth = 0;
+
+    let mut flush = |token: &mut String, depth: i32| {
+        let san = token.trim_start_matches(|c: char| c.is_ascii_digit() || c == '.');
+        let is_result = ["1-0", "0-1", "1/2-1/2", "*"].contains(&token.as_str());
+        if depth == 0 && !san.is_empty() && !san.starts_with('$') && !is_result {
+            moves.push(san.to_string());
+        }
+        token.clear();
+    };
+
+    while let Some(c) = chars.next() {
+        match c {
+            '{' => {
+                flush(&mut token, depth);
+                chars.by_ref().find(|&c| c == '}');
+            }
+            ';' => {
+                flush(&mut token, depth);
+                chars.by_ref().find(|&c| c == '\n');
+            }
+            '[' if depth == 0 => {
+                let tag: String = chars.by_ref().take_while(|&c| c != ']').collect();
+                if let Some((name, value)) = tag.trim().split_once(char::is_whitespace) {
+                    let value = value.trim();
+                    let value = value.strip_prefix('"').unwrap_or(value);
+                    let value = value.strip_suffix('"').unwrap_or(value);
+                    tags.push((
+                        name.to_string(),
+                        value.replace("\\\"", "\"").replace("\\\\", "\\"),
+                    ));
+                }
+            }
+            '(' => {
+                flush(&mut token, depth);
+                depth += 1;
+            }
+            ')' => {
+                flush(&mut token, depth);
+                depth -= 1;
+            }
+            c if c.is_whitespace() => flush(&mut token, depth),
+            c => token.push(c),
+        }
+    }
+    flush(&mut token, depth);
+
+    (tags, moves)
+}
+
+impl GameState {
+    /// Replays the main line of `pgn` under `rules` between the players its
+    /// White and Black tags name. Stops at the first move that cannot be
+    /// played and reports it with the game as it stood before.
+    pub fn from_pgn(pgn: &str, rules: Rules) -> (GameState, Option<IllegalMove>) {
+        let (tags, moves) = parse(pgn);
+        let tag = |name: &str| {
+            tags.iter()
+                .find(|(n, _)| n == name)
+                .map_or("?".to_string(), |(_, v)| v.clone())
+        };
+
+        let mut game = GameState::new(tag("White"), tag("Black"));
+        for (i, san) in moves.into_iter().enumerate() {
+            let played = game.find_san(&san).and_then(|(from, to, promotion)| {
+                let mut next = game.clone();
+                rules.apply_move(&mut next, from, to, promotion)?;
+                Ok(next)
+            });
+
+            match played {
+                Ok(next) => game = next,
+                Err(e) => {
+                    let illegal = IllegalMove {
+                        ply: i as u32 + 1,
+                        san,
+                        reason: e.to_string(),
+                    };
+                    return (game, Some(illegal));
+                }
+            }
+        }
+
+        (game, None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pb::{game::GameResult, query::Position};
+
+    #[test]
+    fn test_pgn_export() {
+        let mut game = GameState::new("Alice".to_string(), "Bob \"B\"".to_string());
+        game.started_at = 1_700_000_000;
+        for ((fx, fy), (tx, ty)) in [((1, 4), (3, 4)), ((6, 4), (4, 4)), ((0, 6), (2, 5))] {
+            game.apply_move(Position { x: fx, y: fy }, Position { x: tx, y: ty })
+                .unwrap();
+        }
+
+        assert_eq!(
+            game.to_pgn(),
+            "[Event \"?\"]\n[Site \"?\"]\n[Date \"2023.11.14\"]\n[Round \"-\"]\n\
+             [White \"Alice\"]\n[Black \"Bob \\\"B\\\"\"]\n[Result \"*\"]\n\n\
+             1. e4 e5 2. Nf3 *\n"
+        );
+
+        game.result = Some(GameResult {
+            winner: Some(Color::Black as i32),
+            termination: Termination::Resignation as i32,
+        });
+        assert!(game
+            .to_pgn()
+            .ends_with("[Result \"0-1\"]\n\n1. e4 e5 2. Nf3 0-1\n"));
+
+        // Knights shuffling back and forth fill more than one line.
+        let mut game = GameState::new("Alice".to_string(), "Bob".to_string());
+        let shuffle = [
+            ((0, 6), (2, 5)),
+            ((7, 6), (5, 5)),
+            ((2, 5), (0, 6)),
+            ((5, 5), (7, 6)),
+        ];
+        for ((fx, fy), (tx, ty)) in shuffle.iter().cycle().take(24) {
+            game.apply_move(Position { x: *fx, y: *fy }, Position { x: *tx, y: *ty })
+                .unwrap();
+        }
+        let pgn = game.to_pgn();
+        let movetext: Vec<_> = pgn.split("\n\n").nth(1).unwrap().lines().collect();
+        assert!(movetext.len() > 1);
+        assert!(movetext.iter().all(|l| l.len() <= 80));
+        assert!(movetext[0].starts_with("1. Nf3 Nf6 2. Ng1 Ng8"));
+        assert!(movetext.last().unwrap().ends_with("12. Ng1 Ng8 *"));
+    }
+
+    #[test]
+    fn test_pgn_export_comments() {
+        let mut game = GameState::new("Alice".to_string(), "Bob".to_string());
+        game.apply_move(Position { x: 1, y: 4 }, Position { x: 3, y: 4 })
+            .unwrap();
+        game.annotate_last_move(Some("!?".to_string()));
+        game.apply_move(Position { x: 6, y: 4 }, Position { x: 4, y: 4 })
+            .unwrap();
+        game.annotate_last_move(Some("the  open game".to_string()));
+
+        assert!(game
+            .to_pgn()
+            .ends_with("\n\n1. e4!? e5 {the open game} *\n"));
+
+        // Both read back as the plain moves.
+        let (imported, illegal) = GameState::from_pgn(&game.to_pgn(), Rules::V6);
+        assert!(illegal.is_none());
+        assert_eq!(imported.moves.len(), 2);
+
+        assert!(validate_comment("!?").is_ok());
+        assert!(validate_comment(" ").is_err());
+        assert!(validate_comment("a } b").is_err());
+        assert!(validate_comment("line\nbreak").is_err());
+        assert!(validate_comment(&"x".repeat(MAX_COMMENT + 1)).is_err());
+    }
+
+    #[test]
+    fn test_pgn_import() {
+        let pgn = r#"[Event "Paris"]
+[White "Legal"]
+[Black "Saint \"Brie\""]
+
+1. e4 e5 2. Nf3 d6 3. Bc4 Bg4{pins the knight, he thinks} 4. Nc3 g6?!
+5. Nxe5! Bxd1 (5... dxe5 6. Qxg4 $1) 6. Bxf7+ Ke7 7. Nd5# 1-0"#;
+
+        let (game, illegal) = GameState::from_pgn(pgn, Rules::V6);
+        assert!(illegal.is_none(), "{:?}", illegal);
+        assert_eq!(
+            (game.white_player.as_str(), game.black_player.as_str()),
+            ("Legal", "Saint \"Brie\"")
+        );
+        assert_eq!(game.moves.len(), 13);
+        assert_eq!(game.moves.last().unwrap().san, "Nd5#");
+        assert_eq!(game.pgn_result(), "1-0");
+        assert!(game.to_pgn().ends_with("6. Bxf7+ Ke7 7. Nd5# 1-0\n"));
+
+        let (game, illegal) = GameState::from_pgn("1. e4 e5 2. Ke3 Nc6", Rules::V6);
+        let illegal = illegal.unwrap();
+        assert_eq!((illegal.ply, illegal.san.as_str()), (3, "Ke3"));
+        assert_eq!(game.moves.len(), 2);
+    }
+}
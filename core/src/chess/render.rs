@@ -0,0 +1,82 @@
+//! Text diagrams of the board, for terminals and logs. `{}` draws White's
+//! pieces as capital letters and Black's as small ones; `{:#}` uses the
+//! Unicode chess glyphs instead.
+
+use super::Square;
+use crate::pb::game::{Board, Color, Piece, PieceKind};
+use std::fmt;
+
+impl Piece {
+    /// ♔♕♖♗♘♙ for White, ♚♛♜♝♞♟ for Black.
+    pub fn glyph(&self) -> char {
+        let white = self.color == Color::White as i32;
+        match (self.kind(), white) {
+            (PieceKind::King, true) => '♔',
+            (PieceKind::Queen, true) => '♕',
+            (PieceKind::Rook, true) => '♖',
+            (PieceKind::Bishop, true) => '♗',
+            (PieceKind::Knight, true) => '♘',
+            (PieceKind::Pawn, true) => '♙',
+            (PieceKind::King, false) => '♚',
+            (PieceKind::Queen, false) => '♛',
+            (PieceKind::Rook, false) => '♜',
+            (PieceKind::Bishop, false) => '♝',
+            (PieceKind::Knight, false) => '♞',
+            (PieceKind::Pawn, false) => '♟',
+        }
+    }
+
+    fn letter(&self) -> char {
+        let letter = self.kind().letter().chars().next().unwrap_or('?');
+        if self.color == Color::White as i32 {
+            letter
+        } else {
+            letter.to_ascii_lowercase()
+        }
+    }
+}
+
+/// The board from White's side, rank 8 at the top, with rank numbers and
+/// file letters around it.
+impl fmt::Display for Board {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for x in (0..8).rev() {
+            write!(f, "{} ", x + 1)?;
+            for y in 0..8 {
+                let square = match self.get_piece_at(Square::new(x, y)) {
+                    Some(p) if f.alternate() => p.glyph(),
+                    Some(p) => p.letter(),
+                    None => '.',
+                };
+                write!(f, " {}", square)?;
+            }
+            writeln!(f)?;
+        }
+        write!(f, "   a b c d e f g h")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pb::{game::GameState, query::Position};
+
+    #[test]
+    fn test_board_diagrams() {
+        let mut game = GameState::new("Alice".to_string(), "Bob".to_string());
+        game.apply_move(Position { x: 1, y: 4 }, Position { x: 3, y: 4 })
+            .unwrap();
+        let board = game.board.unwrap();
+
+        let ascii = board.to_string();
+        let lines: Vec<_> = ascii.lines().collect();
+        assert_eq!(lines[0], "8  r n b q k b n r");
+        assert_eq!(lines[4], "4  . . . . P . . .");
+        assert_eq!(lines[8], "   a b c d e f g h");
+
+        let unicode = format!("{:#}", board);
+        let lines: Vec<_> = unicode.lines().collect();
+        assert_eq!(lines[0], "8  ♜ ♞ ♝ ♛ ♚ ♝ ♞ ♜");
+        assert_eq!(lines[4], "4  . . . . ♙ . . .");
+        assert_eq!(lines[7], "1  ♖ ♘ ♗ ♕ ♔ ♗ ♘ ♖");
+    }
+}
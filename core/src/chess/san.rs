@@ -0,0 +1,134 @@
+//! Reading standard algebraic notation. A move is looked up among the legal
+//! moves of the side to move, so "Nf3" needs only as much disambiguation as
+//! the position calls for, and more is tolerated.
+
+use super::{Square, PROMOTIONS};
+use crate::{
+    errors::AppError,
+    pb::{
+        game::{GameState, PieceKind},
+        query::Position,
+    },
+};
+
+/// A SAN move taken apart.
+struct San {
+    kind: PieceKind,
+    /// File letters and rank digits the moving piece has to match.
+    hint: String,
+    to: Square,
+    promotion: Option<PieceKind>,
+}
+
+impl San {
+    fn parse(san: &str) -> Option<Self> {
+        if !san.is_ascii() {
+            return None;
+        }
+        let mut rest = san.trim_end_matches(['+', '#', '!', '?']);
+
+        let mut promotion = None;
+        if let Some(letter) = rest.chars().last().filter(char::is_ascii_alphabetic) {
+            promotion = Some(PieceKind::from_letter(&letter.to_string())?);
+            rest = rest[..rest.len() - 1].trim_end_matches('=');
+        }
+
+        let split = rest.len().checked_sub(2)?;
+        let (prefix, target) = rest.split_at(split);
+        let mut target = target.bytes();
+        let (file, rank) = (target.next()?, target.next()?);
+        if !(b'a'..=b'h').contains(&file) || !(b'1'..=b'8').contains(&rank) {
+            return None;
+        }
+
+        let (kind, hint) = match prefix.chars().next() {
+            Some(c) if c.is_ascii_uppercase() => {
+                (PieceKind::from_letter(&c.to_string())?, &prefix[1..])
+            }
+            _ => (PieceKind::Pawn, prefix),
+        };
+        let hint = hint.replace('x', "");
+        if hint.len() > 2 || !hint.chars().all(|c| matches!(c, 'a'..='h' | '1'..='8')) {
+            return None;
+        }
+
+        Some(Self {
+            kind,
+            hint,
+            to: Square::new(rank - b'1', file - b'a'),
+            promotion,
+        })
+    }
+
+    fn matches(&self, game: &GameState, from: &Position, to: &Position) -> bool {
+        let board = game.board.as_ref().unwrap();
+        let Ok(square) = Square::try_from(from) else {
+            return false;
+        };
+        let name = GameState::position_to_notation(from);
+
+        Position::from(self.to) == *to
+            && board
+                .get_piece_at(square)
+                .is_some_and(|p| p.kind() == self.kind)
+            && self.hint.chars().all(|c| name.contains(c))
+            && match self.promotion {
+                Some(kind) => game.promotes(from, to) && PROMOTIONS.contains(&kind),
+                None => true,
+            }
+    }
+}
+
+impl GameState {
+    /// The legal move `san` names, with the letter of the piece it promotes
+    /// to if it says.
+    pub fn find_san(
+        &self,
+        san: &str,
+    ) -> Result<(Position, Position, Option<&'static str>), AppError> {
+        let illegal = || AppError::InternalGameError(format!("{} is not a legal move here", san));
+        let parsed = San::parse(san).ok_or_else(illegal)?;
+
+        let mut candidates = self
+            .legal_moves()
+            .into_iter()
+            .filter(|(from, to)| parsed.matches(self, from, to));
+        let (from, to) = candidates.next().ok_or_else(illegal)?;
+        if candidates.next().is_some() {
+            return Err(AppError::InternalGameError(format!(
+                "{} is ambiguous here",
+                san
+            )));
+        }
+
+        Ok((from, to, parsed.promotion.map(PieceKind::letter)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::pb::game::{Board, GameState};
+
+    #[test]
+    fn test_find_san() {
+        let game = GameState::new("Alice".to_string(), "Bob".to_string())
+            .with_board(Board::from_fen("k7/4P3/8/8/8/2N3N1/8/4K3").unwrap());
+        let found = |san| {
+            game.find_san(san)
+                .map(|(from, to, promotion)| (from.x, from.y, to.x, to.y, promotion))
+        };
+
+        assert_eq!(found("Nge2").unwrap(), (2, 6, 1, 4, None));
+        assert_eq!(found("Nc3e2").unwrap(), (2, 2, 1, 4, None));
+        assert_eq!(found("Nh5!?").unwrap(), (2, 6, 4, 7, None));
+        assert_eq!(found("e8=N").unwrap(), (6, 4, 7, 4, Some("N")));
+        assert_eq!(found("e8Q+").unwrap(), (6, 4, 7, 4, Some("Q")));
+        assert_eq!(found("e8").unwrap(), (6, 4, 7, 4, None));
+
+        let error = |san| found(san).unwrap_err().to_string();
+        assert!(error("Ne2").contains("ambiguous"));
+        for bad in ["e8=K", "Nd4", "O-O", "Ke9", "e", "Zf3", "Nè2"] {
+            assert!(error(bad).contains("not a legal move"), "{}", bad);
+        }
+    }
+}
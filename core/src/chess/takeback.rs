@@ -0,0 +1,135 @@
+//! Taking the last move back. The board has no undo, so the game is replayed
+//! from its start position without that move. The history keeps the move and
+//! records the takeback after it, so the blocks chained on the history show
+//! both.
+
+use super::{parse_uci, Board};
+use crate::{
+    consensus::rules::Rules,
+    errors::AppError,
+    pb::game::{Checks, Color, GameState},
+};
+
+impl GameState {
+    /// Entries in the history, one per move ever made and per takeback.
+    pub fn history_len(&self) -> u32 {
+        self.moves.len() as u32 + 2 * self.takebacks.unwrap_or_default()
+    }
+
+    /// A takeback signed at history length `ply` may revert the last move of
+    /// a game still in progress, unless the game is dark.
+    pub fn validate_takeback(&self, ply: Option<u32>) -> Result<(), AppError> {
+        if self.is_over() {
+            return Err(AppError::InternalGameError(
+                "The game is already over".to_string(),
+            ));
+        }
+
+        if self.is_dark() {
+            return Err(AppError::InternalGameError(
+                "Dark games cannot take moves back".to_string(),
+            ));
+        }
+
+        if self.moves.is_empty() {
+            return Err(AppError::InternalGameError(
+                "There is no move to take back".to_string(),
+            ));
+        }
+
+        if ply != Some(self.history_len()) {
+            return Err(AppError::InternalGameError(
+                "The takeback was signed for another move".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Reverts the last move by replaying the others under `rules`.
+    pub fn take_back(&mut self, rules: Rules) -> Result<(), AppError> {
+        let mut moves = std::mem::take(&mut self.moves);
+        moves.pop();
+
+        let mut game = Self {
+            board: Some(match self.start_position {
+                Some(id) => Board::chess960(id)?,
+                None => Board::new(),
+            }),
+            turn: Color::White as i32,
+            en_passant: None,
+            halfmove_clock: 0,
+            fullmove_number: 1,
+            history: Some(String::new()),
+            checks: self.checks.as_ref().map(|_| Checks::default()),
+            ..self.clone()
+        };
+        for record in moves {
+            let (from, to, promotion) = parse_uci(&record.uci)?;
+            rules.apply_move(&mut game, from, to, promotion)?;
+            game.annotate_last_move(record.comment);
+        }
+
+        let history = self.history.get_or_insert_with(String::new);
+        let n = history.split_whitespace().count();
+        history.push_str(&format!(
+            "{}{}. takeback",
+            if n == 0 { "" } else { " " },
+            n + 1
+        ));
+        game.history = self.history.take();
+        game.takebacks = Some(self.takebacks.unwrap_or_default() + 1);
+        *self = game;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        consensus::rules::Rules,
+        pb::{game::GameState, query::Position},
+    };
+
+    #[test]
+    fn test_take_back() {
+        let mut game = GameState::new("Alice".to_string(), "Bob".to_string());
+        for ((fx, fy), (tx, ty)) in [((1, 4), (3, 4)), ((6, 3), (4, 3)), ((3, 4), (4, 3))] {
+            game.apply_move(Position { x: fx, y: fy }, Position { x: tx, y: ty })
+                .unwrap();
+        }
+        // Comments on the moves kept stay with them.
+        game.moves[0].comment = Some("!".to_string());
+        let before_capture = {
+            let mut game = GameState::new("Alice".to_string(), "Bob".to_string());
+            for ((fx, fy), (tx, ty)) in [((1, 4), (3, 4)), ((6, 3), (4, 3))] {
+                game.apply_move(Position { x: fx, y: fy }, Position { x: tx, y: ty })
+                    .unwrap();
+            }
+            game.moves[0].comment = Some("!".to_string());
+            game
+        };
+
+        assert!(game.validate_takeback(Some(2)).is_err());
+        game.validate_takeback(Some(3)).unwrap();
+        game.take_back(Rules::V6).unwrap();
+
+        assert_eq!(game.board, before_capture.board);
+        assert_eq!(game.turn, before_capture.turn);
+        assert_eq!(game.moves, before_capture.moves);
+        assert!(game.history.as_deref().unwrap().ends_with("takeback"));
+        // The signature for the move just taken back is spent.
+        assert_eq!(game.history_len(), 4);
+        assert!(game.validate_takeback(Some(3)).is_err());
+        game.validate_takeback(Some(4)).unwrap();
+
+        game.take_back(Rules::V6).unwrap();
+        game.take_back(Rules::V6).unwrap();
+        assert_eq!(
+            game.board,
+            GameState::new(String::new(), String::new()).board
+        );
+        assert!(game.validate_takeback(Some(game.history_len())).is_err());
+    }
+}
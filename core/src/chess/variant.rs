@@ -0,0 +1,174 @@
+//! Variants that keep the standard moves and change how a game is won. Each
+//! one implements [`VariantRules`], which consensus runs after every move, so
+//! a new variant is an impl and an arm in [`GameState::variant_rules`].
+//! Dark chess and Chess960 change how moves are made or where the pieces
+//! start, not the win, and live in their own modules.
+
+use crate::pb::game::{Checks, Color, GameState, Termination, Variant};
+
+pub trait VariantRules: Sync {
+    /// Keeps the variant's own state up to date after a move.
+    fn after_move(&self, _game: &mut GameState) {}
+
+    /// How the side that just moved won by the variant's rules, if it did.
+    fn won(&self, game: &GameState) -> Option<Termination>;
+}
+
+pub struct Standard;
+
+impl VariantRules for Standard {
+    fn won(&self, _: &GameState) -> Option<Termination> {
+        None
+    }
+}
+
+/// The first king on one of the four centre squares wins.
+pub struct KingOfTheHill;
+
+impl VariantRules for KingOfTheHill {
+    fn won(&self, game: &GameState) -> Option<Termination> {
+        let king = game.board.as_ref()?.king_location(mover(game) as i32)?;
+        ((3..=4).contains(&king.x()) && (3..=4).contains(&king.y()))
+            .then_some(Termination::HillReached)
+    }
+}
+
+/// The third check wins.
+pub struct ThreeCheck;
+
+impl VariantRules for ThreeCheck {
+    fn after_move(&self, game: &mut GameState) {
+        if game
+            .board
+            .as_ref()
+            .is_some_and(|b| b.is_in_check(game.turn))
+        {
+            let mover = mover(game);
+            let checks = game.checks.get_or_insert_with(Checks::default);
+            match mover {
+                Color::White => checks.white += 1,
+                Color::Black => checks.black += 1,
+            }
+        }
+    }
+
+    fn won(&self, game: &GameState) -> Option<Termination> {
+        let checks = game.checks.as_ref()?;
+        let given = match mover(game) {
+            Color::White => checks.white,
+            Color::Black => checks.black,
+        };
+        (given >= 3).then_some(Termination::ThirdCheck)
+    }
+}
+
+/// The side that just moved.
+fn mover(game: &GameState) -> Color {
+    Color::from_i32((game.turn + 1) % 2).expect("Correct color")
+}
+
+impl GameState {
+    pub fn variant_rules(&self) -> &'static dyn VariantRules {
+        match Variant::from_i32(self.variant) {
+            Some(Variant::KingOfTheHill) => &KingOfTheHill,
+            Some(Variant::ThreeCheck) => &ThreeCheck,
+            _ => &Standard,
+        }
+    }
+
+    /// Runs the variant's rules on the move just applied, ending the game as
+    /// a win for the side that made it if the variant says so.
+    pub fn adjudicate_variant(&mut self) {
+        if self.is_over() {
+            return;
+        }
+
+        let rules = self.variant_rules();
+        rules.after_move(self);
+        if let Some(termination) = rules.won(self) {
+            self.finish(termination, Some(mover(self)));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{consensus::rules::Rules, pb::query::Position};
+
+    /// From and to as (rank, file).
+    type Move = ((u32, u32), (u32, u32));
+
+    fn play(variant: Variant, moves: &[Move]) -> GameState {
+        let mut game =
+            GameState::new("Alice".to_string(), "Bob".to_string()).with_variant(variant as i32);
+        for &(from, to) in moves {
+            Rules::V6
+                .apply_move(
+                    &mut game,
+                    Position {
+                        x: from.0,
+                        y: from.1,
+                    },
+                    Position { x: to.0, y: to.1 },
+                    None,
+                )
+                .unwrap();
+        }
+        game
+    }
+
+    #[test]
+    fn test_variant_wins() {
+        // e3 e6 Ke2 Ke7 Kd3 Kd6 Kd4
+        let walk = [
+            ((1, 4), (2, 4)),
+            ((6, 4), (5, 4)),
+            ((0, 4), (1, 4)),
+            ((7, 4), (6, 4)),
+            ((1, 4), (2, 3)),
+            ((6, 4), (5, 3)),
+            ((2, 3), (3, 3)),
+        ];
+        let hill = play(Variant::KingOfTheHill, &walk);
+        assert_eq!(
+            hill.result.map(|r| (r.winner, r.termination)),
+            Some((Some(Color::White as i32), Termination::HillReached as i32))
+        );
+        assert!(!play(Variant::Standard, &walk).is_over());
+
+        // e3 f6 Qh5+ g6 Qxg6+ hxg6 Bd3 d6 Bb5+
+        let checks = [
+            ((1, 4), (2, 4)),
+            ((6, 5), (5, 5)),
+            ((0, 3), (4, 7)),
+            ((6, 6), (5, 6)),
+            ((4, 7), (5, 6)),
+            ((6, 7), (5, 6)),
+            ((0, 5), (2, 3)),
+            ((6, 3), (5, 3)),
+        ];
+        let game = play(Variant::ThreeCheck, &checks);
+        assert_eq!(game.checks, Some(Checks { white: 2, black: 0 }));
+        assert!(!game.is_over());
+
+        let mut game = game;
+        Rules::V6
+            .apply_move(
+                &mut game,
+                Position { x: 2, y: 3 },
+                Position { x: 4, y: 1 },
+                None,
+            )
+            .unwrap();
+        assert_eq!(
+            game.result.map(|r| (r.winner, r.termination)),
+            Some((Some(Color::White as i32), Termination::ThirdCheck as i32))
+        );
+
+        // A takeback takes its check back with it.
+        let mut game = play(Variant::ThreeCheck, &checks[..5]);
+        game.take_back(Rules::V6).unwrap();
+        assert_eq!(game.checks, Some(Checks { white: 1, black: 0 }));
+    }
+}
@@ -0,0 +1,36 @@
+use crate::backup::BackupConfig;
+use crate::network::{
+    access::InternalConfig, digest::DigestConfig, operator::OperatorConfig, web::WebConfig,
+};
+use serde::Deserialize;
+
+/// Settings of one node's deployment, unlike the genesis, which the whole
+/// network shares. Every section may be left out for its defaults.
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct NodeConfig {
+    /// CORS and protocol settings of the gRPC port.
+    #[serde(default)]
+    pub web: WebConfig,
+    /// Scheduled encrypted backups, off if left out.
+    pub backup: Option<BackupConfig>,
+    /// "Your move" digests for correspondence games, off if left out.
+    pub digests: Option<DigestConfig>,
+    /// A separate listener for validator and operator calls; without one
+    /// the gRPC port serves them.
+    pub internal: Option<InternalConfig>,
+    /// The node's name, contact and region, advertised to its peers.
+    pub operator: Option<OperatorConfig>,
+}
+
+impl NodeConfig {
+    pub fn from_file(path: &str) -> Result<Self, Box<dyn std::error::Error>> {
+        let config: Self = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        // Lists that do not parse are refused before the node starts.
+        let _ = config.web.cors()?;
+        if let Some(operator) = &config.operator {
+            operator.validate()?;
+        }
+        Ok(config)
+    }
+}
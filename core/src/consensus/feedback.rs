@@ -0,0 +1,635 @@
+use super::types::Block;
+use crate::{
+    errors::AppError,
+    network::p2p::{FEEDBACK_TOPIC, LOCAL_KEYS},
+    pb::{
+        game::GameResult,
+        query::{
+            LifecycleReceipt, Rejection, Transaction, TransactionKind, TransactionStatusResponse,
+            TxProgress, TxStatus,
+        },
+    },
+    App, PEERS,
+};
+use alloy_primitives::B256;
+use libp2p::identity::PublicKey;
+use prost::Message;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// How long a submission is remembered, so retries within it are answered
+/// from the first one instead of broadcast again.
+const SUBMISSION_TTL: Duration = Duration::from_secs(300);
+/// Pending transactions a node keeps track of at most.
+pub const MEMPOOL_CAPACITY: usize = 1024;
+/// Pending transactions of one sender beyond which its others go first.
+pub const SENDER_QUOTA: usize = 16;
+/// A pending transaction this old, or whose game committed another since,
+/// has most likely been overtaken and goes first.
+const STALE_AFTER: Duration = Duration::from_secs(60);
+
+impl Rejection {
+    /// Builds a rejection of `tx_hash` signed with this node's libp2p key.
+    pub fn new(tx_hash: String, error: &AppError, view_n: u32) -> Result<Self, AppError> {
+        let mut rejection = Rejection {
+            tx_hash,
+            code: error.code(),
+            reason: error.to_string(),
+            view_n,
+            validator: LOCAL_KEYS.public().to_peer_id().to_string(),
+            public_key: LOCAL_KEYS.public().to_protobuf_encoding(),
+            signature: Vec::new(),
+        };
+
+        rejection.signature = LOCAL_KEYS
+            .sign(&rejection.encode_to_vec())
+            .map_err(|e| AppError::PeerError(e.to_string()))?;
+
+        Ok(rejection)
+    }
+
+    /// Checks that the signature is valid and made by the named validator.
+    pub fn verify(&self) -> Result<(), AppError> {
+        let public_key = PublicKey::from_protobuf_encoding(&self.public_key)
+            .map_err(|e| AppError::PeerError(e.to_string()))?;
+
+        if public_key.to_peer_id().to_string() != self.validator {
+            return Err(AppError::PeerError("rejection key mismatch".into()));
+        }
+
+        let unsigned = Rejection {
+            signature: Vec::new(),
+            ..self.clone()
+        };
+
+        match public_key.verify(&unsigned.encode_to_vec(), &self.signature) {
+            true => Ok(()),
+            false => Err(AppError::PeerError("invalid rejection signature".into())),
+        }
+    }
+}
+
+impl TransactionKind {
+    /// Whether the transaction ends a game, and so gets a receipt.
+    pub fn is_lifecycle(self) -> bool {
+        matches!(self, Self::Abort | Self::AbortByAgreement | Self::Resign)
+    }
+}
+
+impl LifecycleReceipt {
+    /// The receipt of the transaction in `block`, committed at `height`.
+    pub fn new(block: &Block, height: u64, result: Option<GameResult>) -> Result<Self, AppError> {
+        Ok(LifecycleReceipt {
+            tx_hash: block.transaction()?.tx_hash(),
+            height,
+            view_n: block.view_n,
+            block_hash: block.hash.clone(),
+            quorum: block
+                .qc
+                .as_ref()
+                .map(|qc| qc.signature.clone())
+                .unwrap_or_default(),
+            leader_signature: block.leader_signature.clone(),
+            result,
+        })
+    }
+
+    /// Checks that `leader`, the leader of the receipt's view, signed it.
+    pub fn verify(&self, leader: &str) -> Result<(), AppError> {
+        let payload = [&self.view_n.to_be_bytes()[..], &self.block_hash].concat();
+
+        match &self.leader_signature {
+            Some(s) if s.validator == leader && s.verify(&payload) => Ok(()),
+            _ => Err(AppError::PeerError("invalid receipt signature".into())),
+        }
+    }
+}
+
+/// How far a pending transaction got before its commit.
+#[derive(Default, Clone, Copy, Debug, PartialEq)]
+pub struct Progress {
+    pub received_by_leader: bool,
+    /// View and hash of the latest block proposing it.
+    pub proposal: Option<(u32, B256)>,
+}
+
+/// A transaction in the mempool, as it is ranked for eviction.
+struct Pending {
+    /// The key that signed it. Transactions are checked before they are
+    /// admitted, so it is a player of the game and not any key the sender
+    /// makes up.
+    sender: String,
+    game: String,
+    since: Instant,
+}
+
+impl Pending {
+    fn new(tx: &Transaction, since: Instant) -> Self {
+        let sender = match tx.team_signatures.first() {
+            Some(s) if tx.pub_key.is_empty() => s.pub_key.clone(),
+            _ => tx.pub_key.clone(),
+        };
+        let game = tx
+            .game_id
+            .clone()
+            .unwrap_or_else(|| format!("{}:{}", tx.white_player, tx.black_player));
+        Self {
+            sender,
+            game,
+            since,
+        }
+    }
+}
+
+/// What this node knows about the fate of submitted transactions.
+#[derive(Default)]
+pub struct TxTracker {
+    /// The mempool: transactions proposed but not committed yet.
+    pending: HashMap<String, Pending>,
+    /// Games by when one of their pending transactions last committed.
+    game_commits: HashMap<String, Instant>,
+    /// Transactions dropped from a full mempool after they were broadcast,
+    /// by when.
+    evicted: HashMap<String, Instant>,
+    /// Committed transactions, with the height of their block.
+    committed: HashMap<String, u64>,
+    rejections: HashMap<String, Vec<Rejection>>,
+    receipts: HashMap<String, LifecycleReceipt>,
+    /// Transactions submitted here, with when they were.
+    submitted: HashMap<String, Instant>,
+    progress: HashMap<String, Progress>,
+}
+
+impl TxTracker {
+    /// Adds `tx` to the mempool. A full one makes room by evicting its
+    /// lowest ranked transaction: first stale ones, then ones of senders over
+    /// SENDER_QUOTA, oldest first. If none ranks below `tx`, `tx` is refused
+    /// instead, with how long to wait before trying again.
+    pub fn admit(&mut self, tx: &Transaction, now: Instant) -> Result<(), Duration> {
+        let tx_hash = tx.tx_hash();
+        if self.committed.contains_key(&tx_hash) || self.pending.contains_key(&tx_hash) {
+            return Ok(());
+        }
+
+        self.evicted
+            .retain(|_, at| now.saturating_duration_since(*at) < SUBMISSION_TTL);
+        self.game_commits
+            .retain(|_, at| now.saturating_duration_since(*at) < STALE_AFTER);
+
+        let pending = Pending::new(tx, now);
+        if self.pending.len() >= MEMPOOL_CAPACITY {
+            let mut per_sender = HashMap::<&str, usize>::new();
+            for p in self.pending.values() {
+                *per_sender.entry(&p.sender).or_default() += 1;
+            }
+            let rank = |p: &Pending| {
+                let stale = now.saturating_duration_since(p.since) >= STALE_AFTER
+                    || self
+                        .game_commits
+                        .get(&p.game)
+                        .is_some_and(|at| *at > p.since);
+                if stale {
+                    0
+                } else if per_sender[p.sender.as_str()] > SENDER_QUOTA {
+                    1
+                } else {
+                    2
+                }
+            };
+            let own_rank = if per_sender
+                .get(pending.sender.as_str())
+                .copied()
+                .unwrap_or(0)
+                >= SENDER_QUOTA
+            {
+                1
+            } else {
+                2
+            };
+
+            let lowest = self
+                .pending
+                .iter()
+                .map(|(hash, p)| (rank(p), p.since, hash))
+                .min()
+                .map(|(rank, _, hash)| (rank, hash.clone()));
+            match lowest {
+                Some((rank, hash)) if rank < own_rank => self.evict(hash, now),
+                _ => return Err(self.retry_after(now)),
+            }
+        }
+
+        self.evicted.remove(&tx_hash);
+        self.pending.insert(tx_hash, pending);
+        Ok(())
+    }
+
+    /// Drops `tx_hash` from this node's mempool. Every entry there has been
+    /// broadcast, so it may still commit through other nodes: it stays
+    /// pending to its sender, and submitting it again stays a duplicate.
+    fn evict(&mut self, tx_hash: String, now: Instant) {
+        self.pending.remove(&tx_hash);
+        self.progress.remove(&tx_hash);
+        self.rejections.remove(&tx_hash);
+        self.evicted.insert(tx_hash, now);
+    }
+
+    /// Until the oldest pending transaction turns stale, and so evictable.
+    fn retry_after(&self, now: Instant) -> Duration {
+        let oldest = self.pending.values().map(|p| p.since).min().unwrap_or(now);
+        STALE_AFTER
+            .saturating_sub(now.saturating_duration_since(oldest))
+            .max(Duration::from_secs(1))
+    }
+
+    /// Claims `tx_hash` for a submission. A transaction submitted here
+    /// within `SUBMISSION_TTL`, or committed at all, is a duplicate, and its
+    /// current status is returned instead.
+    pub fn submit(&mut self, tx_hash: &str, now: Instant) -> Option<TxStatus> {
+        self.submitted
+            .retain(|_, at| now.saturating_duration_since(*at) < SUBMISSION_TTL);
+
+        if !self.submitted.contains_key(tx_hash) && !self.committed.contains_key(tx_hash) {
+            self.submitted.insert(tx_hash.to_string(), now);
+            return None;
+        }
+        Some(self.status(tx_hash).0)
+    }
+
+    /// Releases a claim on a submission that was not broadcast after all.
+    pub fn withdraw(&mut self, tx_hash: &str) {
+        self.submitted.remove(tx_hash);
+        self.pending.remove(tx_hash);
+        self.rejections.remove(tx_hash);
+    }
+
+    pub fn mark_received_by_leader(&mut self, tx_hash: String) {
+        if !self.committed.contains_key(&tx_hash) {
+            self.progress.entry(tx_hash).or_default().received_by_leader = true;
+        }
+    }
+
+    /// Notes the block proposing `tx_hash`; a later view's proposal replaces
+    /// an earlier one that did not make it.
+    pub fn mark_proposed(&mut self, tx_hash: String, view_n: u32, block_hash: B256) {
+        if !self.committed.contains_key(&tx_hash) {
+            let progress = self.progress.entry(tx_hash).or_default();
+            progress.received_by_leader = true;
+            progress.proposal = Some((view_n, block_hash));
+        }
+    }
+
+    pub fn progress(&self, tx_hash: &str) -> Progress {
+        self.progress.get(tx_hash).copied().unwrap_or_default()
+    }
+
+    /// Notes that `tx_hash` committed in the block at `height`.
+    pub fn mark_committed(&mut self, tx_hash: String, height: u64) {
+        if let Some(pending) = self.pending.remove(&tx_hash) {
+            self.game_commits.insert(pending.game, Instant::now());
+        }
+        self.evicted.remove(&tx_hash);
+        self.progress.remove(&tx_hash);
+        self.rejections.remove(&tx_hash);
+        self.committed.insert(tx_hash, height);
+    }
+
+    /// Height of the block `tx_hash` committed in, if it did.
+    pub fn committed_height(&self, tx_hash: &str) -> Option<u64> {
+        self.committed.get(tx_hash).copied()
+    }
+
+    pub fn add_receipt(&mut self, receipt: LifecycleReceipt) {
+        self.receipts.insert(receipt.tx_hash.clone(), receipt);
+    }
+
+    pub fn receipt(&self, tx_hash: &str) -> Option<LifecycleReceipt> {
+        self.receipts.get(tx_hash).cloned()
+    }
+
+    /// Records a rejection of a pending transaction, at most one per
+    /// validator, so the mempool bounds how many are kept.
+    pub fn add_rejection(&mut self, rejection: Rejection) {
+        if !self.pending.contains_key(&rejection.tx_hash) {
+            return;
+        }
+
+        let rejections = self
+            .rejections
+            .entry(rejection.tx_hash.clone())
+            .or_default();
+        if !rejections
+            .iter()
+            .any(|r| r.validator == rejection.validator)
+        {
+            rejections.push(rejection);
+        }
+    }
+
+    pub fn status(&self, tx_hash: &str) -> (TxStatus, Vec<Rejection>) {
+        let rejections = self.rejections.get(tx_hash).cloned().unwrap_or_default();
+
+        let status = if self.committed.contains_key(tx_hash) {
+            TxStatus::Committed
+        } else if rejections.len() > PEERS as usize / 3 {
+            TxStatus::Rejected
+        } else if self.pending.contains_key(tx_hash)
+            || self.evicted.contains_key(tx_hash)
+            || !rejections.is_empty()
+        {
+            TxStatus::Pending
+        } else {
+            TxStatus::NotFound
+        };
+
+        (status, rejections)
+    }
+}
+
+impl App {
+    /// Records and gossips this node's refusal of `tx`, so the originator can
+    /// learn why it will not commit.
+    pub async fn reject(
+        &self,
+        tx: &Transaction,
+        error: &AppError,
+        view_n: u32,
+    ) -> Result<(), AppError> {
+        let rejection = Rejection::new(tx.tx_hash(), error, view_n)?;
+        self.txs.write().await.add_rejection(rejection.clone());
+        self.progress.notify_waiters();
+        self.publish(FEEDBACK_TOPIC.clone(), rejection.encode_to_vec())
+            .await
+    }
+
+    /// Records a validator's gossiped rejection. Those of other peers would
+    /// let anyone mark a transaction rejected.
+    pub async fn add_rejection(&self, rejection: Rejection) -> Result<(), AppError> {
+        if !self.is_validator(&rejection.validator).await {
+            return Err(AppError::PeerError("rejection from a non-validator".into()));
+        }
+        rejection.verify()?;
+        self.txs.write().await.add_rejection(rejection);
+        self.progress.notify_waiters();
+        Ok(())
+    }
+
+    pub async fn transaction_status(&self, tx_hash: &str) -> TransactionStatusResponse {
+        let txs = self.txs.read().await;
+        let (status, rejections) = txs.status(tx_hash);
+        TransactionStatusResponse {
+            status: status as i32,
+            rejections,
+            height: txs.committed_height(tx_hash),
+        }
+    }
+
+    /// Waits until `tx_hash` commits or enough validators reject it, and
+    /// returns its status then.
+    pub async fn settled(&self, tx_hash: &str) -> TransactionStatusResponse {
+        loop {
+            // Taken before the read, so a step in between still wakes us.
+            let progress = self.progress.notified();
+            let committed = self.committed.notified();
+            let status = self.transaction_status(tx_hash).await;
+            if status.status == TxStatus::Committed as i32
+                || status.status == TxStatus::Rejected as i32
+            {
+                return status;
+            }
+            tokio::select! {
+                _ = progress => {}
+                _ = committed => {}
+            }
+        }
+    }
+
+    pub async fn transaction_progress(&self, tx_hash: &str) -> TxProgress {
+        let ((status, rejections), progress, height) = {
+            let txs = self.txs.read().await;
+            (
+                txs.status(tx_hash),
+                txs.progress(tx_hash),
+                txs.committed_height(tx_hash),
+            )
+        };
+        let votes = match progress.proposal {
+            Some((_, hash)) => self
+                .consensus
+                .read()
+                .await
+                .votes
+                .get(&hash)
+                .map_or(0, |votes| votes.len()),
+            None => 0,
+        };
+
+        TxProgress {
+            status: status as i32,
+            received_by_leader: progress.received_by_leader || status == TxStatus::Committed,
+            proposed_in_view: progress.proposal.map(|(view_n, _)| view_n),
+            votes: votes as u32,
+            validators: PEERS,
+            rejections,
+            height,
+        }
+    }
+
+    pub async fn mark_received_by_leader(&self, tx_hash: String) {
+        self.txs.write().await.mark_received_by_leader(tx_hash);
+        self.progress.notify_waiters();
+    }
+
+    pub async fn mark_proposed(&self, tx_hash: String, view_n: u32, block_hash: B256) {
+        self.txs
+            .write()
+            .await
+            .mark_proposed(tx_hash, view_n, block_hash);
+        self.progress.notify_waiters();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rejection_signature_roundtrip() {
+        let error = AppError::InvalidTransactionError("invalid move".into());
+        let mut rejection = Rejection::new("0xabc".into(), &error, 3).unwrap();
+        assert!(rejection.verify().is_ok());
+
+        rejection.view_n = 4;
+        assert!(rejection.verify().is_err());
+    }
+
+    #[test]
+    fn test_status_needs_enough_distinct_rejections() {
+        let mut tracker = TxTracker::default();
+        let tx = Transaction::default();
+        tracker.admit(&tx, Instant::now()).unwrap();
+        let hash = tx.tx_hash();
+
+        let error = AppError::InvalidTransactionError("invalid move".into());
+        let rejection = Rejection::new(hash.clone(), &error, 0).unwrap();
+        tracker.add_rejection(rejection.clone());
+        tracker.add_rejection(rejection.clone());
+        assert_eq!(tracker.status(&hash).0, TxStatus::Pending);
+
+        tracker.add_rejection(Rejection {
+            validator: "other".into(),
+            ..rejection
+        });
+        assert_eq!(tracker.status(&hash).0, TxStatus::Rejected);
+
+        tracker.mark_committed(hash.clone(), 7);
+        assert_eq!(tracker.status(&hash), (TxStatus::Committed, vec![]));
+        assert_eq!(tracker.committed_height(&hash), Some(7));
+
+        // Rejections of transactions not in the mempool are not kept.
+        tracker.add_rejection(Rejection::new("0xdef".into(), &error, 0).unwrap());
+        assert_eq!(tracker.status("0xdef"), (TxStatus::NotFound, vec![]));
+    }
+
+    #[tokio::test]
+    async fn test_only_validators_reject() {
+        let app = App::new(tokio::sync::mpsc::channel(1).0);
+        let tx = Transaction::default();
+        app.txs.write().await.admit(&tx, Instant::now()).unwrap();
+        let error = AppError::InvalidTransactionError("invalid move".into());
+        let rejection = Rejection::new(tx.tx_hash(), &error, 0).unwrap();
+
+        assert!(app.add_rejection(rejection).await.is_err());
+        assert!(app
+            .transaction_status(&tx.tx_hash())
+            .await
+            .rejections
+            .is_empty());
+    }
+
+    #[test]
+    fn test_resubmissions_are_duplicates() {
+        let mut tracker = TxTracker::default();
+        let start = Instant::now();
+        let tx = Transaction::default();
+        let abc = tx.tx_hash();
+        let abc = abc.as_str();
+
+        assert_eq!(tracker.submit(abc, start), None);
+        tracker.admit(&tx, start).unwrap();
+        assert_eq!(tracker.submit(abc, start), Some(TxStatus::Pending));
+
+        tracker.submit("0xdef", start);
+        tracker.withdraw("0xdef");
+        assert_eq!(tracker.submit("0xdef", start), None);
+
+        // Forgotten once stale, unless committed.
+        let later = start + SUBMISSION_TTL;
+        tracker.mark_committed("0xdef".into(), 9);
+        assert_eq!(tracker.submit(abc, later), None);
+        assert_eq!(tracker.submit("0xdef", later), Some(TxStatus::Committed));
+    }
+
+    #[test]
+    fn test_full_mempool_evicts_by_rank() {
+        let mut tracker = TxTracker::default();
+        let start = Instant::now();
+        let tx = |sender: &str, n: usize| Transaction {
+            pub_key: sender.into(),
+            game_id: Some(n.to_string()),
+            ..Default::default()
+        };
+
+        // One sender over its quota, everyone else with one each.
+        let greedy: Vec<_> = (0..=SENDER_QUOTA).map(|n| tx("greedy", n)).collect();
+        for t in &greedy {
+            tracker.admit(t, start).unwrap();
+        }
+        for n in greedy.len()..MEMPOOL_CAPACITY {
+            tracker.admit(&tx(&format!("s{}", n), n), start).unwrap();
+        }
+
+        // A newcomer pushes out one of the greedy sender's, which was
+        // broadcast and so is still pending as far as its sender knows.
+        for t in &greedy {
+            tracker.submit(&t.tx_hash(), start);
+        }
+        tracker.admit(&tx("newcomer", 0), start).unwrap();
+        let evicted: Vec<_> = greedy
+            .iter()
+            .filter(|t| !tracker.pending.contains_key(&t.tx_hash()))
+            .collect();
+        assert_eq!(evicted.len(), 1);
+        assert_eq!(tracker.status(&evicted[0].tx_hash()).0, TxStatus::Pending);
+        assert!(tracker
+            .submit(&evicted[0].tx_hash(), start)
+            .is_some_and(|status| status == TxStatus::Pending));
+
+        // Now at its quota, the greedy sender has to wait for a stale one.
+        assert_eq!(tracker.admit(&tx("greedy", 99), start), Err(STALE_AFTER));
+        assert_eq!(
+            tracker.admit(&tx("greedy", 99), start + STALE_AFTER),
+            Ok(())
+        );
+        assert_eq!(tracker.pending.len(), MEMPOOL_CAPACITY);
+    }
+
+    #[test]
+    fn test_receipts_carry_the_leader_signature() {
+        use crate::consensus::types::{BlockBuilder, QuorumCertificate};
+
+        let local = LOCAL_KEYS.public().to_peer_id().to_string();
+        let tx = Transaction {
+            kind: TransactionKind::Abort as i32,
+            ..Default::default()
+        };
+        let mut block = BlockBuilder::default()
+            .with_tx(tx.clone())
+            .with_view_n(2)
+            .build();
+        block.qc = Some(QuorumCertificate::default().with_signature(vec![local.clone()]));
+        block.sign_as_leader().unwrap();
+
+        let mut tracker = TxTracker::default();
+        tracker.add_receipt(LifecycleReceipt::new(&block, 7, None).unwrap());
+        let mut receipt = tracker.receipt(&tx.tx_hash()).unwrap();
+        assert_eq!(
+            (receipt.height, receipt.quorum.clone()),
+            (7, vec![local.clone()])
+        );
+        receipt.verify(&local).unwrap();
+        assert!(receipt.verify("another validator").is_err());
+
+        receipt.view_n = 3;
+        assert!(receipt.verify(&local).is_err());
+    }
+
+    async fn step(app: &App) -> (bool, Option<u32>, u32) {
+        let progress = app.transaction_progress("0xabc").await;
+        (
+            progress.received_by_leader,
+            progress.proposed_in_view,
+            progress.votes,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_progress_through_consensus() {
+        let app = App::new(tokio::sync::mpsc::channel(1).0);
+        let hash = B256::repeat_byte(1);
+        assert_eq!(step(&app).await, (false, None, 0));
+        app.mark_received_by_leader("0xabc".into()).await;
+        assert_eq!(step(&app).await, (true, None, 0));
+
+        app.mark_proposed("0xabc".into(), 4, hash).await;
+        app.count_vote(hash, "a".into()).await;
+        app.count_vote(hash, "b".into()).await;
+        assert_eq!(step(&app).await, (true, Some(4), 2));
+        assert_eq!(app.transaction_progress("0xabc").await.validators, PEERS);
+
+        app.txs.write().await.mark_committed("0xabc".into(), 1);
+        let committed = app.transaction_progress("0xabc").await;
+        assert_eq!(committed.status, TxStatus::Committed as i32);
+        assert!(committed.received_by_leader);
+    }
+}
@@ -0,0 +1,342 @@
+use crate::{
+    errors::AppError,
+    features::Feature,
+    pb::query::{GovernanceAction, GovernanceRequest, ValidatorSignature},
+    state::ConsensusState,
+    App, CONNECTED_PEERS, PEERS,
+};
+use prost::Message;
+use std::collections::HashSet;
+use tracing::info;
+
+/// A node version as major, minor and patch.
+pub type Version = (u64, u64, u64);
+
+/// Reads a "major.minor.patch" version; anything after the patch, such as a
+/// pre-release, is not compared.
+pub fn parse_version(s: &str) -> Option<Version> {
+    let mut parts = s.trim().splitn(3, '.');
+    let (major, minor, rest) = (parts.next()?, parts.next()?, parts.next()?);
+    let patch = rest.split(|c: char| !c.is_ascii_digit()).next()?;
+    Some((
+        major.parse().ok()?,
+        minor.parse().ok()?,
+        patch.parse().ok()?,
+    ))
+}
+
+/// This node's version.
+pub fn node_version() -> Version {
+    parse_version(env!("CARGO_PKG_VERSION")).expect("the crate version is major.minor.patch")
+}
+
+impl ConsensusState {
+    /// Refuses blocks at `height` when a committed REQUIRE_VERSION asks for
+    /// a newer node than this one by then.
+    pub fn check_node_version(&self, height: u64) -> Result<(), AppError> {
+        match self.required_version {
+            Some((min, activation)) if height >= activation && node_version() < min => {
+                Err(AppError::BlockValidationError(format!(
+                    "node version {} is below the required {}.{}.{}",
+                    env!("CARGO_PKG_VERSION"),
+                    min.0,
+                    min.1,
+                    min.2
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl GovernanceRequest {
+    /// What validators sign: the request with no signatures attached.
+    pub fn payload(&self) -> Vec<u8> {
+        GovernanceRequest {
+            signatures: Vec::new(),
+            ..self.clone()
+        }
+        .encode_to_vec()
+    }
+
+    /// Signs the request with this node's libp2p key.
+    pub fn endorse(&self) -> Result<ValidatorSignature, AppError> {
+        ValidatorSignature::sign(&self.payload())
+    }
+
+    fn rules_activation(&self) -> Result<(u32, u64), AppError> {
+        match (self.rules_version, self.activation_height) {
+            (Some(version), Some(height)) => Ok((version, height)),
+            _ => Err(AppError::InvalidTransactionError(
+                "rules upgrade needs a version and an activation height".into(),
+            )),
+        }
+    }
+
+    fn version_requirement(&self) -> Result<(Version, u64), AppError> {
+        match (
+            self.min_version.as_deref().and_then(parse_version),
+            self.activation_height,
+        ) {
+            (Some(version), Some(height)) => Ok((version, height)),
+            _ => Err(AppError::InvalidTransactionError(
+                "a version requirement needs a version and an activation height".into(),
+            )),
+        }
+    }
+
+    fn governed_feature(&self) -> Result<Feature, AppError> {
+        self.feature
+            .as_deref()
+            .and_then(Feature::from_name)
+            .ok_or_else(|| AppError::InvalidTransactionError("unknown feature".into()))
+    }
+
+    /// Distinct validators among `validators` with a valid signature.
+    fn signers(&self, validators: &[String]) -> HashSet<String> {
+        let payload = self.payload();
+
+        self.signatures
+            .iter()
+            .filter(|s| validators.contains(&s.validator) && s.verify(&payload))
+            .map(|s| s.validator.clone())
+            .collect()
+    }
+}
+
+impl App {
+    pub async fn is_halted(&self) -> bool {
+        self.consensus.read().await.halted
+    }
+
+    /// The validators whose signatures count: the ones the registry names,
+    /// or before there is one those in rotation.
+    pub async fn validator_set(&self) -> Vec<String> {
+        match &self.validator_registry {
+            Some(registry) => registry.iter().cloned().collect(),
+            None => CONNECTED_PEERS.read().await.clone(),
+        }
+    }
+
+    /// Checks a halt, resume, rules upgrade, feature switch or version
+    /// requirement against the chain as `consensus` has it, for the block at
+    /// `height`: a nonce above the last one committed, more than 2/3 of the
+    /// validators signing, and whatever the action itself needs.
+    pub async fn validate_governance(
+        &self,
+        r: &GovernanceRequest,
+        consensus: &ConsensusState,
+        height: u64,
+    ) -> Result<(), AppError> {
+        if r.nonce <= consensus.governance_nonce {
+            return Err(AppError::InvalidTransactionError(
+                "stale governance nonce".into(),
+            ));
+        }
+
+        let signers = r.signers(&self.validator_set().await);
+        if signers.len() <= (2 * PEERS as usize) / 3 {
+            return Err(AppError::InvalidQcError);
+        }
+
+        match r.action() {
+            GovernanceAction::Halt | GovernanceAction::Resume => Ok(()),
+            GovernanceAction::ScheduleRules => {
+                let (version, activation) = r.rules_activation()?;
+                self.rules.read().await.check(activation, version, height)
+            }
+            GovernanceAction::EnableFeature | GovernanceAction::DisableFeature => {
+                r.governed_feature().map(|_| ())
+            }
+            GovernanceAction::RequireVersion => {
+                let (_, activation) = r.version_requirement()?;
+                if activation < height {
+                    return Err(AppError::InvalidTransactionError(
+                        "a version requirement cannot activate in the past".into(),
+                    ));
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Applies a committed governance transaction to `consensus`, the chain
+    /// state the commit holds, and to the rules and features it governs.
+    pub async fn govern(
+        &self,
+        r: &GovernanceRequest,
+        consensus: &mut ConsensusState,
+        height: u64,
+    ) -> Result<(), AppError> {
+        self.validate_governance(r, consensus, height).await?;
+
+        match r.action() {
+            GovernanceAction::Halt => consensus.halted = true,
+            GovernanceAction::Resume => consensus.halted = false,
+            GovernanceAction::ScheduleRules => {
+                let (version, activation) = r.rules_activation()?;
+                self.rules
+                    .write()
+                    .await
+                    .schedule(activation, version, height)?;
+            }
+            action @ (GovernanceAction::EnableFeature | GovernanceAction::DisableFeature) => {
+                self.features.write().await.set(
+                    r.governed_feature()?,
+                    action == GovernanceAction::EnableFeature,
+                );
+            }
+            GovernanceAction::RequireVersion => {
+                consensus.required_version = Some(r.version_requirement()?);
+            }
+        }
+
+        consensus.governance_nonce = r.nonce;
+        info!("Governance action {:?} at nonce {}", r.action(), r.nonce);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        consensus::types::{BlockBuilder, QuorumCertificate},
+        errors::AppError,
+        network::p2p::LOCAL_KEYS,
+        pb::query::{Transaction, TransactionKind},
+    };
+    use libp2p::identity::Keypair;
+    use tokio::sync::mpsc;
+
+    fn signed(r: &GovernanceRequest, keys: &[Keypair]) -> GovernanceRequest {
+        let signatures = keys
+            .iter()
+            .map(|key| ValidatorSignature {
+                validator: key.public().to_peer_id().to_string(),
+                public_key: hex::encode(key.public().to_protobuf_encoding()),
+                signature: hex::encode(key.sign(&r.payload()).unwrap()),
+            })
+            .collect();
+        GovernanceRequest {
+            signatures,
+            ..r.clone()
+        }
+    }
+
+    async fn commit(app: &App, r: GovernanceRequest) -> Result<(), AppError> {
+        let tx = Transaction {
+            kind: TransactionKind::Govern as i32,
+            governance: Some(r),
+            ..Default::default()
+        };
+        let mut block = BlockBuilder::default()
+            .with_previous_block_hash(app.consensus.read().await.head)
+            .with_tx(tx)
+            .with_rules_version(app.rules_version_at(app.next_height()).await)
+            .with_height(app.next_height())
+            .build();
+        let hash = block.block_hash()?;
+        let voters: Vec<String> = (0..PEERS).map(|i| format!("voter {}", i)).collect();
+        for voter in &voters {
+            app.count_vote(hash, voter.clone()).await;
+        }
+        block.qc = Some(
+            QuorumCertificate::default()
+                .with_block_hash(hash)
+                .with_signature(voters),
+        );
+        app.commit_block(block).await
+    }
+
+    #[tokio::test]
+    async fn test_governance_applies_once_committed() {
+        let keys: Vec<_> = (0..PEERS).map(|_| Keypair::generate_ed25519()).collect();
+        let mut app = App::new(mpsc::channel(1).0);
+        app.validator_registry = Some(
+            keys.iter()
+                .map(|k| k.public().to_peer_id().to_string())
+                .collect(),
+        );
+        let halt = GovernanceRequest {
+            action: GovernanceAction::Halt as i32,
+            nonce: 1,
+            ..Default::default()
+        };
+
+        // Two of four validators are not enough.
+        assert!(commit(&app, signed(&halt, &keys[..2])).await.is_err());
+        assert!(!app.is_halted().await);
+
+        commit(&app, signed(&halt, &keys[..3])).await.unwrap();
+        assert!(app.is_halted().await);
+        assert_eq!(app.consensus.read().await.governance_nonce, 1);
+
+        let moving = Transaction::default();
+        assert!(matches!(
+            app.is_valid_tx(&moving, 0).await,
+            Err(AppError::HaltedError)
+        ));
+
+        // The same request again is a replay, while a resume goes through
+        // even though the network is halted.
+        assert!(commit(&app, signed(&halt, &keys)).await.is_err());
+        let resume = GovernanceRequest {
+            action: GovernanceAction::Resume as i32,
+            nonce: 2,
+            ..Default::default()
+        };
+        commit(&app, signed(&resume, &keys[1..])).await.unwrap();
+        assert!(!app.is_halted().await);
+        assert_eq!(app.height.load(std::sync::atomic::Ordering::Relaxed), 2);
+    }
+
+    #[tokio::test]
+    async fn test_required_version_stops_old_nodes_at_its_height() {
+        let keys: Vec<_> = (0..PEERS).map(|_| Keypair::generate_ed25519()).collect();
+        let mut app = App::new(mpsc::channel(1).0);
+        app.validator_registry = Some(
+            keys.iter()
+                .map(|k| k.public().to_peer_id().to_string())
+                .collect(),
+        );
+        let require = |min_version: &str, activation_height| GovernanceRequest {
+            action: GovernanceAction::RequireVersion as i32,
+            nonce: 1,
+            min_version: Some(min_version.into()),
+            activation_height: Some(activation_height),
+            ..Default::default()
+        };
+
+        assert!(commit(&app, signed(&require("newest", 5), &keys))
+            .await
+            .is_err());
+        commit(&app, signed(&require("999.0.0-rc.1", 5), &keys))
+            .await
+            .unwrap();
+
+        let consensus = app.consensus.read().await;
+        assert_eq!(consensus.required_version, Some(((999, 0, 0), 5)));
+        assert!(consensus.check_node_version(4).is_ok());
+        assert!(consensus.check_node_version(5).is_err());
+    }
+
+    #[test]
+    fn test_signers_only_count_valid_validator_signatures() {
+        let local = LOCAL_KEYS.public().to_peer_id().to_string();
+        let mut r = GovernanceRequest {
+            action: GovernanceAction::Halt as i32,
+            nonce: 1,
+            signatures: Vec::new(),
+            ..Default::default()
+        };
+
+        let signature = r.endorse().unwrap();
+        r.signatures = vec![signature.clone(), signature.clone()];
+        assert_eq!(r.signers(std::slice::from_ref(&local)).len(), 1);
+        assert!(r.signers(&["someone else".into()]).is_empty());
+
+        r.nonce = 2;
+        assert!(r.signers(&[local]).is_empty());
+    }
+}
@@ -1,239 +1,949 @@
+use super::rules::Rules;
 use super::types::{Block, BlockBuilder, QuorumCertificate};
+use crate::chess::{diff::BoardDiff, parse_uci, pgn::validate_comment};
 use crate::errors::AppError;
+use crate::features::Feature;
+use crate::network::admin::RECENT_BLOCKS;
 use crate::network::utils::SwarmMessageType;
-use crate::pb::game::Color;
-use crate::pb::query::Transaction;
+use crate::pb::game::{Color, Termination};
+use crate::pb::query::{
+    AcceptSeekRequest, CancelSeekRequest, DebugValidateResponse, LifecycleReceipt, Seek,
+    TeamSignature, Transaction, TransactionKind, ValidatorSignature,
+};
 use crate::{
     pb::{game::GameState, query::StartRequest},
     App, PEERS,
 };
-use crate::{CLOCK, CONNECTED_PEERS, VIEW_N_ROT_INTERVAL};
+use crate::{CLOCK, CONNECTED_PEERS, MAX_CLOCK_DRIFT, MIN_HEIGHT_WAIT, VIEW_N_ROT_INTERVAL};
 use alloy_primitives::{keccak256, B256};
 use chrono::{TimeZone, Utc};
 use libp2p::gossipsub::IdentTopic;
 use libsecp256k1::{verify, Message, PublicKey, Signature};
 use sha2::{Digest, Sha256};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::RwLock;
 use tracing::info;
 
 impl App {
     pub async fn get_current_leader(&self) -> Result<String, AppError> {
-        match CONNECTED_PEERS
-            .read()
+        self.leader_of(self.view_n.load(std::sync::atomic::Ordering::Relaxed))
             .await
-            .get(self.view_n.load(std::sync::atomic::Ordering::Relaxed) % PEERS as usize)
-        {
+    }
+
+    pub async fn leader_of(&self, view_n: usize) -> Result<String, AppError> {
+        match CONNECTED_PEERS.read().await.get(view_n % PEERS as usize) {
             Some(peer) => Ok(peer.clone()),
             None => Err(AppError::NoLeaderError),
         }
     }
 
+    /// Whether `peer_id` may validate at all: only the validators the
+    /// registry names, and none before there is one.
+    pub fn may_validate(&self, peer_id: &str) -> bool {
+        self.validator_registry
+            .as_ref()
+            .is_some_and(|validators| validators.contains(peer_id))
+    }
+
+    /// Whether `peer_id` is among the validators in rotation, whose votes
+    /// count towards a quorum.
+    pub async fn is_validator(&self, peer_id: &str) -> bool {
+        CONNECTED_PEERS.read().await.iter().any(|p| p == peer_id)
+    }
+
+    /// Moves to the view after `block`'s. Only a block whose QC checks out
+    /// against the votes seen here can move the view, and only forward, so a
+    /// hostile view number cannot drag the node ahead of its peers.
+    pub async fn advance_view_past(&self, block: &Block) -> Result<(), AppError> {
+        let qc = block.qc.as_ref().ok_or(AppError::InvalidQcError)?;
+        if qc.block_hash != block.hash {
+            return Err(AppError::InvalidQcError);
+        }
+        self.is_valid_qc(qc).await?;
+
+        self.view_n.fetch_max(
+            block.view_n as usize + 1,
+            std::sync::atomic::Ordering::Relaxed,
+        );
+        Ok(())
+    }
+
     pub async fn commit_block(&self, block: Block) -> Result<(), AppError> {
-        if let Some(ref qc) = block.qc {
-            self.is_valid_qc(qc).await?;
-
-            let version = self.db.read().await.clone();
-
-            if let Some(g) = self.db.write().await.get_mut(&format!(
-                "{}:{}",
-                block.tx.white_player, block.tx.black_player
-            )) {
-                let real_block = BlockBuilder::default()
-                    .with_previous_block_hash(block.previous_block_hash)
-                    .with_history(g.history.clone().unwrap())
-                    .with_tx(block.tx.clone())
-                    .with_view_n(block.view_n)
-                    .build();
-
-                if real_block.hash != block.hash || qc.block_hash != block.hash {
-                    return Err(AppError::BlockValidationError("invalid block".into()));
-                }
+        let Some(ref qc) = block.qc else {
+            return Err(AppError::InvalidQcError);
+        };
+
+        let validators = CONNECTED_PEERS.read().await.clone();
+        // Held to the end, so commits run one at a time.
+        let mut consensus = self.consensus.write().await;
+        consensus.validate_qc(qc, &validators)?;
+
+        // Commits run one at a time, so the head is the block's parent and
+        // the block the one above it.
+        if block.height != self.next_height() {
+            return Err(AppError::BlockValidationError("wrong height".into()));
+        }
+
+        let tx = block.transaction()?;
+        let rules_version = self.rules_version_at(block.height).await;
+        let real_block = BlockBuilder::default()
+            .with_previous_block_hash(block.previous_hash()?)
+            .with_history(self.history_of(tx).await?)
+            .with_tx(tx.clone())
+            .with_view_n(block.view_n)
+            .with_rules_version(rules_version)
+            .with_height(block.height)
+            .with_timestamp(block.timestamp)
+            .build();
+
+        if real_block.hash != block.hash || qc.block_hash != block.hash {
+            return Err(AppError::BlockValidationError("invalid block".into()));
+        }
+
+        // Checked again as it commits, so of two acceptances of a seek
+        // validated side by side only the first starts a game.
+        let closed_seek = match (tx.seek_acceptance()?, tx.seek_cancellation()?) {
+            (Some(r), _) => Some(&r.seek_id),
+            (None, r) => r.map(|r| &r.seek_id),
+        };
+        if closed_seek.is_some_and(|id| !consensus.seeks.contains_key(id)) {
+            return Err(AppError::InvalidTransactionError(
+                "no such open seek".into(),
+            ));
+        }
+
+        let applied = match (tx.governance_request()?, tx.game_start()?) {
+            (Some(r), _) => {
+                self.govern(r, &mut consensus, block.height).await?;
+                None
+            }
+            (None, Some(r)) => Some(self.start_committed(&r, &block).await?),
+            (None, None) if tx.lists_seek()? => None,
+            (None, None) => Some(self.apply_to_game(tx, &block, rules_version).await?),
+        };
+        // Seeks open and close in block order, so every node lists the same
+        // ones. A closed seek stays closed until it would have expired anyway.
+        if let Some(seek) = tx.seek_creation()? {
+            consensus.seeks.insert(seek.id(), seek.clone());
+        }
+        if let Some(id) = closed_seek {
+            if let Some(seek) = consensus.seeks.remove(id) {
+                consensus.closed_seeks.insert(id.clone(), seek.expires_at);
+            }
+        }
+        consensus
+            .seeks
+            .retain(|_, seek| seek.expires_at > block.timestamp);
+        consensus
+            .closed_seeks
+            .retain(|_, expires_at| *expires_at > block.timestamp);
+
+        consensus.head = block.block_hash()?;
+        consensus.head_timestamp = block.timestamp;
+        consensus.latest_timestamp = block.timestamp as u64;
+        *CLOCK.write().await = Utc.timestamp_opt(block.timestamp, 0).unwrap();
+
+        let height = block.height;
+        self.height
+            .store(height, std::sync::atomic::Ordering::Relaxed);
+        let mut txs = self.txs.write().await;
+        txs.mark_committed(tx.tx_hash(), height);
+        if let Some((_, game, _)) = &applied {
+            if TransactionKind::from_i32(tx.kind).is_some_and(TransactionKind::is_lifecycle) {
+                txs.add_receipt(LifecycleReceipt::new(&block, height, game.result.clone())?);
+            }
+        }
+        drop(txs);
+        // Credited to the validator that signed the block as leader, which
+        // every node reads the same, not to this node's idea of the leader.
+        let proposer = block
+            .proposer()
+            .filter(|p| validators.iter().any(|v| v == p));
+        self.participation
+            .write()
+            .await
+            .credit(height, proposer, qc);
+
+        let diff = applied.as_ref().and_then(|(_, _, diff)| diff.clone());
+        let mut recent_blocks = self.recent_blocks.write().await;
+        recent_blocks.push_back((height, block.clone(), diff.clone()));
+        if recent_blocks.len() > RECENT_BLOCKS {
+            recent_blocks.pop_front();
+        }
+        drop(recent_blocks);
+        self.committed.notify_waiters();
 
-                if let Err(e) = g.apply_move(block.tx.action[0].clone(), block.tx.action[1].clone())
-                {
-                    self.db.write().await.clone_from(&version);
+        if let Some((game_key, game, _)) = &applied {
+            if let Some(digests) = &self.digests {
+                digests.update(game_key, game, block.timestamp).await;
+            }
+
+            #[cfg(feature = "nats")]
+            if let Some(sink) = &self.sink {
+                sink.publish_block(height, &block, diff.as_ref(), game_key, game);
+            }
+        }
+
+        info!("Committed block: {:?}", block);
+        Ok(())
+    }
+
+    /// Applies the move or lifecycle transaction `tx` of a committed `block`
+    /// to its game. Returns the game's key, the game as it is now and, for a
+    /// move, what it changed on the board.
+    async fn apply_to_game(
+        &self,
+        tx: &Transaction,
+        block: &Block,
+        rules_version: u32,
+    ) -> Result<(String, GameState, Option<BoardDiff>), AppError> {
+        let Some((game_key, game)) = self
+            .db
+            .find(tx.game_id.as_deref(), &tx.white_player, &tx.black_player)
+            .await
+        else {
+            return Err(AppError::BlockValidationError("no such game".into()));
+        };
+        let mut game = game.write().await;
+
+        // Applied to a copy, so a transaction that fails leaves the game as
+        // it was.
+        let rules = Rules::from_version(rules_version)?;
+        let mut g = game.clone();
+        let was_over = g.is_over();
+        let mut diff = None;
+        match TransactionKind::from_i32(tx.kind) {
+            // A move made after the mover's time ran out loses on time.
+            Some(TransactionKind::Move | TransactionKind::Commit | TransactionKind::Reveal)
+                if g.out_of_time(block.timestamp) =>
+            {
+                g.lose_on_time();
+            }
+            Some(TransactionKind::Move) => {
+                let (from, to) = tx.squares()?;
+                g.punch_clock(block.timestamp);
+                match rules.apply_move(&mut g, from, to, tx.promotion.as_deref()) {
+                    Ok(d) => diff = Some(d),
+                    Err(e) => return Err(AppError::InvalidTransactionError(e.to_string())),
+                }
+                g.annotate_last_move(tx.comment.clone());
+            }
+            Some(TransactionKind::Abort | TransactionKind::AbortByAgreement) => {
+                g.finish(Termination::Aborted, None);
+            }
+            Some(TransactionKind::Takeback) => {
+                // Neither side gets the time the takeback took back.
+                g.charge_clock(block.timestamp);
+                g.take_back(rules)
+                    .map_err(|e| AppError::InvalidTransactionError(e.to_string()))?;
+            }
+            Some(TransactionKind::Resign) => {
+                let color = self
+                    .resigning_side(tx, &g)
+                    .map_err(|e| AppError::InvalidTransactionError(e.to_string()))?;
+                g.resign(color);
+            }
+            Some(TransactionKind::Commit) => {
+                let commitment = tx.commitment.clone().unwrap_or_default();
+                if let Err(e) = g.commit_move(commitment) {
                     return Err(AppError::InvalidTransactionError(e.to_string()));
                 }
-            } else {
-                return Err(AppError::BlockValidationError("no such game".into()));
             }
+            Some(TransactionKind::Reveal) => {
+                let salt = tx.salt.as_deref().unwrap_or_default();
+                let (from, to) = tx.squares()?;
+                let promotion = rules
+                    .validate_king_safety(&g, &from, &to)
+                    .and_then(|_| rules.promotion(&g, &from, &to, tx.promotion.as_deref()))
+                    .map_err(|e| AppError::InvalidTransactionError(e.to_string()))?;
+                // A dark side's time runs until its move is revealed.
+                g.punch_clock(block.timestamp);
+                match g.reveal_move(from, to, salt, promotion) {
+                    Ok(d) => diff = Some(d),
+                    Err(e) => return Err(AppError::InvalidTransactionError(e.to_string())),
+                }
+                g.annotate_last_move(tx.comment.clone());
+                rules.adjudicate_mate(&mut g);
+            }
+            Some(
+                TransactionKind::Govern
+                | TransactionKind::Start
+                | TransactionKind::AcceptSeek
+                | TransactionKind::CreateSeek
+                | TransactionKind::CancelSeek,
+            )
+            | None => {
+                return Err(AppError::InvalidTransactionError(
+                    "unknown transaction kind".into(),
+                ))
+            }
+        }
 
-            self.latest_block_hash.write().await.clone_from(&block.hash);
-            self.latest_timestamp
-                .write()
-                .await
-                .clone_from(&(block.timestamp as u64));
-            *CLOCK.write().await = Utc.timestamp_opt(block.timestamp, 0).unwrap();
-
-            info!("Committed block: {:?}", block);
-            Ok(())
-        } else {
-            Err(AppError::InvalidQcError)
+        if !was_over && g.is_over() {
+            self.stats.write().await.record_game(&g);
         }
+        self.state_hasher.write().await.update(&game_key, &g);
+        *game = g;
+
+        // The diff travels with the block, so followers need not fetch the
+        // whole board. A dark game's stays hidden until it ends, like its board.
+        let diff = diff.filter(|_| !game.is_dark() || game.is_over());
+        Ok((game_key, game.clone(), diff))
     }
 
-    pub async fn approve_proposal(&self, proposal: Block, source: String) -> Result<(), AppError> {
+    pub async fn approve_proposal(&self, proposal: Block) -> Result<(), AppError> {
         if self.view_n.load(std::sync::atomic::Ordering::Relaxed) as u32 != proposal.view_n {
             return Err(AppError::BlockValidationError("invalid view".into()));
         }
 
-        if source != self.get_current_leader().await? {
-            return Err(AppError::BlockValidationError("incorrect leader".into()));
-        }
+        proposal.verify_leader(&self.get_current_leader().await?)?;
 
-        let latest_block_hash = self.latest_block_hash.read().await.clone();
+        let latest_block_hash = self.consensus.read().await.head;
 
-        if latest_block_hash != proposal.previous_block_hash {
+        if latest_block_hash != proposal.previous_hash()? {
             return Err(AppError::BlockValidationError("invalid block".into()));
         }
 
+        let tx = proposal.transaction()?;
+
+        if proposal.height != self.next_height() {
+            return Err(AppError::BlockValidationError("wrong height".into()));
+        }
+        if proposal.rules_version != self.rules_version_at(proposal.height).await {
+            return Err(AppError::BlockValidationError("wrong rules version".into()));
+        }
+        self.consensus
+            .read()
+            .await
+            .check_node_version(proposal.height)?;
+        // Games start and time out by block time, so the leader may not
+        // pick it: it goes forward and stays near everyone's clock.
+        let head_timestamp = self.consensus.read().await.head_timestamp;
+        if proposal.timestamp < head_timestamp
+            || (proposal.timestamp - self.now().timestamp()).abs() > MAX_CLOCK_DRIFT
+        {
+            return Err(AppError::BlockValidationError("wrong block time".into()));
+        }
+
         let real_block = BlockBuilder::default()
-            .with_previous_block_hash(proposal.previous_block_hash)
-            .with_tx(proposal.tx.clone())
-            .with_history(
-                self.db
-                    .read()
-                    .await
-                    .get(&format!(
-                        "{}:{}",
-                        proposal.tx.white_player, proposal.tx.black_player
-                    ))
-                    .unwrap()
-                    .history
-                    .clone()
-                    .unwrap(),
-            )
+            .with_previous_block_hash(latest_block_hash)
+            .with_tx(tx.clone())
+            .with_history(self.history_of(tx).await?)
             .with_view_n(proposal.view_n)
+            .with_rules_version(proposal.rules_version)
+            .with_height(proposal.height)
+            .with_timestamp(proposal.timestamp)
             .build();
 
         if real_block.hash != proposal.hash {
             return Err(AppError::BlockValidationError("invalid block".into()));
         }
 
-        if let Err(e) = self.is_valid_tx(&proposal.tx).await {
+        if let Err(e) = self.is_valid_tx(tx, proposal.timestamp).await {
             return Err(AppError::BlockValidationError(e.to_string()));
         }
 
         info!("Approve proposal: {:?}", proposal);
 
-        if proposal.tx.game_state_hash == Some(self.calculate_game_state_hash(&proposal.tx).await?)
-        {
+        if tx.game_state_hash == self.calculate_game_state_hash(tx).await? {
             Ok(())
         } else {
             Err(AppError::BlockValidationError("inequal game states".into()))
         }
     }
 
-    pub async fn is_valid_tx(&self, tx: &Transaction) -> Result<(), AppError> {
-        let game = match self
-            .db
-            .read()
+    /// A copy of the game `tx` is for.
+    pub async fn game_of(&self, tx: &Transaction) -> Option<GameState> {
+        self.db
+            .get(tx.game_id.as_deref(), &tx.white_player, &tx.black_player)
             .await
-            .get(&format!("{}:{}", tx.white_player, tx.black_player))
-        {
-            Some(game) => game.clone(),
-            None => return Err(AppError::InvalidTransactionError("no such game".into())),
+    }
+
+    /// The history a block carrying `tx` commits to: its game's, or none
+    /// for governance and starts.
+    pub async fn history_of(&self, tx: &Transaction) -> Result<String, AppError> {
+        if tx.governance_request()?.is_some() || tx.game_start()?.is_some() || tx.lists_seek()? {
+            return Ok(String::new());
+        }
+
+        self.game_of(tx)
+            .await
+            .map(|game| game.history.unwrap_or_default())
+            .ok_or_else(|| AppError::BlockValidationError("no such game".into()))
+    }
+
+    /// Fills in the action and promotion of a move given in SAN, from the
+    /// current board of its game, or in UCI.
+    pub async fn resolve_notation(&self, tx: &mut Transaction) -> Result<(), AppError> {
+        let (from, to, promotion) = match (&tx.san, &tx.uci) {
+            (None, None) => return Ok(()),
+            (Some(_), Some(_)) => {
+                return Err(AppError::InvalidTransactionError(
+                    "a move is given in SAN or UCI, not both".into(),
+                ))
+            }
+            (Some(san), None) => self
+                .game_of(tx)
+                .await
+                .ok_or_else(|| AppError::InvalidTransactionError("no such game".into()))?
+                .find_san(san)?,
+            (None, Some(uci)) => parse_uci(uci)?,
         };
+        tx.action = vec![from, to];
+        tx.promotion = promotion.map(String::from);
+        Ok(())
+    }
 
-        game.validate_move(&tx.action[0], &tx.action[1])?;
-        self.validate_signature(tx).await?;
+    /// Validates `tx` against the current state of its game, as of the unix
+    /// `timestamp` of the block that would carry it.
+    pub async fn is_valid_tx(&self, tx: &Transaction, timestamp: i64) -> Result<(), AppError> {
+        // Governance goes through while halted, or nothing could resume.
+        if let Some(r) = tx.governance_request()? {
+            let consensus = self.consensus.read().await;
+            return self
+                .validate_governance(r, &consensus, self.next_height())
+                .await;
+        }
 
-        if tx.pub_key
-            != match Color::from_i32(game.turn).expect("correct color") {
-                Color::White => game.white_player,
-                Color::Black => game.black_player,
+        if self.is_halted().await {
+            return Err(AppError::HaltedError);
+        }
+
+        if let Some(r) = tx.start_request()? {
+            // With a cap, a game takes up its players' slots, so only they
+            // may start it. Seeks carry both players' signatures already.
+            if self.max_games_per_player.is_some() {
+                r.validate_signatures()?;
             }
+            return self.validate_start(tx, r).await;
+        }
+        if let Some(r) = tx.seek_acceptance()? {
+            return self.validate_acceptance(tx, r, timestamp).await;
+        }
+        if let Some(seek) = tx.seek_creation()? {
+            return self.validate_seek_creation(tx, seek, timestamp).await;
+        }
+        if let Some(r) = tx.seek_cancellation()? {
+            return self.validate_seek_cancellation(tx, r, timestamp).await;
+        }
+
+        // Signed without a game id, an ending would also end the players'
+        // next game.
+        if TransactionKind::from_i32(tx.kind).is_some_and(TransactionKind::is_lifecycle)
+            && tx.game_id.is_none()
         {
-            return Err(AppError::InvalidTransactionError("invalud turn".into()));
+            return Err(AppError::InvalidTransactionError(
+                "a game-ending transaction names its game".into(),
+            ));
         }
 
-        Ok(())
-    }
+        let Some(game) = self.game_of(tx).await else {
+            return Err(AppError::InvalidTransactionError("no such game".into()));
+        };
 
-    pub async fn calculate_game_state_hash(&self, tx: &Transaction) -> Result<String, AppError> {
-        let game = self
-            .db
-            .read()
-            .await
-            .get(&format!("{}:{}", tx.white_player, tx.black_player))
-            .unwrap()
-            .to_owned();
+        // Comments ride along with moves; the rules never look at them.
+        if let Some(comment) = &tx.comment {
+            if !matches!(
+                TransactionKind::from_i32(tx.kind),
+                Some(TransactionKind::Move | TransactionKind::Reveal)
+            ) {
+                return Err(AppError::InvalidTransactionError(
+                    "only moves carry comments".into(),
+                ));
+            }
+            validate_comment(comment)?;
+        }
 
-        let serialized = serde_json::to_string(&game)
-            .map_err(|e| AppError::BlockValidationError(e.to_string()))?;
+        match TransactionKind::from_i32(tx.kind) {
+            Some(TransactionKind::Move) => {
+                let (from, to) = tx.squares()?;
 
-        Ok(keccak256(serialized).to_string())
-    }
+                if game.is_dark() {
+                    return Err(AppError::InvalidTransactionError(
+                        "dark games move by commit and reveal".into(),
+                    ));
+                }
 
-    async fn validate_signature(&self, tx: &Transaction) -> Result<(), AppError> {
-        let message = serde_json::json!({
-            "whitePlayer": tx.white_player,
-            "blackPlayer": tx.black_player,
-            "action": [
-                {"x": tx.action[0].x, "y": tx.action[0].y},
-                {"x": tx.action[1].x, "y": tx.action[1].y},
-            ],
-        });
+                // The signature covers only the SAN or UCI, so the move filled
+                // in from it has to be the one it names.
+                let named = match (&tx.san, &tx.uci) {
+                    (Some(_), Some(_)) => {
+                        return Err(AppError::InvalidTransactionError(
+                            "a move is given in SAN or UCI, not both".into(),
+                        ))
+                    }
+                    (Some(san), None) => Some(game.find_san(san)?),
+                    (None, Some(uci)) => Some(parse_uci(uci)?),
+                    (None, None) => None,
+                };
+                if let Some((from, to, promotion)) = named {
+                    if tx.action != [from, to] || tx.promotion.as_deref() != promotion {
+                        return Err(AppError::InvalidTransactionError(
+                            "move does not match its notation".into(),
+                        ));
+                    }
+                }
 
-        let message_str = serde_json::to_string(&message)
-            .map_err(|e| AppError::InvalidTransactionError(e.to_string()))?;
-        let message_hash = Sha256::digest(message_str.as_bytes());
-        let message = Message::parse_slice(&message_hash)
-            .map_err(|e| AppError::InvalidTransactionError(e.to_string()))?;
-        let signature_bytes = hex::decode(&tx.signature)
-            .map_err(|e| AppError::InvalidTransactionError(e.to_string()))?;
+                self.rules_at(self.next_height()).await?.validate_move(
+                    &game,
+                    &from,
+                    &to,
+                    tx.promotion.as_deref(),
+                )?;
+                self.validate_signer(tx, &game)?;
+            }
+            Some(TransactionKind::Abort) => {
+                self.validate_signer(tx, &game)?;
+                game.validate_abort(timestamp)?;
+            }
+            Some(TransactionKind::AbortByAgreement) => {
+                game.validate_agreed_abort()?;
+                self.validate_agreement(tx, &game)?;
+            }
+            Some(TransactionKind::Takeback) => {
+                game.validate_takeback(tx.ply)?;
+                self.validate_both_sides(tx, &game)?;
+            }
+            Some(TransactionKind::Resign) => {
+                game.validate_resign()?;
+                self.resigning_side(tx, &game)?;
+            }
+            Some(TransactionKind::Commit) => {
+                tx.commitment.as_ref().ok_or_else(|| {
+                    AppError::InvalidTransactionError("commit needs a commitment".into())
+                })?;
+                game.validate_commit()?;
+                self.validate_signer(tx, &game)?;
+            }
+            Some(TransactionKind::Reveal) => {
+                let salt = tx.salt.as_deref().unwrap_or_default();
+                let (from, to) = tx.squares()?;
+                game.validate_reveal(&from, &to, salt)?;
+                let rules = self.rules_at(self.next_height()).await?;
+                rules.validate_king_safety(&game, &from, &to)?;
+                rules.promotion(&game, &from, &to, tx.promotion.as_deref())?;
+                self.validate_signer(tx, &game)?;
+            }
+            Some(
+                TransactionKind::Govern
+                | TransactionKind::Start
+                | TransactionKind::AcceptSeek
+                | TransactionKind::CreateSeek
+                | TransactionKind::CancelSeek,
+            )
+            | None => {
+                return Err(AppError::InvalidTransactionError(
+                    "unknown transaction kind".into(),
+                ))
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Runs the validation pipeline for a gossiped transaction stage by stage
+    /// and reports how long each stage took.
+    pub async fn debug_validate(&self, raw: &str) -> DebugValidateResponse {
+        let mut report = DebugValidateResponse::default();
 
-        let signature = match Signature::parse_standard_slice(&signature_bytes) {
-            Ok(sig) => sig,
+        let started = Instant::now();
+        let tx: Transaction = match serde_json::from_str(raw) {
+            Ok(tx) => tx,
             Err(e) => {
-                return Err(AppError::InvalidTransactionError(e.to_string()));
+                report.error = Some(e.to_string());
+                return report;
             }
         };
+        report.deserialize_us = started.elapsed().as_micros() as u64;
 
-        let public_key_bytes = hex::decode(&tx.pub_key)
-            .map_err(|e| AppError::InvalidTransactionError(e.to_string()))?;
-        let public_key = match PublicKey::parse_slice(&public_key_bytes, None) {
-            Ok(key) => key,
-            Err(e) => {
-                return Err(AppError::InvalidTransactionError(e.to_string()));
+        let started = Instant::now();
+        let game = self.game_of(&tx).await;
+        report.lock_us = started.elapsed().as_micros() as u64;
+
+        let game = match game {
+            Some(game) => game,
+            None => {
+                report.error = Some("no such game".into());
+                return report;
             }
         };
 
-        match verify(&message, &signature, &public_key) {
-            true => Ok(()),
-            false => Err(AppError::InvalidTransactionError(
-                "invalid signature".into(),
+        let mut first_error = |e: AppError| {
+            report.error.get_or_insert(e.to_string());
+        };
+
+        let started = Instant::now();
+        let signature = self.validate_signer(&tx, &game);
+        let signature_us = started.elapsed().as_micros() as u64;
+        if let Err(e) = signature {
+            first_error(e);
+        }
+
+        let started = Instant::now();
+        let rules = match TransactionKind::from_i32(tx.kind) {
+            Some(TransactionKind::Move) => tx
+                .squares()
+                .and_then(|(from, to)| game.validate_move(&from, &to)),
+            Some(TransactionKind::Abort) => game.validate_abort(self.now().timestamp()),
+            Some(TransactionKind::Commit) => game.validate_commit(),
+            Some(TransactionKind::Resign) => game.validate_resign(),
+            Some(TransactionKind::Takeback) => game.validate_takeback(tx.ply),
+            Some(TransactionKind::Reveal) => tx.squares().and_then(|(from, to)| {
+                game.validate_reveal(&from, &to, tx.salt.as_deref().unwrap_or_default())
+            }),
+            _ => Err(AppError::InvalidTransactionError(
+                "malformed transaction".into(),
             )),
+        };
+        let rules_us = started.elapsed().as_micros() as u64;
+        if let Err(e) = rules {
+            first_error(e);
         }
+
+        let started = Instant::now();
+        let hash = serde_json::to_string(&game).map(keccak256);
+        let hash_us = started.elapsed().as_micros() as u64;
+        if let Err(e) = hash {
+            first_error(e.into());
+        }
+
+        report.signature_us = signature_us;
+        report.rules_us = rules_us;
+        report.hash_us = hash_us;
+        report
     }
 
-    async fn is_valid_qc(&self, qc: &QuorumCertificate) -> Result<(), AppError> {
-        if let Some(res) = self.state_votes.read().await.get(&qc.block_hash).cloned() {
-            let intersection_count = res
-                .intersection(&HashSet::from_iter(qc.signature.iter().cloned()))
-                .count();
-            if intersection_count > (2 * PEERS as usize) / 3 {
-                return Ok(());
-            } else {
-                return Err(AppError::InvalidQcError);
+    /// Hash of the game `tx` is for, as the node taking it attaches it;
+    /// None for governance, which has no game.
+    pub async fn calculate_game_state_hash(
+        &self,
+        tx: &Transaction,
+    ) -> Result<Option<String>, AppError> {
+        if tx.governance_request()?.is_some() || tx.game_start()?.is_some() || tx.lists_seek()? {
+            return Ok(None);
+        }
+
+        let game = self
+            .game_of(tx)
+            .await
+            .ok_or_else(|| AppError::InvalidTransactionError("no such game".into()))?;
+
+        let serialized = serde_json::to_string(&game)?;
+
+        Ok(Some(keccak256(serialized).to_string()))
+    }
+
+    /// Checks that `tx` is authorised by the side to move, or by either side
+    /// for aborts.
+    fn validate_signer(&self, tx: &Transaction, game: &GameState) -> Result<(), AppError> {
+        match TransactionKind::from_i32(tx.kind) {
+            Some(TransactionKind::Move | TransactionKind::Commit | TransactionKind::Reveal) => {
+                let color = Color::from_i32(game.turn).expect("correct color");
+                self.validate_side(tx, game, color)
             }
+            _ => self
+                .validate_side(tx, game, Color::White)
+                .or_else(|_| self.validate_side(tx, game, Color::Black))
+                .map_err(|_| AppError::InvalidTransactionError("only players can abort".into())),
+        }
+    }
+
+    /// Checks that one side signed `tx` and the other cosigned it.
+    fn validate_both_sides(&self, tx: &Transaction, game: &GameState) -> Result<(), AppError> {
+        let agreed = |signer, cosigner| {
+            self.validate_side(tx, game, signer).is_ok()
+                && self.validate_cosigners(tx, game, cosigner).is_ok()
+        };
+
+        if agreed(Color::White, Color::Black) || agreed(Color::Black, Color::White) {
+            Ok(())
         } else {
-            Err(AppError::InvalidQcError)
+            Err(AppError::InvalidTransactionError(
+                "both players have to sign".into(),
+            ))
+        }
+    }
+
+    /// The side that signed a resignation.
+    fn resigning_side(&self, tx: &Transaction, game: &GameState) -> Result<Color, AppError> {
+        [Color::White, Color::Black]
+            .into_iter()
+            .find(|&color| self.validate_side(tx, game, color).is_ok())
+            .ok_or_else(|| AppError::InvalidTransactionError("only players can resign".into()))
+    }
+
+    /// Checks that an abort by agreement has the consent of at least one side,
+    /// and of White too once White has moved.
+    fn validate_agreement(&self, tx: &Transaction, game: &GameState) -> Result<(), AppError> {
+        let consents = |color| {
+            self.validate_side(tx, game, color).is_ok()
+                || self.validate_cosigners(tx, game, color).is_ok()
+        };
+        let white = consents(Color::White);
+
+        if !white && !consents(Color::Black) {
+            return Err(AppError::InvalidTransactionError(
+                "only players can abort".into(),
+            ));
+        }
+        if !white && !game.moves.is_empty() {
+            return Err(AppError::InvalidTransactionError(
+                "white has moved and must agree to the abort".into(),
+            ));
         }
+
+        Ok(())
     }
 
+    /// Checks that the cosignatures of `tx` authorise the side of `color`.
+    fn validate_cosigners(
+        &self,
+        tx: &Transaction,
+        game: &GameState,
+        color: Color,
+    ) -> Result<(), AppError> {
+        let message = signing_message(tx)?;
+        let signed = match game.team(color) {
+            Some(team) => has_signatures(&message, &tx.cosignatures, &team.members, team.threshold),
+            None => has_signatures(&message, &tx.cosignatures, &[game.player(color).into()], 1),
+        };
+
+        if !signed {
+            return Err(AppError::InvalidTransactionError(
+                "missing cosignatures".into(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Checks that `tx` is signed by the player of `color`, or by at least the
+    /// threshold of distinct members when that side is a team.
+    fn validate_side(
+        &self,
+        tx: &Transaction,
+        game: &GameState,
+        color: Color,
+    ) -> Result<(), AppError> {
+        let message = signing_message(tx)?;
+
+        match game.team(color) {
+            Some(team) => {
+                if !has_signatures(&message, &tx.team_signatures, &team.members, team.threshold) {
+                    return Err(AppError::InvalidTransactionError(
+                        "not enough team signatures".into(),
+                    ));
+                }
+
+                Ok(())
+            }
+            None => {
+                if tx.pub_key != game.player(color) {
+                    return Err(AppError::InvalidTransactionError("invalud turn".into()));
+                }
+
+                verify_signature(&message, &tx.signature, &tx.pub_key)
+            }
+        }
+    }
+
+    /// Counts the vote of `voter` for `hash` once, whichever paths deliver
+    /// it. Returns whether it is new.
+    pub async fn count_vote(&self, hash: B256, voter: String) -> bool {
+        let new = self.consensus.write().await.count_vote(hash, voter);
+        self.progress.notify_waiters();
+        new
+    }
+
+    async fn is_valid_qc(&self, qc: &QuorumCertificate) -> Result<(), AppError> {
+        let validators = CONNECTED_PEERS.read().await.clone();
+        self.consensus.read().await.validate_qc(qc, &validators)
+    }
+
+    /// Counts a vote sent straight to this node as leader, if a validator
+    /// in rotation signed it. Returns whether it is new.
+    pub async fn count_signed_vote(
+        &self,
+        hash: B256,
+        vote: ValidatorSignature,
+    ) -> Result<bool, AppError> {
+        if !self.is_validator(&vote.validator).await
+            || !vote.verify(&QuorumCertificate::vote_payload(&hash))
+        {
+            return Err(AppError::PeerError(format!(
+                "invalid vote from {}",
+                vote.validator
+            )));
+        }
+        let new = self.consensus.write().await.count_signed_vote(hash, vote);
+        self.progress.notify_waiters();
+        Ok(new)
+    }
+
+    /// Creates the game `r` starts, if it may start now.
     pub async fn start_game_if_possible(&self, r: StartRequest) -> Result<(), AppError> {
-        let game_key = format!("{}:{}", r.white_player, r.black_player);
-        let mut db_locked = self.db.write().await;
-        if db_locked.contains_key(&game_key) {
-            Err(AppError::StartGameError("already in game".into()))
-        } else {
-            db_locked.insert(game_key, GameState::new(r.white_player, r.black_player));
-            Ok(())
+        let mut games = self.db.write().await;
+        let game = self.check_start(&r, &games).await?;
+
+        let game_key = r.game_id();
+        self.state_hasher.write().await.update(&game_key, &game);
+        if let Some(digests) = &self.digests {
+            digests.update(&game_key, &game, r.started_at).await;
+        }
+        games.insert(game_key, Arc::new(RwLock::new(game)));
+        Ok(())
+    }
+
+    /// Checks that `r` may start its game among `games`, and returns it.
+    async fn check_start(
+        &self,
+        r: &StartRequest,
+        games: &HashMap<String, Arc<RwLock<GameState>>>,
+    ) -> Result<GameState, AppError> {
+        r.validate()?;
+        if let Some(feature) = Feature::of_variant(r.variant) {
+            self.require(feature).await?;
+        }
+        for team in r.white_team.iter().chain(r.black_team.iter()) {
+            self.require(Feature::Teams).await?;
+            team.validate()?;
+        }
+        let game = r.game()?;
+
+        if games.contains_key(&r.game_id()) {
+            return Err(AppError::StartGameError("game already started".into()));
+        }
+        if let Some(max) = self.max_games_per_player {
+            for player in [&r.white_player, &r.black_player] {
+                let mut live = 0;
+                for game in games.values() {
+                    let g = game.read().await;
+                    if !g.is_over() && (g.white_player == *player || g.black_player == *player) {
+                        live += 1;
+                    }
+                }
+                if live >= max {
+                    return Err(AppError::StartGameError(format!(
+                        "{} already plays {} games",
+                        player, max
+                    )));
+                }
+            }
+        }
+
+        Ok(game)
+    }
+
+    /// Checks a START transaction against the games as they are now. Its
+    /// players and game id, if given, have to be those of its request.
+    async fn validate_start(&self, tx: &Transaction, r: &StartRequest) -> Result<(), AppError> {
+        if (&tx.white_player, &tx.black_player) != (&r.white_player, &r.black_player)
+            || tx.game_id.as_ref().is_some_and(|id| *id != r.game_id())
+        {
+            return Err(AppError::InvalidTransactionError(
+                "a start names the game of its request".into(),
+            ));
+        }
+
+        self.check_start(r, &*self.db.read().await).await?;
+        Ok(())
+    }
+
+    /// Checks an ACCEPT_SEEK transaction as of the unix `timestamp` of the
+    /// block that would carry it: a valid acceptance of a seek committed
+    /// blocks opened and none closed, starting a game that may start.
+    async fn validate_acceptance(
+        &self,
+        tx: &Transaction,
+        r: &AcceptSeekRequest,
+        timestamp: i64,
+    ) -> Result<(), AppError> {
+        self.require(Feature::Seeks).await?;
+        let seek = r.validate(timestamp)?;
+        if self.consensus.read().await.seeks.get(&r.seek_id) != Some(seek) {
+            return Err(AppError::StartGameError("no such open seek".into()));
         }
+
+        self.validate_start(tx, &r.start_request()?).await
+    }
+
+    /// Checks a CREATE_SEEK transaction as of the unix `timestamp` of the
+    /// block that would carry it: a valid seek, listed under its creator,
+    /// that no committed block opened before.
+    async fn validate_seek_creation(
+        &self,
+        tx: &Transaction,
+        seek: &Seek,
+        timestamp: i64,
+    ) -> Result<(), AppError> {
+        self.require(Feature::Seeks).await?;
+        seek.validate(timestamp)?;
+        if tx.white_player != seek.creator
+            || tx.pub_key != seek.creator
+            || !tx.black_player.is_empty()
+        {
+            return Err(AppError::InvalidTransactionError(
+                "a seek is listed under its creator".into(),
+            ));
+        }
+
+        let id = seek.id();
+        let consensus = self.consensus.read().await;
+        if consensus.seeks.contains_key(&id) || consensus.closed_seeks.contains_key(&id) {
+            return Err(AppError::StartGameError("seek already exists".into()));
+        }
+        Ok(())
+    }
+
+    /// Checks a CANCEL_SEEK transaction as of the unix `timestamp` of the
+    /// block that would carry it: the creator's cancellation of a seek that
+    /// is open in the committed state.
+    async fn validate_seek_cancellation(
+        &self,
+        tx: &Transaction,
+        r: &CancelSeekRequest,
+        timestamp: i64,
+    ) -> Result<(), AppError> {
+        self.require(Feature::Seeks).await?;
+        let consensus = self.consensus.read().await;
+        let Some(seek) = consensus.seeks.get(&r.seek_id) else {
+            return Err(AppError::StartGameError("no such open seek".into()));
+        };
+        r.validate(seek, timestamp)?;
+        if tx.white_player != seek.creator
+            || tx.pub_key != seek.creator
+            || !tx.black_player.is_empty()
+        {
+            return Err(AppError::InvalidTransactionError(
+                "a seek is cancelled under its creator".into(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Creates the game a committed START transaction starts, as of the
+    /// block's time.
+    async fn start_committed(
+        &self,
+        r: &StartRequest,
+        block: &Block,
+    ) -> Result<(String, GameState, Option<BoardDiff>), AppError> {
+        let r = StartRequest {
+            started_at: block.timestamp,
+            ..r.clone()
+        };
+        let game_key = r.game_id();
+        self.start_game_if_possible(r)
+            .await
+            .map_err(|e| AppError::InvalidTransactionError(e.to_string()))?;
+
+        let game = self.db.get(Some(&game_key), "", "").await;
+        Ok((game_key, game.unwrap_or_default(), None))
     }
 
-    pub async fn publish(&self, topic: IdentTopic, data: String) -> Result<(), AppError> {
+    pub async fn publish(&self, topic: IdentTopic, data: Vec<u8>) -> Result<(), AppError> {
         self.swarm_tx
             .send(SwarmMessageType::Publish(topic, data))
             .await
@@ -241,17 +951,15 @@ impl App {
     }
 
     pub async fn update_view_if_needed(&self) {
-        let latest_timestamp = self.latest_timestamp.read().await.clone();
-        let current_clock = Utc::now();
-        let elapsed = current_clock.timestamp() as u64 - latest_timestamp;
+        let mut consensus = self.consensus.write().await;
+        let current_clock = self.now();
+        let elapsed = (current_clock.timestamp() as u64).saturating_sub(consensus.latest_timestamp);
 
-        if elapsed >= VIEW_N_ROT_INTERVAL
-            && self.latest_block_hash.read().await.clone() != B256::ZERO
-        {
+        if elapsed >= VIEW_N_ROT_INTERVAL && consensus.head != B256::ZERO {
             self.view_n
                 .fetch_add(1, std::sync::atomic::Ordering::Relaxed);
 
-            *self.latest_timestamp.write().await = current_clock.timestamp() as u64;
+            consensus.latest_timestamp = current_clock.timestamp() as u64;
             *CLOCK.write().await = current_clock;
 
             info!(
@@ -261,8 +969,829 @@ impl App {
         }
     }
 
+    /// Waits until this node has committed `min_height` blocks, up to
+    /// MIN_HEIGHT_WAIT. Returns whether the height was reached.
+    pub async fn wait_for_height(&self, min_height: u64) -> bool {
+        let reached = async {
+            loop {
+                let notified = self.committed.notified();
+                if self.height.load(std::sync::atomic::Ordering::Relaxed) >= min_height {
+                    return;
+                }
+                notified.await;
+            }
+        };
+
+        tokio::time::timeout(MIN_HEIGHT_WAIT, reached).await.is_ok()
+    }
+
     pub async fn get_state_hash(&self) -> B256 {
-        let db_locked = self.db.read().await;
-        keccak256(serde_json::to_string(&*db_locked).unwrap().as_bytes())
+        self.state_hasher.read().await.root()
+    }
+}
+
+/// Whether `signatures` hold valid signatures over `message` from at least
+/// `threshold` distinct `members`.
+pub(crate) fn has_signatures(
+    message: &serde_json::Value,
+    signatures: &[TeamSignature],
+    members: &[String],
+    threshold: u32,
+) -> bool {
+    let signers: HashSet<_> = signatures
+        .iter()
+        .filter(|s| {
+            members.contains(&s.pub_key)
+                && verify_signature(message, &s.signature, &s.pub_key).is_ok()
+        })
+        .map(|s| s.pub_key.as_str())
+        .collect();
+
+    signers.len() >= threshold as usize
+}
+
+/// Checks a secp256k1 signature over the sha256 of the JSON-encoded `message`,
+/// the same scheme the client uses to sign transactions.
+pub fn verify_signature(
+    message: &serde_json::Value,
+    signature: &str,
+    pub_key: &str,
+) -> Result<(), AppError> {
+    let message_str = serde_json::to_string(message)?;
+    let message_hash = Sha256::digest(message_str.as_bytes());
+    let message = Message::parse_slice(&message_hash)?;
+    let signature = Signature::parse_standard_slice(&hex::decode(signature)?)?;
+    let public_key = PublicKey::parse_slice(&hex::decode(pub_key)?, None)?;
+
+    match verify(&message, &signature, &public_key) {
+        true => Ok(()),
+        false => Err(AppError::InvalidTransactionError(
+            "invalid signature".into(),
+        )),
+    }
+}
+
+/// The message a transaction's signatures are made over, as documented on
+/// TransactionKind in query.proto.
+pub fn signing_message(tx: &Transaction) -> Result<serde_json::Value, AppError> {
+    let mut message = match TransactionKind::from_i32(tx.kind) {
+        Some(TransactionKind::Move) if tx.san.is_some() => serde_json::json!({
+            "whitePlayer": tx.white_player,
+            "blackPlayer": tx.black_player,
+            "san": tx.san,
+        }),
+        Some(TransactionKind::Move) if tx.uci.is_some() => serde_json::json!({
+            "whitePlayer": tx.white_player,
+            "blackPlayer": tx.black_player,
+            "uci": tx.uci,
+        }),
+        Some(TransactionKind::Move) if tx.action.len() != 2 => {
+            return Err(AppError::InvalidTransactionError(
+                "move needs a source and a target".into(),
+            ))
+        }
+        Some(TransactionKind::Move) => serde_json::json!({
+            "whitePlayer": tx.white_player,
+            "blackPlayer": tx.black_player,
+            "action": [
+                {"x": tx.action[0].x, "y": tx.action[0].y},
+                {"x": tx.action[1].x, "y": tx.action[1].y},
+            ],
+        }),
+        Some(TransactionKind::Commit) => serde_json::json!({
+            "whitePlayer": tx.white_player,
+            "blackPlayer": tx.black_player,
+            "kind": TransactionKind::Commit.as_str_name(),
+            "commitment": tx.commitment,
+        }),
+        Some(TransactionKind::Reveal) if tx.action.len() != 2 => {
+            return Err(AppError::InvalidTransactionError(
+                "reveal needs a source and a target".into(),
+            ))
+        }
+        Some(TransactionKind::Reveal) => serde_json::json!({
+            "whitePlayer": tx.white_player,
+            "blackPlayer": tx.black_player,
+            "kind": TransactionKind::Reveal.as_str_name(),
+            "action": [
+                {"x": tx.action[0].x, "y": tx.action[0].y},
+                {"x": tx.action[1].x, "y": tx.action[1].y},
+            ],
+            "salt": tx.salt,
+        }),
+        Some(TransactionKind::Takeback) => serde_json::json!({
+            "whitePlayer": tx.white_player,
+            "blackPlayer": tx.black_player,
+            "kind": TransactionKind::Takeback.as_str_name(),
+            "ply": tx.ply,
+        }),
+        kind => serde_json::json!({
+            "whitePlayer": tx.white_player,
+            "blackPlayer": tx.black_player,
+            "kind": kind.map(|k| k.as_str_name()),
+        }),
+    };
+
+    // A move in SAN or UCI names its promotion in the notation.
+    let notation =
+        tx.kind == TransactionKind::Move as i32 && (tx.san.is_some() || tx.uci.is_some());
+    if let Some(promotion) = tx.promotion.as_ref().filter(|_| !notation) {
+        message["promotion"] = promotion.as_str().into();
+    }
+    if let Some(game_id) = &tx.game_id {
+        message["gameId"] = game_id.as_str().into();
+    }
+    if let Some(comment) = &tx.comment {
+        message["comment"] = comment.as_str().into();
+    }
+
+    Ok(message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        chess::pgn::MAX_COMMENT,
+        pb::{game::Team, query::Position},
+    };
+    use libsecp256k1::{sign, SecretKey};
+    use tokio::sync::mpsc;
+
+    fn keypair() -> (SecretKey, String) {
+        let secret_key = SecretKey::random(&mut rand::thread_rng());
+        let public_key = PublicKey::from_secret_key(&secret_key);
+        (secret_key, hex::encode(public_key.serialize_compressed()))
+    }
+
+    fn team_signature(
+        tx: &Transaction,
+        (secret_key, pub_key): &(SecretKey, String),
+    ) -> TeamSignature {
+        let message = serde_json::to_string(&signing_message(tx).unwrap()).unwrap();
+        let hash = Sha256::digest(message.as_bytes());
+        let (signature, _) = sign(&Message::parse_slice(&hash).unwrap(), secret_key);
+
+        TeamSignature {
+            pub_key: pub_key.clone(),
+            signature: hex::encode(signature.serialize()),
+        }
+    }
+
+    #[test]
+    fn test_team_move_needs_threshold_signatures() {
+        let app = App::new(mpsc::channel(1).0);
+        let members = [keypair(), keypair(), keypair()];
+        let game = GameState::new("white team".into(), "black".into()).with_teams(
+            Some(Team {
+                members: members.iter().map(|(_, pk)| pk.clone()).collect(),
+                threshold: 2,
+            }),
+            None,
+        );
+
+        let mut tx = Transaction {
+            white_player: "white team".into(),
+            black_player: "black".into(),
+            action: vec![Position { x: 1, y: 4 }, Position { x: 3, y: 4 }],
+            ..Default::default()
+        };
+
+        tx.team_signatures = vec![team_signature(&tx, &members[0])];
+        assert!(app.validate_signer(&tx, &game).is_err());
+
+        tx.team_signatures.push(team_signature(&tx, &members[0]));
+        assert!(app.validate_signer(&tx, &game).is_err());
+
+        tx.team_signatures.push(team_signature(&tx, &members[2]));
+        assert!(app.validate_signer(&tx, &game).is_ok());
+    }
+
+    #[test]
+    fn test_abort_by_agreement_needs_white_once_it_moved() {
+        let app = App::new(mpsc::channel(1).0);
+        let (white, black) = (keypair(), keypair());
+        let mut game = GameState::new(white.1.clone(), black.1.clone());
+
+        let mut tx = Transaction {
+            white_player: white.1.clone(),
+            black_player: black.1.clone(),
+            kind: TransactionKind::AbortByAgreement as i32,
+            ..Default::default()
+        };
+        let signed = team_signature(&tx, &black);
+        (tx.pub_key, tx.signature) = (signed.pub_key, signed.signature);
+        assert!(app.validate_agreement(&tx, &game).is_ok());
+
+        game.apply_move(Position { x: 1, y: 4 }, Position { x: 3, y: 4 })
+            .unwrap();
+        assert!(app.validate_agreement(&tx, &game).is_err());
+
+        tx.cosignatures = vec![team_signature(&tx, &white)];
+        assert!(app.validate_agreement(&tx, &game).is_ok());
+        assert!(game.validate_agreed_abort().is_ok());
+
+        game.apply_move(Position { x: 6, y: 4 }, Position { x: 4, y: 4 })
+            .unwrap();
+        assert!(game.validate_agreed_abort().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_either_player_may_resign() {
+        let app = App::new(mpsc::channel(1).0);
+        let (white, black, stranger) = (keypair(), keypair(), keypair());
+        let start = StartRequest {
+            white_player: white.1.clone(),
+            black_player: black.1.clone(),
+            ..Default::default()
+        };
+        app.start_game_if_possible(start.clone()).await.unwrap();
+
+        let mut tx = Transaction {
+            white_player: white.1.clone(),
+            black_player: black.1.clone(),
+            kind: TransactionKind::Resign as i32,
+            ..Default::default()
+        };
+        // Without its game id, the resignation would fit the players' next
+        // game too.
+        let signed = team_signature(&tx, &black);
+        (tx.pub_key, tx.signature) = (signed.pub_key, signed.signature);
+        assert!(matches!(
+            app.is_valid_tx(&tx, 0).await,
+            Err(AppError::InvalidTransactionError(_))
+        ));
+
+        tx.game_id = Some(start.game_id());
+        let signed = team_signature(&tx, &stranger);
+        (tx.pub_key, tx.signature) = (signed.pub_key, signed.signature);
+        assert!(app.is_valid_tx(&tx, 0).await.is_err());
+
+        // Black resigns on White's turn.
+        let signed = team_signature(&tx, &black);
+        (tx.pub_key, tx.signature) = (signed.pub_key, signed.signature);
+        app.is_valid_tx(&tx, 0).await.unwrap();
+
+        let mut game = app.game_of(&tx).await.unwrap();
+        game.resign(app.resigning_side(&tx, &game).unwrap());
+        assert_eq!(
+            game.result.as_ref().map(|r| (r.winner, r.termination)),
+            Some((Some(Color::White as i32), Termination::Resignation as i32))
+        );
+        assert!(game.validate_resign().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_notated_moves_must_match_their_action() {
+        let app = App::new(mpsc::channel(1).0);
+        let (white, black) = (keypair(), keypair());
+        app.start_game_if_possible(StartRequest {
+            white_player: white.1.clone(),
+            black_player: black.1.clone(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let mut tx = Transaction {
+            white_player: white.1.clone(),
+            black_player: black.1.clone(),
+            san: Some("Nf3".into()),
+            ..Default::default()
+        };
+        let signed = team_signature(&tx, &white);
+        (tx.pub_key, tx.signature) = (signed.pub_key, signed.signature);
+
+        app.resolve_notation(&mut tx).await.unwrap();
+        assert_eq!(
+            tx.action,
+            [Position { x: 0, y: 6 }, Position { x: 2, y: 5 }]
+        );
+        app.is_valid_tx(&tx, 0).await.unwrap();
+
+        tx.action[1] = Position { x: 2, y: 7 };
+        assert!(app.is_valid_tx(&tx, 0).await.is_err());
+
+        tx.san = Some("Nf4".into());
+        assert!(app.resolve_notation(&mut tx).await.is_err());
+
+        tx.uci = Some("g1f3".into());
+        assert!(app.resolve_notation(&mut tx).await.is_err());
+        tx.san = None;
+        let signed = team_signature(&tx, &white);
+        (tx.pub_key, tx.signature) = (signed.pub_key, signed.signature);
+        app.resolve_notation(&mut tx).await.unwrap();
+        app.is_valid_tx(&tx, 0).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_moves_off_the_board_are_refused() {
+        let app = App::new(mpsc::channel(1).0);
+        let (white, black) = (keypair(), keypair());
+        app.start_game_if_possible(StartRequest {
+            white_player: white.1.clone(),
+            black_player: black.1.clone(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        for action in [
+            vec![Position { x: 1, y: 4 }],
+            vec![Position { x: 1, y: 4 }, Position { x: 8, y: 4 }],
+            vec![Position { x: u32::MAX, y: 0 }, Position { x: 3, y: 4 }],
+        ] {
+            let mut tx = Transaction {
+                white_player: white.1.clone(),
+                black_player: black.1.clone(),
+                action,
+                ..Default::default()
+            };
+            assert!(tx.squares().is_err());
+            if tx.action.len() == 2 {
+                let signed = team_signature(&tx, &white);
+                (tx.pub_key, tx.signature) = (signed.pub_key, signed.signature);
+            }
+            assert!(app.is_valid_tx(&tx, 0).await.is_err());
+            let raw = serde_json::to_string(&tx).unwrap();
+            assert!(app.debug_validate(&raw).await.error.is_some());
+        }
+    }
+
+    #[test]
+    fn test_proposals_carry_the_leader_signature() {
+        let local = crate::network::p2p::LOCAL_KEYS
+            .public()
+            .to_peer_id()
+            .to_string();
+        let mut block = BlockBuilder::default().with_view_n(3).build();
+        assert!(block.verify_leader(&local).is_err());
+        assert_eq!(block.proposer(), None);
+
+        block.sign_as_leader().unwrap();
+        block.verify_leader(&local).unwrap();
+        assert!(block.verify_leader("another validator").is_err());
+        // The block's own signature names who proposed it, on every node.
+        assert_eq!(block.proposer(), Some(local.as_str()));
+
+        block.view_n = 4;
+        assert!(block.verify_leader(&local).is_err());
+        assert_eq!(block.proposer(), None);
+    }
+
+    #[tokio::test]
+    async fn test_votes_count_once_per_voter() {
+        let app = App::new(mpsc::channel(1).0);
+        let hash = B256::repeat_byte(1);
+
+        assert!(app.count_vote(hash, "a".into()).await);
+        assert!(!app.count_vote(hash, "a".into()).await);
+        assert!(app.count_vote(hash, "b".into()).await);
+        assert_eq!(app.consensus.read().await.votes[&hash].len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_same_players_keep_several_games() {
+        let app = App::new(mpsc::channel(1).0);
+        let (alice, bob) = (keypair().1, keypair().1);
+        let start = |nonce| StartRequest {
+            white_player: alice.clone(),
+            black_player: bob.clone(),
+            started_at: 1000,
+            nonce,
+            ..Default::default()
+        };
+        let (first, second) = (start(7), start(3));
+
+        app.start_game_if_possible(first.clone()).await.unwrap();
+        app.start_game_if_possible(second.clone()).await.unwrap();
+        assert!(app.start_game_if_possible(first.clone()).await.is_err());
+        assert_eq!(app.db.snapshot().await.len(), 2);
+
+        // A move names its game; without an id it goes to the latest one.
+        let (key, game) = app.db.find(None, &alice, &bob).await.unwrap();
+        assert_eq!(key, first.game_id());
+        game.write()
+            .await
+            .apply_move(Position { x: 1, y: 4 }, Position { x: 3, y: 4 })
+            .unwrap();
+        for (r, moves) in [(&first, 1), (&second, 0)] {
+            let game = app.db.get(Some(&r.game_id()), &alice, &bob).await;
+            assert_eq!(game.unwrap().moves.len(), moves);
+        }
+        let id = first.game_id();
+        let swapped = app.db.get(Some(&id), &bob, &alice);
+        assert!(swapped.await.is_none());
+
+        // The id is fixed before the start commits, whenever that is.
+        let later = StartRequest {
+            started_at: 2000,
+            ..first.clone()
+        };
+        assert_eq!(later.game_id(), id);
+    }
+
+    #[tokio::test]
+    async fn test_a_move_signed_for_one_game_is_refused_in_another() {
+        let app = App::new(mpsc::channel(1).0);
+        let (white, black) = (keypair(), keypair());
+        let start = |nonce| StartRequest {
+            white_player: white.1.clone(),
+            black_player: black.1.clone(),
+            nonce,
+            ..Default::default()
+        };
+        let (first, second) = (start(1), start(2));
+        for r in [&first, &second] {
+            app.start_game_if_possible(r.clone()).await.unwrap();
+        }
+
+        let mut tx = Transaction {
+            white_player: white.1.clone(),
+            black_player: black.1.clone(),
+            action: vec![Position { x: 1, y: 4 }, Position { x: 3, y: 4 }],
+            game_id: Some(first.game_id()),
+            ..Default::default()
+        };
+        let signed = team_signature(&tx, &white);
+        (tx.pub_key, tx.signature) = (signed.pub_key, signed.signature);
+        app.is_valid_tx(&tx, 0).await.unwrap();
+
+        tx.game_id = Some(second.game_id());
+        assert!(app.is_valid_tx(&tx, 0).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_comments_are_bounded_and_signed() {
+        let app = App::new(mpsc::channel(1).0);
+        let (white, black) = (keypair(), keypair());
+        app.start_game_if_possible(StartRequest {
+            white_player: white.1.clone(),
+            black_player: black.1.clone(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+
+        let sign = |tx: &mut Transaction| {
+            let signed = team_signature(tx, &white);
+            (tx.pub_key, tx.signature) = (signed.pub_key, signed.signature);
+        };
+        let mut tx = Transaction {
+            white_player: white.1.clone(),
+            black_player: black.1.clone(),
+            action: vec![Position { x: 1, y: 4 }, Position { x: 3, y: 4 }],
+            comment: Some("!?".into()),
+            ..Default::default()
+        };
+        sign(&mut tx);
+        app.is_valid_tx(&tx, 0).await.unwrap();
+
+        // The comment is signed, so it cannot be swapped for another.
+        tx.comment = Some("??".into());
+        assert!(app.is_valid_tx(&tx, 0).await.is_err());
+
+        tx.comment = Some("x".repeat(MAX_COMMENT + 1));
+        sign(&mut tx);
+        assert!(app.is_valid_tx(&tx, 0).await.is_err());
+
+        tx.kind = TransactionKind::Abort as i32;
+        tx.comment = Some("gg".into());
+        sign(&mut tx);
+        assert!(app.is_valid_tx(&tx, 0).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_malformed_starts_are_refused() {
+        let mut app = App::new(mpsc::channel(1).0);
+        let (white, black) = (keypair().1, keypair().1);
+        let start = |white: &str, black: &str| StartRequest {
+            white_player: white.into(),
+            black_player: black.into(),
+            ..Default::default()
+        };
+
+        for bad in [
+            start(&white, &white),
+            start(&white, ""),
+            start("Alice", &black),
+            start(&white, &black[2..]),
+        ] {
+            assert!(app.start_game_if_possible(bad).await.is_err());
+        }
+        assert!(app.db.snapshot().await.is_empty());
+
+        // A team side goes by the team's name.
+        let team = Team {
+            members: vec![keypair().1, keypair().1],
+            threshold: 1,
+        };
+        app.start_game_if_possible(StartRequest {
+            white_team: Some(team),
+            ..start("Team A", &black)
+        })
+        .await
+        .unwrap();
+
+        app.max_games_per_player = Some(2);
+        app.start_game_if_possible(start(&white, &black))
+            .await
+            .unwrap();
+        let third = StartRequest {
+            nonce: 1,
+            ..start(&white, &black)
+        };
+        assert!(app.start_game_if_possible(third.clone()).await.is_err());
+
+        // Finished games do not count.
+        let (_, game) = app.db.find(None, &white, &black).await.unwrap();
+        game.write().await.finish(Termination::Aborted, None);
+        app.start_game_if_possible(third).await.unwrap();
+    }
+
+    /// A block carrying `tx` at `height`, with a QC every validator voted
+    /// for.
+    async fn certified(app: &App, tx: Transaction, height: u64) -> Block {
+        let validators: Vec<String> = (0..PEERS).map(|i| format!("validator {}", i)).collect();
+        let mut block = BlockBuilder::default()
+            .with_previous_block_hash(app.consensus.read().await.head)
+            .with_history(app.history_of(&tx).await.unwrap())
+            .with_tx(tx)
+            .with_rules_version(app.rules_version_at(height).await)
+            .with_height(height)
+            .with_timestamp(Utc::now().timestamp())
+            .build();
+        let hash = block.block_hash().unwrap();
+        for v in &validators {
+            app.count_vote(hash, v.clone()).await;
+        }
+        block.qc = Some(
+            QuorumCertificate::default()
+                .with_block_hash(hash)
+                .with_signature(validators),
+        );
+        block
+    }
+
+    #[tokio::test]
+    async fn test_blocks_commit_at_the_next_height_only() {
+        let app = App::new(mpsc::channel(1).0);
+        let (white, black) = (keypair().1, keypair().1);
+        app.start_game_if_possible(StartRequest {
+            white_player: white.clone(),
+            black_player: black.clone(),
+            ..Default::default()
+        })
+        .await
+        .unwrap();
+        let pawn_to = |to: u32| Transaction {
+            white_player: white.clone(),
+            black_player: black.clone(),
+            action: vec![Position { x: 1, y: 4 }, Position { x: to, y: 4 }],
+            ..Default::default()
+        };
+
+        let early = certified(&app, pawn_to(3), 2).await;
+        assert!(app.commit_block(early).await.is_err());
+        app.commit_block(certified(&app, pawn_to(3), 1).await)
+            .await
+            .unwrap();
+        let again = certified(&app, pawn_to(2), 1).await;
+        assert!(app.commit_block(again).await.is_err());
+        assert_eq!(app.height.load(std::sync::atomic::Ordering::Relaxed), 1);
+    }
+
+    #[tokio::test]
+    async fn test_starts_race_in_block_order() {
+        let mut app = App::new(mpsc::channel(1).0);
+        app.max_games_per_player = Some(1);
+        let alice = keypair();
+        let start = |black: (SecretKey, String)| {
+            let mut r = StartRequest {
+                white_player: alice.1.clone(),
+                black_player: black.1.clone(),
+                nonce: 1,
+                ..Default::default()
+            };
+            for player in [&alice, &black] {
+                r.signatures.push(TeamSignature {
+                    pub_key: player.1.clone(),
+                    signature: signed(&r.signing_message(), player),
+                });
+            }
+            Transaction {
+                white_player: r.white_player.clone(),
+                black_player: r.black_player.clone(),
+                kind: TransactionKind::Start as i32,
+                start: Some(r),
+                ..Default::default()
+            }
+        };
+        let (first, second) = (start(keypair()), start(keypair()));
+
+        // Nobody may take up Alice's game without her.
+        let mut forged = start(keypair());
+        forged.start.as_mut().unwrap().signatures.remove(0);
+        assert!(app.is_valid_tx(&forged, 0).await.is_err());
+
+        // Each may take Alice's one game until the other commits.
+        for tx in [&first, &second] {
+            app.is_valid_tx(tx, 0).await.unwrap();
+        }
+        let block = certified(&app, first.clone(), 1).await;
+        let started_at = block.timestamp;
+        app.commit_block(block).await.unwrap();
+
+        let id = first.start.as_ref().unwrap().game_id();
+        let game = app.db.get(Some(&id), "", "").await.unwrap();
+        assert_eq!((game.started_at, game.moves.len()), (started_at, 0));
+        assert!(app.is_valid_tx(&second, 0).await.is_err());
+        assert!(app.is_valid_tx(&first, 0).await.is_err());
+
+        // A start may only name the game of its request.
+        let mut other = start(keypair());
+        other.game_id = Some(id);
+        assert!(app.is_valid_tx(&other, 0).await.is_err());
+    }
+
+    fn signed(message: &serde_json::Value, (secret_key, _): &(SecretKey, String)) -> String {
+        let hash = Sha256::digest(serde_json::to_string(message).unwrap().as_bytes());
+        let (signature, _) = sign(&Message::parse_slice(&hash).unwrap(), secret_key);
+        hex::encode(signature.serialize())
+    }
+
+    #[tokio::test]
+    async fn test_seeks_start_games_once_accepted_in_a_block() {
+        let app = App::new(mpsc::channel(1).0);
+        let (creator, acceptor, latecomer) = (keypair(), keypair(), keypair());
+        let now = Utc::now().timestamp();
+        let seek = |time_control: &str, expires_at: i64| {
+            let mut seek = Seek {
+                creator: creator.1.clone(),
+                time_control: time_control.into(),
+                expires_at,
+                ..Default::default()
+            };
+            seek.signature = signed(&seek.signing_message(), &creator);
+            seek
+        };
+
+        // Neither a time control the node cannot read nor one open too long.
+        assert!(app.create_seek(seek("blitz", now + 60)).await.is_err());
+        assert!(app
+            .create_seek(seek("5+3", now + 2 * 60 * 60))
+            .await
+            .is_err());
+        let open = seek("5+3", now + 60);
+        let seek_id = open.id();
+        let create = app.create_seek(open).await.unwrap();
+
+        let accept = |player: &(SecretKey, String)| {
+            let message = serde_json::json!({ "seekId": seek_id, "acceptor": player.1 });
+            AcceptSeekRequest {
+                seek_id: seek_id.clone(),
+                acceptor: player.1.clone(),
+                signature: signed(&message, player),
+                ..Default::default()
+            }
+        };
+        // Open only once committed, so every node lists and accepts alike.
+        assert!(app.list_seeks().await.is_empty());
+        assert!(app.accept_seek(accept(&acceptor)).await.is_err());
+        let forged = Transaction {
+            white_player: acceptor.1.clone(),
+            pub_key: acceptor.1.clone(),
+            ..create.clone()
+        };
+        assert!(app.is_valid_tx(&forged, now).await.is_err());
+        app.is_valid_tx(&create, now).await.unwrap();
+        app.commit_block(certified(&app, create.clone(), 1).await)
+            .await
+            .unwrap();
+        assert_eq!(app.list_seeks().await[0].seek_id, seek_id);
+        assert!(app.is_valid_tx(&create, now).await.is_err());
+
+        let first = app.accept_seek(accept(&acceptor)).await.unwrap();
+        let second = app.accept_seek(accept(&latecomer)).await.unwrap();
+        app.is_valid_tx(&first, now).await.unwrap();
+        assert!(app.is_valid_tx(&first, now + 60).await.is_err());
+        assert!(app.game_of(&first).await.is_none());
+
+        let block = certified(&app, first.clone(), 2).await;
+        let started_at = block.timestamp;
+        app.commit_block(block).await.unwrap();
+
+        let game = app.game_of(&first).await.unwrap();
+        assert_eq!(game.started_at, started_at);
+        assert_eq!(game.time_control.map(|tc| tc.base), Some(300));
+        // Accepted once, the seek is gone for everyone else, and cannot be
+        // opened again.
+        assert!(app.is_valid_tx(&second, now).await.is_err());
+        assert!(app.list_seeks().await.is_empty());
+        assert!(app.is_valid_tx(&create, now).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_seeks_are_cancelled_by_their_creator() {
+        let app = App::new(mpsc::channel(1).0);
+        let (creator, acceptor) = (keypair(), keypair());
+        let now = Utc::now().timestamp();
+        let mut seek = Seek {
+            creator: creator.1.clone(),
+            expires_at: now + 60,
+            ..Default::default()
+        };
+        seek.signature = signed(&seek.signing_message(), &creator);
+        let seek_id = seek.id();
+        let create = app.create_seek(seek).await.unwrap();
+        app.commit_block(certified(&app, create, 1).await)
+            .await
+            .unwrap();
+
+        let cancel = |player: &(SecretKey, String)| {
+            let message = serde_json::json!({ "seekId": seek_id, "kind": "CANCEL_SEEK" });
+            CancelSeekRequest {
+                seek_id: seek_id.clone(),
+                signature: signed(&message, player),
+            }
+        };
+        let message = serde_json::json!({ "seekId": seek_id, "acceptor": acceptor.1 });
+        let acceptance = app
+            .accept_seek(AcceptSeekRequest {
+                seek_id: seek_id.clone(),
+                acceptor: acceptor.1.clone(),
+                signature: signed(&message, &acceptor),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+
+        assert!(app.cancel_seek(cancel(&acceptor)).await.is_err());
+        let cancellation = app.cancel_seek(cancel(&creator)).await.unwrap();
+        app.is_valid_tx(&cancellation, now).await.unwrap();
+        app.commit_block(certified(&app, cancellation.clone(), 2).await)
+            .await
+            .unwrap();
+
+        assert!(app.list_seeks().await.is_empty());
+        assert!(app.is_valid_tx(&cancellation, now).await.is_err());
+        // An acceptance made while the seek was open starts no game now.
+        assert!(app.is_valid_tx(&acceptance, now).await.is_err());
+        assert!(app
+            .commit_block(certified(&app, acceptance.clone(), 3).await)
+            .await
+            .is_err());
+        assert!(app.game_of(&acceptance).await.is_none());
+    }
+
+    #[test]
+    fn test_only_registered_validators_may_validate() {
+        let mut app = App::new(mpsc::channel(1).0);
+        assert!(!app.may_validate("anyone"));
+
+        app.validator_registry = Some(["a".to_string(), "b".to_string()].into());
+        assert!(app.may_validate("a"));
+        assert!(!app.may_validate("observer"));
+    }
+
+    #[tokio::test]
+    async fn test_hostile_view_needs_a_valid_qc() {
+        let app = App::new(mpsc::channel(1).0);
+        let validators: Vec<String> = (0..PEERS).map(|i| format!("validator {}", i)).collect();
+        let certified = |view_n: u32, signers: &[String]| {
+            let mut block = BlockBuilder::default().with_view_n(view_n).build();
+            block.qc = Some(
+                QuorumCertificate::default()
+                    .with_block_hash(block.block_hash().unwrap())
+                    .with_signature(signers.to_vec()),
+            );
+            block
+        };
+
+        let hostile = certified(1_000_000, &validators);
+        assert!(app.advance_view_past(&hostile).await.is_err());
+        assert_eq!(app.view_n.load(std::sync::atomic::Ordering::Relaxed), 0);
+
+        let block = certified(3, &validators);
+        app.consensus.write().await.votes.insert(
+            block.block_hash().unwrap(),
+            validators[..2].iter().cloned().collect(),
+        );
+        assert!(app.advance_view_past(&block).await.is_err());
+
+        app.consensus.write().await.votes.insert(
+            block.block_hash().unwrap(),
+            validators.iter().cloned().collect(),
+        );
+        app.advance_view_past(&block).await.unwrap();
+        assert_eq!(app.view_n.load(std::sync::atomic::Ordering::Relaxed), 4);
+
+        let stale = certified(1, &validators);
+        app.consensus.write().await.votes.insert(
+            stale.block_hash().unwrap(),
+            validators.iter().cloned().collect(),
+        );
+        app.advance_view_past(&stale).await.unwrap();
+        assert_eq!(app.view_n.load(std::sync::atomic::Ordering::Relaxed), 4);
     }
 }
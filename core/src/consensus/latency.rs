@@ -0,0 +1,111 @@
+use super::types::VoteLatency;
+use crate::App;
+use alloy_primitives::B256;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+/// Upper bounds of the vote latency histogram buckets, in milliseconds. The
+/// last bucket counts everything slower.
+pub const LATENCY_BUCKETS_MS: [u64; 8] = [50, 100, 250, 500, 1_000, 2_500, 5_000, 10_000];
+
+/// Proposals nobody reached a quorum on are forgotten after this long.
+const PROPOSAL_TTL: Duration = Duration::from_secs(60);
+
+impl VoteLatency {
+    pub fn from_samples(mut samples: Vec<u64>) -> Option<Self> {
+        samples.sort_unstable();
+
+        Some(Self {
+            votes: samples.len() as u32,
+            min_ms: *samples.first()?,
+            median_ms: samples[samples.len() / 2],
+            max_ms: *samples.last()?,
+        })
+    }
+}
+
+#[derive(Serialize, Default, Clone)]
+pub struct LatencyHistogram {
+    pub buckets: [u64; LATENCY_BUCKETS_MS.len() + 1],
+    pub count: u64,
+    pub sum_ms: u64,
+}
+
+impl LatencyHistogram {
+    pub fn observe(&mut self, ms: u64) {
+        let bucket = LATENCY_BUCKETS_MS
+            .iter()
+            .position(|bound| ms <= *bound)
+            .unwrap_or(LATENCY_BUCKETS_MS.len());
+
+        self.buckets[bucket] += 1;
+        self.count += 1;
+        self.sum_ms += ms;
+    }
+}
+
+/// Vote arrival times the leader keeps for its open proposals.
+#[derive(Default)]
+pub struct VoteTimer {
+    proposals: HashMap<B256, (Instant, Vec<u64>)>,
+}
+
+impl VoteTimer {
+    pub fn propose(&mut self, hash: B256) {
+        self.proposals
+            .retain(|_, (at, _)| at.elapsed() < PROPOSAL_TTL);
+        self.proposals.insert(hash, (Instant::now(), Vec::new()));
+    }
+
+    /// Records a vote on `hash`, returning its latency in milliseconds.
+    pub fn vote(&mut self, hash: &B256) -> Option<u64> {
+        let (at, samples) = self.proposals.get_mut(hash)?;
+        let ms = at.elapsed().as_millis() as u64;
+        samples.push(ms);
+        Some(ms)
+    }
+
+    pub fn finish(&mut self, hash: &B256) -> Option<VoteLatency> {
+        VoteLatency::from_samples(self.proposals.remove(hash)?.1)
+    }
+}
+
+impl App {
+    /// Times a validator's first vote on a block this node proposed.
+    pub async fn record_vote(&self, hash: &B256) {
+        if let Some(ms) = self.vote_timer.write().await.vote(hash) {
+            self.vote_latency.write().await.observe(ms);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_vote_latency_summary() {
+        assert!(VoteLatency::from_samples(Vec::new()).is_none());
+
+        let summary = VoteLatency::from_samples(vec![300, 10, 40]).unwrap();
+        assert_eq!(
+            (
+                summary.votes,
+                summary.min_ms,
+                summary.median_ms,
+                summary.max_ms
+            ),
+            (3, 10, 40, 300)
+        );
+
+        let mut histogram = LatencyHistogram::default();
+        for ms in [10, 50, 51, 60_000] {
+            histogram.observe(ms);
+        }
+        assert_eq!(histogram.buckets[0], 2);
+        assert_eq!(histogram.buckets[1], 1);
+        assert_eq!(histogram.buckets[LATENCY_BUCKETS_MS.len()], 1);
+        assert_eq!(histogram.count, 4);
+    }
+}
@@ -1,2 +1,10 @@
+pub mod feedback;
+pub mod governance;
 pub mod hotstuff;
+pub mod latency;
+pub mod participation;
+pub mod rules;
+pub mod state_hash;
 pub mod types;
+#[cfg(test)]
+mod vectors;
@@ -0,0 +1,89 @@
+//! Participation points per validator and epoch, credited as blocks commit:
+//! one for leading the view a block was proposed in and one for each vote in
+//! its QC. Every node derives them from the same committed blocks, so
+//! community networks can reward operators off-chain against figures anyone
+//! can check.
+
+use crate::{
+    pb::{consensus::QuorumCertificate, query::ValidatorPoints},
+    App,
+};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Blocks per epoch. Epoch `n` covers heights `n * EPOCH_BLOCKS` up to the
+/// next epoch's first height.
+pub const EPOCH_BLOCKS: u64 = 1_000;
+
+pub fn epoch_of(height: u64) -> u64 {
+    height / EPOCH_BLOCKS
+}
+
+#[derive(Default)]
+pub struct Participation(BTreeMap<u64, BTreeMap<String, ValidatorPoints>>);
+
+impl Participation {
+    /// Credits the block committed at `height` to its leader and voters.
+    pub fn credit(&mut self, height: u64, leader: Option<&str>, qc: &QuorumCertificate) {
+        let epoch = self.0.entry(epoch_of(height)).or_default();
+
+        if let Some(leader) = leader {
+            points(epoch, leader).proposals += 1;
+        }
+        for voter in qc.signature.iter().collect::<BTreeSet<_>>() {
+            points(epoch, voter).votes += 1;
+        }
+    }
+
+    /// Points earned in `epoch`, by validator.
+    pub fn epoch(&self, epoch: u64) -> Vec<ValidatorPoints> {
+        self.0
+            .get(&epoch)
+            .map(|points| points.values().cloned().collect())
+            .unwrap_or_default()
+    }
+}
+
+fn points<'a>(
+    epoch: &'a mut BTreeMap<String, ValidatorPoints>,
+    validator: &str,
+) -> &'a mut ValidatorPoints {
+    epoch
+        .entry(validator.to_string())
+        .or_insert_with(|| ValidatorPoints {
+            validator: validator.to_string(),
+            ..Default::default()
+        })
+}
+
+impl App {
+    pub fn current_epoch(&self) -> u64 {
+        epoch_of(self.next_height())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_credits_leaders_and_voters_per_epoch() {
+        let qc = |voters: &[&str]| {
+            QuorumCertificate::default()
+                .with_signature(voters.iter().map(|v| v.to_string()).collect())
+        };
+
+        let mut participation = Participation::default();
+        participation.credit(1, Some("a"), &qc(&["a", "b", "c", "b"]));
+        participation.credit(2, Some("b"), &qc(&["a", "b", "c"]));
+        participation.credit(EPOCH_BLOCKS, None, &qc(&["d"]));
+
+        let points = participation.epoch(0);
+        assert_eq!(points.len(), 3);
+        assert_eq!((points[0].proposals, points[0].votes), (1, 2));
+        assert_eq!((points[1].proposals, points[1].votes), (1, 2));
+        assert_eq!((points[2].proposals, points[2].votes), (0, 2));
+
+        assert_eq!(participation.epoch(1)[0].validator, "d");
+        assert!(participation.epoch(2).is_empty());
+    }
+}
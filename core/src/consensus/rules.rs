@@ -0,0 +1,419 @@
+//! Versioned game rules. Every block records the rules version in force at
+//! its height, so a rules fix activates at the same block on every node and
+//! history validated under older rules stays valid.
+
+use crate::{
+    chess::diff::BoardDiff,
+    errors::AppError,
+    pb::{
+        game::{GameState, PieceKind},
+        query::Position,
+    },
+    App,
+};
+use std::collections::BTreeMap;
+use std::sync::atomic::Ordering;
+
+/// Activation heights of the rules versions every network starts with.
+pub const GENESIS_RULES: &[(u64, u32)] = &[(0, 1)];
+
+/// Each version keeps every rule of the ones before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Rules {
+    V1,
+    /// Adds draws by insufficient material and by dead, fully blocked
+    /// positions.
+    V2,
+    /// Adds en passant.
+    V3,
+    /// Adds promotion, to a queen unless the move names another piece.
+    V4,
+    /// Ends the game on checkmate.
+    V5,
+    /// Ends the game drawn on stalemate.
+    V6,
+    /// Refuses moves that leave the mover's own king in check.
+    V7,
+}
+
+impl Rules {
+    pub fn from_version(version: u32) -> Result<Self, AppError> {
+        match version {
+            1 => Ok(Self::V1),
+            2 => Ok(Self::V2),
+            3 => Ok(Self::V3),
+            4 => Ok(Self::V4),
+            5 => Ok(Self::V5),
+            6 => Ok(Self::V6),
+            7 => Ok(Self::V7),
+            _ => Err(AppError::BlockValidationError(format!(
+                "unknown rules version {}",
+                version
+            ))),
+        }
+    }
+
+    /// Versions before V3 never leave an en passant target behind, so only
+    /// king safety tells versions apart here.
+    pub fn validate_move(
+        self,
+        game: &GameState,
+        from: &Position,
+        to: &Position,
+        promotion: Option<&str>,
+    ) -> Result<(), AppError> {
+        game.validate_move(from, to)?;
+        self.validate_king_safety(game, from, to)?;
+        self.promotion(game, from, to, promotion)?;
+        Ok(())
+    }
+
+    /// From V7, refuses a move that leaves the mover's own king in check.
+    pub fn validate_king_safety(
+        self,
+        game: &GameState,
+        from: &Position,
+        to: &Position,
+    ) -> Result<(), AppError> {
+        if self >= Self::V7 {
+            game.validate_king_safety(from, to)?;
+        }
+        Ok(())
+    }
+
+    /// The piece the move promotes to, if any.
+    pub fn promotion(
+        self,
+        game: &GameState,
+        from: &Position,
+        to: &Position,
+        requested: Option<&str>,
+    ) -> Result<Option<PieceKind>, AppError> {
+        if self < Self::V4 {
+            return match requested {
+                Some(_) => Err(AppError::InvalidTransactionError(
+                    "promotion needs rules version 4".into(),
+                )),
+                None => Ok(None),
+            };
+        }
+
+        let requested = requested
+            .map(|letter| {
+                PieceKind::from_letter(letter).ok_or_else(|| {
+                    AppError::InvalidTransactionError(format!("unknown piece {}", letter))
+                })
+            })
+            .transpose()?;
+        game.validate_promotion(from, to, requested)?;
+        Ok(game
+            .promotes(from, to)
+            .then(|| requested.unwrap_or(PieceKind::Queen)))
+    }
+
+    pub fn apply_move(
+        self,
+        game: &mut GameState,
+        from: Position,
+        to: Position,
+        promotion: Option<&str>,
+    ) -> Result<BoardDiff, AppError> {
+        self.validate_king_safety(game, &from, &to)?;
+        let promotion = self.promotion(game, &from, &to, promotion)?;
+        let diff = game.apply_promoting_move(from, to, promotion)?;
+
+        if self < Self::V3 {
+            game.en_passant = None;
+        }
+        self.adjudicate_mate(game);
+        game.adjudicate_variant();
+        if self >= Self::V2 && !game.is_over() {
+            game.adjudicate_dead_position();
+        }
+
+        Ok(diff)
+    }
+
+    /// Ends the game if the move just applied left the side to move without
+    /// a legal reply. Dark games reveal their moves outside `apply_move` and
+    /// call this directly.
+    pub fn adjudicate_mate(self, game: &mut GameState) {
+        if self >= Self::V5 {
+            game.adjudicate_checkmate();
+        }
+        if self >= Self::V6 {
+            game.adjudicate_stalemate();
+        }
+    }
+}
+
+/// Rules version by activation height.
+pub struct RulesSchedule(BTreeMap<u64, u32>);
+
+impl Default for RulesSchedule {
+    fn default() -> Self {
+        Self(GENESIS_RULES.iter().copied().collect())
+    }
+}
+
+impl RulesSchedule {
+    /// A schedule of (height, version) activations, as a genesis lists them.
+    pub fn from_activations(activations: impl IntoIterator<Item = (u64, u32)>) -> Self {
+        Self(activations.into_iter().collect())
+    }
+
+    pub fn version_at(&self, height: u64) -> u32 {
+        self.0
+            .range(..=height)
+            .next_back()
+            .map(|(_, version)| *version)
+            .unwrap_or(GENESIS_RULES[0].1)
+    }
+
+    /// Activates `version` at `height`, which must still be ahead of
+    /// `next_height` so no block already proposed changes its rules.
+    pub fn schedule(
+        &mut self,
+        height: u64,
+        version: u32,
+        next_height: u64,
+    ) -> Result<(), AppError> {
+        self.check(height, version, next_height)?;
+        self.0.insert(height, version);
+        Ok(())
+    }
+
+    /// Whether `schedule` would take the activation.
+    pub fn check(&self, height: u64, version: u32, next_height: u64) -> Result<(), AppError> {
+        Rules::from_version(version)?;
+
+        if height <= next_height {
+            return Err(AppError::InvalidTransactionError(
+                "activation height has passed".into(),
+            ));
+        }
+
+        if self.version_at(height) > version {
+            return Err(AppError::InvalidTransactionError(
+                "rules versions cannot go back".into(),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl App {
+    /// Height of the next block to be committed.
+    pub fn next_height(&self) -> u64 {
+        self.height.load(Ordering::Relaxed) + 1
+    }
+
+    pub async fn rules_version_at(&self, height: u64) -> u32 {
+        self.rules.read().await.version_at(height)
+    }
+
+    pub async fn rules_at(&self, height: u64) -> Result<Rules, AppError> {
+        Rules::from_version(self.rules_version_at(height).await)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_schedule_activates_at_height() {
+        let mut schedule = RulesSchedule([(0, 1), (20, 2)].into_iter().collect());
+        assert_eq!(schedule.version_at(19), 1);
+        assert_eq!(schedule.version_at(20), 2);
+        assert_eq!(schedule.version_at(u64::MAX), 2);
+
+        assert!(schedule.schedule(10, 1, 10).is_err());
+        assert!(schedule.schedule(30, 1, 10).is_err());
+        assert!(schedule.schedule(30, 8, 10).is_err());
+
+        schedule.schedule(15, 1, 10).unwrap();
+        assert_eq!(schedule.version_at(15), 1);
+        assert_eq!(RulesSchedule::default().version_at(100), GENESIS_RULES[0].1);
+    }
+
+    #[test]
+    fn test_en_passant_needs_v3() {
+        let (e5, d6) = (Position { x: 4, y: 4 }, Position { x: 5, y: 3 });
+        let setup = [
+            ((1, 4), (3, 4)),
+            ((6, 0), (5, 0)),
+            ((3, 4), (4, 4)),
+            ((6, 3), (4, 3)),
+        ];
+
+        for (rules, allowed) in [(Rules::V2, false), (Rules::V3, true)] {
+            let mut game = GameState::new("Alice".to_string(), "Bob".to_string());
+            for ((fx, fy), (tx, ty)) in setup {
+                rules
+                    .apply_move(
+                        &mut game,
+                        Position { x: fx, y: fy },
+                        Position { x: tx, y: ty },
+                        None,
+                    )
+                    .unwrap();
+            }
+            assert_eq!(rules.validate_move(&game, &e5, &d6, None).is_ok(), allowed);
+        }
+    }
+
+    #[test]
+    fn test_promotion_needs_v4() {
+        use crate::pb::game::{Board, Cell, Color, Piece, PieceKind, Row};
+
+        let mut rows = vec![Row::from(vec![Cell::default(); 8]); 8];
+        for (kind, color, x, y) in [
+            ("K", Color::White, 0, 4),
+            ("K", Color::Black, 7, 7),
+            ("P", Color::White, 6, 0),
+            ("R", Color::Black, 5, 7),
+        ] {
+            rows[x][y] = Cell::new(Piece::new(color, PieceKind::from_letter(kind).unwrap()));
+        }
+        let game =
+            GameState::new("Alice".to_string(), "Bob".to_string()).with_board(Board::from(rows));
+        let (a7, a8) = (Position { x: 6, y: 0 }, Position { x: 7, y: 0 });
+        let promoted = |rules: Rules, promotion| {
+            let mut game = game.clone();
+            rules
+                .apply_move(&mut game, a7.clone(), a8.clone(), promotion)
+                .map(|_| {
+                    game.board.unwrap().rows[7][0]
+                        .piece
+                        .clone()
+                        .unwrap()
+                        .kind()
+                        .letter()
+                })
+        };
+
+        assert_eq!(promoted(Rules::V3, None).unwrap(), "P");
+        assert!(promoted(Rules::V3, Some("Q")).is_err());
+        assert_eq!(promoted(Rules::V4, None).unwrap(), "Q");
+        assert_eq!(promoted(Rules::V4, Some("N")).unwrap(), "N");
+        assert!(promoted(Rules::V4, Some("K")).is_err());
+        assert!(Rules::V4
+            .validate_move(
+                &game,
+                &Position { x: 0, y: 4 },
+                &Position { x: 1, y: 4 },
+                Some("Q")
+            )
+            .is_err());
+
+        let mut game = game.clone();
+        Rules::V4.apply_move(&mut game, a7, a8, Some("R")).unwrap();
+        assert_eq!(game.moves[0].uci, "a7a8r");
+        assert_eq!(game.moves[0].san, "a8=R+");
+    }
+
+    #[test]
+    fn test_checkmate_ends_the_game_from_v5() {
+        use crate::pb::game::{Color, Termination};
+
+        // Fool's mate.
+        let moves = [
+            ((1, 5), (2, 5)),
+            ((6, 4), (4, 4)),
+            ((1, 6), (3, 6)),
+            ((7, 3), (3, 7)),
+        ];
+
+        for (rules, mated) in [(Rules::V4, false), (Rules::V5, true)] {
+            let mut game = GameState::new("Alice".to_string(), "Bob".to_string());
+            for ((fx, fy), (tx, ty)) in moves {
+                rules
+                    .apply_move(
+                        &mut game,
+                        Position { x: fx, y: fy },
+                        Position { x: tx, y: ty },
+                        None,
+                    )
+                    .unwrap();
+            }
+
+            assert_eq!(game.moves.last().unwrap().san, "Qh4#");
+            assert_eq!(game.is_over(), mated);
+            if let Some(result) = &game.result {
+                assert_eq!(result.winner, Some(Color::Black as i32));
+                assert_eq!(result.termination, Termination::Checkmate as i32);
+                assert!(rules
+                    .validate_move(
+                        &game,
+                        &Position { x: 1, y: 0 },
+                        &Position { x: 2, y: 0 },
+                        None
+                    )
+                    .is_err());
+            }
+        }
+    }
+
+    #[test]
+    fn test_king_safety_needs_v7() {
+        use crate::pb::game::{Board, Cell, Color, Piece, PieceKind, Row};
+
+        // The bishop on e2 shields its king from the rook on e8.
+        let mut rows = vec![Row::from(vec![Cell::default(); 8]); 8];
+        for (kind, color, x, y) in [
+            (PieceKind::King, Color::White, 0, 4),
+            (PieceKind::Bishop, Color::White, 1, 4),
+            (PieceKind::Rook, Color::Black, 7, 4),
+            (PieceKind::King, Color::Black, 7, 0),
+        ] {
+            rows[x][y] = Cell::new(Piece::new(color, kind));
+        }
+        let game =
+            GameState::new("Alice".to_string(), "Bob".to_string()).with_board(Board::from(rows));
+        let (e2, d3) = (Position { x: 1, y: 4 }, Position { x: 2, y: 3 });
+
+        for (rules, allowed) in [(Rules::V6, true), (Rules::V7, false)] {
+            assert_eq!(rules.validate_move(&game, &e2, &d3, None).is_ok(), allowed);
+            let mut game = game.clone();
+            let applied = rules.apply_move(&mut game, e2.clone(), d3.clone(), None);
+            assert_eq!(applied.is_ok(), allowed);
+        }
+    }
+
+    #[test]
+    fn test_stalemate_is_a_draw_from_v6() {
+        use crate::pb::game::{Board, Cell, Color, Piece, PieceKind, Row, Termination};
+
+        // Qc7 leaves the black king on a8 without a move.
+        let mut rows = vec![Row::from(vec![Cell::default(); 8]); 8];
+        for (kind, color, x, y) in [
+            (PieceKind::King, Color::White, 5, 1),
+            (PieceKind::Queen, Color::White, 5, 2),
+            (PieceKind::King, Color::Black, 7, 0),
+        ] {
+            rows[x][y] = Cell::new(Piece::new(color, kind));
+        }
+        let game =
+            GameState::new("Alice".to_string(), "Bob".to_string()).with_board(Board::from(rows));
+
+        for (rules, drawn) in [(Rules::V5, false), (Rules::V6, true)] {
+            let mut game = game.clone();
+            rules
+                .apply_move(
+                    &mut game,
+                    Position { x: 5, y: 2 },
+                    Position { x: 6, y: 2 },
+                    None,
+                )
+                .unwrap();
+
+            assert_eq!(game.is_over(), drawn);
+            if let Some(result) = &game.result {
+                assert_eq!(result.winner, None);
+                assert_eq!(result.termination, Termination::Stalemate as i32);
+            }
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use crate::pb::game::GameState;
+use alloy_primitives::B256;
+use std::collections::BTreeMap;
+
+/// Incremental hash of the whole game DB.
+///
+/// Every game is hashed on its own and the root is folded over the sorted
+/// per-game digests, so a commit only re-serializes the game it touched
+/// instead of the entire DB.
+#[derive(Default, Debug)]
+pub struct StateHasher {
+    leaves: BTreeMap<String, blake3::Hash>,
+}
+
+impl StateHasher {
+    pub fn update(&mut self, key: &str, game: &GameState) {
+        let serialized = serde_json::to_vec(game).expect("GameState is serializable");
+        self.leaves
+            .insert(key.to_owned(), blake3::hash(&serialized));
+    }
+
+    pub fn root(&self) -> B256 {
+        let mut hasher = blake3::Hasher::new();
+        for (key, leaf) in self.leaves.iter() {
+            hasher.update(&(key.len() as u64).to_le_bytes());
+            hasher.update(key.as_bytes());
+            hasher.update(leaf.as_bytes());
+        }
+        B256::from(*hasher.finalize().as_bytes())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pb::query::Position;
+    use alloy_primitives::keccak256;
+    use std::collections::HashMap;
+    use std::time::Instant;
+
+    fn games(n: usize) -> Vec<(String, GameState)> {
+        (0..n)
+            .map(|i| {
+                let (w, b) = (format!("w{}", i), format!("b{}", i));
+                (format!("{}:{}", w, b), GameState::new(w, b))
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_root_is_insertion_order_independent() {
+        let mut forward = StateHasher::default();
+        let mut backward = StateHasher::default();
+        let games = games(8);
+
+        for (key, game) in games.iter() {
+            forward.update(key, game);
+        }
+        for (key, game) in games.iter().rev() {
+            backward.update(key, game);
+        }
+
+        assert_eq!(forward.root(), backward.root());
+    }
+
+    #[test]
+    fn test_incremental_update_matches_rebuild() {
+        let mut games = games(8);
+        let mut incremental = StateHasher::default();
+        for (key, game) in games.iter() {
+            incremental.update(key, game);
+        }
+        let before = incremental.root();
+
+        games[3]
+            .1
+            .apply_move(Position { x: 1, y: 4 }, Position { x: 3, y: 4 })
+            .unwrap();
+        incremental.update(&games[3].0, &games[3].1);
+
+        let mut rebuilt = StateHasher::default();
+        for (key, game) in games.iter() {
+            rebuilt.update(key, game);
+        }
+
+        assert_ne!(before, incremental.root());
+        assert_eq!(rebuilt.root(), incremental.root());
+    }
+
+    #[test]
+    #[ignore = "benchmark, run with --ignored --nocapture"]
+    fn bench_blake3_incremental_vs_keccak_json() {
+        let games = games(10_000);
+        let db: HashMap<String, GameState> = games.iter().cloned().collect();
+        let mut hasher = StateHasher::default();
+        for (key, game) in games.iter() {
+            hasher.update(key, game);
+        }
+
+        let start = Instant::now();
+        for _ in 0..10 {
+            keccak256(serde_json::to_string(&db).unwrap().as_bytes());
+        }
+        let keccak = start.elapsed() / 10;
+
+        let start = Instant::now();
+        for (key, game) in games.iter().take(10) {
+            hasher.update(key, game);
+            hasher.root();
+        }
+        let blake = start.elapsed() / 10;
+
+        println!(
+            "keccak over JSON: {:?}, blake3 incremental: {:?}",
+            keccak, blake
+        );
+    }
+}
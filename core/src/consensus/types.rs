@@ -1,25 +1,24 @@
-use crate::pb::query::Transaction;
+use crate::chess::Square;
+use crate::errors::AppError;
+use crate::network::p2p::LOCAL_KEYS;
+pub use crate::pb::consensus::{Block, Commit, QuorumCertificate, VoteLatency};
+use crate::pb::query::{
+    AcceptSeekRequest, CancelSeekRequest, GovernanceRequest, Position, Seek, StartRequest,
+    Transaction, TransactionKind, ValidatorSignature,
+};
 use alloy_primitives::{keccak256, B256};
-use chrono::Utc;
+use libp2p::identity::PublicKey;
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Clone, Debug, Default)]
-pub struct Block {
-    pub view_n: u32,
-    pub previous_block_hash: B256,
-    pub tx: Transaction,
-    pub hash: B256,
-    pub history: String,
-    pub timestamp: i64,
-    pub qc: Option<QuorumCertificate>,
-}
-
 #[derive(Serialize, Deserialize, Default)]
 pub struct BlockBuilder {
     view_n: u32,
     previous_block_hash: B256,
     history: String,
     tx: Transaction,
+    rules_version: u32,
+    height: u64,
+    timestamp: i64,
 }
 
 impl BlockBuilder {
@@ -42,37 +41,259 @@ impl BlockBuilder {
         Self { tx, ..self }
     }
 
+    pub fn with_rules_version(self, rules_version: u32) -> Self {
+        Self {
+            rules_version,
+            ..self
+        }
+    }
+
+    pub fn with_height(self, height: u64) -> Self {
+        Self { height, ..self }
+    }
+
+    pub fn with_timestamp(self, timestamp: i64) -> Self {
+        Self { timestamp, ..self }
+    }
+
     pub fn build(self) -> Block {
         Block {
             view_n: self.view_n,
-            previous_block_hash: self.previous_block_hash,
-            tx: self.tx.clone(),
+            previous_block_hash: self.previous_block_hash.to_vec(),
+            tx: Some(self.tx.clone()),
             history: self.history.clone(),
-            timestamp: Utc::now().timestamp(),
-            hash: keccak256(&serde_json::to_string(&self).unwrap()),
+            timestamp: self.timestamp,
+            hash: keccak256(serde_json::to_string(&self).unwrap()).to_vec(),
             qc: None,
+            rules_version: self.rules_version,
+            vote_latency: None,
+            leader_signature: None,
+            height: self.height,
         }
     }
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug, Default)]
-pub struct QuorumCertificate {
-    pub block_hash: B256,
-    pub signature: Vec<String>,
+impl Block {
+    pub fn block_hash(&self) -> Result<B256, AppError> {
+        to_b256(&self.hash)
+    }
+
+    pub fn previous_hash(&self) -> Result<B256, AppError> {
+        to_b256(&self.previous_block_hash)
+    }
+
+    /// What the leader signs: the view number, big endian, then the hash.
+    pub fn leader_payload(&self) -> Vec<u8> {
+        [&self.view_n.to_be_bytes()[..], &self.hash].concat()
+    }
+
+    /// Signs the block as the leader of its view.
+    pub fn sign_as_leader(&mut self) -> Result<(), AppError> {
+        self.leader_signature = Some(ValidatorSignature::sign(&self.leader_payload())?);
+        Ok(())
+    }
+
+    /// The validator whose leader signature the block carries, if it
+    /// checks out.
+    pub fn proposer(&self) -> Option<&str> {
+        self.leader_signature
+            .as_ref()
+            .filter(|s| s.verify(&self.leader_payload()))
+            .map(|s| s.validator.as_str())
+    }
+
+    /// Checks that `leader` signed the block, whoever relayed it.
+    pub fn verify_leader(&self, leader: &str) -> Result<(), AppError> {
+        match self.proposer() {
+            Some(proposer) if proposer == leader => Ok(()),
+            _ => Err(AppError::BlockValidationError(
+                "missing or invalid leader signature".into(),
+            )),
+        }
+    }
+
+    pub fn transaction(&self) -> Result<&Transaction, AppError> {
+        self.tx
+            .as_ref()
+            .ok_or_else(|| AppError::BlockValidationError("missing transaction".into()))
+    }
 }
 
 impl QuorumCertificate {
     pub fn with_block_hash(self, block_hash: B256) -> Self {
-        Self { block_hash, ..self }
+        Self {
+            block_hash: block_hash.to_vec(),
+            ..self
+        }
     }
 
     pub fn with_signature(self, signature: Vec<String>) -> Self {
         Self { signature, ..self }
     }
+
+    pub fn with_votes(self, votes: Vec<ValidatorSignature>) -> Self {
+        Self { votes, ..self }
+    }
+
+    /// What a validator signs to vote for the block with `hash`; the prefix
+    /// keeps a vote from passing for a leader signature or the other way round.
+    pub fn vote_payload(hash: &B256) -> Vec<u8> {
+        [&b"vote:"[..], hash.as_slice()].concat()
+    }
+
+    pub fn block_hash(&self) -> Result<B256, AppError> {
+        to_b256(&self.block_hash)
+    }
+}
+
+impl Commit {
+    pub fn block(&self) -> Result<&Block, AppError> {
+        self.block
+            .as_ref()
+            .ok_or_else(|| AppError::BlockValidationError("missing block".into()))
+    }
+}
+
+impl Transaction {
+    /// Hash of the client-signed part of the transaction, i.e. without the
+    /// game state hash the receiving node attaches.
+    pub fn tx_hash(&self) -> String {
+        let tx = Transaction {
+            game_state_hash: None,
+            ..self.clone()
+        };
+        keccak256(serde_json::to_string(&tx).unwrap()).to_string()
+    }
+
+    /// The request a GOVERN transaction carries; None for every other kind,
+    /// which may not carry one.
+    pub fn governance_request(&self) -> Result<Option<&GovernanceRequest>, AppError> {
+        match (TransactionKind::from_i32(self.kind), &self.governance) {
+            (Some(TransactionKind::Govern), Some(r)) => Ok(Some(r)),
+            (Some(TransactionKind::Govern), None) => Err(AppError::InvalidTransactionError(
+                "governance needs a request".into(),
+            )),
+            (_, Some(_)) => Err(AppError::InvalidTransactionError(
+                "only governance carries a request".into(),
+            )),
+            (_, None) => Ok(None),
+        }
+    }
+
+    /// The request a START transaction carries; None for every other kind,
+    /// which may not carry one.
+    pub fn start_request(&self) -> Result<Option<&StartRequest>, AppError> {
+        match (TransactionKind::from_i32(self.kind), &self.start) {
+            (Some(TransactionKind::Start), Some(r)) => Ok(Some(r)),
+            (Some(TransactionKind::Start), None) => Err(AppError::InvalidTransactionError(
+                "a start needs a request".into(),
+            )),
+            (_, Some(_)) => Err(AppError::InvalidTransactionError(
+                "only starts carry a start request".into(),
+            )),
+            (_, None) => Ok(None),
+        }
+    }
+
+    /// The acceptance an ACCEPT_SEEK transaction carries; None for every
+    /// other kind, which may not carry one.
+    pub fn seek_acceptance(&self) -> Result<Option<&AcceptSeekRequest>, AppError> {
+        match (TransactionKind::from_i32(self.kind), &self.accept_seek) {
+            (Some(TransactionKind::AcceptSeek), Some(r)) => Ok(Some(r)),
+            (Some(TransactionKind::AcceptSeek), None) => Err(AppError::InvalidTransactionError(
+                "an acceptance needs a request".into(),
+            )),
+            (_, Some(_)) => Err(AppError::InvalidTransactionError(
+                "only acceptances carry a seek".into(),
+            )),
+            (_, None) => Ok(None),
+        }
+    }
+
+    /// The seek a CREATE_SEEK transaction opens; None for every other kind,
+    /// which may not carry one.
+    pub fn seek_creation(&self) -> Result<Option<&Seek>, AppError> {
+        match (TransactionKind::from_i32(self.kind), &self.seek) {
+            (Some(TransactionKind::CreateSeek), Some(seek)) => Ok(Some(seek)),
+            (Some(TransactionKind::CreateSeek), None) => Err(AppError::InvalidTransactionError(
+                "a seek creation needs a seek".into(),
+            )),
+            (_, Some(_)) => Err(AppError::InvalidTransactionError(
+                "only seek creations carry a seek".into(),
+            )),
+            (_, None) => Ok(None),
+        }
+    }
+
+    /// The cancellation a CANCEL_SEEK transaction carries; None for every
+    /// other kind, which may not carry one.
+    pub fn seek_cancellation(&self) -> Result<Option<&CancelSeekRequest>, AppError> {
+        match (TransactionKind::from_i32(self.kind), &self.cancel_seek) {
+            (Some(TransactionKind::CancelSeek), Some(r)) => Ok(Some(r)),
+            (Some(TransactionKind::CancelSeek), None) => Err(AppError::InvalidTransactionError(
+                "a cancellation needs a request".into(),
+            )),
+            (_, Some(_)) => Err(AppError::InvalidTransactionError(
+                "only cancellations carry a cancellation".into(),
+            )),
+            (_, None) => Ok(None),
+        }
+    }
+
+    /// Whether `self` opens or cancels a seek, which touches no game.
+    pub fn lists_seek(&self) -> Result<bool, AppError> {
+        Ok(self.seek_creation()?.is_some() || self.seek_cancellation()?.is_some())
+    }
+
+    /// The start of the game a START or ACCEPT_SEEK transaction creates.
+    pub fn game_start(&self) -> Result<Option<StartRequest>, AppError> {
+        if let Some(r) = self.seek_acceptance()? {
+            return r.start_request().map(Some);
+        }
+        Ok(self.start_request()?.cloned())
+    }
+
+    /// The source and target of a move or reveal. Both come from the network,
+    /// so they are checked to be squares on the board before anything
+    /// indexes it with them.
+    pub fn squares(&self) -> Result<(Position, Position), AppError> {
+        let [from, to] = self.action.as_slice() else {
+            return Err(AppError::InvalidTransactionError(
+                "a move needs a source and a target".into(),
+            ));
+        };
+        let (from, to) = (Square::try_from(from)?, Square::try_from(to)?);
+        Ok((from.into(), to.into()))
+    }
+}
+
+impl ValidatorSignature {
+    /// Signs `payload` with this node's libp2p key.
+    pub fn sign(payload: &[u8]) -> Result<Self, AppError> {
+        let signature = LOCAL_KEYS
+            .sign(payload)
+            .map_err(|e| AppError::PeerError(e.to_string()))?;
+
+        Ok(Self {
+            validator: LOCAL_KEYS.public().to_peer_id().to_string(),
+            public_key: hex::encode(LOCAL_KEYS.public().to_protobuf_encoding()),
+            signature: hex::encode(signature),
+        })
+    }
+
+    /// Whether the signature is over `payload` and the key is the validator's.
+    pub fn verify(&self, payload: &[u8]) -> bool {
+        let public_key = hex::decode(&self.public_key)
+            .ok()
+            .and_then(|k| PublicKey::from_protobuf_encoding(&k).ok());
+        let signature = hex::decode(&self.signature).unwrap_or_default();
+
+        public_key.is_some_and(|k| {
+            k.to_peer_id().to_string() == self.validator && k.verify(payload, &signature)
+        })
+    }
 }
 
-#[derive(Serialize, Deserialize, Clone, Debug, Default)]
-pub struct Commit {
-    pub decision: bool,
-    pub block: Block,
+fn to_b256(bytes: &[u8]) -> Result<B256, AppError> {
+    B256::try_from(bytes).map_err(|_| AppError::BlockValidationError("malformed hash".into()))
 }
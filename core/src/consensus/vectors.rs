@@ -0,0 +1,189 @@
+//! Conformance vectors: canonical encodings of what nodes sign, hash and
+//! gossip, kept in `vectors/` for other client implementations to check
+//! themselves against. The test fails if the node stops reproducing them;
+//! after an intended format change, bump VERSION and regenerate with
+//! `UPDATE_VECTORS=1 cargo test vectors`.
+
+use super::hotstuff::{signing_message, verify_signature};
+use super::types::{BlockBuilder, QuorumCertificate};
+use crate::chess::dark::commitment;
+use crate::pb::query::{
+    GovernanceAction, GovernanceRequest, Position, Transaction, TransactionKind,
+};
+use alloy_primitives::B256;
+use libsecp256k1::{sign, Message, PublicKey, SecretKey};
+use prost::Message as _;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+const VERSION: u32 = 4;
+const PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/vectors/v4.json");
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+struct Vectors {
+    version: u32,
+    /// The signing key every vector uses, hex, and its compressed public key.
+    secret_key: String,
+    public_key: String,
+    transactions: Vec<TransactionVector>,
+    blocks: Vec<BlockVector>,
+    governance: Vec<GovernanceVector>,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+struct TransactionVector {
+    name: String,
+    /// The signed transaction as clients send it, JSON encoded.
+    transaction: String,
+    /// Exact string whose sha256 is signed, and that hash as hex.
+    signing_message: String,
+    message_hash: String,
+    tx_hash: String,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+struct BlockVector {
+    name: String,
+    view_n: u32,
+    previous_block_hash: String,
+    history: String,
+    rules_version: u32,
+    height: u64,
+    /// Unix seconds, hashed with the rest.
+    timestamp: i64,
+    transaction: String,
+    hash: String,
+    /// QC over the block as gossiped, protobuf encoded, as hex.
+    qc: String,
+}
+
+#[derive(Serialize, Deserialize, PartialEq, Debug)]
+struct GovernanceVector {
+    name: String,
+    request: String,
+    /// Prost encoding validators sign, as hex.
+    payload: String,
+}
+
+fn secret_key() -> SecretKey {
+    SecretKey::parse(&[7; 32]).unwrap()
+}
+
+fn public_key() -> String {
+    hex::encode(PublicKey::from_secret_key(&secret_key()).serialize_compressed())
+}
+
+fn signed(mut tx: Transaction) -> TransactionVector {
+    let message = serde_json::to_string(&signing_message(&tx).unwrap()).unwrap();
+    let hash = Sha256::digest(message.as_bytes());
+    let (signature, _) = sign(&Message::parse_slice(&hash).unwrap(), &secret_key());
+    tx.signature = hex::encode(signature.serialize());
+
+    TransactionVector {
+        name: TransactionKind::from_i32(tx.kind)
+            .unwrap()
+            .as_str_name()
+            .to_lowercase(),
+        transaction: serde_json::to_string(&tx).unwrap(),
+        signing_message: message,
+        message_hash: hex::encode(hash),
+        tx_hash: tx.tx_hash(),
+    }
+}
+
+fn generate() -> Vectors {
+    let (from, to) = (Position { x: 1, y: 4 }, Position { x: 3, y: 4 });
+    let base = Transaction {
+        white_player: public_key(),
+        black_player: "black".into(),
+        pub_key: public_key(),
+        ..Default::default()
+    };
+
+    let transactions = vec![
+        signed(Transaction {
+            action: vec![from.clone(), to.clone()],
+            ..base.clone()
+        }),
+        signed(Transaction {
+            kind: TransactionKind::Abort as i32,
+            game_id: Some(format!("0x{}", "cd".repeat(32))),
+            ..base.clone()
+        }),
+        signed(Transaction {
+            kind: TransactionKind::Commit as i32,
+            commitment: Some(commitment(&from, &to, "salt")),
+            ..base.clone()
+        }),
+        signed(Transaction {
+            kind: TransactionKind::Reveal as i32,
+            action: vec![from, to],
+            salt: Some("salt".into()),
+            ..base
+        }),
+    ];
+
+    let tx: Transaction = serde_json::from_str(&transactions[0].transaction).unwrap();
+    let block = BlockBuilder::default()
+        .with_view_n(3)
+        .with_previous_block_hash(B256::repeat_byte(0xab))
+        .with_history("1. e4".into())
+        .with_tx(tx.clone())
+        .with_rules_version(1)
+        .with_height(12)
+        .with_timestamp(1_700_000_000)
+        .build();
+    let qc = QuorumCertificate::default()
+        .with_block_hash(block.block_hash().unwrap())
+        .with_signature(vec!["validator a".into(), "validator b".into()]);
+
+    let governance = GovernanceRequest {
+        action: GovernanceAction::ScheduleRules as i32,
+        nonce: 7,
+        rules_version: Some(2),
+        activation_height: Some(1_000),
+        ..Default::default()
+    };
+
+    Vectors {
+        version: VERSION,
+        secret_key: hex::encode(secret_key().serialize()),
+        public_key: public_key(),
+        transactions,
+        blocks: vec![BlockVector {
+            name: "move".into(),
+            view_n: block.view_n,
+            previous_block_hash: hex::encode(&block.previous_block_hash),
+            history: block.history.clone(),
+            rules_version: block.rules_version,
+            height: block.height,
+            timestamp: block.timestamp,
+            transaction: serde_json::to_string(&tx).unwrap(),
+            hash: hex::encode(&block.hash),
+            qc: hex::encode(qc.encode_to_vec()),
+        }],
+        governance: vec![GovernanceVector {
+            name: "schedule_rules".into(),
+            request: serde_json::to_string(&governance).unwrap(),
+            payload: hex::encode(governance.payload()),
+        }],
+    }
+}
+
+#[test]
+fn test_node_reproduces_vectors() {
+    let vectors = generate();
+    if std::env::var_os("UPDATE_VECTORS").is_some() {
+        let json = serde_json::to_string_pretty(&vectors).unwrap();
+        std::fs::write(PATH, json + "\n").unwrap();
+    }
+
+    let shipped: Vectors = serde_json::from_str(&std::fs::read_to_string(PATH).unwrap()).unwrap();
+    assert_eq!(vectors, shipped);
+
+    for v in &shipped.transactions {
+        let tx: Transaction = serde_json::from_str(&v.transaction).unwrap();
+        let message = signing_message(&tx).unwrap();
+        assert!(verify_signature(&message, &tx.signature, &shipped.public_key).is_ok());
+    }
+}
@@ -1,6 +1,10 @@
+use crate::pb::query::ErrorDetail;
+use prost::Message;
 use thiserror::Error;
+use tonic::{codegen::Bytes, Code, Status};
 
 #[derive(Debug, Error)]
+#[allow(clippy::enum_variant_names)]
 pub enum AppError {
     #[error("Failed to start the game: {0}")]
     StartGameError(String),
@@ -11,6 +15,12 @@ pub enum AppError {
     #[error("Invalid transaction: {0}")]
     InvalidTransactionError(String),
 
+    #[error("Malformed signature: {0}")]
+    SignatureError(#[from] libsecp256k1::Error),
+
+    #[error("Malformed hex: {0}")]
+    EncodingError(#[from] hex::FromHexError),
+
     #[error("Block validation failed: {0}")]
     BlockValidationError(String),
 
@@ -20,6 +30,9 @@ pub enum AppError {
     #[error("Quorum certificate invalid")]
     InvalidQcError,
 
+    #[error("Network is halted")]
+    HaltedError,
+
     #[error("gRPC server error: {0}")]
     GrpcServerError(String),
 
@@ -29,6 +42,108 @@ pub enum AppError {
     #[error("Swarm error: {0}")]
     SwarmError(String),
 
+    #[error("Rate limited, retry in {0}s")]
+    RateLimitError(u64),
+
+    #[error("Serialization failed: {0}")]
+    SerializationError(#[from] serde_json::Error),
+
     #[error("Unknown error")]
     UnknownError,
 }
+
+impl AppError {
+    /// Stable numeric code: 1xxx game rules, 2xxx transactions, 3xxx consensus,
+    /// 4xxx networking, 5xxx internal.
+    pub fn code(&self) -> u32 {
+        match self {
+            Self::StartGameError(_) => 1001,
+            Self::InternalGameError(_) => 1002,
+            Self::InvalidTransactionError(_) => 2001,
+            Self::SignatureError(_) => 2002,
+            Self::EncodingError(_) => 2003,
+            Self::BlockValidationError(_) => 3001,
+            Self::NoLeaderError => 3002,
+            Self::InvalidQcError => 3003,
+            Self::HaltedError => 3004,
+            Self::GrpcServerError(_) => 4001,
+            Self::PeerError(_) => 4002,
+            Self::SwarmError(_) => 4003,
+            Self::RateLimitError(_) => 4004,
+            Self::SerializationError(_) => 5001,
+            Self::UnknownError => 5000,
+        }
+    }
+
+    /// Whether resubmitting the same request later may succeed.
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            Self::NoLeaderError
+                | Self::HaltedError
+                | Self::GrpcServerError(_)
+                | Self::PeerError(_)
+                | Self::SwarmError(_)
+                | Self::RateLimitError(_)
+        )
+    }
+
+    pub fn detail(&self) -> ErrorDetail {
+        ErrorDetail {
+            code: self.code(),
+            retryable: self.is_retryable(),
+        }
+    }
+
+    fn grpc_code(&self) -> Code {
+        match self {
+            Self::StartGameError(_) | Self::InternalGameError(_) => Code::FailedPrecondition,
+            Self::InvalidTransactionError(_) | Self::SignatureError(_) | Self::EncodingError(_) => {
+                Code::InvalidArgument
+            }
+            Self::BlockValidationError(_) | Self::InvalidQcError => Code::Aborted,
+            Self::NoLeaderError | Self::HaltedError | Self::PeerError(_) | Self::SwarmError(_) => {
+                Code::Unavailable
+            }
+            Self::RateLimitError(_) => Code::ResourceExhausted,
+            Self::GrpcServerError(_) | Self::SerializationError(_) => Code::Internal,
+            Self::UnknownError => Code::Unknown,
+        }
+    }
+}
+
+/// Carries the error code and retryability as an encoded `ErrorDetail` in the
+/// status details.
+impl From<AppError> for Status {
+    fn from(e: AppError) -> Self {
+        Status::with_details(
+            e.grpc_code(),
+            e.to_string(),
+            Bytes::from(e.detail().encode_to_vec()),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_carries_error_detail() {
+        let status = Status::from(AppError::RateLimitError(5));
+        let detail = ErrorDetail::decode(status.details()).unwrap();
+
+        assert_eq!(status.code(), Code::ResourceExhausted);
+        assert_eq!(detail.code, 4004);
+        assert!(detail.retryable);
+    }
+
+    #[test]
+    fn test_wrapped_errors_keep_their_source() {
+        let e = AppError::from(hex::decode("zz").unwrap_err());
+
+        assert_eq!(e.code(), 2003);
+        assert!(!e.is_retryable());
+        assert!(std::error::Error::source(&e).is_some());
+    }
+}
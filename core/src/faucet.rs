@@ -0,0 +1,56 @@
+use crate::errors::AppError;
+use libsecp256k1::{PublicKey, SecretKey};
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+use tokio::sync::Mutex;
+
+/// Minimal spacing between any two issued keypairs, regardless of client.
+const GLOBAL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Hands out throwaway keypairs on demo networks. Each client may get one
+/// keypair per `interval`, and the node as a whole one per `GLOBAL_INTERVAL`.
+pub struct Faucet {
+    interval: Duration,
+    issued: Mutex<HashMap<String, Instant>>,
+    last_issued: Mutex<Option<Instant>>,
+}
+
+impl Faucet {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            issued: Mutex::new(HashMap::new()),
+            last_issued: Mutex::new(None),
+        }
+    }
+
+    pub async fn issue(&self, client: String) -> Result<(SecretKey, PublicKey), AppError> {
+        let now = Instant::now();
+        let mut issued = self.issued.lock().await;
+        let mut last_issued = self.last_issued.lock().await;
+
+        if let Some(last) = *last_issued {
+            if now.duration_since(last) < GLOBAL_INTERVAL {
+                return Err(AppError::RateLimitError(GLOBAL_INTERVAL.as_secs()));
+            }
+        }
+
+        if let Some(last) = issued.get(&client) {
+            let elapsed = now.duration_since(*last);
+            if elapsed < self.interval {
+                return Err(AppError::RateLimitError(
+                    (self.interval - elapsed).as_secs() + 1,
+                ));
+            }
+        }
+
+        issued.retain(|_, t| now.duration_since(*t) < self.interval);
+        issued.insert(client, now);
+        *last_issued = Some(now);
+
+        let secret_key = SecretKey::random(&mut rand::thread_rng());
+        let public_key = PublicKey::from_secret_key(&secret_key);
+
+        Ok((secret_key, public_key))
+    }
+}
@@ -0,0 +1,143 @@
+use crate::{errors::AppError, pb::game::Variant, App};
+use std::collections::BTreeSet;
+
+/// Parts of the protocol a network can switch off, so a conservative network
+/// runs plain chess and a playground network everything, from one binary.
+/// Standard chess is always on.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum Feature {
+    /// The dark chess variant.
+    Dark,
+    /// The Chess960 variant.
+    Chess960,
+    /// The King of the Hill variant.
+    KingOfTheHill,
+    /// The Three-Check variant.
+    ThreeCheck,
+    /// Consultation games played by k-of-n teams.
+    Teams,
+    /// The open-seek lobby.
+    Seeks,
+}
+
+impl Feature {
+    pub const ALL: [Feature; 6] = [
+        Feature::Dark,
+        Feature::Chess960,
+        Feature::KingOfTheHill,
+        Feature::ThreeCheck,
+        Feature::Teams,
+        Feature::Seeks,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Dark => "dark",
+            Self::Chess960 => "chess960",
+            Self::KingOfTheHill => "king_of_the_hill",
+            Self::ThreeCheck => "three_check",
+            Self::Teams => "teams",
+            Self::Seeks => "seeks",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|f| f.name() == name)
+    }
+
+    pub fn is_variant(self) -> bool {
+        matches!(
+            self,
+            Self::Dark | Self::Chess960 | Self::KingOfTheHill | Self::ThreeCheck
+        )
+    }
+
+    /// The flag a variant is gated by; `None` for standard chess.
+    pub fn of_variant(variant: i32) -> Option<Self> {
+        match Variant::from_i32(variant)? {
+            Variant::Standard => None,
+            Variant::Dark => Some(Self::Dark),
+            Variant::Chess960 => Some(Self::Chess960),
+            Variant::KingOfTheHill => Some(Self::KingOfTheHill),
+            Variant::ThreeCheck => Some(Self::ThreeCheck),
+        }
+    }
+}
+
+/// The enabled features. Networks start with all of them unless their
+/// genesis says otherwise.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Features(BTreeSet<Feature>);
+
+impl Default for Features {
+    fn default() -> Self {
+        Self(Feature::ALL.into_iter().collect())
+    }
+}
+
+impl Features {
+    pub fn only(features: impl IntoIterator<Item = Feature>) -> Self {
+        Self(features.into_iter().collect())
+    }
+
+    pub fn is_enabled(&self, feature: Feature) -> bool {
+        self.0.contains(&feature)
+    }
+
+    pub fn set(&mut self, feature: Feature, enabled: bool) {
+        if enabled {
+            self.0.insert(feature);
+        } else {
+            self.0.remove(&feature);
+        }
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        self.0.iter().map(|f| f.name()).collect()
+    }
+}
+
+impl App {
+    pub async fn require(&self, feature: Feature) -> Result<(), AppError> {
+        if self.features.read().await.is_enabled(feature) {
+            Ok(())
+        } else {
+            Err(AppError::StartGameError(format!(
+                "{} is disabled on this network",
+                feature.name()
+            )))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_feature_flags() {
+        let mut features = Features::only([Feature::Seeks]);
+        assert!(!features.is_enabled(Feature::Dark));
+        assert_eq!(
+            Feature::of_variant(Variant::Dark as i32),
+            Some(Feature::Dark)
+        );
+        assert_eq!(Feature::of_variant(Variant::Standard as i32), None);
+
+        features.set(Feature::Dark, true);
+        features.set(Feature::Seeks, false);
+        assert_eq!(features.names(), ["dark"]);
+        assert_eq!(
+            Features::default().names(),
+            [
+                "dark",
+                "chess960",
+                "king_of_the_hill",
+                "three_check",
+                "teams",
+                "seeks"
+            ]
+        );
+        assert_eq!(Feature::from_name("teams"), Some(Feature::Teams));
+    }
+}
@@ -0,0 +1,247 @@
+use crate::{
+    consensus::rules::{Rules, RulesSchedule},
+    features::{Feature, Features},
+    pb::{game::Variant, query::GenesisError},
+    PEERS,
+};
+use libp2p::PeerId;
+use serde::Deserialize;
+use std::collections::HashSet;
+
+/// Network launch configuration, as agreed on by the operators.
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Genesis {
+    /// Peer ids of the validators.
+    pub validators: Vec<String>,
+    /// Votes needed for a quorum certificate.
+    pub quorum: u32,
+    /// Game variants the network accepts, by name, e.g. "dark". All of them
+    /// when empty.
+    #[serde(default)]
+    pub variants: Vec<String>,
+    /// Other features to enable, e.g. "teams" or "seeks". All of them when
+    /// left out.
+    #[serde(default)]
+    pub features: Option<Vec<String>>,
+    /// Rules versions and the heights they activate at.
+    #[serde(default)]
+    pub rules: Vec<RulesActivation>,
+    /// Games a player may have going at once. No limit when left out.
+    #[serde(default)]
+    pub max_games_per_player: Option<u32>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct RulesActivation {
+    pub height: u64,
+    pub version: u32,
+}
+
+fn error(field: impl Into<String>, message: impl Into<String>) -> GenesisError {
+    GenesisError {
+        field: field.into(),
+        message: message.into(),
+    }
+}
+
+impl Genesis {
+    /// Parses and checks a genesis file, reporting every problem found.
+    pub fn validate_json(json: &str) -> Vec<GenesisError> {
+        match serde_json::from_str::<Genesis>(json) {
+            Ok(genesis) => genesis.validate(),
+            Err(e) => vec![error("", e.to_string())],
+        }
+    }
+
+    pub fn validate(&self) -> Vec<GenesisError> {
+        let mut errors = Vec::new();
+
+        let mut seen = HashSet::new();
+        for (i, validator) in self.validators.iter().enumerate() {
+            let field = format!("validators[{}]", i);
+            if validator.parse::<PeerId>().is_err() {
+                errors.push(error(&field, "not a peer id"));
+            }
+            if !seen.insert(validator) {
+                errors.push(error(&field, "duplicate validator"));
+            }
+        }
+
+        if self.validators.len() != PEERS as usize {
+            errors.push(error(
+                "validators",
+                format!(
+                    "expected {} validators, got {}",
+                    PEERS,
+                    self.validators.len()
+                ),
+            ));
+        }
+
+        let n = self.validators.len() as u32;
+        if self.quorum <= 2 * n / 3 || self.quorum > n {
+            errors.push(error(
+                "quorum",
+                format!(
+                    "must be more than 2/3 of the {} validators and at most all of them",
+                    n
+                ),
+            ));
+        }
+
+        for (i, variant) in self.variants.iter().enumerate() {
+            if Variant::from_str_name(&variant.to_uppercase()).is_none() {
+                errors.push(error(format!("variants[{}]", i), "unknown variant"));
+            }
+        }
+
+        for (i, name) in self.features.iter().flatten().enumerate() {
+            if Feature::from_name(name).is_none_or(|f| f.is_variant()) {
+                errors.push(error(format!("features[{}]", i), "unknown feature"));
+            }
+        }
+
+        if self.rules.first().is_some_and(|r| r.height != 0) {
+            errors.push(error(
+                "rules[0].height",
+                "the first rules must activate at 0",
+            ));
+        }
+
+        for (i, activation) in self.rules.iter().enumerate() {
+            if Rules::from_version(activation.version).is_err() {
+                errors.push(error(
+                    format!("rules[{}].version", i),
+                    "unknown rules version",
+                ));
+            }
+
+            if let Some(previous) = i.checked_sub(1).map(|j| &self.rules[j]) {
+                if activation.height <= previous.height {
+                    errors.push(error(
+                        format!("rules[{}].height", i),
+                        "activation heights must increase",
+                    ));
+                }
+                if activation.version < previous.version {
+                    errors.push(error(
+                        format!("rules[{}].version", i),
+                        "rules versions cannot go back",
+                    ));
+                }
+            }
+        }
+
+        if self.max_games_per_player == Some(0) {
+            errors.push(error(
+                "max_games_per_player",
+                "must allow at least one game; leave it out for no limit",
+            ));
+        }
+
+        errors
+    }
+
+    /// The feature flags the network starts with.
+    pub fn features(&self) -> Features {
+        let variants: Vec<_> = self
+            .variants
+            .iter()
+            .filter_map(|v| Variant::from_str_name(&v.to_uppercase()))
+            .filter_map(|v| Feature::of_variant(v as i32))
+            .collect();
+
+        Features::only(Feature::ALL.into_iter().filter(|f| {
+            if f.is_variant() {
+                self.variants.is_empty() || variants.contains(f)
+            } else {
+                self.features
+                    .as_ref()
+                    .is_none_or(|names| names.iter().any(|n| n == f.name()))
+            }
+        }))
+    }
+
+    pub fn rules_schedule(&self) -> RulesSchedule {
+        if self.rules.is_empty() {
+            return RulesSchedule::default();
+        }
+        RulesSchedule::from_activations(self.rules.iter().map(|r| (r.height, r.version)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn validators() -> Vec<String> {
+        (0..PEERS).map(|_| PeerId::random().to_string()).collect()
+    }
+
+    #[test]
+    fn test_valid_genesis() {
+        let json = serde_json::json!({
+            "validators": validators(),
+            "quorum": PEERS,
+            "variants": ["standard", "dark"],
+            "rules": [{"height": 0, "version": 1}],
+        });
+
+        assert!(Genesis::validate_json(&json.to_string()).is_empty());
+    }
+
+    #[test]
+    fn test_genesis_reports_every_error() {
+        let mut validators = validators();
+        validators[1] = "nope".into();
+        validators[2] = validators[0].clone();
+
+        let json = serde_json::json!({
+            "validators": validators,
+            "quorum": 2,
+            "variants": ["bughouse"],
+            "features": ["teams", "dark"],
+            "rules": [{"height": 5, "version": 1}, {"height": 5, "version": 9}],
+            "max_games_per_player": 0,
+        });
+
+        let fields: Vec<_> = Genesis::validate_json(&json.to_string())
+            .into_iter()
+            .map(|e| e.field)
+            .collect();
+        assert_eq!(
+            fields,
+            [
+                "validators[1]",
+                "validators[2]",
+                "quorum",
+                "variants[0]",
+                "features[1]",
+                "rules[0].height",
+                "rules[1].version",
+                "rules[1].height",
+                "max_games_per_player",
+            ]
+        );
+
+        assert_eq!(Genesis::validate_json("{}")[0].field, "");
+    }
+
+    #[test]
+    fn test_genesis_feature_flags() {
+        let json = serde_json::json!({
+            "validators": validators(),
+            "quorum": PEERS,
+            "variants": ["standard"],
+            "features": ["seeks"],
+        });
+        let genesis: Genesis = serde_json::from_value(json).unwrap();
+        assert_eq!(genesis.features().names(), ["seeks"]);
+
+        let json = serde_json::json!({"validators": validators(), "quorum": PEERS});
+        let genesis: Genesis = serde_json::from_value(json).unwrap();
+        assert_eq!(genesis.features(), Features::default());
+    }
+}
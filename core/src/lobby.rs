@@ -0,0 +1,384 @@
+use crate::{
+    chess::chess960::POSITIONS,
+    consensus::hotstuff::{has_signatures, verify_signature},
+    errors::AppError,
+    features::Feature,
+    pb::{
+        game::{Color, GameState, TimeControl, Variant},
+        query::{
+            AcceptSeekRequest, CancelSeekRequest, Seek, SeekEntry, StartRequest, Transaction,
+            TransactionKind,
+        },
+    },
+    App,
+};
+use alloy_primitives::keccak256;
+use libsecp256k1::PublicKey;
+
+/// Longest a seek may stay open, in seconds.
+const MAX_SEEK_TTL: i64 = 60 * 60;
+
+impl Seek {
+    pub(crate) fn signing_message(&self) -> serde_json::Value {
+        serde_json::json!({
+            "creator": self.creator,
+            "timeControl": self.time_control,
+            "variant": self.variant,
+            "color": self.color,
+            "nonce": self.nonce,
+            "expiresAt": self.expires_at,
+        })
+    }
+
+    pub fn id(&self) -> String {
+        keccak256(self.signing_message().to_string()).to_string()
+    }
+
+    /// Checks the seek as of the unix `timestamp`: signed by its creator,
+    /// open, though not for longer than MAX_SEEK_TTL, and for a game the
+    /// node can start.
+    pub fn validate(&self, timestamp: i64) -> Result<(), AppError> {
+        verify_signature(&self.signing_message(), &self.signature, &self.creator)?;
+        if self.expires_at <= timestamp {
+            return Err(AppError::StartGameError("seek expired".into()));
+        }
+        if self.expires_at > timestamp.saturating_add(MAX_SEEK_TTL) {
+            return Err(AppError::StartGameError("seek open for too long".into()));
+        }
+        self.variant()?;
+        self.time_control()?;
+        Ok(())
+    }
+
+    fn variant(&self) -> Result<Variant, AppError> {
+        if self.variant.is_empty() {
+            return Ok(Variant::Standard);
+        }
+        Variant::from_str_name(&self.variant.to_uppercase())
+            .ok_or_else(|| AppError::StartGameError(format!("unknown variant {:?}", self.variant)))
+    }
+
+    fn time_control(&self) -> Result<Option<TimeControl>, AppError> {
+        if self.time_control.trim().is_empty() {
+            return Ok(None);
+        }
+        TimeControl::parse(&self.time_control)
+            .map(Some)
+            .ok_or_else(|| {
+                AppError::StartGameError(format!("malformed time control {:?}", self.time_control))
+            })
+    }
+
+    /// Resolves the seat of the creator against `acceptor`. Random seeks, and
+    /// the start position of Chess960 ones, are decided by the seek id and
+    /// acceptor, so every node derives the same game. It starts at the time
+    /// of the block accepting the seek.
+    pub fn start_request(&self, acceptor: String) -> Result<StartRequest, AppError> {
+        let creator_is_white = match self.color.and_then(Color::from_i32) {
+            Some(Color::White) => true,
+            Some(Color::Black) => false,
+            None => keccak256(format!("{}:{}", self.id(), acceptor))[0] & 1 == 0,
+        };
+
+        let variant = self.variant()?;
+        // Drawn like the seat colours, from the seek id and acceptor.
+        let start_position = (variant == Variant::Chess960).then(|| {
+            let digest = keccak256(format!("{}:{}:position", self.id(), acceptor));
+            u32::from_be_bytes(digest[..4].try_into().unwrap()) % POSITIONS
+        });
+
+        let (white_player, black_player) = if creator_is_white {
+            (self.creator.clone(), acceptor)
+        } else {
+            (acceptor, self.creator.clone())
+        };
+
+        Ok(StartRequest {
+            white_player,
+            black_player,
+            nonce: self.nonce,
+            variant: variant as i32,
+            start_position,
+            time_control: self.time_control()?,
+            ..Default::default()
+        })
+    }
+}
+
+impl AcceptSeekRequest {
+    /// Checks the acceptance and the seek it carries as of the unix
+    /// `timestamp`, and returns the seek.
+    pub fn validate(&self, timestamp: i64) -> Result<&Seek, AppError> {
+        let seek = self
+            .seek
+            .as_ref()
+            .ok_or_else(|| AppError::StartGameError("an acceptance carries its seek".into()))?;
+        seek.validate(timestamp)?;
+        if seek.id() != self.seek_id {
+            return Err(AppError::StartGameError(
+                "the seek is not the one accepted".into(),
+            ));
+        }
+
+        let message = serde_json::json!({
+            "seekId": self.seek_id,
+            "acceptor": self.acceptor,
+        });
+        verify_signature(&message, &self.signature, &self.acceptor)?;
+
+        if seek.creator == self.acceptor {
+            return Err(AppError::StartGameError("cannot accept own seek".into()));
+        }
+        Ok(seek)
+    }
+
+    /// The start of the game accepting the seek creates.
+    pub fn start_request(&self) -> Result<StartRequest, AppError> {
+        self.seek
+            .as_ref()
+            .ok_or_else(|| AppError::StartGameError("an acceptance carries its seek".into()))?
+            .start_request(self.acceptor.clone())
+    }
+}
+
+/// Whether `key` is a secp256k1 public key in hex, as players sign with.
+fn is_public_key(key: &str) -> bool {
+    hex::decode(key).is_ok_and(|bytes| PublicKey::parse_slice(&bytes, None).is_ok())
+}
+
+impl StartRequest {
+    /// Checks the players before anything is derived from them: two
+    /// different ones, each a public key unless a team plays that side,
+    /// when it is the team's name.
+    pub fn validate(&self) -> Result<(), AppError> {
+        if self.white_player == self.black_player {
+            return Err(AppError::StartGameError(
+                "a player cannot play against itself".into(),
+            ));
+        }
+
+        for (player, team) in [
+            (&self.white_player, &self.white_team),
+            (&self.black_player, &self.black_team),
+        ] {
+            let valid = match team {
+                Some(_) => !player.trim().is_empty(),
+                None => is_public_key(player),
+            };
+            if !valid {
+                return Err(AppError::StartGameError(format!(
+                    "malformed player {:?}",
+                    player
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// What each side signs to start the game: all of the request but its
+    /// signatures and the start time, which only the commit sets.
+    pub(crate) fn signing_message(&self) -> serde_json::Value {
+        serde_json::json!({
+            "whitePlayer": self.white_player,
+            "blackPlayer": self.black_player,
+            "visibility": self.visibility,
+            "spectators": self.spectators,
+            "whiteTeam": self.white_team,
+            "blackTeam": self.black_team,
+            "variant": self.variant,
+            "nonce": self.nonce,
+            "startPosition": self.start_position,
+            "timeControl": self.time_control,
+        })
+    }
+
+    /// Checks that both sides signed the request: each player, or enough
+    /// members of a team playing a side.
+    pub fn validate_signatures(&self) -> Result<(), AppError> {
+        let message = self.signing_message();
+        for (player, team) in [
+            (&self.white_player, &self.white_team),
+            (&self.black_player, &self.black_team),
+        ] {
+            let signed = match team {
+                Some(team) => {
+                    has_signatures(&message, &self.signatures, &team.members, team.threshold)
+                }
+                None => has_signatures(&message, &self.signatures, std::slice::from_ref(player), 1),
+            };
+            if !signed {
+                return Err(AppError::StartGameError(format!(
+                    "{} did not sign the start",
+                    player
+                )));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The game this start creates.
+    pub fn game(&self) -> Result<GameState, AppError> {
+        if self.time_control.as_ref().is_some_and(|tc| tc.base == 0) {
+            return Err(AppError::StartGameError(
+                "a timed game needs some base time".into(),
+            ));
+        }
+
+        let game = GameState::new(self.white_player.clone(), self.black_player.clone())
+            .with_access(self.visibility, self.spectators.clone())
+            .with_teams(self.white_team.clone(), self.black_team.clone())
+            .with_variant(self.variant)
+            .with_started_at(self.started_at)
+            .with_start_nonce(self.nonce)
+            .with_game_id(self.game_id())
+            .with_time_control(self.time_control.clone());
+
+        match (game.is_chess960(), self.start_position) {
+            (true, Some(id)) => game.with_start_position(id),
+            (true, None) => Err(AppError::StartGameError(
+                "a chess960 game needs its start position".into(),
+            )),
+            (false, Some(_)) => Err(AppError::StartGameError(
+                "only chess960 games choose a start position".into(),
+            )),
+            (false, None) => Ok(game),
+        }
+    }
+
+    /// The id of the game this start creates: the hash of the players,
+    /// variant, nonce and start position, so the same two players can start
+    /// any number of games and every node derives the same id for each. The
+    /// start time is left out, since it is only known once the start commits,
+    /// and so is anything added to the request later, so ids stay stable.
+    pub fn game_id(&self) -> String {
+        let fields = serde_json::json!([
+            self.white_player,
+            self.black_player,
+            self.variant,
+            self.nonce,
+            self.start_position,
+        ]);
+        keccak256(fields.to_string()).to_string()
+    }
+}
+
+impl GameState {
+    /// The start request that created this game, as far as the game keeps
+    /// it: all of it.
+    pub fn start_request(&self) -> StartRequest {
+        StartRequest {
+            white_player: self.white_player.clone(),
+            black_player: self.black_player.clone(),
+            visibility: self.visibility,
+            spectators: self.spectators.clone(),
+            started_at: self.started_at,
+            white_team: self.white_team.clone(),
+            black_team: self.black_team.clone(),
+            variant: self.variant,
+            nonce: self.start_nonce.unwrap_or_default(),
+            start_position: self.start_position,
+            time_control: self.time_control.clone(),
+            signatures: Vec::new(),
+        }
+    }
+}
+
+impl CancelSeekRequest {
+    pub(crate) fn signing_message(&self) -> serde_json::Value {
+        serde_json::json!({
+            "seekId": self.seek_id,
+            "kind": TransactionKind::CancelSeek.as_str_name(),
+        })
+    }
+
+    /// Checks the cancellation of `seek` as of the unix `timestamp`: signed
+    /// by its creator while it is still open.
+    pub fn validate(&self, seek: &Seek, timestamp: i64) -> Result<(), AppError> {
+        if seek.id() != self.seek_id {
+            return Err(AppError::StartGameError(
+                "the seek is not the one cancelled".into(),
+            ));
+        }
+        if seek.expires_at <= timestamp {
+            return Err(AppError::StartGameError("seek expired".into()));
+        }
+        verify_signature(&self.signing_message(), &self.signature, &seek.creator)
+    }
+}
+
+impl App {
+    /// The CREATE_SEEK transaction for `seek`. It is listed, and can be
+    /// accepted, once it commits.
+    pub async fn create_seek(&self, seek: Seek) -> Result<Transaction, AppError> {
+        self.require(Feature::Seeks).await?;
+        seek.validate(self.now().timestamp())?;
+
+        Ok(Transaction {
+            white_player: seek.creator.clone(),
+            pub_key: seek.creator.clone(),
+            kind: TransactionKind::CreateSeek as i32,
+            seek: Some(seek),
+            ..Default::default()
+        })
+    }
+
+    /// The CANCEL_SEEK transaction for `r`, closing a committed seek once it
+    /// commits itself.
+    pub async fn cancel_seek(&self, r: CancelSeekRequest) -> Result<Transaction, AppError> {
+        self.require(Feature::Seeks).await?;
+        let Some(seek) = self.consensus.read().await.seeks.get(&r.seek_id).cloned() else {
+            return Err(AppError::StartGameError("no such seek".into()));
+        };
+        r.validate(&seek, self.now().timestamp())?;
+
+        Ok(Transaction {
+            white_player: seek.creator.clone(),
+            pub_key: seek.creator,
+            kind: TransactionKind::CancelSeek as i32,
+            cancel_seek: Some(r),
+            ..Default::default()
+        })
+    }
+
+    /// The seeks committed blocks opened and that are still open.
+    pub async fn list_seeks(&self) -> Vec<SeekEntry> {
+        let now = self.now().timestamp();
+        let mut entries: Vec<_> = self
+            .consensus
+            .read()
+            .await
+            .seeks
+            .iter()
+            .filter(|(_, seek)| seek.expires_at > now)
+            .map(|(id, seek)| SeekEntry {
+                seek_id: id.clone(),
+                seek: Some(seek.clone()),
+            })
+            .collect();
+        entries.sort_by(|a, b| a.seek_id.cmp(&b.seek_id));
+        entries
+    }
+
+    /// The ACCEPT_SEEK transaction for `r`, carrying the seek from the
+    /// committed ones. The game starts, and the seek closes, once it commits.
+    pub async fn accept_seek(&self, mut r: AcceptSeekRequest) -> Result<Transaction, AppError> {
+        self.require(Feature::Seeks).await?;
+        r.seek = self.consensus.read().await.seeks.get(&r.seek_id).cloned();
+        if r.seek.is_none() {
+            return Err(AppError::StartGameError("no such seek".into()));
+        }
+        r.validate(self.now().timestamp())?;
+
+        let start = r.start_request()?;
+        Ok(Transaction {
+            white_player: start.white_player.clone(),
+            black_player: start.black_player.clone(),
+            kind: TransactionKind::AcceptSeek as i32,
+            game_id: Some(start.game_id()),
+            accept_seek: Some(r),
+            ..Default::default()
+        })
+    }
+}
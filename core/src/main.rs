@@ -1,68 +1,195 @@
+mod backup;
 mod chess;
+mod config;
 mod consensus;
 mod errors;
+mod faucet;
+mod features;
+mod genesis;
+mod lobby;
 mod network;
-use alloy_primitives::B256;
+mod schema;
+mod state;
+mod stats;
+mod watch;
+use backup::Backups;
+use chess::diff::BoardDiff;
 use chrono::{DateTime, Utc};
 use clap::{Arg, ArgAction, Command};
+use config::NodeConfig;
+use consensus::feedback::TxTracker;
+use consensus::latency::{LatencyHistogram, VoteTimer};
+use consensus::participation::Participation;
+use consensus::rules::RulesSchedule;
+use consensus::state_hash::StateHasher;
+use consensus::types::Block;
 use dotenv::dotenv;
+use faucet::Faucet;
+use features::Features;
 use futures::StreamExt;
+use genesis::Genesis;
 use libp2p::{
-    core::upgrade, mplex, noise, swarm::SwarmBuilder, tcp::TokioTcpConfig, Multiaddr, PeerId,
-    Transport,
+    core::upgrade,
+    mplex, noise,
+    swarm::{dial_opts::DialOpts, SwarmBuilder, SwarmEvent},
+    tcp::TokioTcpConfig,
+    Multiaddr, PeerId, Transport, TransportExt,
 };
+use network::access::Access;
+use network::admin;
 use network::backend::NodeServicerBuilder;
-use network::p2p::{create_behaviour, match_behaviour, LOCAL_KEYS};
-use network::utils::SwarmMessageType;
+use network::connections::ConnectionMetrics;
+use network::decoder::Decoder;
+use network::digest::Digests;
+use network::operator::OPERATOR;
+use network::p2p::{create_behaviour, match_behaviour, read_key, LOCAL_KEYS, NODE_KEY};
+use network::ratelimit::InboundLimiter;
+use network::recorder::{self, Input, Recorder};
+use network::systemd;
+use network::utils::{self, AddressFamily, GossipParams, SwarmMessageType};
+use network::versioning;
 use once_cell::sync::Lazy;
-use std::collections::{HashMap, HashSet};
+use state::{ConsensusState, Games};
+use stats::Stats;
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::error::Error;
-use std::sync::atomic::AtomicUsize;
+use std::num::NonZeroU8;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicI64, AtomicU64, AtomicUsize};
 use std::time::Duration;
-use tokio::sync::{mpsc, RwLock};
+use tokio::sync::{mpsc, Notify, RwLock};
 use tonic::transport::Server;
 use tonic_web::GrpcWebLayer;
-use tower_http::cors::{Any, CorsLayer};
+use tower::util::{option_layer, MapRequestLayer};
 use tracing::{error, info};
-use tracing_subscriber;
 
 const PEERS: u32 = 4;
 const VIEW_N_ROT_INTERVAL: u64 = 10;
+const FIRST_MOVE_TIMEOUT: i64 = 120;
+const MIN_HEIGHT_WAIT: Duration = Duration::from_secs(2);
+const MAX_CLOCK_DRIFT: i64 = 15;
 static CONNECTED_PEERS: Lazy<RwLock<Vec<String>>> = Lazy::new(|| RwLock::new(Vec::new()));
 static CLOCK: Lazy<RwLock<DateTime<Utc>>> = Lazy::new(|| RwLock::new(Utc::now()));
 
+/// Generated code, one module per proto package version. Each package also
+/// re-exports the version the node is written against.
 pub mod pb {
     pub mod game {
-        tonic::include_proto!("game");
+        pub mod v1 {
+            tonic::include_proto!("game.v1");
+        }
+        pub use v1::*;
     }
     pub mod query {
-        tonic::include_proto!("query");
+        pub mod v1 {
+            tonic::include_proto!("query.v1");
+        }
+        pub use v1::*;
+    }
+    pub mod consensus {
+        tonic::include_proto!("consensus");
     }
+    pub mod kind;
 }
 
-use pb::game::GameState;
 use pb::query::node_server::NodeServer;
+use pb::query::ValidateGenesisResponse;
 
 pub struct App {
     pub swarm_tx: mpsc::Sender<SwarmMessageType>,
-    pub db: RwLock<HashMap<String, GameState>>,
-    pub state_votes: RwLock<HashMap<B256, HashSet<String>>>,
-    pub latest_block_hash: RwLock<B256>,
-    pub latest_timestamp: RwLock<u64>,
+    /// See `state` for the order locks are taken in.
+    pub consensus: RwLock<ConsensusState>,
+    pub db: Games,
+    pub state_hasher: RwLock<StateHasher>,
+    pub peer_versions: RwLock<HashMap<String, String>>,
+    /// Gossip each peer may forward us, checked before parsing.
+    pub inbound_limiter: RwLock<InboundLimiter>,
+    pub connections: RwLock<ConnectionMetrics>,
+    /// Parses gossip off the swarm loop; without it, as in replays, gossip
+    /// is parsed and handled inline.
+    pub decoder: Option<Decoder>,
+    pub txs: RwLock<TxTracker>,
     pub view_n: AtomicUsize,
+    pub height: AtomicU64,
+    pub committed: Notify,
+    /// Woken when a pending transaction gets further: proposed or voted for.
+    pub progress: Notify,
     pub local_peer_id: Option<String>,
+    pub faucet: Option<Faucet>,
+    pub recorder: Option<Recorder>,
+    /// While replaying, when the input being replayed arrived, in
+    /// milliseconds since the epoch; 0 otherwise. See `App::now`.
+    pub replay_clock: AtomicI64,
+    #[cfg(feature = "nats")]
+    pub sink: Option<network::sink::NatsSink>,
+    pub digests: Option<Digests>,
+    /// The latest blocks with what their moves changed on the board.
+    pub recent_blocks: RwLock<VecDeque<(u64, Block, Option<BoardDiff>)>>,
+    pub rules: RwLock<RulesSchedule>,
+    /// Whether this node only relays gossip and hands out peer addresses.
+    pub seed: bool,
+    /// Whether this node sends its votes straight to the leader instead of
+    /// gossiping them.
+    pub direct_votes: bool,
+    /// Seed nodes among the connected peers; they never lead or vote.
+    pub seeds: RwLock<HashSet<String>>,
+    /// The validators the genesis names, or without one this node and the
+    /// peers given with `--peers`. No peer validates before it is set.
+    pub validator_registry: Option<HashSet<String>>,
+    /// Games a player may have going at once, as the genesis sets; no limit
+    /// without one.
+    pub max_games_per_player: Option<u32>,
+    /// Connected peers that identified as validators. Only these lead and
+    /// vote; the rest are merely routed through.
+    pub validator_peers: RwLock<HashSet<String>>,
+    pub vote_timer: RwLock<VoteTimer>,
+    pub vote_latency: RwLock<LatencyHistogram>,
+    pub gossip: RwLock<GossipParams>,
+    pub features: RwLock<Features>,
+    pub stats: RwLock<Stats>,
+    pub participation: RwLock<Participation>,
 }
 
 impl App {
     pub fn new(swarm_tx: mpsc::Sender<SwarmMessageType>) -> App {
         App {
             swarm_tx,
-            db: RwLock::new(HashMap::new()),
-            state_votes: RwLock::new(HashMap::new()),
-            latest_block_hash: RwLock::new(B256::default()),
-            latest_timestamp: RwLock::new(Utc::now().timestamp() as u64),
+            consensus: RwLock::new(ConsensusState {
+                latest_timestamp: Utc::now().timestamp() as u64,
+                ..Default::default()
+            }),
+            db: Games::default(),
+            state_hasher: RwLock::new(StateHasher::default()),
+            peer_versions: RwLock::new(HashMap::new()),
+            inbound_limiter: RwLock::new(InboundLimiter::default()),
+            connections: RwLock::new(ConnectionMetrics::default()),
+            decoder: None,
+            txs: RwLock::new(TxTracker::default()),
             view_n: AtomicUsize::new(0),
+            height: AtomicU64::new(0),
+            committed: Notify::new(),
+            progress: Notify::new(),
             local_peer_id: None,
+            faucet: None,
+            recorder: None,
+            replay_clock: AtomicI64::new(0),
+            #[cfg(feature = "nats")]
+            sink: None,
+            digests: None,
+            recent_blocks: RwLock::new(VecDeque::new()),
+            rules: RwLock::new(RulesSchedule::default()),
+            seed: false,
+            direct_votes: false,
+            seeds: RwLock::new(HashSet::new()),
+            validator_registry: None,
+            max_games_per_player: None,
+            validator_peers: RwLock::new(HashSet::new()),
+            vote_timer: RwLock::new(VoteTimer::default()),
+            vote_latency: RwLock::new(LatencyHistogram::default()),
+            gossip: RwLock::new(GossipParams::for_validators(PEERS as usize)),
+            features: RwLock::new(Features::default()),
+            stats: RwLock::new(Stats::default()),
+            participation: RwLock::new(Participation::default()),
         }
     }
 }
@@ -72,27 +199,7 @@ async fn main() -> Result<(), Box<dyn Error>> {
     dotenv().ok();
     tracing_subscriber::fmt::init();
 
-    let local_peer_id = LOCAL_KEYS.public().to_peer_id();
-
-    let noise_keys = noise::Keypair::<noise::X25519Spec>::new()
-        .into_authentic(&LOCAL_KEYS)
-        .expect("Signing libp2p-noise static DH keypair failed.");
-
-    let transport = TokioTcpConfig::new()
-        .upgrade(upgrade::Version::V1)
-        .authenticate(noise::NoiseConfig::xx(noise_keys).into_authenticated())
-        .multiplex(mplex::MplexConfig::new())
-        .boxed();
-
-    let mut swarm = Box::new(
-        SwarmBuilder::new(transport, create_behaviour().await?, local_peer_id.clone())
-            .executor(Box::new(|fut| {
-                tokio::spawn(fut);
-            }))
-            .build(),
-    );
-
-    let matches = Command::new("Chess Network Node")
+    let command = Command::new("Chess Network Node")
         .arg(
             Arg::new("peers")
                 .short('p')
@@ -110,81 +217,546 @@ async fn main() -> Result<(), Box<dyn Error>> {
                 .default_value("50050")
                 .action(ArgAction::Set),
         )
-        .get_matches();
+        .arg(
+            Arg::new("p2p-port")
+                .long("p2p-port")
+                .help("Listen for peers on this port instead of a free one")
+                .default_value("0")
+                .value_parser(clap::value_parser!(u16))
+                .action(ArgAction::Set),
+        )
+        .arg(
+            Arg::new("key")
+                .long("key")
+                .help("Use the ed25519 key in this file, 64 hex characters, as the node's identity")
+                .value_name("FILE")
+                .action(ArgAction::Set),
+        )
+        .arg(
+            Arg::new("faucet")
+                .long("faucet")
+                .help("Serve throwaway demo keypairs over gRPC")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("faucet-interval")
+                .long("faucet-interval")
+                .help("Seconds a client must wait between demo keypairs")
+                .default_value("3600")
+                .value_parser(clap::value_parser!(u64))
+                .action(ArgAction::Set),
+        )
+        .arg(
+            Arg::new("admin-port")
+                .long("admin-port")
+                .help("Serve the read-only admin dashboard on this port")
+                .value_parser(clap::value_parser!(u16))
+                .action(ArgAction::Set),
+        )
+        .arg(
+            Arg::new("address-family")
+                .long("address-family")
+                .help("Listen on and dial only these address families: ipv4, ipv6 or dual")
+                .default_value("dual")
+                .value_parser(clap::value_parser!(AddressFamily))
+                .action(ArgAction::Set),
+        )
+        .arg(
+            Arg::new("seed")
+                .long("seed")
+                .help("Run as a seed node: relay gossip and hand out validator addresses without validating")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("direct-votes")
+                .long("direct-votes")
+                .help("Send votes straight to the leader instead of gossiping them to every validator")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("genesis")
+                .long("genesis")
+                .help("Start from this genesis file's rules versions and feature flags")
+                .value_name("FILE")
+                .action(ArgAction::Set),
+        )
+        .arg(
+            Arg::new("config")
+                .long("config")
+                .help("Read this node's deployment settings, such as CORS origins, from this JSON file")
+                .value_name("FILE")
+                .action(ArgAction::Set),
+        )
+        .arg(
+            Arg::new("validate-genesis")
+                .long("validate-genesis")
+                .help("Check a genesis file, print the errors as JSON and exit")
+                .value_name("FILE")
+                .action(ArgAction::Set),
+        )
+        .arg(
+            Arg::new("restored")
+                .long("restored")
+                .help("Start from the games `restore` wrote to this directory")
+                .value_name("DIR")
+                .action(ArgAction::Set),
+        )
+        .arg(
+            Arg::new("record")
+                .long("record")
+                .help("Append every gossip message and gRPC request to this file")
+                .value_name("FILE")
+                .action(ArgAction::Set),
+        )
+        .arg(
+            Arg::new("replay")
+                .long("replay")
+                .help("Feed a recording back through the handlers, on its clock, and exit")
+                .value_name("FILE")
+                .conflicts_with("record")
+                .action(ArgAction::Set),
+        )
+        .subcommand(
+            Command::new("watch")
+                .about("Follow a game's board and moves live from a node")
+                .arg(
+                    Arg::new("game")
+                        .long("game")
+                        .help("The game to watch, by its id or by its players for their latest")
+                        .value_name("GAME_ID|WHITE:BLACK")
+                        .required(true)
+                        .action(ArgAction::Set),
+                )
+                .arg(
+                    Arg::new("endpoint")
+                        .long("endpoint")
+                        .help("gRPC address of the node to watch from")
+                        .default_value("http://127.0.0.1:50050")
+                        .action(ArgAction::Set),
+                )
+                .arg(
+                    Arg::new("unicode")
+                        .long("unicode")
+                        .help("Draw the pieces as Unicode chess glyphs")
+                        .action(ArgAction::SetTrue),
+                ),
+        )
+        .subcommand(
+            Command::new("board")
+                .about("Print a game's board as each of several nodes has it")
+                .arg(
+                    Arg::new("game")
+                        .long("game")
+                        .help("The game to print, by its id or by its players for their latest")
+                        .value_name("GAME_ID|WHITE:BLACK")
+                        .required(true)
+                        .action(ArgAction::Set),
+                )
+                .arg(
+                    Arg::new("endpoint")
+                        .long("endpoint")
+                        .help("gRPC address of a node to ask; repeat to compare nodes")
+                        .default_value("http://127.0.0.1:50050")
+                        .action(ArgAction::Append),
+                )
+                .arg(
+                    Arg::new("unicode")
+                        .long("unicode")
+                        .help("Draw the pieces as Unicode chess glyphs")
+                        .action(ArgAction::SetTrue),
+                ),
+        )
+
+        .subcommand(
+            Command::new("restore")
+                .about("Decrypt a backup into a directory")
+                .arg(
+                    Arg::new("backup")
+                        .long("backup")
+                        .help("The backup file to restore")
+                        .value_name("FILE")
+                        .required(true)
+                        .action(ArgAction::Set),
+                )
+                .arg(
+                    Arg::new("key-file")
+                        .long("key-file")
+                        .help("The backups' encryption key, 64 hex characters")
+                        .value_name("FILE")
+                        .required(true)
+                        .action(ArgAction::Set),
+                )
+                .arg(
+                    Arg::new("to")
+                        .long("to")
+                        .help("Write games.json, recording.jsonl and node.key here")
+                        .value_name("DIR")
+                        .required(true)
+                        .action(ArgAction::Set),
+                ),
+        );
+
+    #[cfg(feature = "nats")]
+    let command = command
+        .arg(
+            Arg::new("nats")
+                .long("nats")
+                .help("Publish committed blocks and games to this NATS server")
+                .value_name("URL")
+                .action(ArgAction::Set),
+        )
+        .arg(
+            Arg::new("nats-subject")
+                .long("nats-subject")
+                .help("Subject for an event kind, e.g. block=chess.blocks or game=chess.games")
+                .value_name("KIND=SUBJECT")
+                .requires("nats")
+                .action(ArgAction::Append),
+        );
+
+    let matches = command.get_matches();
+
+    match matches.subcommand() {
+        Some(("watch", args)) => {
+            let endpoint = args.get_one::<String>("endpoint").unwrap().clone();
+            let game = args.get_one::<String>("game").unwrap();
+            return watch::run(endpoint, game, args.get_flag("unicode")).await;
+        }
+        Some(("board", args)) => {
+            let endpoints = args
+                .get_many::<String>("endpoint")
+                .unwrap()
+                .cloned()
+                .collect();
+            let game = args.get_one::<String>("game").unwrap();
+            return watch::show(endpoints, game, args.get_flag("unicode")).await;
+        }
+        Some(("restore", args)) => {
+            let arg = |name| Path::new(args.get_one::<String>(name).unwrap());
+            let snapshot = backup::restore(arg("backup"), arg("key-file"), arg("to"))
+                .map_err(|e| e as Box<dyn Error>)?;
+            println!(
+                "Restored {} games at height {}, backed up at {}",
+                snapshot.games.len(),
+                snapshot.height,
+                snapshot.created_at
+            );
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    if let Some(path) = matches.get_one::<String>("validate-genesis") {
+        let errors = Genesis::validate_json(&std::fs::read_to_string(path)?);
+        let ok = errors.is_empty();
+        println!(
+            "{}",
+            serde_json::to_string(&ValidateGenesisResponse { ok, errors })?
+        );
+        std::process::exit(if ok { 0 } else { 1 });
+    }
+
+    let config = match matches.get_one::<String>("config") {
+        Some(path) => NodeConfig::from_file(path)?,
+        None => NodeConfig::default(),
+    };
+    if let Some(operator) = &config.operator {
+        OPERATOR
+            .set(operator.clone())
+            .expect("the operator is set before identify announces it");
+    }
+
+    if let Some(path) = matches.get_one::<String>("key") {
+        NODE_KEY
+            .set(read_key(path)?)
+            .expect("the key is set before anything signs");
+    }
+    let local_peer_id = LOCAL_KEYS.public().to_peer_id();
+
+    let noise_keys = noise::Keypair::<noise::X25519Spec>::new()
+        .into_authentic(&LOCAL_KEYS)
+        .expect("Signing libp2p-noise static DH keypair failed.");
+
+    let (tcp, bandwidth) = TokioTcpConfig::new().with_bandwidth_logging();
+    let transport = tcp
+        .upgrade(upgrade::Version::V1)
+        .authenticate(noise::NoiseConfig::xx(noise_keys).into_authenticated())
+        .multiplex(mplex::MplexConfig::new())
+        .boxed();
+
+    let mut swarm = Box::new(
+        SwarmBuilder::new(
+            transport,
+            create_behaviour(matches.get_flag("seed")).await?,
+            local_peer_id,
+        )
+        .dial_concurrency_factor(NonZeroU8::new(2).unwrap())
+        .executor(Box::new(|fut| {
+            tokio::spawn(fut);
+        }))
+        .build(),
+    );
 
-    if let Some(peers) = matches.get_many::<String>("peers") {
-        let mut peer_iter = peers.into_iter();
+    let family = *matches.get_one::<AddressFamily>("address-family").unwrap();
+
+    // A peer given with several addresses is dialed on all of them at once,
+    // IPv6 first, and keeps whichever connects.
+    let mut peers: Vec<(PeerId, Vec<Multiaddr>)> = Vec::new();
+    if let Some(args) = matches.get_many::<String>("peers") {
+        let mut peer_iter = args.into_iter();
         while let (Some(multiaddr), Some(peer_id_str)) = (peer_iter.next(), peer_iter.next()) {
-            let multiaddr: Multiaddr = multiaddr.parse()?;
+            let multiaddrs = utils::resolve(multiaddr.parse()?).await;
             let peer_id: PeerId = peer_id_str.parse()?;
-            swarm.dial(multiaddr.clone())?;
 
+            match peers.iter_mut().find(|(p, _)| *p == peer_id) {
+                Some((_, addrs)) => addrs.extend(multiaddrs),
+                None => peers.push((peer_id, multiaddrs)),
+            }
+        }
+    }
+
+    // Without a genesis, the validators are this node and the peers it was
+    // told to dial, never whoever identifies as one.
+    let named_peers: HashSet<String> = peers
+        .iter()
+        .map(|(peer_id, _)| peer_id.to_string())
+        .chain([local_peer_id.to_string()])
+        .collect();
+
+    for (peer_id, addrs) in peers {
+        let addrs = family.dial_order(addrs);
+        if addrs.is_empty() {
+            error!("No {:?} address for {:?}", family, peer_id);
+            continue;
+        }
+
+        swarm.dial(DialOpts::peer_id(peer_id).addresses(addrs.clone()).build())?;
+
+        for addr in addrs.iter() {
             swarm
                 .behaviour_mut()
                 .kademlia
-                .add_address(&peer_id, multiaddr.clone());
+                .add_address(&peer_id, addr.clone());
+        }
 
-            swarm.behaviour_mut().gossipsub.add_explicit_peer(&peer_id);
+        swarm.behaviour_mut().gossipsub.add_explicit_peer(&peer_id);
 
-            info!("Dialed with {:?}, {:?}", peer_id, multiaddr);
-        }
+        info!("Dialed with {:?}, {:?}", peer_id, addrs);
     }
 
-    swarm.listen_on("/ip4/0.0.0.0/tcp/0".parse()?)?;
+    for addr in family.listen_addrs(*matches.get_one::<u16>("p2p-port").unwrap()) {
+        swarm.listen_on(addr)?;
+    }
 
     let (swarm_tx, mut swarm_rx) = mpsc::channel::<SwarmMessageType>(100);
     let app = Box::leak(Box::new(App::new(swarm_tx)));
     app.local_peer_id = Some(local_peer_id.to_string());
+    app.seed = matches.get_flag("seed");
+    app.direct_votes = matches.get_flag("direct-votes");
+    app.connections.get_mut().bandwidth = Some(bandwidth);
+    if let Some(path) = matches.get_one::<String>("genesis") {
+        let json = std::fs::read_to_string(path)?;
+        let errors = Genesis::validate_json(&json);
+        if !errors.is_empty() {
+            return Err(format!("invalid genesis: {}", serde_json::to_string(&errors)?).into());
+        }
 
-    let node_servicer = NodeServicerBuilder::default().with_app(&*app).build();
+        let genesis: Genesis = serde_json::from_str(&json)?;
+        *app.features.get_mut() = genesis.features();
+        *app.rules.get_mut() = genesis.rules_schedule();
+        app.max_games_per_player = genesis.max_games_per_player;
+        app.validator_registry = Some(genesis.validators.into_iter().collect());
+    }
+    if app.validator_registry.is_none() {
+        app.validator_registry = Some(named_peers);
+    }
+    if let Some(dir) = matches.get_one::<String>("restored") {
+        let games = app
+            .load_restored(Path::new(dir))
+            .await
+            .map_err(|e| e as Box<dyn Error>)?;
+        info!("Loaded {} restored games from {}", games, dir);
+    }
+    if matches.get_flag("faucet") {
+        let interval = *matches.get_one::<u64>("faucet-interval").unwrap();
+        app.faucet = Some(Faucet::new(Duration::from_secs(interval)));
+    }
+
+    if let Some(path) = matches.get_one::<String>("record") {
+        app.recorder = Some(Recorder::create(path, local_peer_id.to_string())?);
+    }
+    #[cfg(feature = "nats")]
+    if let Some(url) = matches.get_one::<String>("nats") {
+        let mut subjects = network::sink::Subjects::default();
+        for mapping in matches
+            .get_many::<String>("nats-subject")
+            .into_iter()
+            .flatten()
+        {
+            subjects.map(mapping)?;
+        }
+        app.sink = Some(network::sink::NatsSink::spawn(url, subjects));
+    }
+    app.digests = config.digests.map(Digests::new);
+    let backups = match config.backup {
+        Some(backup) => Some(
+            Backups::new(
+                backup,
+                matches.get_one::<String>("record").map(PathBuf::from),
+            )
+            .map_err(|e| e as Box<dyn Error>)?,
+        ),
+        None => None,
+    };
+    let replay = matches.get_one::<String>("replay");
+    if let Some(path) = replay {
+        app.local_peer_id = Some(recorder::recorded_peer_id(path)?);
+    }
+
+    let decoder_queue = replay.is_none().then(|| {
+        let (decoder, queue) = Decoder::new();
+        app.decoder = Some(decoder);
+        queue
+    });
+
+    let app: &'static App = app;
+    if let Some(queue) = decoder_queue {
+        tokio::spawn(queue.run(app));
+    }
+    let node_servicer = NodeServicerBuilder::default().with_app(app).build();
+
+    if let Some(path) = replay {
+        tokio::spawn(async move { while swarm_rx.recv().await.is_some() {} });
+        recorder::replay(path, app, &node_servicer).await?;
+        return Ok(());
+    }
+
+    if let Some(backups) = backups {
+        tokio::spawn(backups.run(app));
+    }
+    tokio::spawn(Digests::run(app));
 
     let grpc_port = matches.get_one::<String>("port").unwrap();
-    let addr = format!("[::]:{}", grpc_port).parse()?;
-    let cors = CorsLayer::new()
-        .allow_origin(Any)
-        .allow_methods(Any)
-        .allow_headers(Any);
+    let addr = format!("{}:{}", family.bind_host(), grpc_port).parse()?;
+    let web = config.web;
+    let cors = web.cors()?;
+    let public = match &config.internal {
+        Some(internal) => {
+            let addr: std::net::SocketAddr = internal.listen.parse()?;
+            let access = Access::Internal {
+                token: internal.token()?.into(),
+                loopback: addr.ip().is_loopback(),
+            };
+            let servicer = NodeServicerBuilder::default().with_app(app).build();
+            tokio::spawn(async move {
+                Server::builder()
+                    .layer(MapRequestLayer::new(versioning::route_legacy))
+                    .layer(access)
+                    .add_service(NodeServer::new(servicer))
+                    .serve(addr)
+                    .await
+                    .expect("internal gRPC server running")
+            });
+            info!("Serving validator and operator calls on {}", addr);
+            Access::Public
+        }
+        None => Access::All,
+    };
 
-    let _ = tokio::spawn(async move {
+    tokio::spawn(async move {
         Server::builder()
-            .accept_http1(true)
+            .accept_http1(web.http1)
             .layer(cors)
-            .layer(GrpcWebLayer::new())
+            .layer(option_layer(web.grpc_web.then(GrpcWebLayer::new)))
+            .layer(MapRequestLayer::new(versioning::route_legacy))
+            .layer(public)
             .add_service(NodeServer::new(node_servicer))
             .serve(addr)
             .await
             .expect("gRPC server running")
     });
 
-    let _ = tokio::spawn(async {
+    if let Some(port) = matches.get_one::<u16>("admin-port") {
+        let addr = format!("{}:{}", family.bind_host(), port).parse()?;
+        tokio::spawn(async move { admin::serve(app, addr).await.expect("admin server running") });
+    }
+
+    tokio::spawn(async {
         loop {
             app.update_view_if_needed().await;
             tokio::time::sleep(Duration::from_secs(1)).await;
         }
     });
 
+    // The main loop pings the watchdog, so a hung loop gets the node restarted.
+    let mut readiness = systemd::Readiness::default();
+    let watchdog_interval = systemd::watchdog_interval();
+    let mut watchdog = tokio::time::interval(watchdog_interval.unwrap_or(Duration::from_secs(1)));
+
     loop {
         tokio::select! {
+            _ = watchdog.tick(), if watchdog_interval.is_some() => {
+                systemd::notify("WATCHDOG=1");
+            }
             Some(cmd) = swarm_rx.recv() => {
                 match cmd {
                     SwarmMessageType::Publish(topic, msg) => {
                         swarm.behaviour_mut().gossipsub.publish(topic, msg)?;
                     }
+                    SwarmMessageType::AddAddress(_, addr) if !family.allows(&addr) => {}
                     SwarmMessageType::AddAddress(peer_id, addr) => {
                         swarm.behaviour_mut().kademlia.add_address(&peer_id, addr);
-                        swarm.behaviour_mut().gossipsub.add_explicit_peer(&peer_id);
+                        if app.gossip.read().await.flood {
+                            swarm.behaviour_mut().gossipsub.add_explicit_peer(&peer_id);
+                        }
+                    }
+                    SwarmMessageType::Vote(leader, vote) => {
+                        swarm.behaviour_mut().votes.send_request(&leader, *vote);
+                    }
+                    SwarmMessageType::AckVote(channel, counted) => {
+                        let _ = swarm.behaviour_mut().votes.send_response(channel, counted);
                     }
                     SwarmMessageType::Bootstrap => {
                         swarm.behaviour_mut().kademlia.bootstrap()?;
-                        let mut peers: Vec<_> = swarm.connected_peers().map(|e| e.to_string()).collect();
-                        peers.push(local_peer_id.to_string());
+                        let validators = app.validator_peers.read().await;
+                        let mut peers: Vec<_> = swarm
+                            .connected_peers()
+                            .map(|e| e.to_string())
+                            .filter(|p| validators.contains(p))
+                            .collect();
+                        drop(validators);
+                        if !app.seed && app.may_validate(&local_peer_id.to_string()) {
+                            peers.push(local_peer_id.to_string());
+                        }
                         peers.sort();
+
+                        let params = GossipParams::for_validators(peers.len());
+                        let was_flooding = app.gossip.read().await.flood;
+                        if params.flood != was_flooding {
+                            info!("Validator set of {}, flooding: {}", peers.len(), params.flood);
+                            let gossipsub = &mut swarm.behaviour_mut().gossipsub;
+                            let remote = peers.iter().filter_map(|p| p.parse::<PeerId>().ok());
+                            for peer_id in remote.filter(|p| *p != local_peer_id) {
+                                if params.flood {
+                                    gossipsub.add_explicit_peer(&peer_id);
+                                } else {
+                                    gossipsub.remove_explicit_peer(&peer_id);
+                                }
+                            }
+                        }
+                        *app.gossip.write().await = params;
+
+                        readiness.validators(peers.len());
                         CONNECTED_PEERS.write().await.clone_from(&peers);
+                        app.record(Input::Peers { peers });
                     }
                 }
             }
             event = swarm.select_next_some() => {
-                if let Err(e) = match_behaviour(event, &app).await {
+                if matches!(event, SwarmEvent::NewListenAddr { .. }) {
+                    readiness.listening();
+                }
+                if let Err(e) = match_behaviour(event, app).await {
                     error!("{:?}", e);
                 }
             }
@@ -0,0 +1,216 @@
+//! Which gRPC methods each listener serves. Without an `internal` section the
+//! gRPC port serves everything, as before. With one, validator and operator
+//! calls move to a second listener of their own, which wants a bearer token,
+//! and the public port refuses them, so they can be firewalled off the
+//! internet. Debugging calls are served on the internal listener only, so a
+//! node without one does not serve them at all.
+
+use futures::future::{self, Either, Ready};
+use serde::Deserialize;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use tonic::body::BoxBody;
+use tonic::codegen::http::{header::AUTHORIZATION, Request, Response};
+use tonic::Status;
+use tower::{Layer, Service};
+
+/// Methods served on the internal listener only, once there is one.
+pub const INTERNAL_METHODS: [&str; 3] = ["Govern", "Peers", "ValidateGenesis"];
+
+/// Methods served on the internal listener and nowhere else, with or without
+/// one. They let any caller make the node do work on demand.
+pub const DEBUG_METHODS: [&str; 1] = ["DebugValidate"];
+
+/// Methods that sign with the node's key. They are served on an internal
+/// listener bound to loopback and nowhere else, with or without one.
+pub const SIGNING_METHODS: [&str; 1] = ["Endorse"];
+
+const SERVICE: &str = "/query.v1.Node/";
+
+/// The `internal` section of the node config.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct InternalConfig {
+    /// Address to listen on, e.g. "127.0.0.1:50060".
+    pub listen: String,
+    /// File holding the token callers send as `authorization: Bearer <token>`.
+    pub token_file: PathBuf,
+}
+
+impl InternalConfig {
+    pub fn token(&self) -> Result<String, Box<dyn std::error::Error>> {
+        let token = std::fs::read_to_string(&self.token_file)?
+            .trim()
+            .to_string();
+        if token.is_empty() {
+            return Err(format!("{} holds no token", self.token_file.display()).into());
+        }
+        Ok(token)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum Access {
+    /// Everything, for a node without an internal listener.
+    All,
+    /// Everything but the internal methods.
+    Public,
+    /// The internal methods, to callers with the token, and the signing
+    /// ones too if it listens on loopback.
+    Internal { token: Arc<str>, loopback: bool },
+}
+
+impl Access {
+    /// Why a call to `path` with `authorization` is refused here, if it is.
+    #[allow(clippy::result_large_err)]
+    pub fn check(&self, path: &str, authorization: Option<&str>) -> Result<(), Status> {
+        let method = path.strip_prefix(SERVICE);
+        let signing = method.is_some_and(|m| SIGNING_METHODS.contains(&m));
+        let debug = method.is_some_and(|m| DEBUG_METHODS.contains(&m));
+        let internal = signing || debug || method.is_some_and(|m| INTERNAL_METHODS.contains(&m));
+
+        match self {
+            Self::Internal {
+                loopback: false, ..
+            }
+            | Self::All
+            | Self::Public
+                if signing =>
+            {
+                Err(Status::permission_denied(format!(
+                    "{} is served on an internal listener bound to loopback",
+                    path
+                )))
+            }
+            Self::All | Self::Public if debug => Err(Status::permission_denied(format!(
+                "{} is served on the internal listener only",
+                path
+            ))),
+            Self::All => Ok(()),
+            Self::Public if internal => Err(Status::permission_denied(format!(
+                "{} is served on the internal port",
+                path
+            ))),
+            Self::Public => Ok(()),
+            Self::Internal { .. } if !internal => Err(Status::permission_denied(format!(
+                "{} is served on the public port",
+                path
+            ))),
+            Self::Internal { token, .. } => {
+                match authorization.and_then(|a| a.strip_prefix("Bearer ")) {
+                    Some(given) if same(given.as_bytes(), token.as_bytes()) => Ok(()),
+                    _ => Err(Status::unauthenticated("missing or wrong token")),
+                }
+            }
+        }
+    }
+}
+
+/// Compares in time independent of where the two differ.
+fn same(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |diff, (x, y)| diff | (x ^ y)) == 0
+}
+
+impl<S> Layer<S> for Access {
+    type Service = AccessControl<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        AccessControl {
+            inner,
+            access: self.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct AccessControl<S> {
+    inner: S,
+    access: Access,
+}
+
+impl<S, B> Service<Request<B>> for AccessControl<S>
+where
+    S: Service<Request<B>, Response = Response<BoxBody>>,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = Either<Ready<Result<Self::Response, Self::Error>>, S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, request: Request<B>) -> Self::Future {
+        let authorization = request
+            .headers()
+            .get(AUTHORIZATION)
+            .and_then(|v| v.to_str().ok());
+        match self.access.check(request.uri().path(), authorization) {
+            Ok(()) => Either::Right(self.inner.call(request)),
+            Err(status) => Either::Left(future::ok(status.to_http())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tonic::Code;
+
+    #[test]
+    fn test_internal_methods_stay_off_the_public_port() {
+        let code = |access: &Access, path, authorization| {
+            access
+                .check(path, authorization)
+                .err()
+                .map(|status| status.code())
+        };
+        let internal = Access::Internal {
+            token: "secret".into(),
+            loopback: true,
+        };
+
+        assert_eq!(code(&Access::All, "/query.v1.Node/Govern", None), None);
+        assert_eq!(
+            code(&Access::Public, "/query.v1.Node/Govern", None),
+            Some(Code::PermissionDenied)
+        );
+        assert_eq!(code(&Access::Public, "/query.v1.Node/State", None), None);
+
+        let govern = "/query.v1.Node/Govern";
+        assert_eq!(code(&internal, govern, Some("Bearer secret")), None);
+        assert_eq!(
+            code(&internal, govern, Some("Bearer guess")),
+            Some(Code::Unauthenticated)
+        );
+        assert_eq!(code(&internal, govern, None), Some(Code::Unauthenticated));
+        assert_eq!(
+            code(&internal, "/query.v1.Node/State", Some("Bearer secret")),
+            Some(Code::PermissionDenied)
+        );
+
+        // Signing with the node key is for loopback callers with the token.
+        let endorse = "/query.v1.Node/Endorse";
+        assert_eq!(code(&internal, endorse, Some("Bearer secret")), None);
+        assert_eq!(code(&internal, endorse, None), Some(Code::Unauthenticated));
+        let exposed = Access::Internal {
+            token: "secret".into(),
+            loopback: false,
+        };
+        for access in [&Access::All, &Access::Public, &exposed] {
+            assert_eq!(
+                code(access, endorse, Some("Bearer secret")),
+                Some(Code::PermissionDenied)
+            );
+        }
+
+        // Debugging calls need the internal listener, wherever it listens.
+        let debug = "/query.v1.Node/DebugValidate";
+        for access in [&Access::All, &Access::Public] {
+            assert_eq!(code(access, debug, None), Some(Code::PermissionDenied));
+        }
+        assert_eq!(code(&exposed, debug, Some("Bearer secret")), None);
+        assert_eq!(code(&exposed, debug, None), Some(Code::Unauthenticated));
+    }
+}
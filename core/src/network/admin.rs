@@ -0,0 +1,173 @@
+use super::operator::{agent_version, OperatorConfig, OPERATOR};
+use crate::{
+    chess::diff::BoardDiff,
+    consensus::{
+        latency::{LatencyHistogram, LATENCY_BUCKETS_MS},
+        types::Block,
+    },
+    network::{connections::ConnectionStats, utils::GossipParams},
+    pb::{game::GameState, query::PeerInfo},
+    App, CONNECTED_PEERS,
+};
+use axum::{extract::State, response::Html, routing::get, Json, Router};
+use serde::Serialize;
+use std::{
+    collections::HashSet, error::Error, net::SocketAddr, sync::atomic::Ordering, time::Instant,
+};
+
+/// How many committed blocks the dashboard keeps around.
+pub const RECENT_BLOCKS: usize = 20;
+
+const INDEX: &str = include_str!("../../admin/index.html");
+
+#[derive(Serialize)]
+struct Status {
+    peer_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    operator: Option<OperatorConfig>,
+    height: u64,
+    view_n: usize,
+    leader: Option<String>,
+    halted: bool,
+    latest_block_hash: String,
+    peers: Vec<PeerInfo>,
+    games: Vec<GameState>,
+    blocks: Vec<BlockSummary>,
+    vote_latency_buckets_ms: [u64; LATENCY_BUCKETS_MS.len()],
+    vote_latency: LatencyHistogram,
+    gossip: GossipParams,
+    connections: ConnectionStats,
+    features: Vec<&'static str>,
+}
+
+#[derive(Serialize)]
+pub struct BlockSummary {
+    height: u64,
+    view_n: u32,
+    hash: String,
+    timestamp: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    game_id: Option<String>,
+    white_player: String,
+    black_player: String,
+    kind: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    diff: Option<BoardDiff>,
+}
+
+impl BlockSummary {
+    pub fn new(height: u64, block: &Block, diff: Option<&BoardDiff>) -> Self {
+        let tx = block.tx.clone().unwrap_or_default();
+
+        Self {
+            height,
+            view_n: block.view_n,
+            hash: hex::encode(&block.hash),
+            timestamp: block.timestamp,
+            kind: tx.kind().as_str_name().to_string(),
+            game_id: tx.game_id,
+            white_player: tx.white_player,
+            black_player: tx.black_player,
+            diff: diff.cloned(),
+        }
+    }
+}
+
+impl App {
+    pub async fn peer_infos(&self) -> Vec<PeerInfo> {
+        let local_peer_id = self.local_peer_id.clone().unwrap_or_default();
+        let local_agent = agent_version();
+        let versions = self.peer_versions.read().await;
+
+        CONNECTED_PEERS
+            .read()
+            .await
+            .iter()
+            .map(|peer_id| {
+                let agent = if *peer_id == local_peer_id {
+                    local_agent.as_str()
+                } else {
+                    versions.get(peer_id).map_or("", String::as_str)
+                };
+                let (version, operator) = OperatorConfig::from_agent_version(agent);
+                PeerInfo {
+                    peer_id: peer_id.clone(),
+                    agent_version: version.to_string(),
+                    is_local: *peer_id == local_peer_id,
+                    name: operator.name.unwrap_or_default(),
+                    contact: operator.contact.unwrap_or_default(),
+                    region: operator.region.unwrap_or_default(),
+                }
+            })
+            .collect()
+    }
+}
+
+/// Serves the read-only dashboard and the status it polls.
+pub async fn serve(
+    app: &'static App,
+    addr: SocketAddr,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let router = Router::new()
+        .route("/", get(|| async { Html(INDEX) }))
+        .route("/api/status", get(status))
+        .with_state(app);
+
+    axum::Server::bind(&addr)
+        .serve(router.into_make_service())
+        .await?;
+
+    Ok(())
+}
+
+async fn status(State(app): State<&'static App>) -> Json<Status> {
+    // The dashboard is anyone's view: restricted games are left out and
+    // dark ones shown as a spectator sees them, their block diffs too.
+    let snapshot = app.db.snapshot().await;
+    let hidden: HashSet<_> = snapshot
+        .iter()
+        .filter(|(_, g)| !g.is_visible_to(None) || (g.is_dark() && !g.is_over()))
+        .map(|(key, _)| key.clone())
+        .collect();
+    let mut games: Vec<_> = snapshot
+        .into_iter()
+        .filter(|(_, g)| g.is_visible_to(None))
+        .map(|(_, g)| g.view_for(None))
+        .collect();
+    games.sort_by(|a, b| {
+        (&a.white_player, &a.black_player).cmp(&(&b.white_player, &b.black_player))
+    });
+
+    let blocks = app
+        .recent_blocks
+        .read()
+        .await
+        .iter()
+        .rev()
+        .map(|(height, block, diff)| {
+            let game_id = block.tx.as_ref().and_then(|tx| tx.game_id.as_ref());
+            let diff = diff
+                .as_ref()
+                .filter(|_| game_id.is_none_or(|id| !hidden.contains(id)));
+            BlockSummary::new(*height, block, diff)
+        })
+        .collect();
+
+    Json(Status {
+        peer_id: app.local_peer_id.clone().unwrap_or_default(),
+        operator: OPERATOR.get().cloned(),
+        height: app.height.load(Ordering::Relaxed),
+        view_n: app.view_n.load(Ordering::Relaxed),
+        leader: app.get_current_leader().await.ok(),
+        halted: app.is_halted().await,
+        latest_block_hash: app.consensus.read().await.head.to_string(),
+        peers: app.peer_infos().await,
+        games,
+        blocks,
+        vote_latency_buckets_ms: LATENCY_BUCKETS_MS,
+        vote_latency: app.vote_latency.read().await.clone(),
+        gossip: *app.gossip.read().await,
+        connections: app.connections.write().await.stats(Instant::now()),
+        features: app.features.read().await.names(),
+    })
+}
@@ -1,130 +1,851 @@
-use super::p2p::{broadcast_block, PROPOSAL_TOPIC, START_TOPIC};
+use super::p2p::{broadcast_block, PROPOSAL_TOPIC};
 use crate::{
+    chess::chess960::POSITIONS,
+    consensus::{hotstuff::verify_signature, participation::EPOCH_BLOCKS},
+    genesis::Genesis,
     pb::{
-        game::GameState,
+        game::{GameState, Variant},
         query::{
-            node_server::Node, IsInGameRequest, IsInGameResponse, StartRequest, StartResponse,
-            StateRequest, StateResponse, Transaction, TransactionResponse,
+            node_server::Node, AcceptSeekRequest, CancelSeekRequest, DebugValidateRequest,
+            DebugValidateResponse, DemoKeypairRequest, DemoKeypairResponse, EvaluateResponse,
+            FenResponse, GameHistoryResponse, GovernanceRequest, GovernanceResponse,
+            ImportPgnRequest, ImportPgnResponse, IsInGameRequest, IsInGameResponse,
+            LegalMovesRequest, LegalMovesResponse, LifecycleReceiptRequest,
+            LifecycleReceiptResponse, ListSeeksRequest, ListSeeksResponse, ParticipationRequest,
+            ParticipationResponse, PeersRequest, PeersResponse, PgnResponse, PlayerStatsRequest,
+            PlayerStatsResponse, Seek, SeekResponse, StartRequest, StartResponse, StateRequest,
+            StateResponse, Transaction, TransactionKind, TransactionResponse,
+            TransactionStatusRequest, TransactionStatusResponse, TxProgress, TxStatus,
+            ValidateGenesisRequest, ValidateGenesisResponse, ValidatorSignature,
         },
     },
     App,
 };
-use alloy_primitives::keccak256;
+use futures::Stream;
+use prost::{DecodeError, Message};
+use rand::Rng;
+use std::error::Error;
+use std::future::Future;
+use std::pin::Pin;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::time::{timeout_at, Instant};
+use tokio_stream::wrappers::ReceiverStream;
 use tonic::{Request, Response, Status};
 
 pub struct NodeServicer {
     app: &'static App,
 }
 
+/// States a watcher may fall behind by before the stream waits for it.
+const WATCH_BUFFER: usize = 16;
+
+/// How long `Start` waits for its game to commit, and the seek calls for
+/// their transactions, without a deadline of the client's own.
+const START_WAIT: Duration = Duration::from_secs(30);
+
+/// How far, in seconds, a viewer's signature time may be from the node's
+/// clock, so a signature seen once cannot be used to read the game later.
+const VIEWER_SIGNATURE_TTL: i64 = 5 * 60;
+
 #[tonic::async_trait]
 impl Node for NodeServicer {
+    type WatchGameStream = Pin<Box<dyn Stream<Item = Result<StateResponse, Status>> + Send>>;
+    type WatchTransactionStream = Pin<Box<dyn Stream<Item = Result<TxProgress, Status>> + Send>>;
+
     async fn start(
         &self,
         request: Request<StartRequest>,
     ) -> Result<Response<StartResponse>, Status> {
+        self.app.record_rpc("Start", request.get_ref());
+        let deadline = deadline(&request);
         let r = request.into_inner();
 
-        self.app
-            .start_game_if_possible(r.clone())
+        within(deadline, async {
+            let (game_id, response) = self.submit_start(r).await?;
+            self.committed(&response.tx_hash).await?;
+
+            Ok(Response::new(StartResponse {
+                state: self.app.db.get(Some(&game_id), "", "").await,
+            }))
+        })
+        .await
+    }
+
+    async fn state(
+        &self,
+        request: Request<StateRequest>,
+    ) -> Result<Response<StateResponse>, Status> {
+        self.app.record_rpc("State", request.get_ref());
+        let state = self.visible_state(&request.into_inner()).await?;
+        let clocks = state
+            .as_ref()
+            .and_then(|g| g.clocks_at(self.app.now().timestamp()));
+
+        Ok(Response::new(StateResponse { state, clocks }))
+    }
+
+    async fn watch_game(
+        &self,
+        request: Request<StateRequest>,
+    ) -> Result<Response<Self::WatchGameStream>, Status> {
+        self.app.record_rpc("WatchGame", request.get_ref());
+        let mut r = request.into_inner();
+        if self.visible_state(&r).await?.is_none() {
+            return Err(Status::not_found("no such game"));
+        }
+        r.min_height = None;
+        // Checked once, so the stream outlives the signature's freshness.
+        let viewer = authenticated_viewer(&r, self.app.now().timestamp()).map(str::to_string);
+
+        let servicer = NodeServicer { app: self.app };
+        let (tx, rx) = mpsc::channel(WATCH_BUFFER);
+        tokio::spawn(async move {
+            let mut last = None;
+            loop {
+                // Taken before the read, so a commit in between still wakes us.
+                let committed = servicer.app.committed.notified();
+                let state = match servicer.visible_state_for(&r, viewer.as_deref()).await {
+                    Ok(Some(state)) => state,
+                    Ok(None) => return,
+                    Err(e) => {
+                        let _ = tx.send(Err(e)).await;
+                        return;
+                    }
+                };
+
+                if last.as_ref() != Some(&state) {
+                    let over = state.is_over();
+                    let response = StateResponse {
+                        clocks: state.clocks_at(servicer.app.now().timestamp()),
+                        state: Some(state.clone()),
+                    };
+                    if tx.send(Ok(response)).await.is_err() || over {
+                        return;
+                    }
+                    last = Some(state);
+                }
+                committed.await;
+            }
+        });
+
+        Ok(Response::new(Box::pin(ReceiverStream::new(rx))))
+    }
+
+    async fn transact(
+        &self,
+        request: Request<Transaction>,
+    ) -> Result<Response<TransactionResponse>, Status> {
+        self.app.record_rpc("Transact", request.get_ref());
+        let deadline = deadline(&request);
+        let r = request.into_inner();
+
+        within(deadline, self.submit(r)).await
+    }
+
+    async fn is_in_game(
+        &self,
+        request: Request<IsInGameRequest>,
+    ) -> Result<Response<IsInGameResponse>, Status> {
+        self.app.record_rpc("IsInGame", request.get_ref());
+        let r = request.into_inner();
+
+        let viewer = authenticated_viewer(&r, self.app.now().timestamp());
+
+        let mut games: Vec<_> = self
+            .app
+            .db
+            .snapshot()
             .await
-            .map_err(|e| Status::internal(e.to_string()))?;
+            .into_iter()
+            .map(|(_, game)| game)
+            .filter(|game| {
+                (game.white_player == r.player || game.black_player == r.player)
+                    && !game.is_over()
+                    && game.is_visible_to(viewer)
+            })
+            .collect();
+        games.sort_by(|a, b| {
+            (b.started_at, b.start_nonce, &b.game_id).cmp(&(
+                a.started_at,
+                a.start_nonce,
+                &a.game_id,
+            ))
+        });
+        let games: Vec<_> = games.iter().map(|game| game.view_for(viewer)).collect();
+
+        Ok(Response::new(IsInGameResponse {
+            state: games.first().cloned(),
+            games,
+        }))
+    }
+
+    async fn create_seek(&self, request: Request<Seek>) -> Result<Response<SeekResponse>, Status> {
+        self.app.record_rpc("CreateSeek", request.get_ref());
+        let deadline = deadline(&request);
+        let r = request.into_inner();
+
+        within(deadline, async {
+            let seek_id = r.id();
+            let tx = self.app.create_seek(r).await.map_err(Status::from)?;
+            let response = self.submit_checked(tx).await?;
+            self.committed(&response.tx_hash).await?;
+
+            Ok(Response::new(SeekResponse { seek_id }))
+        })
+        .await
+    }
+
+    async fn cancel_seek(
+        &self,
+        request: Request<CancelSeekRequest>,
+    ) -> Result<Response<SeekResponse>, Status> {
+        self.app.record_rpc("CancelSeek", request.get_ref());
+        let deadline = deadline(&request);
+        let r = request.into_inner();
+
+        within(deadline, async {
+            let seek_id = r.seek_id.clone();
+            let tx = self.app.cancel_seek(r).await.map_err(Status::from)?;
+            let response = self.submit_checked(tx).await?;
+            self.committed(&response.tx_hash).await?;
+
+            Ok(Response::new(SeekResponse { seek_id }))
+        })
+        .await
+    }
+
+    async fn list_seeks(
+        &self,
+        request: Request<ListSeeksRequest>,
+    ) -> Result<Response<ListSeeksResponse>, Status> {
+        self.app.record_rpc("ListSeeks", request.get_ref());
+
+        Ok(Response::new(ListSeeksResponse {
+            seeks: self.app.list_seeks().await,
+        }))
+    }
 
-        let spread = serde_json::to_string(&r).map_err(|e| Status::internal(e.to_string()))?;
+    async fn accept_seek(
+        &self,
+        request: Request<AcceptSeekRequest>,
+    ) -> Result<Response<StartResponse>, Status> {
+        self.app.record_rpc("AcceptSeek", request.get_ref());
+        let deadline = deadline(&request);
+        let r = request.into_inner();
 
+        within(deadline, async {
+            let tx = self
+                .app
+                .accept_seek(r.clone())
+                .await
+                .map_err(Status::from)?;
+            let game_id = tx.game_id.clone().unwrap_or_default();
+            let response = self.submit_checked(tx).await?;
+            self.committed(&response.tx_hash).await?;
+
+            let state = self.app.db.get(Some(&game_id), "", "").await;
+            Ok(Response::new(StartResponse {
+                state: state.map(|game| game.view_for(Some(&r.acceptor))),
+            }))
+        })
+        .await
+    }
+
+    async fn demo_keypair(
+        &self,
+        request: Request<DemoKeypairRequest>,
+    ) -> Result<Response<DemoKeypairResponse>, Status> {
+        self.app.record_rpc("DemoKeypair", request.get_ref());
+        let faucet = self
+            .app
+            .faucet
+            .as_ref()
+            .ok_or_else(|| Status::unimplemented("faucet is disabled"))?;
+
+        let client = request
+            .remote_addr()
+            .map(|a| a.ip().to_string())
+            .unwrap_or_default();
+
+        let (secret_key, public_key) = faucet.issue(client).await.map_err(Status::from)?;
+
+        Ok(Response::new(DemoKeypairResponse {
+            private_key: hex::encode(secret_key.serialize()),
+            public_key: hex::encode(public_key.serialize_compressed()),
+        }))
+    }
+
+    async fn peers(
+        &self,
+        request: Request<PeersRequest>,
+    ) -> Result<Response<PeersResponse>, Status> {
+        self.app.record_rpc("Peers", request.get_ref());
+
+        Ok(Response::new(PeersResponse {
+            peers: self.app.peer_infos().await,
+        }))
+    }
+
+    async fn debug_validate(
+        &self,
+        request: Request<DebugValidateRequest>,
+    ) -> Result<Response<DebugValidateResponse>, Status> {
+        self.app.record_rpc("DebugValidate", request.get_ref());
+        let r = request.into_inner();
+
+        Ok(Response::new(
+            self.app.debug_validate(&r.transaction_json).await,
+        ))
+    }
+
+    async fn transaction_status(
+        &self,
+        request: Request<TransactionStatusRequest>,
+    ) -> Result<Response<TransactionStatusResponse>, Status> {
+        self.app.record_rpc("TransactionStatus", request.get_ref());
+        let r = request.into_inner();
+
+        Ok(Response::new(self.app.transaction_status(&r.tx_hash).await))
+    }
+
+    async fn watch_transaction(
+        &self,
+        request: Request<TransactionStatusRequest>,
+    ) -> Result<Response<Self::WatchTransactionStream>, Status> {
+        self.app.record_rpc("WatchTransaction", request.get_ref());
+        let tx_hash = request.into_inner().tx_hash;
+
+        let app = self.app;
+        let (tx, rx) = mpsc::channel(WATCH_BUFFER);
+        tokio::spawn(async move {
+            let mut last = None;
+            loop {
+                // Taken before the read, so a step in between still wakes us.
+                let progress = app.progress.notified();
+                let committed = app.committed.notified();
+                let current = app.transaction_progress(&tx_hash).await;
+
+                if last.as_ref() != Some(&current) {
+                    let done = current.status == TxStatus::Committed as i32
+                        || current.status == TxStatus::Rejected as i32
+                        || current.status == TxStatus::MempoolFull as i32;
+                    if tx.send(Ok(current.clone())).await.is_err() || done {
+                        return;
+                    }
+                    last = Some(current);
+                }
+                tokio::select! {
+                    _ = progress => {}
+                    _ = committed => {}
+                }
+            }
+        });
+
+        Ok(Response::new(Box::pin(ReceiverStream::new(rx))))
+    }
+
+    async fn get_lifecycle_receipt(
+        &self,
+        request: Request<LifecycleReceiptRequest>,
+    ) -> Result<Response<LifecycleReceiptResponse>, Status> {
+        self.app
+            .record_rpc("GetLifecycleReceipt", request.get_ref());
+        let r = request.into_inner();
+
+        Ok(Response::new(LifecycleReceiptResponse {
+            receipt: self.app.txs.read().await.receipt(&r.tx_hash),
+        }))
+    }
+
+    async fn govern(
+        &self,
+        request: Request<GovernanceRequest>,
+    ) -> Result<Response<GovernanceResponse>, Status> {
+        self.app.record_rpc("Govern", request.get_ref());
+        let tx = Transaction {
+            kind: TransactionKind::Govern as i32,
+            governance: Some(request.into_inner()),
+            ..Default::default()
+        };
+
+        // Refused here with the reason, rather than as a bare ok: false.
         self.app
-            .publish(START_TOPIC.to_owned(), spread)
+            .is_valid_tx(&tx, self.app.now().timestamp())
             .await
-            .map_err(|e| Status::internal(e.to_string()))?;
+            .map_err(Status::from)?;
+        let response = self.submit(tx).await?.into_inner();
+        if !response.ok {
+            return Err(Status::resource_exhausted("governance request not taken"));
+        }
 
-        Ok(Response::new(StartResponse {
-            state: Some(GameState::new(r.white_player, r.black_player)),
+        let consensus = self.app.consensus.read().await;
+        Ok(Response::new(GovernanceResponse {
+            halted: consensus.halted,
+            nonce: consensus.governance_nonce,
+            tx_hash: response.tx_hash,
         }))
     }
 
-    async fn state(
+    async fn endorse(
         &self,
-        request: Request<StateRequest>,
-    ) -> Result<Response<StateResponse>, Status> {
+        request: Request<GovernanceRequest>,
+    ) -> Result<Response<ValidatorSignature>, Status> {
+        self.app.record_rpc("Endorse", request.get_ref());
+
+        Ok(Response::new(
+            request.into_inner().endorse().map_err(Status::from)?,
+        ))
+    }
+
+    async fn validate_genesis(
+        &self,
+        request: Request<ValidateGenesisRequest>,
+    ) -> Result<Response<ValidateGenesisResponse>, Status> {
+        self.app.record_rpc("ValidateGenesis", request.get_ref());
+        let errors = Genesis::validate_json(&request.into_inner().genesis);
+
+        Ok(Response::new(ValidateGenesisResponse {
+            ok: errors.is_empty(),
+            errors,
+        }))
+    }
+
+    async fn get_player_stats(
+        &self,
+        request: Request<PlayerStatsRequest>,
+    ) -> Result<Response<PlayerStatsResponse>, Status> {
+        self.app.record_rpc("GetPlayerStats", request.get_ref());
         let r = request.into_inner();
 
-        if let Some(state) = self
+        Ok(Response::new(PlayerStatsResponse {
+            stats: Some(self.app.player_stats(&r.player).await),
+        }))
+    }
+
+    async fn get_fen(
+        &self,
+        request: Request<StateRequest>,
+    ) -> Result<Response<FenResponse>, Status> {
+        self.app.record_rpc("GetFen", request.get_ref());
+        let state = self.visible_state(&request.into_inner()).await?;
+
+        Ok(Response::new(FenResponse {
+            fen: state.map(|s| s.to_fen()),
+        }))
+    }
+
+    async fn export_pgn(
+        &self,
+        request: Request<StateRequest>,
+    ) -> Result<Response<PgnResponse>, Status> {
+        self.app.record_rpc("ExportPgn", request.get_ref());
+        let state = self.visible_state(&request.into_inner()).await?;
+
+        Ok(Response::new(PgnResponse {
+            pgn: state.map(|s| s.to_pgn()),
+        }))
+    }
+
+    async fn game_history(
+        &self,
+        request: Request<StateRequest>,
+    ) -> Result<Response<GameHistoryResponse>, Status> {
+        self.app.record_rpc("GameHistory", request.get_ref());
+        let state = self.visible_state(&request.into_inner()).await?;
+
+        Ok(Response::new(match state {
+            Some(state) => GameHistoryResponse {
+                history: state.history,
+                moves: state.moves,
+            },
+            None => GameHistoryResponse::default(),
+        }))
+    }
+
+    async fn import_pgn(
+        &self,
+        request: Request<ImportPgnRequest>,
+    ) -> Result<Response<ImportPgnResponse>, Status> {
+        self.app.record_rpc("ImportPgn", request.get_ref());
+        let rules = self
             .app
-            .db
-            .read()
+            .rules_at(self.app.next_height())
             .await
-            .get(&format!("{}:{}", r.white_player, r.black_player))
-        {
-            return Ok(Response::new(StateResponse {
-                state: Some(state.clone()),
-            }));
+            .map_err(Status::from)?;
+        let (state, illegal_move) = GameState::from_pgn(&request.into_inner().pgn, rules);
+
+        Ok(Response::new(ImportPgnResponse {
+            state: Some(state),
+            illegal_move,
+        }))
+    }
+
+    async fn legal_moves(
+        &self,
+        request: Request<LegalMovesRequest>,
+    ) -> Result<Response<LegalMovesResponse>, Status> {
+        self.app.record_rpc("LegalMoves", request.get_ref());
+        let r = request.into_inner();
+        let (Some(game), Some(square)) = (r.game, r.square) else {
+            return Err(Status::invalid_argument("a game and a square are required"));
+        };
+        let state = self
+            .visible_state(&game)
+            .await?
+            .ok_or_else(|| Status::not_found("no such game"))?;
+
+        Ok(Response::new(LegalMovesResponse {
+            targets: state.legal_targets(&square),
+        }))
+    }
+
+    async fn evaluate(
+        &self,
+        request: Request<StateRequest>,
+    ) -> Result<Response<EvaluateResponse>, Status> {
+        self.app.record_rpc("Evaluate", request.get_ref());
+        let state = self.visible_state(&request.into_inner()).await?;
+
+        Ok(Response::new(EvaluateResponse {
+            evaluation: state.and_then(|s| s.board).map(|b| b.evaluate()),
+        }))
+    }
+
+    async fn participation(
+        &self,
+        request: Request<ParticipationRequest>,
+    ) -> Result<Response<ParticipationResponse>, Status> {
+        self.app.record_rpc("Participation", request.get_ref());
+        let epoch = request
+            .into_inner()
+            .epoch
+            .unwrap_or_else(|| self.app.current_epoch());
+
+        Ok(Response::new(ParticipationResponse {
+            epoch,
+            first_height: epoch.saturating_mul(EPOCH_BLOCKS),
+            last_height: epoch
+                .saturating_add(1)
+                .saturating_mul(EPOCH_BLOCKS)
+                .saturating_sub(1),
+            validators: self.app.participation.read().await.epoch(epoch),
+        }))
+    }
+}
+
+/// The client's deadline, from the `grpc-timeout` header. The spec allows
+/// at most 8 digits; longer values, or ones past what an `Instant` can hold,
+/// are ignored rather than trusted.
+fn deadline<T>(request: &Request<T>) -> Option<Instant> {
+    let timeout = request.metadata().get("grpc-timeout")?.to_str().ok()?;
+    let (value, unit) = timeout.split_at(timeout.len().checked_sub(1)?);
+    if value.is_empty() || value.len() > 8 {
+        return None;
+    }
+    let value: u64 = value.parse().ok()?;
+
+    let timeout = match unit {
+        "H" => Duration::from_secs(value.checked_mul(3600)?),
+        "M" => Duration::from_secs(value.checked_mul(60)?),
+        "S" => Duration::from_secs(value),
+        "m" => Duration::from_millis(value),
+        "u" => Duration::from_micros(value),
+        "n" => Duration::from_nanos(value),
+        _ => return None,
+    };
+
+    Instant::now().checked_add(timeout)
+}
+
+/// Runs `f` until the client's deadline, dropping it and its pending waits
+/// once the deadline passes.
+async fn within<T>(
+    deadline: Option<Instant>,
+    f: impl Future<Output = Result<T, Status>>,
+) -> Result<T, Status> {
+    match deadline {
+        Some(deadline) => match timeout_at(deadline, f).await {
+            Ok(result) => result,
+            Err(_) => Err(Status::deadline_exceeded("deadline exceeded")),
+        },
+        None => f.await,
+    }
+}
+
+/// A request a viewer signs to see the games only they may.
+trait ViewerSigned {
+    /// The viewer, their signature and the unix time they signed at.
+    fn claim(&self) -> Option<(&str, &str, i64)>;
+    /// What the viewer signs, signedAt included.
+    fn signed_message(&self, signed_at: i64) -> serde_json::Value;
+}
+
+impl ViewerSigned for StateRequest {
+    fn claim(&self) -> Option<(&str, &str, i64)> {
+        Some((
+            self.viewer.as_deref()?,
+            self.signature.as_deref()?,
+            self.signed_at?,
+        ))
+    }
+
+    fn signed_message(&self, signed_at: i64) -> serde_json::Value {
+        let mut message = serde_json::json!({
+            "whitePlayer": self.white_player,
+            "blackPlayer": self.black_player,
+            "viewer": self.viewer,
+            "signedAt": signed_at,
+        });
+        if let Some(game_id) = &self.game_id {
+            message["gameId"] = game_id.as_str().into();
         }
+        message
+    }
+}
 
-        return Ok(Response::new(StateResponse { state: None }));
+impl ViewerSigned for IsInGameRequest {
+    fn claim(&self) -> Option<(&str, &str, i64)> {
+        Some((&self.player, self.signature.as_deref()?, self.signed_at?))
     }
 
-    async fn transact(
+    fn signed_message(&self, signed_at: i64) -> serde_json::Value {
+        serde_json::json!({ "player": self.player, "signedAt": signed_at })
+    }
+}
+
+/// Returns the viewer of a request if it carries their valid signature,
+/// made within VIEWER_SIGNATURE_TTL of the unix time `now`.
+fn authenticated_viewer(r: &impl ViewerSigned, now: i64) -> Option<&str> {
+    let (viewer, signature, signed_at) = r.claim()?;
+    if now.abs_diff(signed_at) > VIEWER_SIGNATURE_TTL as u64 {
+        return None;
+    }
+
+    verify_signature(&r.signed_message(signed_at), signature, viewer)
+        .ok()
+        .map(|_| viewer)
+}
+
+impl NodeServicer {
+    /// Fills in what a start request leaves to the node and submits it as a
+    /// START transaction. Returns the id of the game it starts.
+    async fn submit_start(
         &self,
-        request: Request<Transaction>,
-    ) -> Result<Response<TransactionResponse>, Status> {
-        let mut r = request.into_inner();
-        if self.app.is_valid_tx(&r).await.is_err() {
-            return Ok(Response::new(TransactionResponse { ok: false }));
-        }
-        r.game_state_hash = Some(
-            keccak256(
-                serde_json::to_string(
-                    self.app
-                        .db
-                        .read()
-                        .await
-                        .get(&format!("{}:{}", r.white_player, r.black_player))
-                        .unwrap(),
-                )
-                .map_err(|e| Status::internal(e.to_string()))?,
-            )
-            .to_string(),
-        );
+        mut r: StartRequest,
+    ) -> Result<(String, TransactionResponse), Status> {
+        r.started_at = 0;
+        if r.nonce == 0 {
+            r.nonce = rand::random();
+        }
+        if r.variant == Variant::Chess960 as i32 && r.start_position.is_none() {
+            r.start_position = Some(rand::thread_rng().gen_range(0..POSITIONS));
+        }
+
+        let game_id = r.game_id();
+        let tx = Transaction {
+            white_player: r.white_player.clone(),
+            black_player: r.black_player.clone(),
+            kind: TransactionKind::Start as i32,
+            game_id: Some(game_id.clone()),
+            start: Some(r),
+            ..Default::default()
+        };
 
-        let serialized = serde_json::to_string(&r).map_err(|e| Status::internal(e.to_string()))?;
+        Ok((game_id, self.submit_checked(tx).await?))
+    }
 
+    /// Submits a START or seek transaction the node made for a client.
+    async fn submit_checked(&self, tx: Transaction) -> Result<TransactionResponse, Status> {
+        // Refused here with the reason, rather than as a bare ok: false.
         self.app
-            .publish(PROPOSAL_TOPIC.clone(), serialized)
+            .is_valid_tx(&tx, self.app.now().timestamp())
+            .await
+            .map_err(Status::from)?;
+        let response = self.submit(tx).await?.into_inner();
+        if !response.ok {
+            return Err(Status::resource_exhausted("transaction not taken"));
+        }
+        Ok(response)
+    }
+
+    /// Waits, up to START_WAIT, for the START or seek transaction `tx_hash`
+    /// to commit.
+    async fn committed(&self, tx_hash: &str) -> Result<(), Status> {
+        let status = tokio::time::timeout(START_WAIT, self.app.settled(tx_hash))
+            .await
+            .map_err(|_| {
+                Status::deadline_exceeded(format!("transaction {} is not committed yet", tx_hash))
+            })?;
+        if status.status != TxStatus::Committed as i32 {
+            let reason = status.rejections.first().map(|r| r.reason.clone());
+            return Err(Status::failed_precondition(
+                reason.unwrap_or_else(|| "transaction rejected".into()),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Validates `r`, takes it into the mempool and broadcasts it, unless it
+    /// was submitted before.
+    async fn submit(&self, mut r: Transaction) -> Result<Response<TransactionResponse>, Status> {
+        let resolved = self.app.resolve_notation(&mut r).await;
+        let tx_hash = r.tx_hash();
+
+        let now = Instant::now().into_std();
+        let mut txs = self.app.txs.write().await;
+        let duplicate = txs
+            .submit(&tx_hash, now)
+            .map(|status| (status, txs.committed_height(&tx_hash)));
+        drop(txs);
+        if let Some((status, height)) = duplicate {
+            return Ok(Response::new(TransactionResponse {
+                ok: status != TxStatus::Rejected,
+                height: height.unwrap_or_default(),
+                tx_hash,
+                duplicate: true,
+                status: status as i32,
+                retry_after: None,
+            }));
+        }
+
+        if resolved.is_err()
+            || self
+                .app
+                .is_valid_tx(&r, self.app.now().timestamp())
+                .await
+                .is_err()
+        {
+            self.app.txs.write().await.withdraw(&tx_hash);
+            return Ok(Response::new(TransactionResponse {
+                ok: false,
+                tx_hash,
+                ..Default::default()
+            }));
+        }
+        r.game_state_hash = self
+            .app
+            .calculate_game_state_hash(&r)
             .await
-            .map_err(|e| Status::internal(e.to_string()))?;
+            .map_err(Status::from)?;
+
+        // Taken before it is broadcast, so a full mempool turns the
+        // client away with a time to come back instead of dropping it.
+        let admitted = self.app.txs.write().await.admit(&r, now);
+        if let Err(retry_after) = admitted {
+            self.app.txs.write().await.withdraw(&tx_hash);
+            return Ok(Response::new(TransactionResponse {
+                ok: false,
+                tx_hash,
+                status: TxStatus::MempoolFull as i32,
+                retry_after: Some(retry_after.as_secs() as u32),
+                ..Default::default()
+            }));
+        }
 
-        if self
+        if let Err(e) = self
             .app
-            .get_current_leader()
+            .publish(PROPOSAL_TOPIC.clone(), r.encode_to_vec())
             .await
-            .map_err(|e| Status::internal(e.to_string()))?
+        {
+            self.app.txs.write().await.withdraw(&tx_hash);
+            return Err(e.into());
+        }
+
+        if self.app.get_current_leader().await.map_err(Status::from)?
             == self.app.local_peer_id.clone().unwrap()
         {
-            broadcast_block(&self.app, &r)
+            broadcast_block(self.app, &r)
                 .await
                 .map_err(|e| Status::internal(e.to_string()))?;
         }
 
-        Ok(Response::new(TransactionResponse { ok: true }))
+        Ok(Response::new(TransactionResponse {
+            ok: true,
+            tx_hash,
+            status: TxStatus::Pending as i32,
+            ..Default::default()
+        }))
     }
 
-    async fn is_in_game(
-        &self,
-        request: Request<IsInGameRequest>,
-    ) -> Result<Response<IsInGameResponse>, Status> {
-        let r = request.into_inner();
+    /// The game a state request asks for, as its viewer may see it.
+    async fn visible_state(&self, r: &StateRequest) -> Result<Option<GameState>, Status> {
+        let viewer = authenticated_viewer(r, self.app.now().timestamp());
+        self.visible_state_for(r, viewer).await
+    }
 
-        for key in self.app.db.read().await.keys() {
-            if key.split(":").any(|p| p == r.player) {
-                return Ok(Response::new(IsInGameResponse {
-                    state: Some(self.app.db.read().await.get(key).unwrap().clone()),
-                }));
+    /// The game a state request asks for, as `viewer`, already
+    /// authenticated, may see it.
+    async fn visible_state_for(
+        &self,
+        r: &StateRequest,
+        viewer: Option<&str>,
+    ) -> Result<Option<GameState>, Status> {
+        if let Some(min_height) = r.min_height {
+            if !self.app.wait_for_height(min_height).await {
+                return Err(Status::unavailable(format!(
+                    "node has not reached height {} yet, retry",
+                    min_height
+                )));
             }
         }
 
-        return Ok(Response::new(IsInGameResponse { state: None }));
+        let Some(state) = self
+            .app
+            .db
+            .get(r.game_id.as_deref(), &r.white_player, &r.black_player)
+            .await
+        else {
+            return Ok(None);
+        };
+
+        if !state.is_visible_to(viewer) {
+            return Err(Status::permission_denied("game is restricted"));
+        }
+
+        Ok(Some(state.view_for(viewer)))
+    }
+
+    /// Runs a recorded request through its handler, discarding the response.
+    pub async fn replay(&self, method: &str, request: &[u8]) -> Result<(), Box<dyn Error>> {
+        match method {
+            "Start" => drop(self.submit_start(decode(request)?.into_inner()).await?),
+            "State" => drop(self.state(decode(request)?).await?),
+            "Transact" => drop(self.transact(decode(request)?).await?),
+            "IsInGame" => drop(self.is_in_game(decode(request)?).await?),
+            "CreateSeek" => drop(self.create_seek(decode(request)?).await?),
+            "CancelSeek" => drop(self.cancel_seek(decode(request)?).await?),
+            "ListSeeks" => drop(self.list_seeks(decode(request)?).await?),
+            "AcceptSeek" => drop(self.accept_seek(decode(request)?).await?),
+            "DemoKeypair" => drop(self.demo_keypair(decode(request)?).await?),
+            "Peers" => drop(self.peers(decode(request)?).await?),
+            "DebugValidate" => drop(self.debug_validate(decode(request)?).await?),
+            "TransactionStatus" => drop(self.transaction_status(decode(request)?).await?),
+            "GetLifecycleReceipt" => drop(self.get_lifecycle_receipt(decode(request)?).await?),
+            "Govern" => drop(self.govern(decode(request)?).await?),
+            "Endorse" => drop(self.endorse(decode(request)?).await?),
+            "ValidateGenesis" => drop(self.validate_genesis(decode(request)?).await?),
+            "GetPlayerStats" => drop(self.get_player_stats(decode(request)?).await?),
+            "Participation" => drop(self.participation(decode(request)?).await?),
+            "GetFen" => drop(self.get_fen(decode(request)?).await?),
+            "ExportPgn" => drop(self.export_pgn(decode(request)?).await?),
+            "GameHistory" => drop(self.game_history(decode(request)?).await?),
+            "ImportPgn" => drop(self.import_pgn(decode(request)?).await?),
+            "LegalMoves" => drop(self.legal_moves(decode(request)?).await?),
+            "Evaluate" => drop(self.evaluate(decode(request)?).await?),
+            "WatchGame" => drop(self.watch_game(decode(request)?).await?),
+            "WatchTransaction" => drop(self.watch_transaction(decode(request)?).await?),
+            _ => return Err(format!("unknown method {}", method).into()),
+        }
+
+        Ok(())
     }
 }
 
+fn decode<M: Message + Default>(bytes: &[u8]) -> Result<Request<M>, DecodeError> {
+    M::decode(bytes).map(Request::new)
+}
+
 #[derive(Default)]
 pub struct NodeServicerBuilder {
     app: Option<&'static App>,
@@ -132,10 +853,7 @@ pub struct NodeServicerBuilder {
 
 impl NodeServicerBuilder {
     pub fn with_app(self, app: &'static App) -> Self {
-        Self {
-            app: Some(app),
-            ..self
-        }
+        Self { app: Some(app) }
     }
 
     pub fn build(self) -> NodeServicer {
@@ -144,3 +862,83 @@ impl NodeServicerBuilder {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_deadline_from_grpc_timeout() {
+        let mut request = Request::new(());
+        assert!(deadline(&request).is_none());
+
+        request
+            .metadata_mut()
+            .insert("grpc-timeout", "50m".parse().unwrap());
+        let deadline = deadline(&request).unwrap();
+        assert!(deadline <= Instant::now() + Duration::from_millis(50));
+
+        let waited = within(Some(deadline), std::future::pending::<Result<(), Status>>()).await;
+        assert_eq!(waited.unwrap_err().code(), tonic::Code::DeadlineExceeded);
+
+        for timeout in ["123456789S", "18446744073709551615H", "S", "-1S"] {
+            request
+                .metadata_mut()
+                .insert("grpc-timeout", timeout.parse().unwrap());
+            assert!(super::deadline(&request).is_none(), "{}", timeout);
+        }
+    }
+
+    #[test]
+    fn test_viewer_signatures_go_stale() {
+        use libsecp256k1::{sign, Message, PublicKey, SecretKey};
+        use sha2::{Digest, Sha256};
+
+        let secret_key = SecretKey::random(&mut rand::thread_rng());
+        let viewer = hex::encode(PublicKey::from_secret_key(&secret_key).serialize_compressed());
+        let signed = |message: serde_json::Value| {
+            let hash = Sha256::digest(serde_json::to_string(&message).unwrap().as_bytes());
+            let (signature, _) = sign(&Message::parse_slice(&hash).unwrap(), &secret_key);
+            Some(hex::encode(signature.serialize()))
+        };
+        let now = 1_700_000_000;
+        let mut r = StateRequest {
+            white_player: "white".into(),
+            black_player: "black".into(),
+            viewer: Some(viewer.clone()),
+            signature: signed(serde_json::json!({
+                "whitePlayer": "white",
+                "blackPlayer": "black",
+                "viewer": viewer,
+                "signedAt": now,
+            })),
+            signed_at: Some(now),
+            ..Default::default()
+        };
+
+        assert_eq!(authenticated_viewer(&r, now + 60), Some(viewer.as_str()));
+        assert_eq!(
+            authenticated_viewer(&r, now + VIEWER_SIGNATURE_TTL + 1),
+            None
+        );
+        // The time is signed, so it cannot be moved forward.
+        r.signed_at = Some(now + 60);
+        assert_eq!(authenticated_viewer(&r, now + 60), None);
+        r.signed_at = None;
+        assert_eq!(authenticated_viewer(&r, now), None);
+
+        // Which games a player is in is guarded the same way.
+        let mut r = IsInGameRequest {
+            player: viewer.clone(),
+            signature: signed(serde_json::json!({ "player": viewer, "signedAt": now })),
+            signed_at: Some(now),
+        };
+        assert_eq!(authenticated_viewer(&r, now + 60), Some(viewer.as_str()));
+        assert_eq!(
+            authenticated_viewer(&r, now + VIEWER_SIGNATURE_TTL + 1),
+            None
+        );
+        r.signed_at = None;
+        assert_eq!(authenticated_viewer(&r, now), None);
+    }
+}
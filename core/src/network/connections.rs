@@ -0,0 +1,165 @@
+//! Connection metrics for the admin dashboard: how often connections open
+//! and close, why dials fail, and how much each peer sends us. A burst of
+//! connection events is logged as churn, usually the first sign of a flaky
+//! network or a peer restarting in a loop.
+
+use libp2p::{bandwidth::BandwidthSinks, swarm::DialError};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+/// The span churn is measured over.
+const CHURN_WINDOW: Duration = Duration::from_secs(60);
+
+/// Connection events within `CHURN_WINDOW` past which churn is logged.
+const CHURN_LIMIT: usize = 30;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConnectionEvent {
+    Established,
+    Closed,
+    DialFailed(&'static str),
+}
+
+#[derive(Default)]
+pub struct ConnectionMetrics {
+    established: u64,
+    closed: u64,
+    dial_failures: BTreeMap<&'static str, u64>,
+    gossip_bytes: HashMap<String, u64>,
+    recent: VecDeque<Instant>,
+    churning: bool,
+    /// Bytes through the transport, once it is built.
+    pub bandwidth: Option<Arc<BandwidthSinks>>,
+}
+
+/// The metrics as the dashboard shows them.
+#[derive(Serialize, Debug, Default)]
+pub struct ConnectionStats {
+    established: u64,
+    closed: u64,
+    dial_failures: BTreeMap<&'static str, u64>,
+    /// Connection events in the last minute.
+    churn: usize,
+    bytes_in: u64,
+    bytes_out: u64,
+    /// Gossip payload bytes each peer forwarded to us.
+    gossip_bytes: BTreeMap<String, u64>,
+}
+
+/// A short name for why a dial failed, to count failures by.
+pub fn dial_failure_reason(error: &DialError) -> &'static str {
+    match error {
+        DialError::Banned => "banned",
+        DialError::ConnectionLimit(_) => "connection_limit",
+        DialError::LocalPeerId => "local_peer_id",
+        DialError::NoAddresses => "no_addresses",
+        DialError::DialPeerConditionFalse(_) => "condition_false",
+        DialError::Aborted => "aborted",
+        DialError::InvalidPeerId(_) => "invalid_peer_id",
+        DialError::WrongPeerId { .. } => "wrong_peer_id",
+        DialError::ConnectionIo(_) => "io",
+        DialError::Transport(_) => "transport",
+    }
+}
+
+impl ConnectionMetrics {
+    pub fn record(&mut self, event: ConnectionEvent, now: Instant) {
+        match event {
+            ConnectionEvent::Established => self.established += 1,
+            ConnectionEvent::Closed => self.closed += 1,
+            ConnectionEvent::DialFailed(reason) => {
+                *self.dial_failures.entry(reason).or_default() += 1
+            }
+        }
+
+        self.recent.push_back(now);
+        let churn = self.churn(now);
+        if churn > CHURN_LIMIT && !self.churning {
+            self.churning = true;
+            warn!(
+                events = churn,
+                window_secs = CHURN_WINDOW.as_secs(),
+                established = self.established,
+                closed = self.closed,
+                dial_failures = ?self.dial_failures,
+                "Connection churn above {} events a minute",
+                CHURN_LIMIT
+            );
+        } else if churn <= CHURN_LIMIT / 2 && self.churning {
+            self.churning = false;
+            info!(events = churn, "Connection churn back to normal");
+        }
+    }
+
+    pub fn add_gossip_bytes(&mut self, peer: &str, bytes: usize) {
+        *self.gossip_bytes.entry(peer.to_string()).or_default() += bytes as u64;
+    }
+
+    /// Forgets the gossip count of a peer we no longer connect to.
+    pub fn forget(&mut self, peer: &str) {
+        self.gossip_bytes.remove(peer);
+    }
+
+    /// Connection events within the window ending at `now`.
+    fn churn(&mut self, now: Instant) -> usize {
+        while self
+            .recent
+            .front()
+            .is_some_and(|at| now.saturating_duration_since(*at) >= CHURN_WINDOW)
+        {
+            self.recent.pop_front();
+        }
+        self.recent.len()
+    }
+
+    pub fn stats(&mut self, now: Instant) -> ConnectionStats {
+        ConnectionStats {
+            established: self.established,
+            closed: self.closed,
+            dial_failures: self.dial_failures.clone(),
+            churn: self.churn(now),
+            bytes_in: self.bandwidth.as_ref().map_or(0, |b| b.total_inbound()),
+            bytes_out: self.bandwidth.as_ref().map_or(0, |b| b.total_outbound()),
+            gossip_bytes: self
+                .gossip_bytes
+                .iter()
+                .map(|(peer, bytes)| (peer.clone(), *bytes))
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_churn_is_flagged_and_clears() {
+        let mut metrics = ConnectionMetrics::default();
+        let start = Instant::now();
+
+        for i in 0..=CHURN_LIMIT {
+            let event = if i % 2 == 0 {
+                ConnectionEvent::Established
+            } else {
+                ConnectionEvent::DialFailed("transport")
+            };
+            metrics.record(event, start);
+        }
+        assert!(metrics.churning);
+        metrics.add_gossip_bytes("peer", 100);
+        metrics.add_gossip_bytes("peer", 20);
+
+        let stats = metrics.stats(start);
+        assert_eq!((stats.established, stats.churn), (16, CHURN_LIMIT + 1));
+        assert_eq!(stats.dial_failures.get("transport"), Some(&15));
+        assert_eq!(stats.gossip_bytes.get("peer"), Some(&120));
+
+        metrics.record(ConnectionEvent::Closed, start + CHURN_WINDOW);
+        assert!(!metrics.churning);
+        assert_eq!(metrics.stats(start + CHURN_WINDOW).churn, 1);
+    }
+}
@@ -0,0 +1,168 @@
+//! Gossip decoding off the swarm loop. The loop only queues each message;
+//! a pool of blocking workers parses several at once, and the results are
+//! handed back in the order the messages arrived, so handlers see the same
+//! sequence as if every message were parsed inline.
+
+use super::p2p::{handle_payload, Payload};
+use crate::App;
+use futures::stream::{FuturesOrdered, StreamExt};
+use libp2p::gossipsub::GossipsubMessage;
+use libp2p::PeerId;
+use prost::DecodeError;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tracing::{error, warn};
+
+/// Messages waiting to be parsed. The swarm loop drops gossip past this
+/// rather than wait, since the handlers it would wait on may be publishing
+/// through it.
+const QUEUE: usize = 1024;
+
+/// Parsed messages waiting for the handlers.
+const HANDOFF: usize = 64;
+
+type Parsed = (Option<PeerId>, Result<Option<Payload>, DecodeError>);
+
+pub struct Decoder {
+    tx: mpsc::Sender<GossipsubMessage>,
+}
+
+/// The receiving end of a `Decoder`, run once the app is in place.
+pub struct DecoderQueue {
+    rx: mpsc::Receiver<GossipsubMessage>,
+}
+
+impl Decoder {
+    pub fn new() -> (Decoder, DecoderQueue) {
+        let (tx, rx) = mpsc::channel(QUEUE);
+        (Decoder { tx }, DecoderQueue { rx })
+    }
+
+    /// Queues `message` for parsing and handling, or drops it if the queue
+    /// is full.
+    pub fn submit(&self, message: GossipsubMessage) {
+        if let Err(mpsc::error::TrySendError::Full(message)) = self.tx.try_send(message) {
+            warn!("Gossip queue full, dropping a {} message", message.topic);
+        }
+    }
+}
+
+impl DecoderQueue {
+    /// Parses queued messages on up to one worker per core and hands them to
+    /// the handlers one at a time, in arrival order.
+    pub async fn run(mut self, app: &'static App) {
+        let workers = std::thread::available_parallelism().map_or(1, |n| n.get());
+        let (parsed_tx, mut parsed_rx) = mpsc::channel::<Parsed>(HANDOFF);
+
+        tokio::spawn(async move {
+            while let Some((source, payload)) = parsed_rx.recv().await {
+                let result = match payload {
+                    Ok(Some(payload)) => handle_payload(payload, source, app).await,
+                    Ok(None) => Ok(()),
+                    Err(e) => Err(e.into()),
+                };
+                if let Err(e) = result {
+                    error!("{:?}", e);
+                }
+            }
+        });
+
+        let mut parsing: FuturesOrdered<JoinHandle<Parsed>> = FuturesOrdered::new();
+        loop {
+            tokio::select! {
+                message = self.rx.recv(), if parsing.len() < workers => {
+                    let Some(message) = message else { break };
+                    parsing.push_back(tokio::task::spawn_blocking(move || {
+                        let payload = Payload::decode(&message.topic, &message.data);
+                        (message.source, payload)
+                    }));
+                }
+                Some(parsed) = parsing.next() => {
+                    let Ok(parsed) = parsed else { continue };
+                    if parsed_tx.send(parsed).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        }
+
+        while let Some(Ok(parsed)) = parsing.next().await {
+            let _ = parsed_tx.send(parsed).await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::p2p::{FEEDBACK_TOPIC, PROPOSAL_TOPIC};
+    use crate::pb::query::{StartRequest, Transaction, TransactionKind, TxStatus};
+    use alloy_primitives::keccak256;
+    use libsecp256k1::{PublicKey, SecretKey};
+    use prost::Message;
+
+    /// The public key standing in for the player `name`.
+    fn player(name: &str) -> String {
+        let secret_key = SecretKey::parse(&keccak256(name).0).unwrap();
+        hex::encode(PublicKey::from_secret_key(&secret_key).serialize_compressed())
+    }
+
+    /// A START transaction for `white` against Bob.
+    fn start(white: &str) -> Transaction {
+        let r = StartRequest {
+            white_player: player(white),
+            black_player: player("Bob"),
+            nonce: 1,
+            ..Default::default()
+        };
+        Transaction {
+            white_player: r.white_player.clone(),
+            black_player: r.black_player.clone(),
+            kind: TransactionKind::Start as i32,
+            start: Some(r),
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_queued_gossip_is_handled_in_order() {
+        let app: &'static App = Box::leak(Box::new(App::new(mpsc::channel(1).0)));
+        let (decoder, queue) = Decoder::new();
+        tokio::spawn(queue.run(app));
+
+        let message = |tx: &Transaction| GossipsubMessage {
+            source: None,
+            data: tx.encode_to_vec(),
+            sequence_number: None,
+            topic: PROPOSAL_TOPIC.hash(),
+        };
+        let pending = |tx: Transaction| async move {
+            app.transaction_status(&tx.tx_hash()).await.status == TxStatus::Pending as i32
+        };
+
+        // Dave's start is handled last, so once it is in the mempool, every
+        // earlier one must be too, whichever worker parsed it first. The
+        // malformed rejection in between is only logged.
+        for i in 0..32 {
+            decoder.submit(message(&start(&format!("Alice{}", i))));
+        }
+        decoder.submit(GossipsubMessage {
+            data: b"not protobuf".to_vec(),
+            topic: FEEDBACK_TOPIC.hash(),
+            ..message(&start("Carol"))
+        });
+        decoder.submit(message(&start("Dave")));
+
+        let handled = async {
+            while !pending(start("Dave")).await {
+                tokio::task::yield_now().await;
+            }
+        };
+        tokio::time::timeout(std::time::Duration::from_secs(10), handled)
+            .await
+            .expect("the last start was handled");
+        for i in 0..32 {
+            assert!(pending(start(&format!("Alice{}", i))).await);
+        }
+    }
+}
@@ -0,0 +1,227 @@
+//! "Your move" digests for correspondence games, i.e. games without a time
+//! control. Instead of a callback per move, the games waiting on each player
+//! are collected and posted to a webhook together every `interval` seconds,
+//! one digest per player. A game the player moves in before then drops out of
+//! their digest. Email or chat delivery hangs off the webhook.
+
+use crate::{pb::game::GameState, App};
+use hyper::{client::HttpConnector, Body, Client, Request};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::time::Duration;
+use tokio::sync::RwLock;
+use tracing::{error, info};
+
+/// The `digests` section of the node config.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct DigestConfig {
+    /// Each digest is POSTed here as JSON.
+    pub webhook: String,
+    /// Seconds between digests.
+    #[serde(default = "default_interval")]
+    pub interval: u64,
+}
+
+fn default_interval() -> u64 {
+    3600
+}
+
+/// One player's digest.
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct Digest {
+    pub player: String,
+    pub games: Vec<WaitingGame>,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct WaitingGame {
+    pub game_id: String,
+    pub white_player: String,
+    pub black_player: String,
+    /// Moves played so far.
+    pub moves: usize,
+    /// Block time at which the game started waiting on the player.
+    pub since: i64,
+}
+
+/// Games waiting on a move, by player and then by game id.
+#[derive(Default)]
+pub struct Waiting(BTreeMap<String, BTreeMap<String, WaitingGame>>);
+
+impl Waiting {
+    /// Notes `game`, under `key`, as it stands at block time `at`: waiting on
+    /// the side to move unless it is over, and on nobody else.
+    pub fn update(&mut self, key: &str, game: &GameState, at: i64) {
+        self.forget(key);
+        if game.time_control.is_some() || game.is_over() || !game.is_visible_to(None) {
+            return;
+        }
+
+        let to_move = if game.turn % 2 == 0 {
+            &game.white_player
+        } else {
+            &game.black_player
+        };
+        self.0.entry(to_move.clone()).or_default().insert(
+            key.to_string(),
+            WaitingGame {
+                game_id: key.to_string(),
+                white_player: game.white_player.clone(),
+                black_player: game.black_player.clone(),
+                moves: game.moves.len(),
+                since: at,
+            },
+        );
+    }
+
+    pub fn forget(&mut self, key: &str) {
+        self.0.retain(|_, games| {
+            games.remove(key);
+            !games.is_empty()
+        });
+    }
+
+    /// Every player's digest, as it stands.
+    pub fn digests(&self) -> Vec<Digest> {
+        self.0
+            .iter()
+            .map(|(player, games)| Digest {
+                player: player.clone(),
+                games: games.values().cloned().collect(),
+            })
+            .collect()
+    }
+
+    /// Clears what `digest` told its player, keeping games that moved on
+    /// while it was sent.
+    pub fn sent(&mut self, digest: &Digest) {
+        let Some(games) = self.0.get_mut(&digest.player) else {
+            return;
+        };
+        for game in &digest.games {
+            if games.get(&game.game_id) == Some(game) {
+                games.remove(&game.game_id);
+            }
+        }
+        if games.is_empty() {
+            self.0.remove(&digest.player);
+        }
+    }
+}
+
+pub struct Digests {
+    config: DigestConfig,
+    waiting: RwLock<Waiting>,
+    client: Client<HttpConnector>,
+}
+
+impl Digests {
+    pub fn new(config: DigestConfig) -> Self {
+        Self {
+            config,
+            waiting: RwLock::new(Waiting::default()),
+            client: Client::new(),
+        }
+    }
+
+    pub async fn update(&self, key: &str, game: &GameState, at: i64) {
+        self.waiting.write().await.update(key, game, at);
+    }
+
+    /// Posts the digests every `interval` seconds.
+    pub async fn run(app: &'static App) {
+        let Some(digests) = &app.digests else {
+            return;
+        };
+
+        let mut interval =
+            tokio::time::interval(Duration::from_secs(digests.config.interval.max(1)));
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            let pending = digests.waiting.read().await.digests();
+            let count = pending.len();
+            for digest in pending {
+                match digests.post(&digest).await {
+                    Ok(()) => digests.waiting.write().await.sent(&digest),
+                    // Left waiting for the next round.
+                    Err(e) => error!("Could not send {}'s digest: {}", digest.player, e),
+                }
+            }
+            if count > 0 {
+                info!("Sent move digests to {} players", count);
+            }
+        }
+    }
+
+    async fn post(&self, digest: &Digest) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let request = Request::post(&self.config.webhook)
+            .header("content-type", "application/json")
+            .body(Body::from(serde_json::to_vec(digest)?))?;
+        let response = self.client.request(request).await?;
+        if !response.status().is_success() {
+            return Err(format!("webhook answered {}", response.status()).into());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pb::{game::TimeControl, query::Position};
+
+    #[test]
+    fn test_digests_batch_games_waiting_on_each_player() {
+        let mut waiting = Waiting::default();
+        let alice_bob = GameState::new("Alice".to_string(), "Bob".to_string());
+        let carol_alice = GameState::new("Carol".to_string(), "Alice".to_string());
+        let blitz = GameState::new("Alice".to_string(), "Dave".to_string()).with_time_control(
+            Some(TimeControl {
+                base: 300,
+                increment: 0,
+            }),
+        );
+
+        waiting.update("Alice:Bob", &alice_bob, 1);
+        waiting.update("Alice:Dave", &blitz, 1);
+        let mut carol_alice_moved = carol_alice.clone();
+        carol_alice_moved
+            .apply_move(Position { x: 1, y: 4 }, Position { x: 3, y: 4 })
+            .unwrap();
+        waiting.update("Carol:Alice", &carol_alice, 1);
+        waiting.update("Carol:Alice", &carol_alice_moved, 2);
+
+        let digests = waiting.digests();
+        assert_eq!(digests.len(), 1);
+        assert_eq!(digests[0].player, "Alice");
+        assert_eq!(
+            digests[0]
+                .games
+                .iter()
+                .map(|g| (g.black_player.as_str(), g.moves, g.since))
+                .collect::<Vec<_>>(),
+            [("Bob", 0, 1), ("Alice", 1, 2)]
+        );
+
+        // A game that moved on while its digest was out is kept.
+        let mut alice_bob_moved = alice_bob;
+        alice_bob_moved
+            .apply_move(Position { x: 1, y: 4 }, Position { x: 3, y: 4 })
+            .unwrap();
+        waiting.update("Alice:Bob", &alice_bob_moved, 3);
+        waiting.sent(&digests[0]);
+        assert_eq!(
+            waiting
+                .digests()
+                .iter()
+                .map(|d| (d.player.as_str(), d.games.len()))
+                .collect::<Vec<_>>(),
+            [("Bob", 1)]
+        );
+    }
+}
@@ -1,3 +1,17 @@
+pub mod access;
+pub mod admin;
 pub mod backend;
+pub mod connections;
+pub mod decoder;
+pub mod digest;
+pub mod operator;
 pub mod p2p;
+pub mod ratelimit;
+pub mod recorder;
+#[cfg(feature = "nats")]
+pub mod sink;
+pub mod systemd;
 pub mod utils;
+pub mod versioning;
+pub mod votes;
+pub mod web;
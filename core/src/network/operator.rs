@@ -0,0 +1,152 @@
+//! What an operator says about their node: a display name, how to reach
+//! them and where the node runs. It rides in the identify agent version,
+//! after the version itself, e.g.
+//! `chess-node/0.1.0 (name=Kasparov's box; region=eu-west; contact=ops@example.org)`,
+//! so peers that do not read it still see a chess node.
+
+use super::p2p::AGENT_VERSION;
+use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
+
+/// Bytes each field may take.
+pub const MAX_FIELD: usize = 64;
+
+/// The `operator` section of the node config, set once at start.
+pub static OPERATOR: OnceCell<OperatorConfig> = OnceCell::new();
+
+/// The `operator` section of the node config. Every field is optional.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct OperatorConfig {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub contact: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+}
+
+impl OperatorConfig {
+    fn fields(&self) -> [(&'static str, &Option<String>); 3] {
+        [
+            ("name", &self.name),
+            ("region", &self.region),
+            ("contact", &self.contact),
+        ]
+    }
+
+    /// Refuses fields that would not survive the trip through identify.
+    pub fn validate(&self) -> Result<(), String> {
+        for (key, value) in self.fields() {
+            if value.as_deref().is_some_and(|v| !is_valid(v)) {
+                return Err(format!(
+                    "operator {} must be 1 to {} bytes without control characters or any of ;()=",
+                    key, MAX_FIELD
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// AGENT_VERSION followed by the fields that are set.
+    pub fn agent_version(&self) -> String {
+        let fields: Vec<_> = self
+            .fields()
+            .into_iter()
+            .filter_map(|(key, value)| value.as_ref().map(|v| format!("{}={}", key, v)))
+            .collect();
+        if fields.is_empty() {
+            AGENT_VERSION.to_string()
+        } else {
+            format!("{} ({})", AGENT_VERSION, fields.join("; "))
+        }
+    }
+
+    /// Splits a peer's agent version into the version and its operator's
+    /// fields. Fields that are malformed or unknown are left out.
+    pub fn from_agent_version(agent: &str) -> (&str, Self) {
+        let Some((version, rest)) = agent.split_once(" (") else {
+            return (agent, Self::default());
+        };
+        let Some(rest) = rest.strip_suffix(')') else {
+            return (agent, Self::default());
+        };
+
+        let mut operator = Self::default();
+        for (key, value) in rest.split(';').filter_map(|f| f.trim().split_once('=')) {
+            let value = Some(value.to_string()).filter(|v| is_valid(v));
+            match key {
+                "name" => operator.name = value,
+                "contact" => operator.contact = value,
+                "region" => operator.region = value,
+                _ => {}
+            }
+        }
+        (version, operator)
+    }
+}
+
+fn is_valid(value: &str) -> bool {
+    !value.trim().is_empty()
+        && value.len() <= MAX_FIELD
+        && !value.contains(|c: char| c.is_control() || ";()=".contains(c))
+}
+
+/// This node's agent version, with its operator's fields if it has any.
+pub fn agent_version() -> String {
+    OPERATOR
+        .get()
+        .map_or_else(|| AGENT_VERSION.to_string(), OperatorConfig::agent_version)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_operator_round_trips_through_agent_version() {
+        let operator = OperatorConfig {
+            name: Some("Kasparov's box".into()),
+            contact: Some("ops@example.org".into()),
+            region: Some("eu-west".into()),
+        };
+        operator.validate().unwrap();
+        let agent = operator.agent_version();
+        assert!(agent.starts_with("chess-node/"));
+        assert_eq!(
+            OperatorConfig::from_agent_version(&agent),
+            (AGENT_VERSION, operator)
+        );
+
+        // Peers without any, or from before operators, are just a version.
+        assert_eq!(
+            OperatorConfig::from_agent_version(AGENT_VERSION),
+            (AGENT_VERSION, OperatorConfig::default())
+        );
+        assert_eq!(
+            OperatorConfig::default().agent_version(),
+            AGENT_VERSION.to_string()
+        );
+
+        let (_, odd) =
+            OperatorConfig::from_agent_version("chess-node/9 (name=x; region=; shoe=11)");
+        assert_eq!(odd.name.as_deref(), Some("x"));
+        assert_eq!((odd.region, odd.contact), (None, None));
+    }
+
+    #[test]
+    fn test_bad_operator_fields_are_refused() {
+        for bad in ["", " ", "a;b", "(x)", "k=v", "line\nbreak"] {
+            let operator = OperatorConfig {
+                name: Some(bad.into()),
+                ..Default::default()
+            };
+            assert!(operator.validate().is_err(), "{:?}", bad);
+        }
+        let long = OperatorConfig {
+            contact: Some("x".repeat(MAX_FIELD + 1)),
+            ..Default::default()
+        };
+        assert!(long.validate().is_err());
+    }
+}
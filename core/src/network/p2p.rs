@@ -1,32 +1,55 @@
 use crate::{
     consensus::types::{Block, BlockBuilder, Commit, QuorumCertificate},
     errors::AppError,
-    network::utils::SwarmMessageType,
-    pb::query::{StartRequest, Transaction},
-    App, PEERS,
+    network::{
+        connections::{dial_failure_reason, ConnectionEvent},
+        operator::agent_version,
+        ratelimit::Verdict,
+        recorder::Input,
+        utils::{SwarmMessageType, GOSSIP_LAZY, MESH_N, MESH_N_HIGH, MESH_N_LOW},
+        votes::{create_votes_behaviour, handle_votes, sign_vote, Vote, VoteCodec},
+    },
+    pb::query::{Rejection, Transaction},
+    App,
 };
 use libp2p::{
     gossipsub::{
         Gossipsub, GossipsubConfigBuilder, GossipsubEvent, GossipsubMessage, IdentTopic as Topic,
-        MessageAuthenticity, ValidationMode,
+        MessageAuthenticity, TopicHash, ValidationMode,
     },
-    identify::{Identify, IdentifyConfig, IdentifyEvent},
+    identify::{Identify, IdentifyConfig, IdentifyEvent, IdentifyInfo},
     identity,
     kad::{protocol, store::MemoryStore, Kademlia, KademliaEvent},
+    request_response::{RequestResponse, RequestResponseEvent},
     swarm::SwarmEvent,
-    NetworkBehaviour,
+    NetworkBehaviour, PeerId,
 };
-use once_cell::sync::Lazy;
-use std::time::Duration;
-use std::{collections::HashSet, error::Error};
-use tracing::info;
-
-pub static LOCAL_KEYS: Lazy<identity::Keypair> = Lazy::new(identity::Keypair::generate_ed25519);
+use once_cell::sync::{Lazy, OnceCell};
+use prost::{DecodeError, Message};
+use std::error::Error;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+pub const AGENT_VERSION: &str = concat!("chess-node/", env!("CARGO_PKG_VERSION"));
+/// Identify protocol version of validators and of seed nodes.
+pub const PROTOCOL_VERSION: &str = "ipfs/1.0.0";
+pub const SEED_PROTOCOL_VERSION: &str = "chess-seed/1.0.0";
+/// How many other peers a node suggests to those it prunes from its mesh.
+pub const PX_PEERS: usize = 16;
+/// The identity given with --key. Without one the node makes up a new
+/// identity every start.
+pub static NODE_KEY: OnceCell<identity::Keypair> = OnceCell::new();
+pub static LOCAL_KEYS: Lazy<identity::Keypair> = Lazy::new(|| {
+    NODE_KEY
+        .get()
+        .cloned()
+        .unwrap_or_else(identity::Keypair::generate_ed25519)
+});
 pub static PROPOSAL_TOPIC: Lazy<Topic> = Lazy::new(|| Topic::new("proposal"));
 pub static QUORUM_TOPIC: Lazy<Topic> = Lazy::new(|| Topic::new("quorum"));
 pub static DECISION_TOPIC: Lazy<Topic> = Lazy::new(|| Topic::new("decision"));
 pub static COMMIT_TOPIC: Lazy<Topic> = Lazy::new(|| Topic::new("commit"));
-pub static START_TOPIC: Lazy<Topic> = Lazy::new(|| Topic::new("start"));
+pub static FEEDBACK_TOPIC: Lazy<Topic> = Lazy::new(|| Topic::new("feedback"));
 
 #[derive(NetworkBehaviour)]
 #[behaviour(out_event = "PeerBehaviour")]
@@ -34,6 +57,7 @@ pub struct Behaviour {
     pub kademlia: Kademlia<MemoryStore>,
     pub identify: Identify,
     pub gossipsub: Gossipsub,
+    pub votes: RequestResponse<VoteCodec>,
 }
 
 #[derive(Debug)]
@@ -41,6 +65,7 @@ pub enum PeerBehaviour {
     Gossipsub(GossipsubEvent),
     Identify(IdentifyEvent),
     Kademlia(KademliaEvent),
+    Votes(Box<RequestResponseEvent<Vote, bool>>),
 }
 
 impl From<IdentifyEvent> for PeerBehaviour {
@@ -61,6 +86,12 @@ impl From<KademliaEvent> for PeerBehaviour {
     }
 }
 
+impl From<RequestResponseEvent<Vote, bool>> for PeerBehaviour {
+    fn from(v: RequestResponseEvent<Vote, bool>) -> Self {
+        Self::Votes(Box::new(v))
+    }
+}
+
 pub async fn match_behaviour(
     event: SwarmEvent<PeerBehaviour, impl Error>,
     app: &App,
@@ -74,19 +105,66 @@ pub async fn match_behaviour(
             );
             Ok(())
         }
+        SwarmEvent::ConnectionEstablished { .. } => {
+            record_connection(app, ConnectionEvent::Established).await;
+            Ok(())
+        }
+        SwarmEvent::ConnectionClosed {
+            peer_id,
+            num_established,
+            ..
+        } => {
+            let mut connections = app.connections.write().await;
+            connections.record(ConnectionEvent::Closed, Instant::now());
+            if num_established == 0 {
+                connections.forget(&peer_id.to_string());
+            }
+            Ok(())
+        }
+        SwarmEvent::OutgoingConnectionError { error, .. } => {
+            let reason = dial_failure_reason(&error);
+            record_connection(app, ConnectionEvent::DialFailed(reason)).await;
+            Ok(())
+        }
+        SwarmEvent::IncomingConnectionError { .. } => {
+            record_connection(app, ConnectionEvent::DialFailed("incoming")).await;
+            Ok(())
+        }
         SwarmEvent::Behaviour(PeerBehaviour::Identify(event)) => handle_identify(event, app).await,
         SwarmEvent::Behaviour(PeerBehaviour::Gossipsub(event)) => {
             handle_gossipsub(event, app).await
         }
         SwarmEvent::Behaviour(PeerBehaviour::Kademlia(event)) => handle_kademlia(event, app).await,
+        SwarmEvent::Behaviour(PeerBehaviour::Votes(event)) => handle_votes(*event, app).await,
         _ => Ok(()),
     }
 }
 
+async fn record_connection(app: &App, event: ConnectionEvent) {
+    app.connections.write().await.record(event, Instant::now());
+}
+
 async fn handle_identify(event: IdentifyEvent, app: &App) -> Result<(), Box<dyn Error>> {
     if let IdentifyEvent::Received { peer_id, info } = event {
         info!("Received peer: {:?}", info);
 
+        app.peer_versions
+            .write()
+            .await
+            .insert(peer_id.to_string(), info.agent_version.clone());
+
+        if info.protocol_version == SEED_PROTOCOL_VERSION {
+            app.seeds.write().await.insert(peer_id.to_string());
+        }
+
+        let mut validators = app.validator_peers.write().await;
+        if announces_validator(&info) && app.may_validate(&peer_id.to_string()) {
+            validators.insert(peer_id.to_string());
+        } else {
+            validators.remove(&peer_id.to_string());
+        }
+        drop(validators);
+
         if info
             .protocols
             .iter()
@@ -104,35 +182,149 @@ async fn handle_identify(event: IdentifyEvent, app: &App) -> Result<(), Box<dyn
     Ok(())
 }
 
+/// Whether a peer identified as a validator: a chess node announcing the
+/// validator protocol version and speaking gossipsub. Seeds and other nodes
+/// of the DHT do not, however many protocols they share with us.
+fn announces_validator(info: &IdentifyInfo) -> bool {
+    info.protocol_version == PROTOCOL_VERSION
+        && info.agent_version.starts_with("chess-node/")
+        && info.protocols.iter().any(|p| p.starts_with("/meshsub/"))
+}
+
 async fn handle_gossipsub(event: GossipsubEvent, app: &App) -> Result<(), Box<dyn Error>> {
-    if let GossipsubEvent::Message { message, .. } = event {
-        // TODO: maybe there are some ways to do this elegant w/o traits
-        if message.topic == START_TOPIC.hash() {
-            handle_start_event(message, app).await?;
-        } else if message.topic == PROPOSAL_TOPIC.hash() {
-            handle_proposal_event(message, app).await?;
-        } else if message.topic == QUORUM_TOPIC.hash() {
-            handle_quorum_event(message, app).await?;
-        } else if message.topic == DECISION_TOPIC.hash() {
-            handle_decision_event(message, app).await?;
-        } else if message.topic == COMMIT_TOPIC.hash() {
-            handle_commit_event(message, app).await?;
+    if let GossipsubEvent::Message {
+        propagation_source,
+        message,
+        ..
+    } = event
+    {
+        app.connections
+            .write()
+            .await
+            .add_gossip_bytes(&propagation_source.to_string(), message.data.len());
+        let verdict = app.inbound_limiter.write().await.check(
+            &propagation_source.to_string(),
+            message.topic.as_str(),
+            Instant::now(),
+        );
+        match verdict {
+            Verdict::Allow => {}
+            Verdict::Throttle => {
+                warn!(
+                    "Dropping {} messages from {} over its rate limit",
+                    message.topic, propagation_source
+                );
+                return Ok(());
+            }
+            Verdict::Drop => return Ok(()),
+        }
+
+        app.record(Input::Gossip {
+            topic: message.topic.to_string(),
+            source: message.source.map(|p| p.to_string()),
+            data: hex::encode(&message.data),
+        });
+        // Seeds only relay; gossipsub forwards the message either way. Our own
+        // messages were already acted on where we published them, so one
+        // handed back to us must not count a second time.
+        if !app.seed && !is_own(&message, app) {
+            match &app.decoder {
+                Some(decoder) => decoder.submit(message),
+                None => handle_gossip_message(message, app).await?,
+            }
         }
     }
 
     Ok(())
 }
 
-async fn handle_start_event(message: GossipsubMessage, app: &App) -> Result<(), Box<dyn Error>> {
-    let msg = String::from_utf8_lossy(&message.data);
-    let req: StartRequest = serde_json::from_str(&msg)?;
-    app.start_game_if_possible(req).await?;
+fn is_own(message: &GossipsubMessage, app: &App) -> bool {
+    message
+        .source
+        .is_some_and(|p| app.local_peer_id.as_deref() == Some(p.to_string().as_str()))
+}
+
+/// Reads an ed25519 secret key stored as 64 hex characters.
+pub fn read_key(path: &str) -> Result<identity::Keypair, Box<dyn Error>> {
+    let bytes = hex::decode(std::fs::read_to_string(path)?.trim())?;
+    let secret = identity::ed25519::SecretKey::from_bytes(bytes)?;
+    Ok(identity::Keypair::Ed25519(secret.into()))
+}
+
+/// A gossip message's payload, parsed according to its topic.
+#[derive(Debug)]
+pub enum Payload {
+    Proposal(Transaction),
+    Quorum(Block),
+    Decision(Commit),
+    Commit(Block),
+    Feedback(Rejection),
+}
+
+impl Payload {
+    /// Parses the protobuf `data` published on `topic`. None for topics the
+    /// node does not act on.
+    pub fn decode(topic: &TopicHash, data: &[u8]) -> Result<Option<Self>, DecodeError> {
+        let payload = if *topic == PROPOSAL_TOPIC.hash() {
+            Self::Proposal(Message::decode(data)?)
+        } else if *topic == QUORUM_TOPIC.hash() {
+            Self::Quorum(Message::decode(data)?)
+        } else if *topic == DECISION_TOPIC.hash() {
+            Self::Decision(Message::decode(data)?)
+        } else if *topic == COMMIT_TOPIC.hash() {
+            Self::Commit(Message::decode(data)?)
+        } else if *topic == FEEDBACK_TOPIC.hash() {
+            Self::Feedback(Message::decode(data)?)
+        } else {
+            return Ok(None);
+        };
+        Ok(Some(payload))
+    }
+}
+
+/// Parses and acts on a gossip message in one go.
+pub async fn handle_gossip_message(
+    message: GossipsubMessage,
+    app: &App,
+) -> Result<(), Box<dyn Error>> {
+    match Payload::decode(&message.topic, &message.data)? {
+        Some(payload) => handle_payload(payload, message.source, app).await,
+        None => Ok(()),
+    }
+}
+
+/// Acts on a parsed gossip message first published by `source`.
+pub async fn handle_payload(
+    payload: Payload,
+    source: Option<PeerId>,
+    app: &App,
+) -> Result<(), Box<dyn Error>> {
+    match payload {
+        Payload::Proposal(tx) => handle_proposal_event(tx, app).await?,
+        Payload::Quorum(block) => handle_quorum_event(block, source, app).await?,
+        Payload::Decision(commit) => handle_decision_event(commit, source, app).await?,
+        Payload::Commit(block) => handle_commit_event(block, source, app).await?,
+        Payload::Feedback(rejection) => app.add_rejection(rejection).await?,
+    }
+
     Ok(())
 }
 
-async fn handle_proposal_event(message: GossipsubMessage, app: &App) -> Result<(), Box<dyn Error>> {
-    let msg = String::from_utf8_lossy(&message.data);
-    let tx: Transaction = serde_json::from_str(&msg)?;
+async fn handle_proposal_event(tx: Transaction, app: &App) -> Result<(), Box<dyn Error>> {
+    // Only one that checks out takes a place in the mempool, so its signer,
+    // which the sender quota counts, is a player and not a made-up key. The
+    // leader still proposes or rejects the others below.
+    if app.is_valid_tx(&tx, app.now().timestamp()).await.is_ok() {
+        // A full mempool does not take it, so neither does this node's block.
+        if let Err(retry_after) = app.txs.write().await.admit(&tx, Instant::now()) {
+            warn!(
+                "Mempool full, dropping {} for {:?}",
+                tx.tx_hash(),
+                retry_after
+            );
+            return Ok(());
+        }
+    }
 
     if app.get_current_leader().await? == app.local_peer_id.clone().unwrap() {
         broadcast_block(app, &tx).await?;
@@ -142,91 +334,94 @@ async fn handle_proposal_event(message: GossipsubMessage, app: &App) -> Result<(
 }
 
 pub async fn broadcast_block(app: &App, tx: &Transaction) -> Result<(), Box<dyn Error>> {
-    match app.is_valid_tx(tx).await {
+    app.mark_received_by_leader(tx.tx_hash()).await;
+    match app.is_valid_tx(tx, app.now().timestamp()).await {
         Ok(_) => {
-            let block = BlockBuilder::default()
-                .with_previous_block_hash(app.latest_block_hash.read().await.clone())
-                .with_history(
-                    app.db
-                        .read()
-                        .await
-                        .get(&format!("{}:{}", tx.white_player, tx.black_player))
-                        .unwrap()
-                        .history
-                        .clone()
-                        .unwrap_or("".to_string()),
-                )
+            let mut block = BlockBuilder::default()
+                .with_previous_block_hash(app.consensus.read().await.head)
+                .with_history(app.history_of(tx).await?)
                 .with_tx(tx.clone())
                 .with_view_n(app.view_n.load(std::sync::atomic::Ordering::Relaxed) as u32)
+                .with_rules_version(app.rules_version_at(app.next_height()).await)
+                .with_height(app.next_height())
+                .with_timestamp(app.now().timestamp())
                 .build();
+            block.sign_as_leader()?;
 
-            app.publish(QUORUM_TOPIC.clone(), serde_json::to_string(&block)?)
+            app.vote_timer.write().await.propose(block.block_hash()?);
+            app.publish(QUORUM_TOPIC.clone(), block.encode_to_vec())
                 .await?;
 
-            app.state_votes
+            // Signed, so the QC vouches for the leader's vote as well.
+            let hash = block.block_hash()?;
+            app.consensus
                 .write()
                 .await
-                .entry(block.hash)
-                .or_insert_with(HashSet::new)
-                .insert(app.local_peer_id.clone().unwrap());
+                .count_signed_vote(hash, sign_vote(&hash)?);
+            app.mark_proposed(tx.tx_hash(), block.view_n, hash).await;
 
             Ok(())
         }
-        Err(e) => Err(Box::new(e)),
+        Err(e) => {
+            let view_n = app.view_n.load(std::sync::atomic::Ordering::Relaxed) as u32;
+            app.reject(tx, &e, view_n).await?;
+            Err(Box::new(e))
+        }
     }
 }
 
-async fn handle_quorum_event(message: GossipsubMessage, app: &App) -> Result<(), AppError> {
-    let msg = String::from_utf8_lossy(&message.data);
-    let block: Block =
-        serde_json::from_str(&msg).map_err(|e| AppError::SwarmError(e.to_string()))?;
-    let source = message.source.unwrap().to_string();
-    let result = app.approve_proposal(block.clone(), source.clone()).await;
-
-    app.state_votes
-        .write()
-        .await
-        .entry(block.hash)
-        .or_insert(HashSet::new())
-        .insert(source);
+async fn handle_quorum_event(
+    block: Block,
+    source: Option<PeerId>,
+    app: &App,
+) -> Result<(), AppError> {
+    let leader = source.unwrap();
+    let source = leader.to_string();
+    let hash = block.block_hash()?;
+    let result = app.approve_proposal(block.clone()).await;
+
+    if app.is_validator(&source).await {
+        app.count_vote(hash, source).await;
+    }
 
-    let hash = block.hash;
+    if let Err(ref e) = result {
+        app.reject(block.transaction()?, e, block.view_n).await?;
+    }
 
     let commit = Commit {
-        block,
+        block: Some(block),
         decision: result.is_ok(),
     };
 
     if result.is_ok() {
-        app.state_votes
-            .write()
-            .await
-            .entry(hash)
-            .or_insert_with(HashSet::new)
-            .insert(app.local_peer_id.clone().unwrap());
+        let tx_hash = commit.block()?.transaction()?.tx_hash();
+        app.mark_proposed(tx_hash, commit.block()?.view_n, hash)
+            .await;
+        app.count_vote(hash, app.local_peer_id.clone().unwrap())
+            .await;
     }
 
-    let publishing_message =
-        serde_json::to_string(&commit).map_err(|e| AppError::SwarmError(e.to_string()))?;
-
-    app.publish(DECISION_TOPIC.clone(), publishing_message)
-        .await?;
+    if !app.direct_votes {
+        app.publish(DECISION_TOPIC.clone(), commit.encode_to_vec())
+            .await?;
+    } else if let (Ok(()), Some(block)) = (&result, commit.block) {
+        app.send_vote(leader, block).await?;
+    }
 
     result
 }
 
-async fn handle_decision_event(message: GossipsubMessage, app: &App) -> Result<(), Box<dyn Error>> {
-    let msg = String::from_utf8_lossy(&message.data);
-    let commit: Commit = serde_json::from_str(&msg)?;
-
-    if let Some(source) = message.source {
-        if commit.decision {
-            app.state_votes
-                .write()
-                .await
-                .entry(commit.block.hash)
-                .or_insert_with(HashSet::new)
-                .insert(source.to_string());
+async fn handle_decision_event(
+    commit: Commit,
+    source: Option<PeerId>,
+    app: &App,
+) -> Result<(), Box<dyn Error>> {
+    if let Some(source) = source {
+        if commit.decision && app.is_validator(&source.to_string()).await {
+            let hash = commit.block()?.block_hash()?;
+            if app.count_vote(hash, source.to_string()).await {
+                app.record_vote(&hash).await;
+            }
         }
     }
 
@@ -237,83 +432,87 @@ async fn handle_decision_event(message: GossipsubMessage, app: &App) -> Result<(
     Ok(())
 }
 
-async fn handle_commitment(commit: Commit, app: &App) -> Result<(), Box<dyn Error>> {
-    if app.view_n.load(std::sync::atomic::Ordering::Relaxed) == commit.block.view_n as usize
-        && app
-            .state_votes
-            .read()
-            .await
-            .get(&commit.block.hash)
-            .is_some_and(|v| v.len() > (2 * PEERS as usize) / 3)
-    {
-        let mut b = commit.block;
+pub async fn handle_commitment(commit: Commit, app: &App) -> Result<(), Box<dyn Error>> {
+    let hash = commit.block()?.block_hash()?;
+
+    let in_view =
+        app.view_n.load(std::sync::atomic::Ordering::Relaxed) == commit.block()?.view_n as usize;
+    let (quorum, signed_votes) = {
+        let consensus = app.consensus.read().await;
+        (consensus.quorum(&hash), consensus.signed_votes(&hash))
+    };
+
+    if let Some(voters) = quorum.filter(|_| in_view) {
+        let mut b = commit.block.unwrap();
         let qc = QuorumCertificate::default()
-            .with_block_hash(b.hash)
-            .with_signature(
-                app.state_votes
-                    .read()
-                    .await
-                    .get(&b.hash)
-                    .unwrap()
-                    .iter()
-                    .cloned()
-                    .collect::<Vec<String>>(),
-            );
+            .with_block_hash(hash)
+            .with_signature(voters)
+            .with_votes(signed_votes);
         b.qc = Some(qc);
+        b.vote_latency = app.vote_timer.write().await.finish(&hash);
 
-        app.publish(COMMIT_TOPIC.clone(), serde_json::to_string(&b)?)
-            .await?;
-
-        app.view_n
-            .store(b.view_n as usize + 1, std::sync::atomic::Ordering::Relaxed);
+        app.publish(COMMIT_TOPIC.clone(), b.encode_to_vec()).await?;
 
+        app.advance_view_past(&b).await?;
         app.commit_block(b).await?;
     }
 
     Ok(())
 }
 
-async fn handle_commit_event(message: GossipsubMessage, app: &App) -> Result<(), Box<dyn Error>> {
-    let msg = String::from_utf8_lossy(&message.data);
-    let block: Block = serde_json::from_str(&msg)?;
-
-    if app.view_n.load(std::sync::atomic::Ordering::Relaxed) == block.clone().view_n as usize
-        && app.get_current_leader().await? == message.source.unwrap().to_string()
+async fn handle_commit_event(
+    block: Block,
+    source: Option<PeerId>,
+    app: &App,
+) -> Result<(), Box<dyn Error>> {
+    // A lagging node may catch up to a later view, but only on a block from
+    // that view's leader whose QC it can verify.
+    if app.view_n.load(std::sync::atomic::Ordering::Relaxed) <= block.view_n as usize
+        && app.leader_of(block.view_n as usize).await? == source.unwrap().to_string()
     {
-        app.view_n.store(
-            block.view_n as usize + 1,
-            std::sync::atomic::Ordering::Relaxed,
-        );
-        app.commit_block(block.clone()).await?;
+        app.advance_view_past(&block).await?;
+        app.commit_block(block).await?;
     }
 
     Ok(())
 }
 
 async fn handle_kademlia(event: KademliaEvent, app: &App) -> Result<(), Box<dyn Error>> {
-    match event {
-        KademliaEvent::RoutingUpdated {
-            peer, addresses, ..
-        } => {
-            for a in addresses.iter() {
-                app.swarm_tx
-                    .send(SwarmMessageType::AddAddress(peer, a.clone()))
-                    .await?;
-            }
-            let _ = app.swarm_tx.send(SwarmMessageType::Bootstrap).await;
+    if let KademliaEvent::RoutingUpdated {
+        peer, addresses, ..
+    } = event
+    {
+        for a in addresses.iter() {
+            app.swarm_tx
+                .send(SwarmMessageType::AddAddress(peer, a.clone()))
+                .await?;
         }
-        _ => {}
+        let _ = app.swarm_tx.send(SwarmMessageType::Bootstrap).await;
     }
     Ok(())
 }
 
-pub async fn create_behaviour() -> Result<Behaviour, Box<dyn Error>> {
+/// Builds the node's behaviour. Nodes suggest other peers to those they prune
+/// from an overfull mesh, which is how a busy seed hands newcomers on to
+/// validators; seeds also announce themselves through identify. Publishing
+/// goes through the mesh, which small validator sets bypass by making every
+/// validator an explicit peer.
+pub async fn create_behaviour(seed: bool) -> Result<Behaviour, Box<dyn Error>> {
+    let mut config = GossipsubConfigBuilder::default();
+    config
+        .heartbeat_interval(Duration::from_secs(10))
+        .validation_mode(ValidationMode::Strict)
+        .mesh_n(MESH_N)
+        .mesh_n_low(MESH_N_LOW)
+        .mesh_n_high(MESH_N_HIGH)
+        .gossip_lazy(GOSSIP_LAZY)
+        .flood_publish(false)
+        .do_px()
+        .prune_peers(PX_PEERS);
+
     let mut gossipsub = Gossipsub::new(
         MessageAuthenticity::Signed(LOCAL_KEYS.clone()),
-        GossipsubConfigBuilder::default()
-            .heartbeat_interval(Duration::from_secs(10))
-            .validation_mode(ValidationMode::Strict)
-            .build()?,
+        config.build()?,
     )?;
 
     for topic in [
@@ -321,7 +520,7 @@ pub async fn create_behaviour() -> Result<Behaviour, Box<dyn Error>> {
         &QUORUM_TOPIC,
         &COMMIT_TOPIC,
         &DECISION_TOPIC,
-        &START_TOPIC,
+        &FEEDBACK_TOPIC,
     ] {
         gossipsub.subscribe(topic)?;
     }
@@ -331,14 +530,23 @@ pub async fn create_behaviour() -> Result<Behaviour, Box<dyn Error>> {
         MemoryStore::new(LOCAL_KEYS.public().to_peer_id()),
     );
 
-    let identify = Identify::new(IdentifyConfig::new(
-        "ipfs/1.0.0".to_string(),
-        LOCAL_KEYS.public(),
-    ));
+    let identify = Identify::new(
+        IdentifyConfig::new(
+            if seed {
+                SEED_PROTOCOL_VERSION
+            } else {
+                PROTOCOL_VERSION
+            }
+            .to_string(),
+            LOCAL_KEYS.public(),
+        )
+        .with_agent_version(agent_version()),
+    );
 
     Ok(Behaviour {
         gossipsub,
         kademlia,
         identify,
+        votes: create_votes_behaviour(),
     })
 }
@@ -0,0 +1,113 @@
+//! Inbound gossip rate limits, checked before a message is parsed. Each peer
+//! that forwards us messages gets a token bucket per topic; a message that
+//! finds its bucket empty is dropped and sets the bucket back further, so a
+//! peer that keeps flooding stays muted until it slows down.
+
+use std::collections::HashMap;
+use std::time::Instant;
+
+/// Messages a peer may forward on one topic at once.
+const BURST: f64 = 64.0;
+
+/// Messages per second a peer may forward on one topic in the long run.
+const RATE: f64 = 32.0;
+
+/// Tokens a dropped message costs on top of the one it would have taken.
+const PENALTY: f64 = 1.0;
+
+/// How far into debt a bucket may go, so a peer is muted at most
+/// `DEBT_LIMIT / RATE` seconds after it stops.
+const DEBT_LIMIT: f64 = 4.0 * BURST;
+
+/// Idle buckets are forgotten once there are this many.
+const MAX_BUCKETS: usize = 4096;
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * RATE).min(BURST);
+        self.updated = now;
+    }
+}
+
+#[derive(Default)]
+pub struct InboundLimiter {
+    buckets: HashMap<(String, String), Bucket>,
+}
+
+/// What became of one inbound message.
+#[derive(Debug, PartialEq, Eq)]
+pub enum Verdict {
+    Allow,
+    /// Dropped, and the first drop since the peer was last within its limit.
+    Throttle,
+    Drop,
+}
+
+impl InboundLimiter {
+    /// Takes a token for a message `peer` forwarded on `topic` at `now`.
+    pub fn check(&mut self, peer: &str, topic: &str, now: Instant) -> Verdict {
+        if self.buckets.len() >= MAX_BUCKETS {
+            self.buckets.retain(|_, bucket| {
+                bucket.refill(now);
+                bucket.tokens < BURST
+            });
+        }
+
+        let bucket = self
+            .buckets
+            .entry((peer.to_string(), topic.to_string()))
+            .or_insert(Bucket {
+                tokens: BURST,
+                updated: now,
+            });
+        bucket.refill(now);
+
+        let was_within = bucket.tokens >= 0.0;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Verdict::Allow;
+        }
+
+        bucket.tokens = (bucket.tokens - 1.0 - PENALTY).max(-DEBT_LIMIT);
+        if was_within {
+            Verdict::Throttle
+        } else {
+            Verdict::Drop
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_floods_are_dropped_and_penalized() {
+        let mut limiter = InboundLimiter::default();
+        let start = Instant::now();
+        let check = |limiter: &mut InboundLimiter, topic, at| {
+            limiter.check("peer", topic, start + Duration::from_secs_f64(at))
+        };
+
+        for _ in 0..BURST as usize {
+            assert_eq!(check(&mut limiter, "move", 0.0), Verdict::Allow);
+        }
+        assert_eq!(check(&mut limiter, "move", 0.0), Verdict::Throttle);
+        assert_eq!(check(&mut limiter, "move", 0.0), Verdict::Drop);
+        // Other topics and peers keep their own buckets.
+        assert_eq!(check(&mut limiter, "quorum", 0.0), Verdict::Allow);
+        assert_eq!(limiter.check("other peer", "move", start), Verdict::Allow);
+
+        // The two drops left the bucket four tokens in debt, which is paid
+        // off before a message passes again.
+        assert_eq!(check(&mut limiter, "move", 4.5 / RATE), Verdict::Throttle);
+        assert_eq!(check(&mut limiter, "move", 7.0 / RATE), Verdict::Allow);
+    }
+}
@@ -0,0 +1,232 @@
+use super::{
+    backend::NodeServicer,
+    p2p::handle_gossip_message,
+    votes::{handle_vote, Vote},
+};
+use crate::{App, CONNECTED_PEERS};
+use chrono::{DateTime, TimeZone, Utc};
+use libp2p::gossipsub::{GossipsubMessage, TopicHash};
+use prost::Message;
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs::File;
+use std::io::{BufRead, BufReader, LineWriter, Write};
+use std::path::Path;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+use tracing::{error, info};
+
+/// One input of the node, in the order it was handled.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Input {
+    /// Identity of the recording node; always the first entry.
+    Node { peer_id: String },
+    /// Connected peer set after a bootstrap, which decides the leader.
+    Peers { peers: Vec<String> },
+    /// A gossip message, its protobuf payload as hex.
+    Gossip {
+        topic: String,
+        source: Option<String>,
+        data: String,
+    },
+    /// A vote sent to us directly.
+    Vote { source: String, vote: Box<Vote> },
+    /// gRPC request by method name, prost encoded as hex.
+    Rpc { method: String, request: String },
+}
+
+#[derive(Serialize, Deserialize)]
+struct Entry {
+    /// Milliseconds since the epoch when the input arrived.
+    at: i64,
+    #[serde(flatten)]
+    input: Input,
+}
+
+/// Appends every input of the node to a JSON lines file.
+pub struct Recorder {
+    file: Mutex<LineWriter<File>>,
+}
+
+impl Recorder {
+    pub fn create(path: impl AsRef<Path>, peer_id: String) -> std::io::Result<Self> {
+        let recorder = Self {
+            file: Mutex::new(LineWriter::new(File::create(path)?)),
+        };
+        recorder.record(Input::Node { peer_id });
+        Ok(recorder)
+    }
+
+    pub fn record(&self, input: Input) {
+        let entry = Entry {
+            at: Utc::now().timestamp_millis(),
+            input,
+        };
+
+        let mut file = self.file.lock().unwrap();
+        if let Err(e) = serde_json::to_writer(&mut *file, &entry)
+            .and_then(|_| file.write_all(b"\n").map_err(serde_json::Error::io))
+        {
+            error!("Failed to record input: {:?}", e);
+        }
+    }
+}
+
+impl App {
+    /// The time handlers act at: the wall clock, or while replaying, when
+    /// the input being replayed arrived, so they decide as they did live.
+    pub fn now(&self) -> DateTime<Utc> {
+        match self.replay_clock.load(Ordering::Relaxed) {
+            0 => Utc::now(),
+            at => Utc.timestamp_millis_opt(at).unwrap(),
+        }
+    }
+
+    pub fn record(&self, input: Input) {
+        if let Some(recorder) = &self.recorder {
+            recorder.record(input);
+        }
+    }
+
+    pub fn record_rpc(&self, method: &str, request: &impl Message) {
+        if self.recorder.is_some() {
+            self.record(Input::Rpc {
+                method: method.to_string(),
+                request: hex::encode(request.encode_to_vec()),
+            });
+        }
+    }
+}
+
+/// Feeds a recording back through the handlers one input at a time, without
+/// waiting between them. Publishes are dropped by the caller. Handlers see
+/// the time each input was recorded at, and views rotate once a recorded
+/// second as the node's timer rotated them.
+pub async fn replay(
+    path: impl AsRef<Path>,
+    app: &App,
+    servicer: &NodeServicer,
+) -> Result<(), Box<dyn Error>> {
+    for (n, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+        let entry: Entry = serde_json::from_str(&line?)?;
+        info!(
+            "Replaying #{} recorded at {}: {:?}",
+            n, entry.at, entry.input
+        );
+
+        let last = app.replay_clock.load(Ordering::Relaxed);
+        if last != 0 {
+            for second in last / 1000 + 1..=entry.at / 1000 {
+                app.replay_clock.store(second * 1000, Ordering::Relaxed);
+                app.update_view_if_needed().await;
+            }
+        }
+        app.replay_clock.store(entry.at, Ordering::Relaxed);
+
+        let result: Result<(), Box<dyn Error>> = match entry.input {
+            Input::Node { .. } => {
+                // The node started as it began recording.
+                app.consensus.write().await.latest_timestamp = (entry.at / 1000) as u64;
+                Ok(())
+            }
+            Input::Peers { peers } => {
+                CONNECTED_PEERS.write().await.clone_from(&peers);
+                Ok(())
+            }
+            Input::Gossip {
+                topic,
+                source,
+                data,
+            } => {
+                let message = GossipsubMessage {
+                    source: source.map(|s| s.parse()).transpose()?,
+                    data: hex::decode(data)?,
+                    sequence_number: None,
+                    topic: TopicHash::from_raw(topic),
+                };
+                handle_gossip_message(message, app).await
+            }
+            Input::Vote { source, vote } => handle_vote(*vote, source.parse()?, app).await,
+            Input::Rpc { method, request } => {
+                servicer.replay(&method, &hex::decode(request)?).await
+            }
+        };
+
+        if let Err(e) = result {
+            error!("#{}: {:?}", n, e);
+        }
+    }
+
+    Ok(())
+}
+
+/// Reads the peer id of the node a recording was taken on.
+pub fn recorded_peer_id(path: impl AsRef<Path>) -> Result<String, Box<dyn Error>> {
+    let mut line = String::new();
+    BufReader::new(File::open(path)?).read_line(&mut line)?;
+
+    match serde_json::from_str::<Entry>(&line)?.input {
+        Input::Node { peer_id } => Ok(peer_id),
+        _ => Err("recording does not start with the node entry".into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::network::backend::NodeServicerBuilder;
+
+    #[test]
+    fn test_recording_starts_with_node_entry() {
+        let path = std::env::temp_dir().join(format!("record-{}.jsonl", std::process::id()));
+        let recorder = Recorder::create(&path, "peer".into()).unwrap();
+        recorder.record(Input::Peers {
+            peers: vec!["peer".into()],
+        });
+        drop(recorder);
+
+        assert_eq!(recorded_peer_id(&path).unwrap(), "peer");
+        let lines = std::fs::read_to_string(&path).unwrap();
+        assert!(lines.lines().nth(1).unwrap().contains(r#""kind":"peers""#));
+
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_replay_runs_on_the_recorded_clock() {
+        let path = std::env::temp_dir().join(format!("replay-{}.jsonl", std::process::id()));
+        let started = 1_700_000_000_000;
+        let entries = [
+            (
+                started,
+                Input::Node {
+                    peer_id: "peer".into(),
+                },
+            ),
+            (
+                started + 25_500,
+                Input::Rpc {
+                    method: "State".into(),
+                    request: String::new(),
+                },
+            ),
+        ];
+        let lines: Vec<String> = entries
+            .into_iter()
+            .map(|(at, input)| serde_json::to_string(&Entry { at, input }).unwrap())
+            .collect();
+        std::fs::write(&path, lines.join("\n")).unwrap();
+
+        let app: &'static App = Box::leak(Box::new(App::new(tokio::sync::mpsc::channel(1).0)));
+        app.consensus.write().await.head = [1; 32].into();
+        let servicer = NodeServicerBuilder::default().with_app(app).build();
+        replay(&path, app, &servicer).await.unwrap();
+
+        // Views rotated 10 and 20 recorded seconds in, as the timer did live.
+        assert_eq!(app.view_n.load(Ordering::Relaxed), 2);
+        assert_eq!(app.now().timestamp_millis(), started + 25_500);
+
+        std::fs::remove_file(path).unwrap();
+    }
+}
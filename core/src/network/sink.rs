@@ -0,0 +1,183 @@
+//! Pushes committed blocks and the games they change to NATS, so larger
+//! deployments can feed them into their own pipelines. Built with the `nats`
+//! feature and switched on with `--nats`.
+
+use super::admin::BlockSummary;
+use crate::{chess::diff::BoardDiff, consensus::types::Block, pb::game::GameState};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::sync::mpsc;
+use tracing::{error, info, warn};
+
+/// Events waiting for the connection; more are dropped rather than slowing
+/// down consensus.
+const QUEUE: usize = 1024;
+const RECONNECT_DELAY: Duration = Duration::from_secs(5);
+
+/// NATS subject of each event kind.
+#[derive(Clone, Debug)]
+pub struct Subjects {
+    pub block: String,
+    pub game: String,
+}
+
+impl Default for Subjects {
+    fn default() -> Self {
+        Self {
+            block: "chess.blocks".into(),
+            game: "chess.games".into(),
+        }
+    }
+}
+
+impl Subjects {
+    /// Applies a `kind=subject` mapping, e.g. `block=prod.chess.blocks`.
+    pub fn map(&mut self, mapping: &str) -> Result<(), String> {
+        let (kind, subject) = mapping
+            .split_once('=')
+            .filter(|(_, s)| !s.is_empty() && !s.contains(char::is_whitespace))
+            .ok_or_else(|| format!("expected kind=subject, got {}", mapping))?;
+
+        match kind {
+            "block" => self.block = subject.into(),
+            "game" => self.game = subject.into(),
+            _ => return Err(format!("unknown event kind {}", kind)),
+        }
+        Ok(())
+    }
+}
+
+pub struct NatsSink {
+    subjects: Subjects,
+    tx: mpsc::Sender<(String, String)>,
+}
+
+impl NatsSink {
+    /// Starts publishing to the server at `url`, reconnecting as needed.
+    pub fn spawn(url: &str, subjects: Subjects) -> Self {
+        let addr = url.trim_start_matches("nats://").to_string();
+        let (tx, rx) = mpsc::channel(QUEUE);
+        tokio::spawn(run(addr, rx));
+
+        Self { subjects, tx }
+    }
+
+    /// Publishes a committed block with the diff of its move and, if it is
+    /// public, the game after it. Dark games are published as a spectator
+    /// sees them.
+    pub fn publish_block(
+        &self,
+        height: u64,
+        block: &Block,
+        diff: Option<&BoardDiff>,
+        game_key: &str,
+        game: &GameState,
+    ) {
+        let summary = serde_json::to_string(&BlockSummary::new(height, block, diff));
+        self.send(&self.subjects.block, summary);
+
+        if game.is_visible_to(None) {
+            let event = serde_json::json!({
+                "height": height,
+                "game": game_key,
+                "state": game.view_for(None),
+            });
+            self.send(&self.subjects.game, Ok(event.to_string()));
+        }
+    }
+
+    fn send(&self, subject: &str, payload: serde_json::Result<String>) {
+        let Ok(payload) = payload else {
+            return;
+        };
+
+        if self.tx.try_send((subject.to_string(), payload)).is_err() {
+            warn!("NATS sink is falling behind, dropping an event");
+        }
+    }
+}
+
+fn frame(subject: &str, payload: &str) -> String {
+    format!("PUB {} {}\r\n{}\r\n", subject, payload.len(), payload)
+}
+
+async fn run(addr: String, mut rx: mpsc::Receiver<(String, String)>) {
+    loop {
+        match publish(&addr, &mut rx).await {
+            Ok(()) => return,
+            Err(e) => error!("NATS connection to {} failed: {}", addr, e),
+        }
+        tokio::time::sleep(RECONNECT_DELAY).await;
+    }
+}
+
+/// Publishes events over one connection until it fails or the node stops
+/// sending events.
+async fn publish(
+    addr: &str,
+    rx: &mut mpsc::Receiver<(String, String)>,
+) -> Result<(), std::io::Error> {
+    let (read, mut write) = TcpStream::connect(addr).await?.into_split();
+    let mut lines = BufReader::new(read).lines();
+
+    // The server opens with INFO and expects CONNECT before anything else.
+    lines.next_line().await?;
+    write
+        .write_all(b"CONNECT {\"verbose\":false,\"pedantic\":false}\r\n")
+        .await?;
+    info!("Publishing events to NATS at {}", addr);
+
+    loop {
+        tokio::select! {
+            line = lines.next_line() => match line? {
+                Some(line) if line.starts_with("PING") => write.write_all(b"PONG\r\n").await?,
+                Some(line) if line.starts_with("-ERR") => warn!("NATS: {}", line),
+                Some(_) => {}
+                None => return Err(std::io::ErrorKind::UnexpectedEof.into()),
+            },
+            event = rx.recv() => match event {
+                Some((subject, payload)) => write.write_all(frame(&subject, &payload).as_bytes()).await?,
+                None => return Ok(()),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpListener;
+
+    #[test]
+    fn test_subject_mappings() {
+        let mut subjects = Subjects::default();
+        subjects.map("game=prod.games").unwrap();
+        assert_eq!(subjects.game, "prod.games");
+        assert_eq!(subjects.block, "chess.blocks");
+
+        assert!(subjects.map("moves=x").is_err());
+        assert!(subjects.map("block").is_err());
+        assert!(subjects.map("block=a b").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_publishes_to_nats() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("nats://{}", listener.local_addr().unwrap());
+        let sink = NatsSink::spawn(&url, Subjects::default());
+
+        let (mut socket, _) = listener.accept().await.unwrap();
+        socket.write_all(b"INFO {}\r\n").await.unwrap();
+        sink.send("chess.blocks", Ok("{}".into()));
+
+        let expected = format!(
+            "CONNECT {{\"verbose\":false,\"pedantic\":false}}\r\n{}",
+            frame("chess.blocks", "{}")
+        );
+        let mut received = vec![0; expected.len()];
+        socket.read_exact(&mut received).await.unwrap();
+        assert_eq!(String::from_utf8(received).unwrap(), expected);
+    }
+}
@@ -0,0 +1,114 @@
+//! systemd's notify protocol, for nodes run as `Type=notify` services with a
+//! `WatchdogSec`. Without `NOTIFY_SOCKET` in the environment every call here
+//! does nothing, so nodes started by hand or in containers are unaffected.
+
+use crate::PEERS;
+use std::time::Duration;
+use tracing::{info, warn};
+
+/// Sends `state`, e.g. "READY=1", to the service manager. Returns whether
+/// there was one to send it to.
+pub fn notify(state: &str) -> bool {
+    let Some(path) = std::env::var_os("NOTIFY_SOCKET") else {
+        return false;
+    };
+
+    match send(&path, state) {
+        Ok(()) => true,
+        Err(e) => {
+            warn!("Could not notify the service manager: {}", e);
+            false
+        }
+    }
+}
+
+#[cfg(unix)]
+fn send(path: &std::ffi::OsStr, state: &str) -> std::io::Result<()> {
+    use std::os::unix::{ffi::OsStrExt, net::UnixDatagram};
+
+    let socket = UnixDatagram::unbound()?;
+    match path.as_bytes().strip_prefix(b"@") {
+        #[cfg(target_os = "linux")]
+        Some(name) => {
+            use std::os::linux::net::SocketAddrExt;
+            let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+            socket.send_to_addr(state.as_bytes(), &addr)?;
+        }
+        _ => {
+            socket.send_to(state.as_bytes(), path)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn send(_: &std::ffi::OsStr, _: &str) -> std::io::Result<()> {
+    Err(std::io::ErrorKind::Unsupported.into())
+}
+
+/// How often to ping the watchdog, half its timeout, if the service manager
+/// keeps one on this process.
+pub fn watchdog_interval() -> Option<Duration> {
+    let usec: u64 = std::env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+    if let Ok(pid) = std::env::var("WATCHDOG_PID") {
+        if pid.parse() != Ok(std::process::id()) {
+            return None;
+        }
+    }
+    Some(Duration::from_micros(usec / 2)).filter(|d| !d.is_zero())
+}
+
+/// Holds READY=1 back until the node is of use: the swarm listens and more
+/// than 2/3 of the validators are connected, enough for blocks to commit.
+#[derive(Default)]
+pub struct Readiness {
+    listening: bool,
+    validators: usize,
+    sent: bool,
+}
+
+impl Readiness {
+    pub fn listening(&mut self) {
+        self.listening = true;
+        self.notify_if_ready();
+    }
+
+    /// `validators` is the validator set, including this node if it is one.
+    pub fn validators(&mut self, validators: usize) {
+        self.validators = validators;
+        self.notify_if_ready();
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.listening && self.validators > (2 * PEERS as usize) / 3
+    }
+
+    fn notify_if_ready(&mut self) {
+        if !self.sent && self.is_ready() {
+            self.sent = true;
+            info!("Ready with {} validators", self.validators);
+            notify(&format!(
+                "READY=1\nSTATUS=Connected to {} validators",
+                self.validators
+            ));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ready_once_listening_with_a_quorum() {
+        let mut readiness = Readiness::default();
+        readiness.validators(PEERS as usize);
+        assert!(!readiness.is_ready());
+
+        readiness.listening();
+        readiness.validators((2 * PEERS as usize) / 3);
+        assert!(!readiness.is_ready());
+        readiness.validators((2 * PEERS as usize) / 3 + 1);
+        assert!(readiness.is_ready());
+    }
+}
@@ -1,7 +1,204 @@
-use libp2p::{gossipsub::IdentTopic, Multiaddr, PeerId};
+use super::votes::Vote;
+use libp2p::{
+    gossipsub::IdentTopic, multiaddr::Protocol, request_response::ResponseChannel, Multiaddr,
+    PeerId,
+};
+use serde::Serialize;
+use std::str::FromStr;
+use std::time::Duration;
+use tracing::warn;
+
+/// Attempts at resolving a peer's DNS name, a second apart, so peers started
+/// together have time to appear.
+const DNS_ATTEMPTS: u32 = 10;
 
 pub enum SwarmMessageType {
-    Publish(IdentTopic, String),
+    Publish(IdentTopic, Vec<u8>),
     AddAddress(PeerId, Multiaddr),
     Bootstrap,
+    /// Our vote, for the leader named.
+    Vote(PeerId, Box<Vote>),
+    /// Whether a vote sent to us was counted.
+    AckVote(ResponseChannel<bool>, bool),
+}
+
+/// Address families the node listens on and dials.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AddressFamily {
+    Ipv4,
+    Ipv6,
+    Dual,
+}
+
+impl AddressFamily {
+    /// Addresses to listen for peers on; port 0 picks a free one.
+    pub fn listen_addrs(self, port: u16) -> Vec<Multiaddr> {
+        let ipv4 = format!("/ip4/0.0.0.0/tcp/{}", port).parse().unwrap();
+        let ipv6 = format!("/ip6/::/tcp/{}", port).parse().unwrap();
+
+        match self {
+            Self::Ipv4 => vec![ipv4],
+            Self::Ipv6 => vec![ipv6],
+            Self::Dual => vec![ipv6, ipv4],
+        }
+    }
+
+    /// Host the gRPC and admin servers bind to.
+    pub fn bind_host(self) -> &'static str {
+        match self {
+            Self::Ipv4 => "0.0.0.0",
+            Self::Ipv6 | Self::Dual => "[::]",
+        }
+    }
+
+    /// Whether `addr` may be dialed; DNS names resolve to either family.
+    pub fn allows(self, addr: &Multiaddr) -> bool {
+        !matches!(
+            (self, addr.iter().next()),
+            (Self::Ipv4, Some(Protocol::Ip6(_) | Protocol::Dns6(_)))
+                | (Self::Ipv6, Some(Protocol::Ip4(_) | Protocol::Dns4(_)))
+        )
+    }
+
+    /// Allowed addresses of a peer in dialing order, IPv6 first so it wins
+    /// when both families are raced.
+    pub fn dial_order(self, mut addrs: Vec<Multiaddr>) -> Vec<Multiaddr> {
+        addrs.retain(|a| self.allows(a));
+        addrs.sort_by_key(|a| {
+            !matches!(a.iter().next(), Some(Protocol::Ip6(_) | Protocol::Dns6(_)))
+        });
+        addrs
+    }
+}
+
+/// Resolves a `/dns`, `/dns4` or `/dns6` address to the IP addresses behind
+/// it, as the transport only dials IPs. Other addresses come back as they are.
+pub async fn resolve(addr: Multiaddr) -> Vec<Multiaddr> {
+    let mut protocols = addr.iter();
+    let (host, ipv4) = match protocols.next() {
+        Some(Protocol::Dns(host)) => (host, None),
+        Some(Protocol::Dns4(host)) => (host, Some(true)),
+        Some(Protocol::Dns6(host)) => (host, Some(false)),
+        _ => return vec![addr.clone()],
+    };
+    let rest: Vec<_> = protocols.collect();
+
+    for attempt in 1..=DNS_ATTEMPTS {
+        match tokio::net::lookup_host((host.as_ref(), 0)).await {
+            Ok(ips) => {
+                return ips
+                    .map(|ip| ip.ip())
+                    .filter(|ip| ipv4.is_none_or(|v4| ip.is_ipv4() == v4))
+                    .map(|ip| {
+                        rest.iter()
+                            .cloned()
+                            .fold(Multiaddr::from(ip), Multiaddr::with)
+                    })
+                    .collect()
+            }
+            Err(e) if attempt == DNS_ATTEMPTS => warn!("Could not resolve {}: {}", addr, e),
+            Err(_) => tokio::time::sleep(Duration::from_secs(1)).await,
+        }
+    }
+
+    Vec::new()
+}
+
+impl FromStr for AddressFamily {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ipv4" => Ok(Self::Ipv4),
+            "ipv6" => Ok(Self::Ipv6),
+            "dual" => Ok(Self::Dual),
+            _ => Err(format!("unknown address family {}", s)),
+        }
+    }
+}
+
+/// Validator sets up to this size flood: every validator is an explicit
+/// gossipsub peer and gets each message straight from whoever sent it.
+pub const FLOOD_VALIDATORS: usize = 8;
+
+/// Gossipsub mesh degree used once the validator set outgrows flooding.
+pub const MESH_N: usize = 6;
+pub const MESH_N_LOW: usize = 4;
+pub const MESH_N_HIGH: usize = 12;
+pub const GOSSIP_LAZY: usize = 6;
+
+/// Gossip parameters in effect for the current validator set. Gossipsub
+/// fixes its mesh degree when it is built, so the set size decides between
+/// flooding through explicit peers and relying on that mesh.
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct GossipParams {
+    pub validators: usize,
+    pub flood: bool,
+    /// Peers a message is sent to directly.
+    pub fanout: usize,
+    pub mesh_n: usize,
+    pub mesh_n_low: usize,
+    pub mesh_n_high: usize,
+    pub gossip_lazy: usize,
+}
+
+impl GossipParams {
+    pub fn for_validators(validators: usize) -> Self {
+        let flood = validators <= FLOOD_VALIDATORS;
+
+        Self {
+            validators,
+            flood,
+            fanout: if flood {
+                validators.saturating_sub(1)
+            } else {
+                MESH_N
+            },
+            mesh_n: MESH_N,
+            mesh_n_low: MESH_N_LOW,
+            mesh_n_high: MESH_N_HIGH,
+            gossip_lazy: GOSSIP_LAZY,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_dial_order_prefers_ipv6() {
+        let addrs: Vec<Multiaddr> = ["/ip4/10.0.0.1/tcp/1", "/ip6/::1/tcp/1", "/dns4/a/tcp/1"]
+            .iter()
+            .map(|a| a.parse().unwrap())
+            .collect();
+
+        let dual = AddressFamily::Dual.dial_order(addrs.clone());
+        assert_eq!(dual[0].to_string(), "/ip6/::1/tcp/1");
+        assert_eq!(dual.len(), 3);
+
+        assert_eq!(AddressFamily::Ipv4.dial_order(addrs.clone()).len(), 2);
+        assert_eq!(AddressFamily::Ipv6.dial_order(addrs).len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_resolves_dns_addresses() {
+        let resolved = resolve("/dns4/localhost/tcp/4001".parse().unwrap()).await;
+        assert!(resolved.contains(&"/ip4/127.0.0.1/tcp/4001".parse().unwrap()));
+
+        let ip: Multiaddr = "/ip6/::1/tcp/1".parse().unwrap();
+        assert_eq!(resolve(ip.clone()).await, vec![ip]);
+    }
+
+    #[test]
+    fn test_gossip_params_follow_validator_count() {
+        let small = GossipParams::for_validators(4);
+        assert!(small.flood);
+        assert_eq!(small.fanout, 3);
+
+        let large = GossipParams::for_validators(50);
+        assert!(!large.flood);
+        assert_eq!(large.fanout, MESH_N);
+        assert_eq!(small.mesh_n, large.mesh_n);
+    }
 }
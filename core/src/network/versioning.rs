@@ -0,0 +1,45 @@
+//! Calls to the unversioned `query.Node` service, which older clients such as
+//! the web frontend still make, are served by the current API version.
+
+use tonic::codegen::http::{uri::PathAndQuery, Request, Uri};
+use tracing::debug;
+
+const LEGACY_SERVICE: &str = "/query.Node/";
+const CURRENT_SERVICE: &str = "/query.v1.Node/";
+
+/// Rewrites a request for a deprecated method name to its current one.
+pub fn route_legacy<B>(mut request: Request<B>) -> Request<B> {
+    let Some(method) = request.uri().path().strip_prefix(LEGACY_SERVICE) else {
+        return request;
+    };
+    debug!("Deprecated call to {}{}", LEGACY_SERVICE, method);
+
+    let path = format!("{}{}", CURRENT_SERVICE, method);
+    let mut parts = request.uri().clone().into_parts();
+    parts.path_and_query = path.parse::<PathAndQuery>().ok();
+    if let Ok(uri) = Uri::from_parts(parts) {
+        *request.uri_mut() = uri;
+    }
+
+    request
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn route(uri: &str) -> String {
+        let request = Request::builder().uri(uri).body(()).unwrap();
+        route_legacy(request).uri().to_string()
+    }
+
+    #[test]
+    fn test_legacy_service_routes_to_v1() {
+        assert_eq!(
+            route("http://node:50050/query.Node/State"),
+            "http://node:50050/query.v1.Node/State"
+        );
+        assert_eq!(route("/query.v1.Node/Start"), "/query.v1.Node/Start");
+        assert_eq!(route("/query.NodeX/Start"), "/query.NodeX/Start");
+    }
+}
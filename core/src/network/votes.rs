@@ -0,0 +1,188 @@
+//! Direct votes (--direct-votes). Gossiping every decision has each validator
+//! hear every other validator's vote, O(n²) messages a block. With direct
+//! votes a validator signs its vote and sends it to the leader alone over a
+//! request-response stream; the leader puts the signed votes in the QC it
+//! broadcasts, so the others can check the QC without having seen them.
+//! Votes count whichever way they arrive, so nodes can switch one at a time.
+
+use crate::{
+    consensus::types::{Block, Commit, QuorumCertificate},
+    errors::AppError,
+    network::{p2p::handle_commitment, recorder::Input, utils::SwarmMessageType},
+    pb::query::ValidatorSignature,
+    App,
+};
+use alloy_primitives::B256;
+use async_trait::async_trait;
+use futures::prelude::*;
+use libp2p::core::upgrade::{read_length_prefixed, write_length_prefixed};
+use libp2p::request_response::{
+    ProtocolName, ProtocolSupport, RequestResponse, RequestResponseCodec, RequestResponseConfig,
+    RequestResponseEvent, RequestResponseMessage,
+};
+use libp2p::PeerId;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::error::Error;
+use std::io;
+use tracing::warn;
+
+/// Largest vote read off a stream; a vote carries its block.
+const MAX_VOTE_SIZE: usize = 1 << 20;
+
+/// A validator's vote for `block`, signed over its hash.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Vote {
+    pub block: Block,
+    pub signature: ValidatorSignature,
+}
+
+impl Vote {
+    pub fn sign(block: Block) -> Result<Self, AppError> {
+        Ok(Self {
+            signature: sign_vote(&block.block_hash()?)?,
+            block,
+        })
+    }
+}
+
+/// This node's vote for the block with `hash`.
+pub fn sign_vote(hash: &B256) -> Result<ValidatorSignature, AppError> {
+    ValidatorSignature::sign(&QuorumCertificate::vote_payload(hash))
+}
+
+#[derive(Clone)]
+pub struct VoteProtocol;
+
+impl ProtocolName for VoteProtocol {
+    fn protocol_name(&self) -> &[u8] {
+        b"/chess/vote/1"
+    }
+}
+
+/// Votes as length-prefixed JSON; the leader answers whether it counted one.
+#[derive(Clone)]
+pub struct VoteCodec;
+
+#[async_trait]
+impl RequestResponseCodec for VoteCodec {
+    type Protocol = VoteProtocol;
+    type Request = Vote;
+    type Response = bool;
+
+    async fn read_request<T>(&mut self, _: &VoteProtocol, io: &mut T) -> io::Result<Vote>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        read_json(io).await
+    }
+
+    async fn read_response<T>(&mut self, _: &VoteProtocol, io: &mut T) -> io::Result<bool>
+    where
+        T: AsyncRead + Unpin + Send,
+    {
+        read_json(io).await
+    }
+
+    async fn write_request<T>(&mut self, _: &VoteProtocol, io: &mut T, vote: Vote) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        write_length_prefixed(io, serde_json::to_vec(&vote)?).await
+    }
+
+    async fn write_response<T>(
+        &mut self,
+        _: &VoteProtocol,
+        io: &mut T,
+        counted: bool,
+    ) -> io::Result<()>
+    where
+        T: AsyncWrite + Unpin + Send,
+    {
+        write_length_prefixed(io, serde_json::to_vec(&counted)?).await
+    }
+}
+
+async fn read_json<T: DeserializeOwned>(io: &mut (impl AsyncRead + Unpin + Send)) -> io::Result<T> {
+    let bytes = read_length_prefixed(io, MAX_VOTE_SIZE).await?;
+    serde_json::from_slice(&bytes).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+pub fn create_votes_behaviour() -> RequestResponse<VoteCodec> {
+    RequestResponse::new(
+        VoteCodec,
+        [(VoteProtocol, ProtocolSupport::Full)],
+        RequestResponseConfig::default(),
+    )
+}
+
+impl App {
+    /// Sends this node's vote for `block` to `leader`, who proposed it.
+    pub async fn send_vote(&self, leader: PeerId, block: Block) -> Result<(), AppError> {
+        self.swarm_tx
+            .send(SwarmMessageType::Vote(leader, Box::new(Vote::sign(block)?)))
+            .await
+            .map_err(|e| AppError::SwarmError(e.to_string()))
+    }
+}
+
+pub async fn handle_votes(
+    event: RequestResponseEvent<Vote, bool>,
+    app: &App,
+) -> Result<(), Box<dyn Error>> {
+    match event {
+        RequestResponseEvent::Message {
+            peer,
+            message:
+                RequestResponseMessage::Request {
+                    request, channel, ..
+                },
+        } => {
+            app.record(Input::Vote {
+                source: peer.to_string(),
+                vote: Box::new(request.clone()),
+            });
+            let result = handle_vote(request, peer, app).await;
+            app.swarm_tx
+                .send(SwarmMessageType::AckVote(channel, result.is_ok()))
+                .await?;
+            result?;
+        }
+        RequestResponseEvent::Message {
+            peer,
+            message:
+                RequestResponseMessage::Response {
+                    response: false, ..
+                },
+        } => warn!("Leader {} did not count our vote", peer),
+        RequestResponseEvent::OutboundFailure { peer, error, .. } => {
+            warn!("Could not send our vote to {}: {:?}", peer, error)
+        }
+        _ => {}
+    }
+
+    Ok(())
+}
+
+/// Counts a vote `source` sent us directly and, if we lead, certifies its
+/// block once enough votes are in.
+pub async fn handle_vote(vote: Vote, source: PeerId, app: &App) -> Result<(), Box<dyn Error>> {
+    if vote.signature.validator != source.to_string() {
+        return Err(format!("{} sent a vote signed by another validator", source).into());
+    }
+
+    let hash = vote.block.block_hash()?;
+    if app.count_signed_vote(hash, vote.signature).await? {
+        app.record_vote(&hash).await;
+    }
+
+    if app.get_current_leader().await? == app.local_peer_id.clone().unwrap() {
+        let commit = Commit {
+            decision: true,
+            block: Some(vote.block),
+        };
+        handle_commitment(commit, app).await?;
+    }
+
+    Ok(())
+}
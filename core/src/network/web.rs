@@ -0,0 +1,165 @@
+//! What browsers may do with the gRPC port. Out of the box only the local
+//! frontend may call it cross-origin, with just the methods and headers
+//! grpc-web needs; a public deployment lists its own origins.
+
+use serde::Deserialize;
+use tonic::codegen::http::{HeaderName, HeaderValue, Method};
+use tower_http::cors::{AllowHeaders, AllowMethods, AllowOrigin, Any, CorsLayer};
+
+/// Origin, method or header lists holding this allow any.
+const ANY: &str = "*";
+
+/// Where the frontend runs in development.
+const DEV_ORIGIN: &str = "http://localhost:3000";
+
+/// Response headers a grpc-web client reads the call's outcome from.
+const EXPOSED_HEADERS: [&str; 3] = ["grpc-status", "grpc-message", "grpc-status-details-bin"];
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields, rename_all = "camelCase")]
+pub struct WebConfig {
+    /// Origins that may call the node from a browser, or "*" for any.
+    #[serde(default = "default_origins")]
+    pub origins: Vec<String>,
+    /// Methods allowed cross-origin.
+    #[serde(default = "default_methods")]
+    pub methods: Vec<String>,
+    /// Request headers allowed cross-origin.
+    #[serde(default = "default_headers")]
+    pub headers: Vec<String>,
+    /// Translate grpc-web calls for the gRPC services.
+    #[serde(default = "enabled")]
+    pub grpc_web: bool,
+    /// Accept HTTP/1.1 connections, which grpc-web needs.
+    #[serde(default = "enabled")]
+    pub http1: bool,
+}
+
+fn default_origins() -> Vec<String> {
+    vec![DEV_ORIGIN.to_string()]
+}
+
+fn default_methods() -> Vec<String> {
+    vec!["POST".to_string()]
+}
+
+fn default_headers() -> Vec<String> {
+    ["content-type", "x-grpc-web", "x-user-agent", "grpc-timeout"]
+        .map(String::from)
+        .to_vec()
+}
+
+fn enabled() -> bool {
+    true
+}
+
+impl Default for WebConfig {
+    fn default() -> Self {
+        Self {
+            origins: default_origins(),
+            methods: default_methods(),
+            headers: default_headers(),
+            grpc_web: true,
+            http1: true,
+        }
+    }
+}
+
+impl WebConfig {
+    /// The CORS layer for the gRPC server, or why the lists do not parse.
+    pub fn cors(&self) -> Result<CorsLayer, String> {
+        if self.grpc_web && !self.http1 {
+            return Err("grpcWeb needs http1".to_string());
+        }
+
+        let origins = if self.origins.iter().any(|o| o == ANY) {
+            AllowOrigin::from(Any)
+        } else {
+            AllowOrigin::list(parse(&self.origins, "origin", |o| {
+                HeaderValue::from_str(o).ok()
+            })?)
+        };
+        let methods = if self.methods.iter().any(|m| m == ANY) {
+            AllowMethods::from(Any)
+        } else {
+            AllowMethods::list(parse(&self.methods, "method", |m| {
+                Method::from_bytes(m.as_bytes()).ok()
+            })?)
+        };
+        let headers = if self.headers.iter().any(|h| h == ANY) {
+            AllowHeaders::from(Any)
+        } else {
+            AllowHeaders::list(parse(&self.headers, "header", |h| {
+                HeaderName::from_bytes(h.as_bytes()).ok()
+            })?)
+        };
+
+        Ok(CorsLayer::new()
+            .allow_origin(origins)
+            .allow_methods(methods)
+            .allow_headers(headers)
+            .expose_headers(EXPOSED_HEADERS.map(HeaderName::from_static)))
+    }
+}
+
+fn parse<T>(
+    values: &[String],
+    what: &str,
+    f: impl Fn(&str) -> Option<T>,
+) -> Result<Vec<T>, String> {
+    values
+        .iter()
+        .map(|v| f(v).ok_or_else(|| format!("invalid {} {:?}", what, v)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tonic::codegen::http::{header, Request, Response};
+    use tower::{service_fn, Layer, ServiceExt};
+
+    /// The origin the layer allows for a request from `origin`, if any.
+    async fn allowed(config: &WebConfig, origin: &str) -> Option<String> {
+        let service = config.cors().unwrap().layer(service_fn(|_| async {
+            Ok::<_, std::convert::Infallible>(Response::new(String::new()))
+        }));
+        let request = Request::post("/query.v1.Node/State")
+            .header(header::ORIGIN, origin)
+            .body(())
+            .unwrap();
+        let response = service.oneshot(request).await.unwrap();
+        response
+            .headers()
+            .get(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+            .map(|v| v.to_str().unwrap().to_string())
+    }
+
+    #[tokio::test]
+    async fn test_cors_config() {
+        let config = WebConfig::default();
+        assert_eq!(
+            allowed(&config, DEV_ORIGIN).await.as_deref(),
+            Some(DEV_ORIGIN)
+        );
+        assert_eq!(allowed(&config, "https://evil.example").await, None);
+
+        let config: WebConfig =
+            serde_json::from_str(r#"{"origins": ["*"], "http1": false, "grpcWeb": false}"#)
+                .unwrap();
+        assert_eq!(
+            allowed(&config, "https://evil.example").await.as_deref(),
+            Some("*")
+        );
+
+        let invalid = |json: &str| {
+            serde_json::from_str::<WebConfig>(json)
+                .unwrap()
+                .cors()
+                .unwrap_err()
+        };
+        assert_eq!(invalid(r#"{"http1": false}"#), "grpcWeb needs http1");
+        assert!(invalid(r#"{"headers": ["bad header"]}"#).contains("invalid header"));
+        assert!(serde_json::from_str::<WebConfig>(r#"{"origin": []}"#).is_err());
+    }
+}
@@ -0,0 +1,45 @@
+//! Piece kinds by letter. JSON spells a kind as its letter, the way pieces
+//! were stored before `PieceKind`, so older states still decode and hash the
+//! same, while a letter that names no piece is rejected on the way in.
+
+use super::game::PieceKind;
+use serde::{de::Error as _, ser::Error as _, Deserialize, Deserializer, Serializer};
+
+impl PieceKind {
+    pub const ALL: [PieceKind; 6] = [
+        PieceKind::Pawn,
+        PieceKind::Knight,
+        PieceKind::Bishop,
+        PieceKind::Rook,
+        PieceKind::Queen,
+        PieceKind::King,
+    ];
+
+    pub fn letter(self) -> &'static str {
+        match self {
+            Self::Pawn => "P",
+            Self::Knight => "N",
+            Self::Bishop => "B",
+            Self::Rook => "R",
+            Self::Queen => "Q",
+            Self::King => "K",
+        }
+    }
+
+    pub fn from_letter(letter: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|k| k.letter() == letter)
+    }
+}
+
+pub fn serialize<S: Serializer>(kind: &i32, serializer: S) -> Result<S::Ok, S::Error> {
+    let kind = PieceKind::from_i32(*kind)
+        .ok_or_else(|| S::Error::custom(format!("unknown piece kind {}", kind)))?;
+    serializer.serialize_str(kind.letter())
+}
+
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<i32, D::Error> {
+    let letter = String::deserialize(deserializer)?;
+    PieceKind::from_letter(&letter)
+        .map(|k| k as i32)
+        .ok_or_else(|| D::Error::custom(format!("unknown piece kind {:?}", letter)))
+}
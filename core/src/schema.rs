@@ -0,0 +1,222 @@
+//! Versioned game records, as backups store them. Each record names the
+//! schema its game was written in and carries the game's hash. Records are
+//! upgraded only when read, one migration at a time, and the upgraded game
+//! must hash the same as GameState writes it back, so a migration cannot
+//! leave behind fields the node would silently drop.
+
+use crate::pb::game::GameState;
+use alloy_primitives::keccak256;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use thiserror::Error;
+
+/// The schema games are written in now.
+pub const SCHEMA: u32 = 3;
+
+#[derive(Debug, Error)]
+pub enum SchemaError {
+    #[error("record schema {0} is newer than this node's {}", SCHEMA)]
+    Newer(u32),
+
+    #[error("record does not match its hash")]
+    Corrupted,
+
+    #[error("migration to schema {0} failed: {1}")]
+    Migration(u32, String),
+
+    #[error("migration to schema {0} does not round-trip through the game")]
+    Unverified(u32),
+
+    #[error("malformed record: {0}")]
+    Malformed(#[from] serde_json::Error),
+}
+
+/// One stored game. Records from before schemas, the bare game, read as
+/// schema 1 without a hash.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(from = "Value")]
+pub struct GameRecord {
+    pub schema: u32,
+    /// keccak256 of the game as written, the same as its game state hash.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub hash: Option<String>,
+    pub game: Value,
+}
+
+struct Migration {
+    /// The schema it upgrades to, from the one before.
+    to: u32,
+    upgrade: fn(&mut Value) -> Result<(), String>,
+}
+
+/// Every migration, in order. A new GameState field that old games need a
+/// value for bumps SCHEMA and adds one here.
+const MIGRATIONS: &[Migration] = &[
+    Migration {
+        to: 2,
+        upgrade: add_game_id,
+    },
+    Migration {
+        to: 3,
+        upgrade: add_move_counters,
+    },
+];
+
+impl GameRecord {
+    pub fn new(game: &GameState) -> Self {
+        let game = serde_json::to_value(game).expect("GameState is serializable");
+        Self {
+            schema: SCHEMA,
+            hash: Some(hash(&game)),
+            game,
+        }
+    }
+
+    /// The game, upgraded to the current schema if it is older.
+    pub fn load(&self) -> Result<GameState, SchemaError> {
+        self.upgrade(MIGRATIONS)
+    }
+
+    fn upgrade(&self, migrations: &[Migration]) -> Result<GameState, SchemaError> {
+        if self.schema > SCHEMA {
+            return Err(SchemaError::Newer(self.schema));
+        }
+        if self.hash.as_ref().is_some_and(|h| *h != hash(&self.game)) {
+            return Err(SchemaError::Corrupted);
+        }
+
+        let mut game = self.game.clone();
+        for migration in migrations.iter().filter(|m| m.to > self.schema) {
+            (migration.upgrade)(&mut game).map_err(|e| SchemaError::Migration(migration.to, e))?;
+            let parsed: GameState = serde_json::from_value(game.clone())?;
+            if hash(&serde_json::to_value(&parsed)?) != hash(&game) {
+                return Err(SchemaError::Unverified(migration.to));
+            }
+        }
+
+        Ok(serde_json::from_value(game)?)
+    }
+}
+
+impl From<Value> for GameRecord {
+    fn from(value: Value) -> Self {
+        match (value.get("schema"), value.get("game")) {
+            (Some(Value::Number(schema)), Some(game)) => Self {
+                schema: schema
+                    .as_u64()
+                    .and_then(|s| u32::try_from(s).ok())
+                    .unwrap_or(u32::MAX),
+                hash: value.get("hash").and_then(Value::as_str).map(String::from),
+                game: game.clone(),
+            },
+            _ => Self {
+                schema: 1,
+                hash: None,
+                game: value,
+            },
+        }
+    }
+}
+
+fn hash(game: &Value) -> String {
+    keccak256(game.to_string()).to_string()
+}
+
+/// 2: games are keyed by id. Games from before get the id their start
+/// request would have had.
+fn add_game_id(game: &mut Value) -> Result<(), String> {
+    let mut state: GameState = serde_json::from_value(game.take()).map_err(|e| e.to_string())?;
+    state.game_id = Some(state.start_request().game_id());
+    *game = serde_json::to_value(state).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// 3: games count their moves as FEN does. Games from before get the
+/// counts their moves imply.
+fn add_move_counters(game: &mut Value) -> Result<(), String> {
+    let mut state: GameState = serde_json::from_value(game.take()).map_err(|e| e.to_string())?;
+    state.halfmove_clock = state
+        .moves
+        .iter()
+        .rev()
+        .take_while(|m| !m.san.contains('x') && !m.san.starts_with(char::is_lowercase))
+        .count() as u32;
+    state.fullmove_number = state.moves.len() as u32 / 2 + 1;
+    *game = serde_json::to_value(state).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pb::query::{Position, StartRequest};
+
+    fn start() -> StartRequest {
+        StartRequest {
+            white_player: "Alice".into(),
+            black_player: "Bob".into(),
+            started_at: 1000,
+            nonce: 7,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_records_upgrade_on_read() {
+        let game = start().game().unwrap();
+        let record = GameRecord::new(&game);
+        let stored: GameRecord =
+            serde_json::from_str(&serde_json::to_string(&record).unwrap()).unwrap();
+        assert_eq!(stored, record);
+        assert_eq!(stored.load().unwrap(), game);
+
+        // A bare game from before schemas gets the id its start implies and
+        // the move counters its moves do.
+        let mut game = game;
+        game.apply_move(Position { x: 1, y: 4 }, Position { x: 3, y: 4 })
+            .unwrap();
+        game.apply_move(Position { x: 7, y: 6 }, Position { x: 5, y: 5 })
+            .unwrap();
+        let mut legacy = serde_json::to_value(&game).unwrap();
+        for field in ["game_id", "halfmove_clock", "fullmove_number"] {
+            legacy.as_object_mut().unwrap().remove(field);
+        }
+        let stored: GameRecord = serde_json::from_value(legacy).unwrap();
+        assert_eq!(stored.schema, 1);
+        assert_eq!(stored.load().unwrap(), game);
+    }
+
+    #[test]
+    fn test_bad_records_are_refused() {
+        let record = GameRecord::new(&start().game().unwrap());
+
+        let mut tampered = record.clone();
+        tampered.game["started_at"] = 2000.into();
+        assert!(matches!(tampered.load(), Err(SchemaError::Corrupted)));
+
+        let newer = GameRecord {
+            schema: SCHEMA + 1,
+            ..record.clone()
+        };
+        assert!(matches!(newer.load(), Err(SchemaError::Newer(_))));
+
+        // A migration leaving a field GameState does not know is caught.
+        fn stray(game: &mut Value) -> Result<(), String> {
+            game["castling"] = "KQkq".into();
+            Ok(())
+        }
+        let old = GameRecord {
+            schema: 1,
+            hash: None,
+            ..record
+        };
+        let migrations = [Migration {
+            to: 2,
+            upgrade: stray,
+        }];
+        assert!(matches!(
+            old.upgrade(&migrations),
+            Err(SchemaError::Unverified(2))
+        ));
+    }
+}
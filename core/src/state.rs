@@ -0,0 +1,392 @@
+//! Node state shared between the consensus, gossip and gRPC tasks.
+//!
+//! Locks are taken in this order and never the other way round:
+//!
+//! 1. `App::consensus`, the head and the votes;
+//! 2. the map of `App::db`;
+//! 3. one game's own lock;
+//! 4. any other lock in `App` (`state_hasher`, `stats`, `txs`, ...), held
+//!    only long enough to update it and never while taking another.
+//!
+//! A commit holds the consensus lock throughout, so commits run one at a
+//! time and nothing sees the head move before the game it changed.
+
+use crate::consensus::governance::Version;
+use crate::{
+    consensus::types::QuorumCertificate,
+    errors::AppError,
+    pb::{
+        game::GameState,
+        query::{Seek, ValidatorSignature},
+    },
+    PEERS,
+};
+use alloy_primitives::B256;
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use tokio::sync::{RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+/// What the chain agreed on so far and the votes towards the next block.
+#[derive(Default)]
+pub struct ConsensusState {
+    /// Hash of the last committed block.
+    pub head: B256,
+    /// Unix seconds of the last committed block.
+    pub head_timestamp: i64,
+    /// Unix seconds of the last commit or view change.
+    pub latest_timestamp: u64,
+    /// Nonce of the last governance transaction committed.
+    pub governance_nonce: u64,
+    /// Seeks committed blocks opened, by id, until they are accepted,
+    /// cancelled or expire.
+    pub seeks: HashMap<String, Seek>,
+    /// Seeks accepted or cancelled in committed blocks, by when they expire;
+    /// none can be opened again before then, nor after.
+    pub closed_seeks: HashMap<String, i64>,
+    /// Whether a committed halt stopped transactions other than governance.
+    pub halted: bool,
+    /// The lowest node version a committed REQUIRE_VERSION asks for, and the
+    /// height from which it does.
+    pub required_version: Option<(Version, u64)>,
+    /// Validators that voted for each block hash.
+    pub votes: HashMap<B256, HashSet<String>>,
+    /// Votes sent straight to this node as leader, for the QCs it builds.
+    pub signed_votes: HashMap<B256, Vec<ValidatorSignature>>,
+}
+
+impl ConsensusState {
+    /// Counts the vote of `voter` for `hash` once, whichever paths deliver
+    /// it. Returns whether it is new.
+    pub fn count_vote(&mut self, hash: B256, voter: String) -> bool {
+        self.votes.entry(hash).or_default().insert(voter)
+    }
+
+    /// Counts a vote sent straight to the leader, keeping its signature.
+    pub fn count_signed_vote(&mut self, hash: B256, vote: ValidatorSignature) -> bool {
+        let new = self.count_vote(hash, vote.validator.clone());
+        if new {
+            self.signed_votes.entry(hash).or_default().push(vote);
+        }
+        new
+    }
+
+    pub fn signed_votes(&self, hash: &B256) -> Vec<ValidatorSignature> {
+        self.signed_votes.get(hash).cloned().unwrap_or_default()
+    }
+
+    /// The validators that voted for `hash`, once they are more than 2/3.
+    pub fn quorum(&self, hash: &B256) -> Option<Vec<String>> {
+        self.votes
+            .get(hash)
+            .filter(|v| v.len() > (2 * PEERS as usize) / 3)
+            .map(|v| v.iter().cloned().collect())
+    }
+
+    /// Checks that more than 2/3 of the validators signing `qc` voted for
+    /// its block, either here or in a vote signed into the QC by one of
+    /// `validators`.
+    pub fn validate_qc(
+        &self,
+        qc: &QuorumCertificate,
+        validators: &[String],
+    ) -> Result<(), AppError> {
+        let hash = qc.block_hash()?;
+        let payload = QuorumCertificate::vote_payload(&hash);
+        let mut votes: HashSet<_> = self.votes.get(&hash).into_iter().flatten().collect();
+        votes.extend(
+            qc.votes
+                .iter()
+                .filter(|v| validators.contains(&v.validator) && v.verify(&payload))
+                .map(|v| &v.validator),
+        );
+
+        let signers: HashSet<_> = qc.signature.iter().collect();
+        if votes.iter().filter(|v| signers.contains(*v)).count() > (2 * PEERS as usize) / 3 {
+            Ok(())
+        } else {
+            Err(AppError::InvalidQcError)
+        }
+    }
+}
+
+/// The game DB, keyed by game id, with a lock per game so a commit to one
+/// game does not stall reads of the others.
+#[derive(Default)]
+pub struct Games(RwLock<HashMap<String, Arc<RwLock<GameState>>>>);
+
+impl Games {
+    /// The key and lock of the game a request names: the game under
+    /// `game_id`, if its players are `white` and `black` or left empty, or
+    /// without an id the latest started game between the two.
+    pub async fn find(
+        &self,
+        game_id: Option<&str>,
+        white: &str,
+        black: &str,
+    ) -> Option<(String, Arc<RwLock<GameState>>)> {
+        let games = self.0.read().await;
+        if let Some(game_id) = game_id {
+            let game = games.get(game_id)?;
+            let g = game.read().await;
+            let players = |given: &str, actual: &str| given.is_empty() || given == actual;
+            if !players(white, &g.white_player) || !players(black, &g.black_player) {
+                return None;
+            }
+            drop(g);
+            return Some((game_id.to_string(), game.clone()));
+        }
+
+        let mut latest = None;
+        for (key, game) in games.iter() {
+            let g = game.read().await;
+            if g.white_player != white || g.black_player != black {
+                continue;
+            }
+            let order = (g.started_at, g.start_nonce, key.clone());
+            if latest.as_ref().is_none_or(|(o, _)| order > *o) {
+                latest = Some((order, game.clone()));
+            }
+        }
+        latest.map(|((_, _, key), game)| (key, game))
+    }
+
+    /// A copy of the game a request names, as in `find`.
+    pub async fn get(&self, game_id: Option<&str>, white: &str, black: &str) -> Option<GameState> {
+        let (_, game) = self.find(game_id, white, black).await?;
+        let game = game.read().await.clone();
+        Some(game)
+    }
+
+    /// Copies of every game with its key.
+    pub async fn snapshot(&self) -> Vec<(String, GameState)> {
+        let games: Vec<_> = self
+            .0
+            .read()
+            .await
+            .iter()
+            .map(|(key, game)| (key.clone(), game.clone()))
+            .collect();
+
+        let mut snapshot = Vec::with_capacity(games.len());
+        for (key, game) in games {
+            snapshot.push((key, game.read().await.clone()));
+        }
+        snapshot
+    }
+
+    /// The map itself, for checking a start against every game.
+    pub async fn read(&self) -> RwLockReadGuard<'_, HashMap<String, Arc<RwLock<GameState>>>> {
+        self.0.read().await
+    }
+
+    /// The map itself, for adding games.
+    pub async fn write(&self) -> RwLockWriteGuard<'_, HashMap<String, Arc<RwLock<GameState>>>> {
+        self.0.write().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ConsensusState;
+    use crate::{
+        consensus::types::{BlockBuilder, QuorumCertificate},
+        pb::query::{Position, StartRequest, Transaction, TransactionKind, ValidatorSignature},
+        App, PEERS,
+    };
+    use alloy_primitives::{keccak256, B256};
+    use libp2p::identity::Keypair;
+    use libsecp256k1::{PublicKey, SecretKey};
+    use std::time::Duration;
+    use tokio::sync::mpsc;
+
+    /// Runs `n` copies of `task` at once on several threads and fails if
+    /// they have not all finished within a few seconds, which would mean
+    /// two of them wait on each other.
+    async fn race<F, Fut>(n: usize, task: F)
+    where
+        F: Fn(usize) -> Fut,
+        Fut: std::future::Future<Output = ()> + Send + 'static,
+    {
+        let handles: Vec<_> = (0..n).map(|i| tokio::spawn(task(i))).collect();
+        let all = futures::future::join_all(handles);
+        let results = tokio::time::timeout(Duration::from_secs(10), all)
+            .await
+            .expect("tasks deadlocked");
+        for result in results {
+            result.unwrap();
+        }
+    }
+
+    fn app() -> &'static App {
+        Box::leak(Box::new(App::new(mpsc::channel(1).0)))
+    }
+
+    /// The public key standing in for the player `name`.
+    fn player(name: &str) -> String {
+        let secret_key = SecretKey::parse(&keccak256(name).0).unwrap();
+        hex::encode(PublicKey::from_secret_key(&secret_key).serialize_compressed())
+    }
+
+    fn start(white: String, black: String) -> StartRequest {
+        StartRequest {
+            white_player: white,
+            black_player: black,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_concurrent_starts_and_reads_finish() {
+        let app = app();
+
+        race(64, |i| async move {
+            let (white, black) = (
+                player(&format!("w{}", i % 8)),
+                player(&format!("b{}", i % 8)),
+            );
+            let _ = app
+                .start_game_if_possible(start(white.clone(), black.clone()))
+                .await;
+            assert!(app.db.get(None, &white, &black).await.is_some());
+            app.db.snapshot().await;
+            app.get_state_hash().await;
+        })
+        .await;
+
+        assert_eq!(app.db.snapshot().await.len(), 8);
+    }
+
+    /// Commits of one game racing with votes, view changes and reads of
+    /// every game, including a commit whose move fails and is rolled back.
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_commits_race_with_votes_and_reads() {
+        let app = app();
+        let validators: Vec<String> = (0..PEERS).map(|i| format!("validator {}", i)).collect();
+        for i in 0..4 {
+            app.start_game_if_possible(start(
+                player(&format!("w{}", i)),
+                player(&format!("b{}", i)),
+            ))
+            .await
+            .unwrap();
+        }
+
+        let commit = |from: Position, to: Position| {
+            let tx = Transaction {
+                white_player: player("w0"),
+                black_player: player("b0"),
+                action: vec![from, to],
+                kind: TransactionKind::Move as i32,
+                ..Default::default()
+            };
+            let validators = validators.clone();
+            async move {
+                let mut block = BlockBuilder::default()
+                    .with_previous_block_hash(app.consensus.read().await.head)
+                    .with_history(
+                        app.db
+                            .get(None, &player("w0"), &player("b0"))
+                            .await
+                            .unwrap()
+                            .history
+                            .unwrap(),
+                    )
+                    .with_tx(tx)
+                    .with_rules_version(app.rules_version_at(app.next_height()).await)
+                    .with_height(app.next_height())
+                    .build();
+                let hash = block.block_hash().unwrap();
+                for v in &validators {
+                    app.count_vote(hash, v.clone()).await;
+                }
+                block.qc = Some(
+                    QuorumCertificate::default()
+                        .with_block_hash(hash)
+                        .with_signature(validators),
+                );
+                app.commit_block(block).await
+            }
+        };
+
+        let readers = tokio::spawn(async move {
+            race(32, |i| async move {
+                app.count_vote(Default::default(), i.to_string()).await;
+                app.update_view_if_needed().await;
+                app.db.snapshot().await;
+                let (white, black) = (
+                    player(&format!("w{}", i % 4)),
+                    player(&format!("b{}", i % 4)),
+                );
+                app.db.get(None, &white, &black).await.unwrap();
+                app.get_state_hash().await;
+            })
+            .await
+        });
+
+        commit(Position { x: 1, y: 4 }, Position { x: 3, y: 4 })
+            .await
+            .unwrap();
+        // A rook cannot jump its own pawn; the game must stay as it was.
+        assert!(commit(Position { x: 7, y: 0 }, Position { x: 4, y: 0 })
+            .await
+            .is_err());
+        commit(Position { x: 6, y: 4 }, Position { x: 4, y: 4 })
+            .await
+            .unwrap();
+        readers.await.unwrap();
+
+        let game = app
+            .db
+            .get(None, &player("w0"), &player("b0"))
+            .await
+            .unwrap();
+        assert_eq!(game.moves.len(), 2);
+        assert_eq!(app.height.load(std::sync::atomic::Ordering::Relaxed), 2);
+    }
+
+    fn signed_vote(key: &Keypair, hash: &B256) -> ValidatorSignature {
+        ValidatorSignature {
+            validator: key.public().to_peer_id().to_string(),
+            public_key: hex::encode(key.public().to_protobuf_encoding()),
+            signature: hex::encode(key.sign(&QuorumCertificate::vote_payload(hash)).unwrap()),
+        }
+    }
+
+    #[test]
+    fn test_signed_votes_certify_without_local_votes() {
+        let consensus = ConsensusState::default();
+        let keys: Vec<_> = (0..PEERS).map(|_| Keypair::generate_ed25519()).collect();
+        let validators: Vec<_> = keys
+            .iter()
+            .map(|k| k.public().to_peer_id().to_string())
+            .collect();
+        let hash = B256::repeat_byte(1);
+        let qc = |votes: Vec<ValidatorSignature>| {
+            QuorumCertificate::default()
+                .with_block_hash(hash)
+                .with_signature(validators.clone())
+                .with_votes(votes)
+        };
+
+        let votes: Vec<_> = keys.iter().map(|k| signed_vote(k, &hash)).collect();
+        consensus
+            .validate_qc(&qc(votes.clone()), &validators)
+            .unwrap();
+
+        // Too few, signed by outsiders, or for another block.
+        let few = qc(votes[..2].to_vec());
+        assert!(consensus.validate_qc(&few, &validators).is_err());
+        assert!(consensus
+            .validate_qc(&qc(votes.clone()), &validators[..2])
+            .is_err());
+        let other = B256::repeat_byte(2);
+        let forged = keys.iter().map(|k| signed_vote(k, &other)).collect();
+        assert!(consensus.validate_qc(&qc(forged), &validators).is_err());
+
+        // Votes seen here make up for ones missing from the QC.
+        let mut consensus = consensus;
+        consensus.count_vote(hash, validators[2].clone());
+        consensus.count_vote(hash, validators[3].clone());
+        consensus.validate_qc(&few, &validators).unwrap();
+    }
+}
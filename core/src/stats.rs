@@ -0,0 +1,118 @@
+use crate::{
+    pb::{
+        game::{Color, GameState, Termination},
+        query::PlayerStats,
+    },
+    App,
+};
+use std::collections::HashMap;
+
+/// How a finished game went for one side.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Outcome {
+    Win,
+    Loss,
+    Draw,
+}
+
+impl PlayerStats {
+    fn record(&mut self, outcome: Outcome) {
+        match outcome {
+            Outcome::Win => {
+                self.wins += 1;
+                self.win_streak += 1;
+                self.best_win_streak = self.best_win_streak.max(self.win_streak);
+            }
+            Outcome::Loss => {
+                self.losses += 1;
+                self.win_streak = 0;
+            }
+            Outcome::Draw => {
+                self.draws += 1;
+                self.win_streak = 0;
+            }
+        }
+    }
+}
+
+/// Per-player results, updated as games finish in committed blocks, so every
+/// node holds the same figures without indexing the chain.
+#[derive(Default)]
+pub struct Stats(HashMap<String, PlayerStats>);
+
+impl Stats {
+    pub fn get(&self, player: &str) -> PlayerStats {
+        self.0.get(player).cloned().unwrap_or_default()
+    }
+
+    /// Credits a game that has just finished to both sides.
+    pub fn record_game(&mut self, game: &GameState) {
+        let Some(result) = &game.result else {
+            return;
+        };
+        if result.termination == Termination::Aborted as i32 {
+            return;
+        }
+
+        for color in [Color::White, Color::Black] {
+            let outcome = match result.winner {
+                None => Outcome::Draw,
+                Some(winner) if winner == color as i32 => Outcome::Win,
+                Some(_) => Outcome::Loss,
+            };
+
+            let players = match game.team(color) {
+                Some(team) => team.members.clone(),
+                None => vec![game.player(color).to_string()],
+            };
+            for player in players {
+                self.0.entry(player).or_default().record(outcome);
+            }
+        }
+    }
+}
+
+impl App {
+    pub async fn player_stats(&self, player: &str) -> PlayerStats {
+        self.stats.read().await.get(player)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pb::game::Team;
+
+    #[test]
+    fn test_records_results_and_streaks() {
+        let mut stats = Stats::default();
+        let finished = |winner: Option<Color>, termination| {
+            let mut game = GameState::new("Alice".to_string(), "Bob".to_string());
+            game.finish(termination, winner);
+            game
+        };
+
+        stats.record_game(&finished(Some(Color::White), Termination::Checkmate));
+        stats.record_game(&finished(Some(Color::White), Termination::Checkmate));
+        stats.record_game(&finished(None, Termination::Aborted));
+        stats.record_game(&finished(None, Termination::Stalemate));
+        stats.record_game(&finished(Some(Color::White), Termination::Checkmate));
+
+        let alice = stats.get("Alice");
+        assert_eq!((alice.wins, alice.losses, alice.draws), (3, 0, 1));
+        assert_eq!((alice.win_streak, alice.best_win_streak), (1, 2));
+        assert_eq!(stats.get("Bob").losses, 3);
+        assert_eq!(stats.get("Carol"), PlayerStats::default());
+
+        let team = Team {
+            members: vec!["Carol".to_string(), "Dave".to_string()],
+            threshold: 2,
+        };
+        let mut game = finished(Some(Color::Black), Termination::Checkmate);
+        game.black_team = Some(team);
+        stats.record_game(&game);
+        assert_eq!(stats.get("Dave").wins, 1);
+        assert_eq!(stats.get("Alice").losses, 1);
+        assert_eq!(stats.get("Bob").losses, 3);
+    }
+}
@@ -0,0 +1,126 @@
+//! `watch`: follows one game from a node's WatchGame stream and redraws it
+//! in the terminal after every move, for headless boxes and for poking at a
+//! local network by hand. `board` prints the game once from each of several
+//! nodes, to see where they disagree.
+
+use crate::pb::{
+    game::{Color, GameState},
+    query::{node_client::NodeClient, StateRequest},
+};
+use std::error::Error;
+
+/// Clears the terminal and puts the cursor at the top left.
+const CLEAR: &str = "\x1b[2J\x1b[H";
+
+/// Streams the game `key` names, by its id or as "white:black", from the
+/// node at `endpoint` until it is over or the node hangs up.
+pub async fn run(endpoint: String, key: &str, unicode: bool) -> Result<(), Box<dyn Error>> {
+    let mut client = NodeClient::connect(endpoint).await?;
+    let mut states = client.watch_game(state_request(key)?).await?.into_inner();
+
+    while let Some(response) = states.message().await? {
+        if let Some(state) = response.state {
+            print!("{}{}", CLEAR, render(&state, unicode));
+        }
+    }
+    Ok(())
+}
+
+/// Prints the game under `key` as each of `endpoints` has it.
+pub async fn show(endpoints: Vec<String>, key: &str, unicode: bool) -> Result<(), Box<dyn Error>> {
+    let request = state_request(key)?;
+    for endpoint in endpoints {
+        println!("== {}", endpoint);
+        // A node that cannot be reached is reported, not a reason to skip
+        // the others.
+        match state(endpoint, request.clone()).await {
+            Ok(Some(state)) => println!("{}", render(&state, unicode)),
+            Ok(None) => println!("no such game\n"),
+            Err(e) => println!("{}\n", e),
+        }
+    }
+    Ok(())
+}
+
+async fn state(
+    endpoint: String,
+    request: StateRequest,
+) -> Result<Option<GameState>, Box<dyn Error>> {
+    let mut client = NodeClient::connect(endpoint).await?;
+    Ok(client.state(request).await?.into_inner().state)
+}
+
+/// A request for the game with the id `key`, or if it is "white:black" for
+/// the latest game between the two.
+fn state_request(key: &str) -> Result<StateRequest, Box<dyn Error>> {
+    let Some((white, black)) = key.split_once(':') else {
+        return Ok(StateRequest {
+            game_id: Some(key.to_string()),
+            ..Default::default()
+        });
+    };
+
+    Ok(StateRequest {
+        white_player: white.to_string(),
+        black_player: black.to_string(),
+        ..Default::default()
+    })
+}
+
+/// The board from White's side, the moves so far and whose turn it is.
+pub fn render(game: &GameState, unicode: bool) -> String {
+    let mut out = format!("{} vs {}\n\n", game.white_player, game.black_player);
+
+    if let Some(board) = &game.board {
+        if unicode {
+            out.push_str(&format!("{:#}\n\n", board));
+        } else {
+            out.push_str(&format!("{}\n\n", board));
+        }
+    }
+
+    let mut moves = Vec::new();
+    for (i, m) in game.moves.iter().enumerate() {
+        if i % 2 == 0 {
+            moves.push(format!("{}.", i / 2 + 1));
+        }
+        moves.push(m.san.clone());
+    }
+    if !moves.is_empty() {
+        out.push_str(&moves.join(" "));
+        out.push('\n');
+    }
+
+    if game.is_over() {
+        out.push_str(&format!("Game over: {}\n", game.pgn_result()));
+    } else if game.turn == Color::White as i32 {
+        out.push_str("White to move\n");
+    } else {
+        out.push_str("Black to move\n");
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pb::query::Position;
+
+    #[test]
+    fn test_render() {
+        let mut game = GameState::new("Alice".to_string(), "Bob".to_string());
+        game.apply_move(Position { x: 1, y: 4 }, Position { x: 3, y: 4 })
+            .unwrap();
+
+        let board = render(&game, false);
+        let lines: Vec<_> = board.lines().collect();
+        assert_eq!(lines[0], "Alice vs Bob");
+        assert_eq!(lines[2], "8  r n b q k b n r");
+        assert_eq!(lines[6], "4  . . . . P . . .");
+        assert_eq!(lines[9], "1  R N B Q K B N R");
+        assert_eq!(lines[10], "   a b c d e f g h");
+        assert_eq!(lines[12], "1. e4");
+        assert_eq!(lines[13], "Black to move");
+    }
+}